@@ -0,0 +1,229 @@
+// Run with `cargo bench --bench compare`, or `cargo bench --bench compare -- push` for one group.
+//
+// The same four operations on the crate's general-purpose lists and on std's Vec, VecDeque and
+// LinkedList, measured with Criterion, which reports each as elements per second (and keeps the
+// previous run around to say whether a change made things faster or slower):
+//
+// - push: 1,000 or 100,000 (LENS) elements into an empty list. One allocation per element for the
+//   node-based lists, amortised away for Vec and VecDeque, and mostly for ArenaList, whose nodes
+//   live in a Vec.
+// - pop: all of them back out again.
+// - iterate: sum the elements. This is where the pointer chasing shows.
+// - sort: none of the linked lists can sort in place, so for them this is what a user would do:
+//   into a Vec, sort_unstable, and back into a list. Vec sorts in place, VecDeque after
+//   make_contiguous.
+//
+// Each list is used the way its module is meant to be used: the stack pushes and pops at the
+// top, the rest push at the back and pop from the front.
+
+use criterion::measurement::WallTime;
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkGroup, BenchmarkId, Criterion,
+    Throughput,
+};
+use linked_list_rc::arena_list::ArenaList;
+use linked_list_rc::{
+    generic_and_iterators, unsafe_doubly_linked_deque, unsafe_single_linked_queue,
+};
+use std::collections::{LinkedList, VecDeque};
+
+const LENS: [usize; 2] = [1_000, 100_000];
+
+// What every list below can do, in the names the bench functions use.
+trait Sequence: Sized {
+    const NAME: &'static str;
+
+    fn new() -> Self;
+    fn push(&mut self, elem: u64);
+    fn pop(&mut self) -> Option<u64>;
+    fn sum(&self) -> u64;
+    fn sort(self) -> Self;
+}
+
+macro_rules! sequence {
+    ($ty:ty, $name:expr, $push:ident, $pop:ident) => {
+        impl Sequence for $ty {
+            const NAME: &'static str = $name;
+
+            fn new() -> Self {
+                <$ty>::new()
+            }
+
+            fn push(&mut self, elem: u64) {
+                self.$push(elem);
+            }
+
+            fn pop(&mut self) -> Option<u64> {
+                self.$pop()
+            }
+
+            fn sum(&self) -> u64 {
+                self.iter().sum()
+            }
+
+            fn sort(self) -> Self {
+                let mut elems: Vec<u64> = self.into_iter().collect();
+                elems.sort_unstable();
+                let mut list = <$ty>::new();
+                for elem in elems {
+                    list.$push(elem);
+                }
+                list
+            }
+        }
+    };
+}
+
+sequence!(
+    generic_and_iterators::List<u64>,
+    "generic_and_iterators",
+    push,
+    pop
+);
+sequence!(
+    unsafe_doubly_linked_deque::List<u64>,
+    "unsafe_doubly_linked_deque",
+    push_back,
+    pop_front
+);
+sequence!(
+    unsafe_single_linked_queue::List<u64>,
+    "unsafe_single_linked_queue",
+    push,
+    pop
+);
+sequence!(ArenaList<u64>, "arena_list", push_back, pop_front);
+sequence!(LinkedList<u64>, "std LinkedList", push_back, pop_front);
+
+impl Sequence for Vec<u64> {
+    const NAME: &'static str = "std Vec";
+
+    fn new() -> Self {
+        Vec::new()
+    }
+
+    fn push(&mut self, elem: u64) {
+        Vec::push(self, elem);
+    }
+
+    fn pop(&mut self) -> Option<u64> {
+        Vec::pop(self)
+    }
+
+    fn sum(&self) -> u64 {
+        self.iter().sum()
+    }
+
+    fn sort(mut self) -> Self {
+        self.sort_unstable();
+        self
+    }
+}
+
+impl Sequence for VecDeque<u64> {
+    const NAME: &'static str = "std VecDeque";
+
+    fn new() -> Self {
+        VecDeque::new()
+    }
+
+    fn push(&mut self, elem: u64) {
+        self.push_back(elem);
+    }
+
+    fn pop(&mut self) -> Option<u64> {
+        self.pop_front()
+    }
+
+    fn sum(&self) -> u64 {
+        self.iter().sum()
+    }
+
+    fn sort(mut self) -> Self {
+        self.make_contiguous().sort_unstable();
+        self
+    }
+}
+
+// A list of `len` elements in a scrambled order, so sorting has something to do.
+fn filled<L: Sequence>(len: usize) -> L {
+    let mut list = L::new();
+    for i in 0..len as u64 {
+        list.push(i.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32);
+    }
+    list
+}
+
+fn push<L: Sequence>(group: &mut BenchmarkGroup<'_, WallTime>, len: usize) {
+    group.bench_with_input(BenchmarkId::new(L::NAME, len), &len, |b, &len| {
+        b.iter(|| {
+            let mut list = L::new();
+            for i in 0..len as u64 {
+                list.push(black_box(i));
+            }
+            list
+        })
+    });
+}
+
+fn pop<L: Sequence>(group: &mut BenchmarkGroup<'_, WallTime>, len: usize) {
+    group.bench_with_input(BenchmarkId::new(L::NAME, len), &len, |b, &len| {
+        b.iter_batched_ref(
+            || filled::<L>(len),
+            |list| {
+                while let Some(elem) = list.pop() {
+                    black_box(elem);
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn iterate<L: Sequence>(group: &mut BenchmarkGroup<'_, WallTime>, len: usize) {
+    let list = filled::<L>(len);
+    group.bench_with_input(BenchmarkId::new(L::NAME, len), &list, |b, list| {
+        b.iter(|| list.sum())
+    });
+}
+
+fn sort<L: Sequence>(group: &mut BenchmarkGroup<'_, WallTime>, len: usize) {
+    group.bench_with_input(BenchmarkId::new(L::NAME, len), &len, |b, &len| {
+        b.iter_batched(|| filled::<L>(len), L::sort, BatchSize::LargeInput)
+    });
+}
+
+type Op = fn(&mut BenchmarkGroup<'_, WallTime>, usize);
+
+// One Criterion group per operation, with every list in it at every length.
+macro_rules! group {
+    ($c:expr, $name:expr, $op:ident) => {{
+        let mut group = $c.benchmark_group($name);
+        for len in LENS {
+            group.throughput(Throughput::Elements(len as u64));
+            let ops: [Op; 7] = [
+                $op::<generic_and_iterators::List<u64>>,
+                $op::<unsafe_doubly_linked_deque::List<u64>>,
+                $op::<unsafe_single_linked_queue::List<u64>>,
+                $op::<ArenaList<u64>>,
+                $op::<LinkedList<u64>>,
+                $op::<VecDeque<u64>>,
+                $op::<Vec<u64>>,
+            ];
+            for op in ops {
+                op(&mut group, len);
+            }
+        }
+        group.finish();
+    }};
+}
+
+fn benches(c: &mut Criterion) {
+    group!(c, "push", push);
+    group!(c, "pop", pop);
+    group!(c, "iterate", iterate);
+    group!(c, "sort", sort);
+}
+
+criterion_group!(compare, benches);
+criterion_main!(compare);
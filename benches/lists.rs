@@ -0,0 +1,63 @@
+// Run with `cargo bench --bench lists`.
+//
+// Sums every element of a list, which is all pointer chasing for the node-per-element deque: one
+// node, and likely one cache miss, per element. The unrolled list walks N elements per node, so
+// the bigger N is the closer it should get to a VecDeque. Building the list is timed too, since
+// that's one allocation per element versus one per N.
+//
+// ArenaList still has one node per element, and iterating it is still a chain of dependent loads,
+// so on a fresh heap (where the deque's nodes come out of the allocator in order anyway) it sums
+// no faster than the deque. What it saves is the allocation per push.
+
+use linked_list_rc::arena_list::ArenaList;
+use linked_list_rc::unrolled_list::UnrolledList;
+use linked_list_rc::unsafe_doubly_linked_deque::List;
+use std::collections::VecDeque;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const LEN: usize = 1_000_000;
+const ROUNDS: usize = 5;
+
+macro_rules! build_and_sum {
+    ($list:ty) => {{
+        let start = Instant::now();
+        let mut list = <$list>::new();
+        for i in 0..LEN {
+            list.push_back(i);
+        }
+        let built = start.elapsed();
+        let start = Instant::now();
+        black_box(list.iter().sum::<usize>());
+        (built, start.elapsed())
+    }};
+}
+
+// Best of ROUNDS, which is the least noisy number on a busy machine.
+fn best(mut run: impl FnMut() -> (Duration, Duration)) -> (Duration, Duration) {
+    let runs: Vec<_> = (0..ROUNDS).map(|_| run()).collect();
+    (
+        runs.iter().map(|r| r.0).min().unwrap(),
+        runs.iter().map(|r| r.1).min().unwrap(),
+    )
+}
+
+fn main() {
+    println!("{:>18} {:>12} {:>12}", "list", "push_back", "sum");
+    let rows = [
+        ("VecDeque", best(|| build_and_sum!(VecDeque<usize>))),
+        ("List", best(|| build_and_sum!(List<usize>))),
+        ("ArenaList", best(|| build_and_sum!(ArenaList<usize>))),
+        (
+            "UnrolledList<8>",
+            best(|| build_and_sum!(UnrolledList<usize, 8>)),
+        ),
+        (
+            "UnrolledList<64>",
+            best(|| build_and_sum!(UnrolledList<usize, 64>)),
+        ),
+    ];
+    for (name, (built, sum)) in rows {
+        println!("{:>18} {:>12.2?} {:>12.2?}", name, built, sum);
+    }
+}
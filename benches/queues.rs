@@ -0,0 +1,92 @@
+// Run with `cargo bench --bench queues`.
+//
+// Each round starts the same number of producer and consumer threads and times how long it takes
+// to push and pop OPS elements per producer. The segmented queue should pull ahead as threads are
+// added, since it allocates (and hands to the epoch collector) once per segment instead of once
+// per element. The flat-combining queue is compared against the plain Mutex around the same
+// deque that it's meant to replace.
+
+use linked_list_rc::flat_combining_queue::FlatCombiningQueue;
+use linked_list_rc::ms_queue::MsQueue;
+use linked_list_rc::seg_queue::SegQueue;
+use linked_list_rc::unsafe_doubly_linked_deque::List;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const OPS: usize = 200_000;
+const ROUNDS: usize = 5;
+
+// The baseline flat combining is up against: one lock taken for every single operation.
+#[derive(Default)]
+struct MutexQueue(Mutex<List<usize>>);
+
+impl MutexQueue {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(&self, elem: usize) {
+        self.0.lock().unwrap().push_back(elem);
+    }
+
+    fn pop(&self) -> Option<usize> {
+        self.0.lock().unwrap().pop_front()
+    }
+}
+
+macro_rules! mpmc {
+    ($queue:ty, $threads:expr) => {{
+        let queue = Arc::new(<$queue>::new());
+        let start = Instant::now();
+        let producers: Vec<_> = (0..$threads)
+            .map(|_| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    for i in 0..OPS {
+                        queue.push(i);
+                    }
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..$threads)
+            .map(|_| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    let mut popped = 0;
+                    while popped < OPS {
+                        if queue.pop().is_some() {
+                            popped += 1;
+                        }
+                    }
+                })
+            })
+            .collect();
+        for h in producers.into_iter().chain(consumers) {
+            h.join().unwrap();
+        }
+        start.elapsed()
+    }};
+}
+
+// Best of ROUNDS, which is the least noisy number on a busy machine.
+fn best(mut run: impl FnMut() -> Duration) -> Duration {
+    (0..ROUNDS).map(|_| run()).min().unwrap()
+}
+
+fn main() {
+    println!(
+        "{:>8} {:>12} {:>12} {:>12} {:>12}",
+        "threads", "MsQueue", "SegQueue", "Mutex", "FlatComb"
+    );
+    for threads in [1, 2, 4, 8] {
+        let ms = best(|| mpmc!(MsQueue<usize>, threads));
+        let seg = best(|| mpmc!(SegQueue<usize>, threads));
+        let mutex = best(|| mpmc!(MutexQueue, threads));
+        let fc = best(|| mpmc!(FlatCombiningQueue<usize>, threads));
+        println!(
+            "{:>8} {:>12.2?} {:>12.2?} {:>12.2?} {:>12.2?}",
+            threads, ms, seg, mutex, fc
+        );
+    }
+}
@@ -0,0 +1,52 @@
+// Run with `cargo bench --bench small_lists`.
+//
+// Builds and drains a lot of short stacks, at lengths from 0 to 16. Up to N elements a
+// SmallList never allocates, so it should be well ahead of List (one allocation per push) and of
+// Vec (at least one per non-empty list) there. Past N it pays for the spill nodes like a List
+// does, on top of the inline part it's already carrying.
+
+use linked_list_rc::generic_and_iterators::List;
+use linked_list_rc::small_list::SmallList;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const LISTS: usize = 100_000;
+const ROUNDS: usize = 5;
+
+macro_rules! build_and_drain {
+    ($list:ty, $push:ident, $len:expr) => {{
+        let start = Instant::now();
+        for i in 0..LISTS {
+            let mut list = <$list>::new();
+            for x in 0..$len {
+                list.$push(black_box(i + x));
+            }
+            while let Some(x) = list.pop() {
+                black_box(x);
+            }
+        }
+        start.elapsed()
+    }};
+}
+
+// Best of ROUNDS, which is the least noisy number on a busy machine.
+fn best(mut run: impl FnMut() -> Duration) -> Duration {
+    (0..ROUNDS).map(|_| run()).min().unwrap()
+}
+
+fn main() {
+    println!(
+        "{:>4} {:>12} {:>12} {:>16} {:>16}",
+        "len", "Vec", "List", "SmallList<4>", "SmallList<8>"
+    );
+    for len in [0, 1, 2, 4, 8, 16] {
+        let vec = best(|| build_and_drain!(Vec<usize>, push, len));
+        let list = best(|| build_and_drain!(List<usize>, push, len));
+        let small4 = best(|| build_and_drain!(SmallList<usize, 4>, push, len));
+        let small8 = best(|| build_and_drain!(SmallList<usize, 8>, push, len));
+        println!(
+            "{:>4} {:>12.2?} {:>12.2?} {:>16.2?} {:>16.2?}",
+            len, vec, list, small4, small8
+        );
+    }
+}
@@ -0,0 +1,53 @@
+// Run with `cargo bench --bench stacks`.
+//
+// Every thread hammers the same stack with push/pop pairs. With one thread the elimination array
+// is pure overhead; as threads are added, pairs that would have fought over `head` cancel out in
+// the array instead, and the elimination stack should scale better than the plain Treiber stack.
+
+use linked_list_rc::elimination_stack::EliminationStack;
+use linked_list_rc::treiber_stack::TreiberStack;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const OPS: usize = 200_000;
+const ROUNDS: usize = 5;
+
+macro_rules! push_pop {
+    ($stack:ty, $threads:expr) => {{
+        let stack = Arc::new(<$stack>::new());
+        let start = Instant::now();
+        let handles: Vec<_> = (0..$threads)
+            .map(|_| {
+                let stack = stack.clone();
+                thread::spawn(move || {
+                    for i in 0..OPS {
+                        stack.push(i);
+                        stack.pop();
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        start.elapsed()
+    }};
+}
+
+// Best of ROUNDS, which is the least noisy number on a busy machine.
+fn best(mut run: impl FnMut() -> Duration) -> Duration {
+    (0..ROUNDS).map(|_| run()).min().unwrap()
+}
+
+fn main() {
+    println!(
+        "{:>8} {:>14} {:>18}",
+        "threads", "TreiberStack", "EliminationStack"
+    );
+    for threads in [1, 2, 4, 8] {
+        let treiber = best(|| push_pop!(TreiberStack<usize>, threads));
+        let elimination = best(|| push_pop!(EliminationStack<usize>, threads));
+        println!("{:>8} {:>14.2?} {:>18.2?}", threads, treiber, elimination);
+    }
+}
@@ -0,0 +1,14 @@
+// With the `cxx` feature: the C++ half of src/cxx_bridge.rs (see there). Nothing otherwise.
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    #[cfg(feature = "cxx")]
+    {
+        cxx_build::bridge("src/cxx_bridge.rs")
+            .std("c++17")
+            .compile("linked_list_rc_cxx");
+        println!("cargo:rerun-if-changed=src/cxx_bridge.rs");
+        println!("cargo:rerun-if-changed=include/linked_list.h");
+    }
+}
@@ -0,0 +1,257 @@
+// cargo +nightly fuzz run unsafe_deque
+//
+// Runs operations on an unsafe_doubly_linked_deque::List and on a VecDeque side by side, and
+// checks they agree after each one, read forwards and backwards. The elements are boxed, so the
+// address sanitizer catches a node freed twice, freed early or never freed, and the starting
+// deque comes from the crate's `arbitrary` feature, already relinked every which way.
+//
+// Most of the operations go through a CursorMut, since that's where the relinking is: a cursor
+// walks to an element and inserts, removes, splits or splices there. The iterators are run from
+// both ends at once until they meet, and an IntoIter is dropped with elements still in it.
+
+#![no_main]
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use linked_list_rc::unsafe_doubly_linked_deque::{CursorMut, List};
+use std::collections::VecDeque;
+use std::mem;
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    PushFront(u16),
+    PushBack(u16),
+    PopFront,
+    PopBack,
+    Append(Vec<u16>),
+    Clear,
+    // Put a cursor on element `at` (modulo len + 1, where len is the ghost) and use it.
+    Cursor { at: u8, ops: Vec<CursorOp> },
+    // One iterator, taking from the front when the next bit of `ends` is 0 and the back when 1.
+    Iter { ends: u64 },
+    IterMut { ends: u64, by: u16 },
+    // Same, through into_iter, dropping it after `n` elements.
+    PartialDrain { ends: u64, n: u8 },
+}
+
+#[derive(Arbitrary, Debug)]
+enum CursorOp {
+    MoveNext,
+    MovePrev,
+    InsertBefore(u16),
+    InsertAfter(u16),
+    RemoveCurrent,
+    // Split the deque at the cursor, then splice the part back in at the same place if
+    // `rejoin`, or drop it.
+    SplitBefore { rejoin: bool },
+    SplitAfter { rejoin: bool },
+    SpliceBefore(Vec<u16>),
+    SpliceAfter(Vec<u16>),
+}
+
+fn boxed(xs: &[u16]) -> List<Box<u16>> {
+    xs.iter().map(|&x| Box::new(x)).collect()
+}
+
+fn check(list: &List<Box<u16>>, model: &VecDeque<u16>) {
+    assert_eq!(list.len(), model.len());
+    assert!(list.iter().map(|x| **x).eq(model.iter().copied()));
+    assert!(list
+        .iter()
+        .rev()
+        .map(|x| **x)
+        .eq(model.iter().rev().copied()));
+}
+
+// The cursor's index against the model's: None for the ghost.
+fn cursor(cursor: &mut CursorMut<'_, Box<u16>>, model: &VecDeque<u16>, at: Option<usize>) {
+    assert_eq!(cursor.index(), at);
+    assert_eq!(cursor.current().map(|x| **x), at.map(|i| model[i]));
+}
+
+fn run_cursor(list: &mut List<Box<u16>>, model: &mut VecDeque<u16>, at: u8, ops: Vec<CursorOp>) {
+    let len = model.len();
+    let mut at = Some(at as usize % (len + 1)).filter(|&i| i < len);
+    let mut cur = list.cursor_mut();
+    for _ in 0..at.map_or(0, |i| i + 1) {
+        cur.move_next();
+    }
+
+    for op in ops {
+        let len = model.len();
+        match op {
+            CursorOp::MoveNext => {
+                cur.move_next();
+                at = match at {
+                    Some(i) if i + 1 < len => Some(i + 1),
+                    Some(_) => None,
+                    None => Some(0).filter(|_| len > 0),
+                };
+            }
+            CursorOp::MovePrev => {
+                cur.move_prev();
+                at = match at {
+                    Some(0) => None,
+                    Some(i) => Some(i - 1),
+                    None => len.checked_sub(1),
+                };
+            }
+            CursorOp::InsertBefore(x) => {
+                cur.insert_before(Box::new(x));
+                match at {
+                    Some(i) => {
+                        model.insert(i, x);
+                        at = Some(i + 1);
+                    }
+                    None => model.push_back(x),
+                }
+            }
+            CursorOp::InsertAfter(x) => {
+                cur.insert_after(Box::new(x));
+                match at {
+                    Some(i) => model.insert(i + 1, x),
+                    None => model.push_front(x),
+                }
+            }
+            CursorOp::RemoveCurrent => {
+                let removed = cur.remove_current().map(|x| *x);
+                assert_eq!(removed, at.and_then(|i| model.remove(i)));
+                at = at.filter(|&i| i < model.len());
+            }
+            CursorOp::SplitBefore { rejoin } => {
+                let before = cur.split_before();
+                let model_before: VecDeque<u16> = match at {
+                    Some(i) => {
+                        let rest = model.split_off(i);
+                        at = Some(0);
+                        mem::replace(model, rest)
+                    }
+                    None => mem::take(model),
+                };
+                check(&before, &model_before);
+                if rejoin {
+                    cur.splice_before(before);
+                    let rest = mem::replace(model, model_before);
+                    // Still on the same element, now after the spliced part.
+                    at = at.map(|_| model.len());
+                    model.extend(rest);
+                }
+            }
+            CursorOp::SplitAfter { rejoin } => {
+                let after = cur.split_after();
+                let model_after = match at {
+                    Some(i) => model.split_off(i + 1),
+                    None => mem::take(model),
+                };
+                check(&after, &model_after);
+                if rejoin {
+                    cur.splice_after(after);
+                    match at {
+                        Some(_) => model.extend(model_after),
+                        None => *model = model_after,
+                    }
+                }
+            }
+            CursorOp::SpliceBefore(xs) => {
+                cur.splice_before(boxed(&xs));
+                match at {
+                    Some(i) => {
+                        for (j, &x) in xs.iter().enumerate() {
+                            model.insert(i + j, x);
+                        }
+                        at = Some(i + xs.len());
+                    }
+                    None => model.extend(&xs),
+                }
+            }
+            CursorOp::SpliceAfter(xs) => {
+                cur.splice_after(boxed(&xs));
+                let i = at.map_or(0, |i| i + 1);
+                for (j, &x) in xs.iter().enumerate() {
+                    model.insert(i + j, x);
+                }
+            }
+        }
+        cursor(&mut cur, model, at);
+    }
+}
+
+fuzz_target!(|input: (List<Box<u16>>, Vec<Op>)| {
+    let (mut list, ops) = input;
+    let mut model: VecDeque<u16> = list.iter().map(|x| **x).collect();
+
+    for op in ops {
+        match op {
+            Op::PushFront(x) => {
+                list.push_front(Box::new(x));
+                model.push_front(x);
+            }
+            Op::PushBack(x) => {
+                list.push_back(Box::new(x));
+                model.push_back(x);
+            }
+            Op::PopFront => assert_eq!(list.pop_front().map(|x| *x), model.pop_front()),
+            Op::PopBack => assert_eq!(list.pop_back().map(|x| *x), model.pop_back()),
+            Op::Append(xs) => {
+                list.append(&mut boxed(&xs));
+                model.extend(xs);
+            }
+            Op::Clear => {
+                list.clear();
+                model.clear();
+            }
+            Op::Cursor { at, ops } => run_cursor(&mut list, &mut model, at, ops),
+            Op::Iter { mut ends } => {
+                let mut iter = list.iter();
+                let (mut front, mut back) = (0, model.len());
+                while front < back {
+                    let x = if ends & 1 == 0 {
+                        front += 1;
+                        (iter.next(), model[front - 1])
+                    } else {
+                        back -= 1;
+                        (iter.next_back(), model[back])
+                    };
+                    assert_eq!(x.0.map(|x| **x), Some(x.1));
+                    assert_eq!(iter.len(), back - front);
+                    ends = ends.rotate_right(1);
+                }
+                assert!(iter.next().is_none() && iter.next_back().is_none());
+            }
+            Op::IterMut { mut ends, by } => {
+                let mut iter = list.iter_mut();
+                let (mut front, mut back) = (0, model.len());
+                while front < back {
+                    let (x, i) = if ends & 1 == 0 {
+                        front += 1;
+                        (iter.next(), front - 1)
+                    } else {
+                        back -= 1;
+                        (iter.next_back(), back)
+                    };
+                    let x = x.unwrap();
+                    **x = x.wrapping_add(by);
+                    model[i] = model[i].wrapping_add(by);
+                    ends = ends.rotate_right(1);
+                }
+                assert!(iter.next().is_none());
+            }
+            Op::PartialDrain { mut ends, n } => {
+                let mut drain = mem::take(&mut list).into_iter();
+                for _ in 0..n {
+                    let x = if ends & 1 == 0 {
+                        (drain.next(), model.pop_front())
+                    } else {
+                        (drain.next_back(), model.pop_back())
+                    };
+                    assert_eq!(x.0.map(|x| *x), x.1);
+                    ends = ends.rotate_right(1);
+                }
+                assert_eq!(drain.len(), model.len());
+                drop(drain);
+                model.clear();
+            }
+        }
+        check(&list, &model);
+    }
+});
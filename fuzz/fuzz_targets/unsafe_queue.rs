@@ -0,0 +1,131 @@
+// cargo +nightly fuzz run unsafe_queue
+//
+// Runs operations on an unsafe_single_linked_queue::List and on a VecDeque side by side, and
+// checks they agree after each one. The elements are boxed, so the address sanitizer cargo fuzz
+// builds with catches a node freed twice, freed early or never freed. The queue to start from
+// is itself arbitrary (the crate's `arbitrary` feature), so it may already have been split and
+// appended back together, or be on pooled nodes.
+//
+// Besides pushing and popping, the operations reach the code a test is least likely to:
+// split_off, pop_all and steal_half handing chains between queues, iter_mut rewriting elements
+// in place, and an IntoIter dropped halfway through draining.
+
+#![no_main]
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use linked_list_rc::unsafe_single_linked_queue::List;
+use std::collections::VecDeque;
+use std::fmt;
+use std::mem;
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Push(u16),
+    Pop,
+    PeekMut(u16),
+    // Split at `at` (modulo len + 1), then append the rest back if `rejoin`, or drop it.
+    SplitOff { at: u8, rejoin: bool },
+    PopAll,
+    StealHalf,
+    Append(Vec<u16>),
+    // Add `by` to the first `n` elements through iter_mut.
+    IterMut { n: u8, by: u16 },
+    // Two iterators over the queue at once, advanced in turn.
+    Iters,
+    // Pop `n` through into_iter, then drop the IntoIter with the rest still in it.
+    PartialDrain(u8),
+}
+
+#[derive(Arbitrary)]
+struct Input {
+    list: List<Box<u16>>,
+    ops: Vec<Op>,
+}
+
+// The queue has no Debug of its own, and a crash report needs one.
+impl fmt::Debug for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Input")
+            .field("list", &self.list.iter().collect::<Vec<_>>())
+            .field("ops", &self.ops)
+            .finish()
+    }
+}
+
+fn check(list: &List<Box<u16>>, model: &VecDeque<u16>) {
+    assert_eq!(list.len(), model.len());
+    assert!(list.iter().map(|x| **x).eq(model.iter().copied()));
+    assert_eq!(list.peek().map(|x| **x), model.front().copied());
+    assert_eq!(list.peek_back().map(|x| **x), model.back().copied());
+}
+
+fuzz_target!(|input: Input| {
+    let Input { mut list, ops } = input;
+    let mut model: VecDeque<u16> = list.iter().map(|x| **x).collect();
+
+    for op in ops {
+        match op {
+            Op::Push(x) => {
+                list.push(Box::new(x));
+                model.push_back(x);
+            }
+            Op::Pop => assert_eq!(list.pop().map(|x| *x), model.pop_front()),
+            Op::PeekMut(x) => {
+                if let Some(front) = list.peek_mut() {
+                    **front = x;
+                    model[0] = x;
+                }
+            }
+            Op::SplitOff { at, rejoin } => {
+                let at = at as usize % (model.len() + 1);
+                let mut rest = list.split_off(at);
+                let model_rest = model.split_off(at);
+                check(&rest, &model_rest);
+                if rejoin {
+                    list.append(&mut rest);
+                    model.extend(model_rest);
+                    assert!(rest.is_empty());
+                }
+            }
+            Op::PopAll => {
+                let all = list.pop_all();
+                check(&all, &mem::take(&mut model));
+            }
+            Op::StealHalf => {
+                let stolen = list.steal_half();
+                let model_stolen = model.split_off(model.len() - model.len() / 2);
+                check(&stolen, &model_stolen);
+            }
+            Op::Append(xs) => {
+                let mut other = List::from_vec(xs.iter().map(|&x| Box::new(x)).collect());
+                list.append(&mut other);
+                model.extend(xs);
+            }
+            Op::IterMut { n, by } => {
+                for (x, y) in list.iter_mut().zip(model.iter_mut()).take(n as usize) {
+                    **x = x.wrapping_add(by);
+                    *y = y.wrapping_add(by);
+                }
+            }
+            Op::Iters => {
+                let (mut a, mut b) = (list.iter(), list.iter());
+                while let Some(x) = a.next() {
+                    assert_eq!(b.next(), Some(x));
+                    assert_eq!(a.len(), b.len());
+                }
+                assert!(b.next().is_none());
+            }
+            Op::PartialDrain(n) => {
+                let mut drain = mem::take(&mut list).into_iter();
+                for _ in 0..n {
+                    assert_eq!(drain.next().map(|x| *x), model.pop_front());
+                }
+                assert_eq!(drain.len(), model.len());
+                drop(drain);
+                model.clear();
+            }
+        }
+        check(&list, &model);
+    }
+});
@@ -0,0 +1,96 @@
+// RAII wrappers over the deque and queue that src/cxx_bridge.rs exposes to C++ (see there).
+//
+// Each object owns one Rust list through a rust::Box: the destructor drops it in Rust, and moving
+// the object moves the Box, never the nodes. There's no copy constructor, the same as the Rust
+// lists have no implicit copy. Build with the include path set to target/cxxbridge and this
+// directory, and link the Rust library.
+
+#pragma once
+
+#include <cstddef>
+#include <cstdint>
+#include <optional>
+#include <utility>
+
+#include "linked_list_rc/src/cxx_bridge.rs.h"
+
+namespace linked_list {
+
+// unsafe_doubly_linked_deque::List<i64>
+class Deque {
+public:
+  Deque() : inner_(ffi::new_deque()) {}
+
+  // Take over a deque that Rust made (or one that came from Rust as a rust::Box).
+  explicit Deque(rust::Box<ffi::Deque> inner) : inner_(std::move(inner)) {}
+
+  Deque(Deque &&) = default;
+  Deque &operator=(Deque &&) = default;
+  Deque(const Deque &) = delete;
+  Deque &operator=(const Deque &) = delete;
+
+  std::size_t size() const { return inner_->len(); }
+  bool empty() const { return size() == 0; }
+
+  void push_front(std::int64_t elem) { inner_->push_front(elem); }
+  void push_back(std::int64_t elem) { inner_->push_back(elem); }
+
+  std::optional<std::int64_t> pop_front() {
+    std::int64_t elem;
+    if (inner_->pop_front(elem)) {
+      return elem;
+    }
+    return std::nullopt;
+  }
+
+  std::optional<std::int64_t> pop_back() {
+    std::int64_t elem;
+    if (inner_->pop_back(elem)) {
+      return elem;
+    }
+    return std::nullopt;
+  }
+
+  void clear() { inner_->clear(); }
+
+  // Give the deque back, to pass it on to Rust. This object mustn't be used afterwards.
+  rust::Box<ffi::Deque> into_inner() && { return std::move(inner_); }
+
+private:
+  rust::Box<ffi::Deque> inner_;
+};
+
+// unsafe_single_linked_queue::List<i64>
+class Queue {
+public:
+  Queue() : inner_(ffi::new_queue()) {}
+
+  explicit Queue(rust::Box<ffi::Queue> inner) : inner_(std::move(inner)) {}
+
+  Queue(Queue &&) = default;
+  Queue &operator=(Queue &&) = default;
+  Queue(const Queue &) = delete;
+  Queue &operator=(const Queue &) = delete;
+
+  std::size_t size() const { return inner_->len(); }
+  bool empty() const { return size() == 0; }
+
+  // At the back.
+  void push(std::int64_t elem) { inner_->push(elem); }
+
+  // From the front.
+  std::optional<std::int64_t> pop() {
+    std::int64_t elem;
+    if (inner_->pop(elem)) {
+      return elem;
+    }
+    return std::nullopt;
+  }
+
+  rust::Box<ffi::Queue> into_inner() && { return std::move(inner_); }
+
+private:
+  rust::Box<ffi::Queue> inner_;
+};
+
+} // namespace linked_list
@@ -0,0 +1,202 @@
+/*
+ Allocators
+
+Every list in this crate gets its nodes from the global allocator, one malloc per node. That's
+often the wrong place for them: a parser building lists it throws away all at once wants them in
+an arena, and a hot queue wants them next to each other. std's answer is the Allocator trait, a
+value the collection allocates through:
+
+    pub unsafe trait Allocator {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError>;
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout);
+    }
+
+with Global (malloc and free) as the default, so that List<T> means List<T, Global> and nothing
+changes for code that doesn't care. generic_and_iterators::List and unsafe_single_linked_queue::List
+take one, and new_in(alloc) builds a list on it.
+
+The trait is still unstable (allocator_api). With the `nightly` feature this module just
+re-exports std's, so any allocator written for std (bumpalo's, say) works here too. Without it,
+it's the copy below: the same two required methods with the same signatures, so an allocator
+written against one compiles against the other.
+
+A collection holds its allocator by value, and &A is an allocator too, so a list can own its
+allocator (List<T, Bump>) or borrow one that many lists share (List<T, &Bump>).
+
+Bump is the simplest allocator there is, and the one the tests use: one block of memory and an
+offset into it. allocate rounds the offset up to the alignment and moves it past the new value;
+deallocate does nothing, and the memory all goes back at once when the Bump is dropped.
+
+block: [ node | node | pad | node |            free            ]
+                                  ^ used
+*/
+
+use std::alloc::{self, Layout};
+use std::cell::Cell;
+use std::ptr::NonNull;
+
+#[cfg(feature = "nightly")]
+pub use std::alloc::{AllocError, Allocator, Global};
+
+#[cfg(not(feature = "nightly"))]
+pub use self::stable::{AllocError, Allocator, Global};
+
+#[cfg(not(feature = "nightly"))]
+mod stable {
+    use std::alloc::{self, Layout};
+    use std::fmt;
+    use std::ptr::{self, NonNull};
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct AllocError;
+
+    impl fmt::Display for AllocError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("memory allocation failed")
+        }
+    }
+
+    impl std::error::Error for AllocError {}
+
+    /// # Safety
+    ///
+    /// Memory returned by `allocate` must stay valid until it's passed to `deallocate` or the
+    /// allocator (and every clone of it) is dropped, and `deallocate` must accept any block from
+    /// `allocate` on this allocator or one of its clones, with the layout it was allocated with.
+    pub unsafe trait Allocator {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError>;
+
+        /// # Safety
+        ///
+        /// `ptr` must be a block currently allocated by this allocator, and `layout` the layout
+        /// it was allocated with.
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout);
+    }
+
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct Global;
+
+    unsafe impl Allocator for Global {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            let raw = if layout.size() == 0 {
+                // What Box uses for zero-sized values: any non-null, aligned address.
+                ptr::without_provenance_mut(layout.align())
+            } else {
+                unsafe { alloc::alloc(layout) }
+            };
+            let raw = NonNull::new(raw).ok_or(AllocError)?;
+            Ok(NonNull::slice_from_raw_parts(raw, layout.size()))
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            if layout.size() != 0 {
+                alloc::dealloc(ptr.as_ptr(), layout);
+            }
+        }
+    }
+
+    unsafe impl<A: Allocator + ?Sized> Allocator for &A {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            (**self).allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            (**self).deallocate(ptr, layout)
+        }
+    }
+}
+
+pub struct Bump {
+    start: NonNull<u8>,
+    layout: Layout,
+    used: Cell<usize>,
+}
+
+// The block is the Bump's alone, like a Box's contents.
+unsafe impl Send for Bump {}
+
+impl Bump {
+    // Room for `bytes` bytes, allocated up front from the global allocator.
+    pub fn with_capacity(bytes: usize) -> Self {
+        // Aligned enough for anything a list node is likely to need.
+        let layout = Layout::from_size_align(bytes.max(1), 16).unwrap();
+        let start = NonNull::new(unsafe { alloc::alloc(layout) })
+            .unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        Bump {
+            start,
+            layout,
+            used: Cell::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.layout.size()
+    }
+
+    // Bytes handed out so far, counting alignment padding. Never goes down.
+    pub fn used(&self) -> usize {
+        self.used.get()
+    }
+}
+
+unsafe impl Allocator for Bump {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let addr = self.start.addr().get() + self.used.get();
+        let offset = addr.next_multiple_of(layout.align()) - self.start.addr().get();
+        let end = offset.checked_add(layout.size()).ok_or(AllocError)?;
+        if end > self.capacity() {
+            return Err(AllocError);
+        }
+        self.used.set(end);
+        // Safety: offset <= end <= capacity, so this stays inside the block.
+        let ptr = unsafe { self.start.add(offset) };
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    // Freed all at once, when the Bump goes.
+    unsafe fn deallocate(&self, _ptr: NonNull<u8>, _layout: Layout) {}
+}
+
+impl Drop for Bump {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.start.as_ptr(), self.layout) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Allocator, Bump, Global};
+    use std::alloc::Layout;
+
+    #[test]
+    fn bump() {
+        let bump = Bump::with_capacity(64);
+        let a = bump.allocate(Layout::new::<u8>()).unwrap();
+        let b = bump.allocate(Layout::new::<u64>()).unwrap();
+        // Padded up to b's alignment.
+        assert_eq!(bump.used(), 16);
+        assert_eq!(b.cast::<u8>().addr().get() % 8, 0);
+        assert_ne!(a.cast::<u8>(), b.cast::<u8>());
+
+        unsafe {
+            b.cast::<u64>().write(7);
+            assert_eq!(b.cast::<u64>().read(), 7);
+            bump.deallocate(b.cast(), Layout::new::<u64>());
+        }
+        // deallocate doesn't give anything back.
+        assert_eq!(bump.used(), 16);
+        assert!(bump.allocate(Layout::new::<[u8; 48]>()).is_ok());
+        assert!(bump.allocate(Layout::new::<u8>()).is_err());
+    }
+
+    #[test]
+    fn global() {
+        let layout = Layout::new::<[u32; 4]>();
+        let block = Global.allocate(layout).unwrap();
+        assert_eq!(block.len(), 16);
+        unsafe { Global.deallocate(block.cast(), layout) };
+
+        let zst = Global.allocate(Layout::new::<()>()).unwrap();
+        unsafe { Global.deallocate(zst.cast(), Layout::new::<()>()) };
+    }
+}
@@ -0,0 +1,320 @@
+/*
+ The persistent stack, thread-safe
+
+persistent_stack.rs ends with the observation that Arc is Rc with atomic reference counts. Swap
+one for the other and the same persistent list can be shared between threads: nodes are never
+mutated once they're linked in, so any number of threads can read the same list, and prepend
+still only allocates the one new node.
+
+What that doesn't give you is a shared *variable* holding a list. Every thread has its own
+List value, and a List can't change what it points at from under another thread. SharedHead is
+that variable: the head pointer of a list kept in an AtomicPtr.
+
+- load() hands out the current list. It's a snapshot: writers can move the head on afterwards,
+  but the snapshot's nodes are immutable and kept alive by its own Arc.
+- Writers build the new list off to the side and CAS the head over to it, retrying if another
+  writer got there first. push() does that for prepend; update() for anything else (tail, a
+  merge, a whole new list).
+
+Each successful write is a new version, and every version ever loaded stays readable for as long
+as someone holds it, sharing its nodes with all the versions that came after. So a SharedHead
+that's only ever pushed to is a lock-free append-only log, with load() as "read everything up to
+now".
+
+The hard part is load(). The AtomicPtr owns one strong count of the head node, and a writer that
+moves the head on gives that count up. If it did so right away, a reader that had just loaded the
+old pointer, but not yet incremented its count, would be incrementing a freed node. So the
+writer hands the old count to the epoch collector instead, and readers stay pinned between
+loading the pointer and taking their own count.
+
+The collector drops that count whenever it gets round to it, on whichever thread, and if it was
+the last one the nodes and their elements go with it. That can be after the SharedHead is gone,
+and after anything an element borrows is gone too, so SharedHead wants T: Send + Sync + 'static.
+*/
+
+use crate::diff::{self, Edit};
+use crate::epoch;
+use crate::pointer_list::{self, ArcKind, PointerKind, Ptr, SharedKind};
+use crate::sync::atomic::{AtomicPtr, Ordering};
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr::{self, NonNull};
+use std::sync::Arc;
+
+// pointer_list's list with Arc as the pointer: everything but diff and SharedHead is there, written
+// once for this list and the Rc and Box ones, including the Drop that unwinds iteratively with
+// Arc::into_inner (the race-free "were we the last one" check; try_unwrap isn't, since two threads
+// can both fail it and then both let go) and the {:#?} that shows each node's reference counts.
+pub type List<T> = pointer_list::List<T, ArcKind>;
+
+type Node<T> = pointer_list::Node<T, ArcKind>;
+
+impl<T> List<T> {
+    // The edits that turn this list into `other`, front to back. See diff.rs.
+    pub fn diff<'a>(&'a self, other: &'a List<T>) -> Vec<Edit<'a, T>>
+    where
+        T: PartialEq,
+    {
+        diff::diff(self.iter(), other.iter())
+    }
+}
+
+pub type Iter<'a, T> = pointer_list::Iter<'a, T, ArcKind>;
+
+/// A shared list can't hold borrowed elements: an old version may be dropped by the epoch
+/// collector long after the borrow ends.
+///
+/// ```compile_fail
+/// use linked_list_rc::arc_persistent_stack::{List, SharedHead};
+///
+/// let word = String::from("short-lived");
+/// let shared = SharedHead::new(List::new().prepend(word.as_str()));
+/// shared.store(List::new());
+/// drop(word);
+/// ```
+pub struct SharedHead<T> {
+    // Owns one strong count of the node it points at (null for the empty list).
+    head: AtomicPtr<Node<T>>,
+    _boo: PhantomData<List<T>>,
+}
+
+// Lists move in and out by value, and every thread can read the elements of every snapshot, so
+// this needs both: the same bounds as Arc<T>. 'static because of the collector, see above.
+unsafe impl<T: Send + Sync + 'static> Send for SharedHead<T> {}
+unsafe impl<T: Send + Sync + 'static> Sync for SharedHead<T> {}
+
+impl<T: Send + Sync + 'static> SharedHead<T> {
+    pub fn new(list: List<T>) -> Self {
+        SharedHead {
+            head: AtomicPtr::new(into_raw(list)),
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn load(&self) -> List<T> {
+        let _guard = epoch::pin();
+        let head = self.head.load(Ordering::Acquire);
+        if head.is_null() {
+            return List::new();
+        }
+        // We're pinned, so the count the AtomicPtr held when we loaded `head` hasn't been given
+        // up yet, even if a writer has already moved the head on.
+        unsafe {
+            Arc::increment_strong_count(head);
+            from_raw(head)
+        }
+    }
+
+    pub fn store(&self, list: List<T>) {
+        drop(self.swap(list));
+    }
+
+    // Replace the list, returning the one that was there.
+    pub fn swap(&self, list: List<T>) -> List<T> {
+        let guard = epoch::pin();
+        let old = self.head.swap(into_raw(list), Ordering::AcqRel);
+        unsafe { self.take_old(&guard, old) }
+    }
+
+    // Replace the list only if it's still `current` (the same nodes, see List::ptr_eq). On
+    // success, returns what was there; on failure, returns `new` back.
+    pub fn compare_exchange(&self, current: &List<T>, new: List<T>) -> Result<List<T>, List<T>> {
+        let guard = epoch::pin();
+        let current = current.head.as_ref().map_or(ptr::null(), Ptr::as_ptr) as *mut Node<T>;
+        let new = into_raw(new);
+        match self
+            .head
+            .compare_exchange(current, new, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(old) => Ok(unsafe { self.take_old(&guard, old) }),
+            Err(_) => Err(from_raw(new)),
+        }
+    }
+
+    // Prepend `elem` to whatever the list is by the time we get there.
+    pub fn push(&self, elem: T) {
+        let mut ours = List::new();
+        ours.push(elem);
+        loop {
+            let current = self.load();
+            // Not published yet, so it's still ours alone to relink. What it pointed at before
+            // goes back into a List to be let go of, like any other link.
+            let node = ArcKind::get_mut(ours.head.as_mut().unwrap()).unwrap();
+            let stale = mem::replace(
+                &mut node.next,
+                current.head.as_ref().map(ArcKind::clone_ptr),
+            );
+            drop(List {
+                head: stale,
+                kind: ArcKind,
+            });
+            match self.compare_exchange(&current, ours) {
+                Ok(_) => return,
+                Err(rejected) => ours = rejected,
+            }
+        }
+    }
+
+    // Swing the head from the current list to `f(current)`, retrying with the newer list if a
+    // writer got in between. `f` may be called more than once. Returns the list it replaced.
+    pub fn update(&self, mut f: impl FnMut(&List<T>) -> List<T>) -> List<T> {
+        let mut current = self.load();
+        loop {
+            match self.compare_exchange(&current, f(&current)) {
+                Ok(old) => return old,
+                Err(_) => current = self.load(),
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> List<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        std::mem::forget(self);
+        from_raw(head)
+    }
+
+    // The count `old` held now belongs to the list we return, except that readers pinned before
+    // the swap may still be about to increment it. So we return our own count, and give the one
+    // the AtomicPtr held to the collector.
+    //
+    // Safety: `old` was just swapped out of `self.head` while `guard` was held.
+    unsafe fn take_old(&self, guard: &epoch::Guard, old: *mut Node<T>) -> List<T> {
+        if old.is_null() {
+            return List::new();
+        }
+        Arc::increment_strong_count(old);
+        guard.defer_unchecked(move || drop(from_raw(old)));
+        from_raw(old)
+    }
+}
+
+// An ArcKind Ptr is what Arc::into_raw returns, so the AtomicPtr's count is an Arc's.
+fn into_raw<T>(mut list: List<T>) -> *mut Node<T> {
+    list.head
+        .take()
+        .map_or(ptr::null_mut(), |node| node.into_raw().as_ptr())
+}
+
+fn from_raw<T>(head: *mut Node<T>) -> List<T> {
+    List {
+        head: NonNull::new(head).map(|node| unsafe { Ptr::from_raw(node) }),
+        kind: ArcKind,
+    }
+}
+
+impl<T: Send + Sync + 'static> Default for SharedHead<T> {
+    fn default() -> Self {
+        Self::new(List::new())
+    }
+}
+
+impl<T> Drop for SharedHead<T> {
+    fn drop(&mut self) {
+        // &mut self: no reader is between its load and its increment, so just give the count up.
+        drop(from_raw(self.head.load(Ordering::Relaxed)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{List, SharedHead};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn basics() {
+        let list = List::new().prepend(1).prepend(2).prepend(3);
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![&3, &2, &1]);
+        assert_eq!(list.tail().head(), Some(&2));
+        assert!(list.tail().tail().ptr_eq(&list.tail().tail()));
+        assert!(!list.ptr_eq(&List::new().prepend(3)));
+    }
+
+    #[test]
+    fn debug_counts() {
+        let xs = List::new().prepend(2).prepend(1);
+        let ys = xs.clone();
+        assert_eq!(format!("{xs:?}"), "[1, 2]");
+        assert_eq!(
+            format!("{xs:#?}"),
+            "[
+    1 (strong: 2, weak: 0) shared,
+    2 (strong: 1, weak: 0) shared,
+]"
+        );
+        drop(ys);
+        assert!(!format!("{xs:#?}").contains("shared"));
+    }
+
+    #[test]
+    fn shared_head() {
+        let shared = SharedHead::default();
+        assert_eq!(shared.load().head(), None);
+
+        shared.push(1);
+        shared.push(2);
+        let v2 = shared.load();
+        assert_eq!(v2.iter().collect::<Vec<_>>(), vec![&2, &1]);
+
+        // A snapshot doesn't see later writes, and shares its nodes with them.
+        shared.push(3);
+        assert_eq!(v2.iter().count(), 2);
+        assert!(shared.load().tail().ptr_eq(&v2));
+
+        // Retarget: pop the head off.
+        let old = shared.update(|list| list.tail());
+        assert_eq!(old.head(), Some(&3));
+        assert!(shared.load().ptr_eq(&v2));
+
+        // A stale `current` loses the CAS and gets its list back.
+        let stale = old;
+        let rejected = shared.compare_exchange(&stale, List::new().prepend(9));
+        assert!(matches!(rejected, Err(list) if list.head() == Some(&9)));
+
+        assert_eq!(shared.swap(List::new()).iter().count(), 2);
+        assert!(shared.into_inner().head().is_none());
+    }
+
+    #[test]
+    fn concurrent_log() {
+        let shared = Arc::new(SharedHead::new(List::new()));
+        let writers: Vec<_> = (0..3)
+            .map(|t| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    for i in 0..500 {
+                        shared.push(t * 500 + i);
+                    }
+                })
+            })
+            .collect();
+        let readers: Vec<_> = (0..2)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    // Every version extends the one before it.
+                    let mut previous = shared.load();
+                    for _ in 0..200 {
+                        let now = shared.load();
+                        let len = now.iter().count();
+                        let prev_len = previous.iter().count();
+                        assert!(len >= prev_len);
+                        let mut suffix = now.clone();
+                        for _ in 0..len - prev_len {
+                            suffix = suffix.tail();
+                        }
+                        assert!(suffix.ptr_eq(&previous));
+                        previous = now;
+                    }
+                })
+            })
+            .collect();
+        for h in writers.into_iter().chain(readers) {
+            h.join().unwrap();
+        }
+
+        let mut all: Vec<i32> = shared.load().iter().copied().collect();
+        all.sort();
+        assert_eq!(all, (0..1500).collect::<Vec<_>>());
+    }
+}
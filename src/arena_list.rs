@@ -0,0 +1,640 @@
+/*
+ An arena-backed doubly linked list
+
+The pointer-based lists in this crate are either fighting the borrow checker (Rc<RefCell<_>>,
+Weak back links) or sidestepping it (raw pointers and unsafe). There's a third way, and it's the
+answer to most "how do I write a linked list in Rust" questions: keep every node in one Vec and
+link them by index.
+
+slots:  [ B | free | A | C | free ]      front = 2, back = 3
+A.next = 0, B.prev = 2, B.next = 3, C.prev = 0, ...
+
+- An index is just a number, so there's no ownership to untangle: the Vec owns every node, links
+  are plain u32s, and there isn't a line of unsafe here.
+- u32 rather than usize halves the size of each link, and 4 billion nodes is plenty. NIL
+  (u32::MAX) plays the part of None.
+- Nodes sit next to each other in memory, and a popped node's slot goes on a free list (threaded
+  through the vacant slots themselves) to be reused by the next push. So a list that's pushed
+  and popped in a steady state never allocates, and its nodes stay packed.
+
+The costs: the Vec never shrinks (a vacant slot in the middle can't be given back without
+renumbering every node after it), and there's no iter_mut. Handing out a &mut to each node in
+list order means borrowing slots of the Vec in an order the borrow checker can't follow, so
+for_each_mut lends them out one at a time instead.
+
+Because nodes never move, an index is also a stable name for an element, good for O(1) removal
+from the middle, or for keeping an index into the list in a HashMap. The catch is that the slot
+is reused once its element is removed, and a plain index would then quietly name whatever was
+pushed next. So every push returns a Handle, the index plus the slot's generation: a counter
+that goes up each time the slot is vacated. get/remove/insert_after/... compare the two and
+treat a handle from an older generation as stale.
+*/
+
+use crate::mem_usage::MemUsage;
+use crate::validate::{self, Invalid};
+use std::fmt::{self, Debug};
+use std::mem::size_of;
+
+const NIL: u32 = u32::MAX;
+
+pub struct ArenaList<T> {
+    slots: Vec<Slot<T>>,
+    front: u32,
+    back: u32,
+    // Head of the free list of vacant slots.
+    free: u32,
+    len: usize,
+}
+
+struct Slot<T> {
+    // Bumped every time the slot is vacated, so handles to what used to be here go stale.
+    generation: u32,
+    entry: Entry<T>,
+}
+
+enum Entry<T> {
+    Occupied { elem: T, prev: u32, next: u32 },
+    Vacant { next_free: u32 },
+}
+
+// Names one element of one ArenaList, for as long as that element is in it. Once it's removed,
+// the handle stays stale even after its slot is reused.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Handle {
+    index: u32,
+    generation: u32,
+}
+
+impl<T> ArenaList<T> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        ArenaList {
+            slots: Vec::with_capacity(capacity),
+            front: NIL,
+            back: NIL,
+            free: NIL,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // How many nodes fit before the arena has to grow.
+    pub fn capacity(&self) -> usize {
+        self.slots.capacity() - self.len
+    }
+
+    // The slots are the nodes (see mem_usage.rs), and the vacant ones and the spare capacity are
+    // still the arena's.
+    pub fn mem_usage(&self) -> MemUsage {
+        MemUsage {
+            heap_bytes: self.slots.capacity() * size_of::<Slot<T>>(),
+            ..MemUsage::nodes::<Slot<T>, T>(self.len, 1)
+        }
+    }
+
+    // The links from front, as for the pointer lists, each to an occupied slot; and every slot
+    // that isn't linked in is on the free list. See validate.rs.
+    pub fn validate(&self) -> Result<(), Invalid> {
+        let link = |i: u32| (i != NIL).then_some(i);
+        let (mut at, mut vacant) = (0, None);
+        let walked = validate::walk_doubly(link(self.front), link(self.back), self.len, |i| {
+            match self.slots.get(i as usize).map(|slot| &slot.entry) {
+                Some(Entry::Occupied { prev, next, .. }) => {
+                    at += 1;
+                    (link(*prev), link(*next))
+                }
+                // Stop here; it's reported below, rather than whatever the walk makes of it.
+                _ => {
+                    vacant.get_or_insert(at);
+                    (None, None)
+                }
+            }
+        });
+        if let Some(at) = vacant {
+            return Err(Invalid::Node { at });
+        }
+        walked?;
+
+        // The rest of the slots, counted from the free list's head.
+        let (mut at, mut occupied) = (0, None);
+        let walked = validate::walk(link(self.free), self.slots.len() - self.len, |i| match self
+            .slots
+            .get(i as usize)
+            .map(|slot| &slot.entry)
+        {
+            Some(Entry::Vacant { next_free }) => {
+                at += 1;
+                link(*next_free)
+            }
+            _ => {
+                occupied.get_or_insert(at);
+                None
+            }
+        });
+        if let Some(at) = occupied {
+            return Err(Invalid::Node { at });
+        }
+        walked.map(drop)
+    }
+
+    fn node(&self, i: u32) -> (&T, u32, u32) {
+        match &self.slots[i as usize].entry {
+            Entry::Occupied { elem, prev, next } => (elem, *prev, *next),
+            Entry::Vacant { .. } => unreachable!("link to a vacant slot"),
+        }
+    }
+
+    fn elem_mut(&mut self, i: u32) -> &mut T {
+        match &mut self.slots[i as usize].entry {
+            Entry::Occupied { elem, .. } => elem,
+            Entry::Vacant { .. } => unreachable!("link to a vacant slot"),
+        }
+    }
+
+    // The next link of `i`, where NIL's next is the front.
+    fn set_next(&mut self, i: u32, to: u32) {
+        match i {
+            NIL => self.front = to,
+            _ => match &mut self.slots[i as usize].entry {
+                Entry::Occupied { next, .. } => *next = to,
+                Entry::Vacant { .. } => unreachable!("link to a vacant slot"),
+            },
+        }
+    }
+
+    // The prev link of `i`, where NIL's prev is the back.
+    fn set_prev(&mut self, i: u32, to: u32) {
+        match i {
+            NIL => self.back = to,
+            _ => match &mut self.slots[i as usize].entry {
+                Entry::Occupied { prev, .. } => *prev = to,
+                Entry::Vacant { .. } => unreachable!("link to a vacant slot"),
+            },
+        }
+    }
+
+    // Put `elem` in a slot between `prev` and `next` (either can be NIL).
+    fn link(&mut self, elem: T, prev: u32, next: u32) -> Handle {
+        let entry = Entry::Occupied { elem, prev, next };
+        let index = match self.free {
+            NIL => {
+                let i = u32::try_from(self.slots.len())
+                    .ok()
+                    .filter(|&i| i != NIL)
+                    .expect("arena list is full");
+                self.slots.push(Slot {
+                    generation: 0,
+                    entry,
+                });
+                i
+            }
+            i => {
+                match std::mem::replace(&mut self.slots[i as usize].entry, entry) {
+                    Entry::Vacant { next_free } => self.free = next_free,
+                    Entry::Occupied { .. } => unreachable!("occupied slot on the free list"),
+                }
+                i
+            }
+        };
+        self.set_next(prev, index);
+        self.set_prev(next, index);
+        self.len += 1;
+        validate::check!(self);
+        Handle {
+            index,
+            generation: self.slots[index as usize].generation,
+        }
+    }
+
+    // Take the node at `i` out of the list and put its slot on the free list.
+    fn unlink(&mut self, i: u32) -> T {
+        let vacant = Entry::Vacant {
+            next_free: self.free,
+        };
+        let slot = &mut self.slots[i as usize];
+        // Wrapping: a handle would have to sit unused through 4 billion reuses of its slot to
+        // come back to life.
+        slot.generation = slot.generation.wrapping_add(1);
+        let (elem, prev, next) = match std::mem::replace(&mut slot.entry, vacant) {
+            Entry::Occupied { elem, prev, next } => (elem, prev, next),
+            Entry::Vacant { .. } => unreachable!("unlinking a vacant slot"),
+        };
+        self.free = i;
+        self.set_next(prev, next);
+        self.set_prev(next, prev);
+        self.len -= 1;
+        validate::check!(self);
+        elem
+    }
+
+    // The slot `handle` names, if it still holds the element the handle was made for.
+    fn resolve(&self, handle: Handle) -> Option<u32> {
+        let slot = self.slots.get(handle.index as usize)?;
+        let live =
+            slot.generation == handle.generation && matches!(slot.entry, Entry::Occupied { .. });
+        live.then_some(handle.index)
+    }
+
+    fn handle(&self, i: u32) -> Option<Handle> {
+        (i != NIL).then(|| Handle {
+            index: i,
+            generation: self.slots[i as usize].generation,
+        })
+    }
+
+    pub fn push_front(&mut self, elem: T) -> Handle {
+        self.link(elem, NIL, self.front)
+    }
+
+    pub fn push_back(&mut self, elem: T) -> Handle {
+        self.link(elem, self.back, NIL)
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        (self.front != NIL).then(|| self.unlink(self.front))
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        (self.back != NIL).then(|| self.unlink(self.back))
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        (self.front != NIL).then(|| self.node(self.front).0)
+    }
+
+    pub fn front_mut(&mut self) -> Option<&mut T> {
+        (self.front != NIL).then(|| self.elem_mut(self.front))
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        (self.back != NIL).then(|| self.node(self.back).0)
+    }
+
+    pub fn back_mut(&mut self) -> Option<&mut T> {
+        (self.back != NIL).then(|| self.elem_mut(self.back))
+    }
+
+    pub fn front_handle(&self) -> Option<Handle> {
+        self.handle(self.front)
+    }
+
+    pub fn back_handle(&self) -> Option<Handle> {
+        self.handle(self.back)
+    }
+
+    /* ---- by handle: all O(1), and None (or Err) if the handle is stale ---- */
+
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.resolve(handle).is_some()
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.resolve(handle).map(|i| self.node(i).0)
+    }
+
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut T> {
+        self.resolve(handle).map(|i| self.elem_mut(i))
+    }
+
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        self.resolve(handle).map(|i| self.unlink(i))
+    }
+
+    // Err gives `elem` back if `handle` is stale.
+    pub fn insert_after(&mut self, handle: Handle, elem: T) -> Result<Handle, T> {
+        match self.resolve(handle) {
+            Some(i) => Ok(self.link(elem, i, self.node(i).2)),
+            None => Err(elem),
+        }
+    }
+
+    pub fn insert_before(&mut self, handle: Handle, elem: T) -> Result<Handle, T> {
+        match self.resolve(handle) {
+            Some(i) => Ok(self.link(elem, self.node(i).1, i)),
+            None => Err(elem),
+        }
+    }
+
+    // The neighbours of `handle`'s element, for walking the list from a handle.
+    pub fn next(&self, handle: Handle) -> Option<Handle> {
+        self.resolve(handle)
+            .and_then(|i| self.handle(self.node(i).2))
+    }
+
+    pub fn prev(&self, handle: Handle) -> Option<Handle> {
+        self.resolve(handle)
+            .and_then(|i| self.handle(self.node(i).1))
+    }
+
+    // Move `handle`'s element to the front or back, keeping its handle valid. False if stale.
+    pub fn move_to_front(&mut self, handle: Handle) -> bool {
+        let Some(i) = self.resolve(handle) else {
+            return false;
+        };
+        if self.front != i {
+            self.detach(i);
+            self.attach(i, NIL, self.front);
+        }
+        validate::check!(self);
+        true
+    }
+
+    pub fn move_to_back(&mut self, handle: Handle) -> bool {
+        let Some(i) = self.resolve(handle) else {
+            return false;
+        };
+        if self.back != i {
+            self.detach(i);
+            self.attach(i, self.back, NIL);
+        }
+        validate::check!(self);
+        true
+    }
+
+    // Unlink the node at `i` from its neighbours but leave it in its slot, for attach.
+    fn detach(&mut self, i: u32) {
+        let (_, prev, next) = self.node(i);
+        self.set_next(prev, next);
+        self.set_prev(next, prev);
+    }
+
+    fn attach(&mut self, i: u32, prev: u32, next: u32) {
+        self.set_prev(i, prev);
+        self.set_next(i, next);
+        self.set_next(prev, i);
+        self.set_prev(next, i);
+    }
+
+    pub fn clear(&mut self) {
+        // Not slots.clear(): the generations have to survive, or old handles could come back.
+        while self.pop_front().is_some() {}
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            list: self,
+            front: self.front,
+            back: self.back,
+            len: self.len,
+        }
+    }
+
+    // Slots are visited in list order, which needn't be slot order, so this hands out one
+    // &mut at a time by index rather than splitting the Vec.
+    pub fn for_each_mut(&mut self, mut f: impl FnMut(&mut T)) {
+        let mut i = self.front;
+        while i != NIL {
+            let next = self.node(i).2;
+            f(self.elem_mut(i));
+            i = next;
+        }
+    }
+}
+impl<T> Default for ArenaList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> Clone for ArenaList<T> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T> Extend<T> for ArenaList<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push_back(elem);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for ArenaList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = ArenaList::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T: Debug> Debug for ArenaList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for ArenaList<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other)
+    }
+}
+
+impl<T: Eq> Eq for ArenaList<T> {}
+
+pub struct Iter<'a, T> {
+    list: &'a ArenaList<T>,
+    front: u32,
+    back: u32,
+    len: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.len == 0 {
+            return None;
+        }
+        let (elem, _, next) = self.list.node(self.front);
+        self.front = next;
+        self.len -= 1;
+        Some(elem)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        let (elem, prev, _) = self.list.node(self.back);
+        self.back = prev;
+        self.len -= 1;
+        Some(elem)
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<T> Clone for Iter<'_, T> {
+    fn clone(&self) -> Self {
+        Iter { ..*self }
+    }
+}
+
+pub struct IntoIter<T> {
+    list: ArenaList<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.list.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.list.len, Some(self.list.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        self.list.pop_back()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for ArenaList<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { list: self }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a ArenaList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ArenaList, NIL};
+    use crate::validate::Invalid;
+
+    #[test]
+    fn basics() {
+        let mut list = ArenaList::new();
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.pop_back(), None);
+
+        list.push_back(2);
+        list.push_back(3);
+        list.push_front(1);
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.front(), Some(&1));
+        assert_eq!(list.back(), Some(&3));
+        *list.back_mut().unwrap() = 30;
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), [1, 2, 30]);
+        assert_eq!(list.iter().rev().copied().collect::<Vec<_>>(), [30, 2, 1]);
+
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.pop_back(), Some(30));
+        assert_eq!(list.pop_back(), Some(2));
+        assert!(list.is_empty());
+        assert_eq!(list.front(), None);
+    }
+
+    #[test]
+    fn slots_are_reused() {
+        let mut list: ArenaList<i32> = (0..4).collect();
+        assert_eq!(list.slots.len(), 4);
+        for i in 4..100 {
+            list.pop_front();
+            list.push_back(i);
+        }
+        // Steady state: every push took the slot the previous pop freed.
+        assert_eq!(list.slots.len(), 4);
+        assert_eq!(list, (96..100).collect());
+
+        list.clear();
+        list.extend([1, 2]);
+        list.for_each_mut(|x| *x *= 10);
+        assert_eq!(format!("{:?}", list), "[10, 20]");
+        assert_eq!(list.clone().into_iter().rev().collect::<Vec<_>>(), [20, 10]);
+    }
+
+    #[test]
+    fn handles() {
+        let mut list = ArenaList::new();
+        let b = list.push_back('b');
+        let d = list.push_back('d');
+        let a = list.push_front('a');
+        let c = list.insert_after(b, 'c').unwrap();
+        assert_eq!(list.iter().collect::<String>(), "abcd");
+        assert_eq!(list.get(c), Some(&'c'));
+        assert_eq!(list.next(b), Some(c));
+        assert_eq!(list.prev(a), None);
+        assert_eq!(list.back_handle(), Some(d));
+
+        assert_eq!(list.remove(b), Some('b'));
+        assert_eq!(list.iter().collect::<String>(), "acd");
+        // `b`'s slot gets reused by the next push, but `b` doesn't come back to life.
+        let e = list.push_back('e');
+        assert_eq!(e.index, b.index);
+        assert!(!list.contains(b));
+        assert_eq!(list.get(b), None);
+        assert_eq!(list.remove(b), None);
+        assert_eq!(list.insert_before(b, 'x'), Err('x'));
+
+        assert!(list.move_to_front(d));
+        assert!(list.move_to_back(a));
+        *list.get_mut(c).unwrap() = 'C';
+        assert_eq!(list.iter().collect::<String>(), "dCea");
+        assert_eq!(list.iter().rev().collect::<String>(), "aeCd");
+
+        list.clear();
+        list.push_back('z');
+        assert_eq!(list.get(d), None);
+    }
+
+    #[test]
+    fn validate() {
+        let mut list: ArenaList<i32> = (0..4).collect();
+        list.pop_front();
+        assert_eq!(list.validate(), Ok(()));
+
+        // Slot 0 is free: point the front back at it.
+        list.front = 0;
+        assert_eq!(list.validate(), Err(Invalid::Node { at: 0 }));
+        list.front = 1;
+
+        // Lose the free slot.
+        list.free = NIL;
+        assert_eq!(
+            list.validate(),
+            Err(Invalid::Len {
+                stored: 1,
+                counted: 0
+            })
+        );
+        list.free = 0;
+
+        list.back = 2;
+        assert_eq!(list.validate(), Err(Invalid::Ends));
+        list.back = 3;
+        assert_eq!(list.validate(), Ok(()));
+    }
+}
@@ -0,0 +1,188 @@
+/*
+ Compile-time assertions
+
+Some properties of a collection are easy to lose and invisible to every runtime test: change a
+PhantomData, swap a Box for a raw pointer, wrap a field in a Cell, and the list still passes its
+tests while having become invariant, or !Send, or Send when it shouldn't be. std's collections
+pin these down in their test suites; this module does the same for ours, as consts, so a change
+that breaks one is a compile error in `cargo build`, not something found by a user. None of it
+generates code.
+
+- Variance. A List<&'static str> should be usable as a List<&'a str>, as Vec<&'static str> is.
+  Covariance over T is what allows it, and it's the PhantomData and pointer types that decide it.
+  The check is a function that returns its argument at the shorter lifetime, which only compiles
+  if the type is covariant. IterMut isn't checked: like std's, it hands out &mut T, which has to
+  be invariant. doubly_linked_deque::List isn't either: its nodes are in RefCells, and a RefCell
+  (like any cell) is invariant, which is right.
+
+- Auto traits. The lists built from raw pointers implement Send and Sync by hand, and each impl
+  has a bound on T that must be exactly right: List<Rc<_>> must not be Send, and List<Cell<_>>
+  may be Send but not Sync. The Rc-based lists must be neither. Everything is Unpin whatever T
+  is: the elements live in their own heap nodes, so moving a list never moves one.
+
+  A type not implementing a trait is checked with the trick from the static_assertions crate: a
+  trait implemented twice for the same type, once for everything and once for the types that
+  implement the trait, is ambiguous to call for those types, and a compile error.
+
+- Niches. An empty link is a null pointer, so Option<Box<Node>> and Option<NonNull<Node>> cost
+  nothing over the pointer. That keeps the stacks one word and the deques two words and a length.
+  An Option around a whole list is free where the list still has a niche spare (the queue's
+  capacity, a Vec's). The one-pointer lists have already used theirs for "empty", so, like std's
+  LinkedList, Option<List> there needs a tag of its own.
+*/
+
+use crate::allocator::Bump;
+use crate::{
+    arc_persistent_stack, arena_list::ArenaList, doubly_linked_deque, generic_and_iterators,
+    indexed_list::IndexedList, persistent_stack, unsafe_doubly_linked_deque,
+    unsafe_single_linked_queue, xor_list::XorList,
+};
+use std::cell::Cell;
+use std::marker::PhantomPinned;
+use std::mem::size_of;
+use std::rc::Rc;
+
+macro_rules! assert_impl {
+    ($trait:path: $($ty:ty),+ $(,)?) => {
+        const _: fn() = || {
+            fn check<T: ?Sized + $trait>() {}
+            $(check::<$ty>();)+
+        };
+    };
+}
+
+macro_rules! assert_not_impl {
+    ($trait:path: $($ty:ty),+ $(,)?) => {
+        const _: fn() = || {
+            trait AmbiguousIfImpl<A> {
+                fn some_item() {}
+            }
+            impl<T: ?Sized> AmbiguousIfImpl<()> for T {}
+            struct Invalid;
+            impl<T: ?Sized + $trait> AmbiguousIfImpl<Invalid> for T {}
+            $(let _ = <$ty as AmbiguousIfImpl<_>>::some_item;)+
+        };
+    };
+}
+
+// $ty<&'static ()> is a $ty<&'a ()>, and the iterators the same with 'static for their own
+// lifetime.
+macro_rules! assert_covariant {
+    ($($ty:ident)::+) => {
+        const _: for<'a> fn($($ty)::+<&'static ()>, &'a ()) -> $($ty)::+<&'a ()> = |x, _| x;
+    };
+    ($($ty:ident)::+<'_>) => {
+        const _: for<'a> fn($($ty)::+<'static, &'static ()>, &'a ()) -> $($ty)::+<'static, &'a ()> =
+            |x, _| x;
+    };
+}
+
+assert_covariant!(generic_and_iterators::List);
+assert_covariant!(generic_and_iterators::IntoIter);
+assert_covariant!(generic_and_iterators::Iter<'_>);
+assert_covariant!(persistent_stack::List);
+assert_covariant!(persistent_stack::Iter<'_>);
+assert_covariant!(arc_persistent_stack::List);
+assert_covariant!(unsafe_single_linked_queue::List);
+assert_covariant!(unsafe_single_linked_queue::IntoIter);
+assert_covariant!(unsafe_single_linked_queue::Iter<'_>);
+assert_covariant!(unsafe_doubly_linked_deque::List);
+assert_covariant!(unsafe_doubly_linked_deque::IntoIter);
+assert_covariant!(unsafe_doubly_linked_deque::Iter<'_>);
+assert_covariant!(ArenaList);
+assert_covariant!(XorList);
+assert_covariant!(IndexedList);
+
+assert_impl!(Send:
+    generic_and_iterators::List<i32>,
+    arc_persistent_stack::List<i32>,
+    unsafe_single_linked_queue::List<i32>,
+    unsafe_single_linked_queue::Iter<'static, i32>,
+    unsafe_single_linked_queue::IterMut<'static, i32>,
+    unsafe_doubly_linked_deque::List<i32>,
+    unsafe_doubly_linked_deque::Iter<'static, i32>,
+    unsafe_doubly_linked_deque::IterMut<'static, i32>,
+    ArenaList<i32>,
+    XorList<i32>,
+);
+assert_impl!(Sync:
+    generic_and_iterators::List<i32>,
+    arc_persistent_stack::List<i32>,
+    unsafe_single_linked_queue::List<i32>,
+    unsafe_single_linked_queue::Iter<'static, i32>,
+    unsafe_single_linked_queue::IterMut<'static, i32>,
+    unsafe_doubly_linked_deque::List<i32>,
+    unsafe_doubly_linked_deque::Iter<'static, i32>,
+    unsafe_doubly_linked_deque::IterMut<'static, i32>,
+    ArenaList<i32>,
+    XorList<i32>,
+);
+
+// The hand-written impls, with elements that are Send but not Sync: the lists and IterMut (which
+// is a &mut List) can move to another thread, but nothing can be shared, and an Iter (a &List)
+// can't go anywhere.
+assert_impl!(Send:
+    unsafe_single_linked_queue::List<Cell<i32>>,
+    unsafe_single_linked_queue::IterMut<'static, Cell<i32>>,
+    unsafe_doubly_linked_deque::List<Cell<i32>>,
+    unsafe_doubly_linked_deque::IterMut<'static, Cell<i32>>,
+    generic_and_iterators::List<Cell<i32>>,
+    generic_and_iterators::IterMut<'static, Cell<i32>>,
+);
+assert_not_impl!(Sync:
+    unsafe_single_linked_queue::List<Cell<i32>>,
+    unsafe_doubly_linked_deque::List<Cell<i32>>,
+    generic_and_iterators::List<Cell<i32>>,
+);
+assert_not_impl!(Send:
+    unsafe_single_linked_queue::Iter<'static, Cell<i32>>,
+    unsafe_doubly_linked_deque::Iter<'static, Cell<i32>>,
+    generic_and_iterators::Iter<'static, Cell<i32>>,
+);
+
+// The allocator goes along with the nodes, like Box<T, A>'s. A Bump can move to another thread
+// but not be shared, so a list that owns one is Send and not Sync, and one that borrows it is
+// neither.
+assert_impl!(Send: generic_and_iterators::List<i32, Bump>);
+assert_not_impl!(Sync: generic_and_iterators::List<i32, Bump>);
+assert_not_impl!(Send: generic_and_iterators::List<i32, &'static Bump>);
+
+// And elements that are neither.
+assert_not_impl!(Send:
+    generic_and_iterators::List<Rc<i32>>,
+    unsafe_single_linked_queue::List<Rc<i32>>,
+    unsafe_doubly_linked_deque::List<Rc<i32>>,
+    XorList<Rc<i32>>,
+);
+
+// Rc links: one thread only, whatever the elements.
+assert_not_impl!(Send: persistent_stack::List<i32>, doubly_linked_deque::List<i32>);
+assert_not_impl!(Sync: persistent_stack::List<i32>, doubly_linked_deque::List<i32>);
+
+assert_impl!(Unpin:
+    generic_and_iterators::List<PhantomPinned>,
+    persistent_stack::List<PhantomPinned>,
+    arc_persistent_stack::List<PhantomPinned>,
+    doubly_linked_deque::List<PhantomPinned>,
+    unsafe_single_linked_queue::List<PhantomPinned>,
+    unsafe_doubly_linked_deque::List<PhantomPinned>,
+    generic_and_iterators::List<PhantomPinned, &'static Bump>,
+    XorList<PhantomPinned>,
+);
+
+const WORD: usize = size_of::<usize>();
+
+const _: () = assert!(size_of::<generic_and_iterators::List<u64>>() == WORD);
+const _: () = assert!(size_of::<persistent_stack::List<u64>>() == WORD);
+const _: () = assert!(size_of::<arc_persistent_stack::List<u64>>() == WORD);
+const _: () = assert!(size_of::<doubly_linked_deque::List<u64>>() == 2 * WORD);
+const _: () = assert!(size_of::<unsafe_doubly_linked_deque::List<u64>>() == 3 * WORD);
+const _: () = assert!(size_of::<generic_and_iterators::List<u64, &Bump>>() == 2 * WORD);
+
+const _: () = assert!(
+    size_of::<Option<unsafe_single_linked_queue::List<u64>>>()
+        == size_of::<unsafe_single_linked_queue::List<u64>>()
+);
+const _: () = assert!(size_of::<Option<ArenaList<u64>>>() == size_of::<ArenaList<u64>>());
+const _: () = assert!(size_of::<Option<XorList<u64>>>() == size_of::<XorList<u64>>());
+const _: () = assert!(size_of::<Option<IndexedList<u64>>>() == size_of::<IndexedList<u64>>());
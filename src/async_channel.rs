@@ -0,0 +1,535 @@
+/*
+ A bounded async channel
+
+mpsc_channel is lock-free but unbounded, and only its receiver can wait. This one is the other way
+around: the buffer is the bounded unsafe queue (List::with_capacity) behind a Mutex, and both sides
+can wait without blocking a thread:
+
+- recv().await waits while the queue is empty. There's only one receiver, so one Waker slot is
+  enough for it.
+- send().await waits while the queue is full. There can be any number of those, so they queue up
+  in a list of waiters, and every value the receiver takes out wakes the sender at the front.
+
+The waiter list is intrusive: each node lives inside the SendFuture that's waiting, not in a
+separate allocation.
+
+          send_waiters.head                  send_waiters.tail
+                 |                                   |
+ SendFuture { waiter } <-> SendFuture { waiter } <-> SendFuture { waiter }
+
+That's only sound because the futures can't move while their node is linked (they're !Unpin, and
+polling needs a Pin), and because a SendFuture that is dropped unlinks its node first. Every read
+or write of a node, by its own future or by whoever walks the list, happens under the channel's
+lock.
+
+Senders are served in order. A new send doesn't overtake the ones already waiting, and a waiter
+that was woken but then dropped without sending (the task was cancelled) passes its turn on to the
+next one, so no wakeup is ever lost.
+*/
+
+use crate::mpsc_channel::{RecvError, SendError, TryRecvError};
+use crate::trace;
+use crate::unsafe_single_linked_queue::List;
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::future::{self, Future};
+use std::marker::PhantomPinned;
+use std::pin::Pin;
+use std::ptr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+}
+
+struct State<T> {
+    queue: List<T>,
+    senders: usize,
+    receiver_alive: bool,
+    recv_waker: Option<Waker>,
+    send_waiters: WaiterList,
+}
+
+// The raw pointers in the waiter list are only followed under the lock.
+unsafe impl<T: Send> Send for State<T> {}
+
+struct Waiter {
+    waker: Option<Waker>,
+    prev: *mut Waiter,
+    next: *mut Waiter,
+    linked: bool,
+    // Taken off the list by the receiver: there's room, and it's this waiter's turn to use it.
+    notified: bool,
+}
+
+struct WaiterList {
+    head: *mut Waiter,
+    tail: *mut Waiter,
+}
+
+impl WaiterList {
+    fn is_empty(&self) -> bool {
+        self.head.is_null()
+    }
+
+    // Safety (for all of these): `waiter` points at a live Waiter, and the caller holds the lock.
+    unsafe fn push_back(&mut self, waiter: *mut Waiter) {
+        (*waiter).prev = self.tail;
+        (*waiter).next = ptr::null_mut();
+        (*waiter).linked = true;
+        match self.tail.as_mut() {
+            Some(tail) => tail.next = waiter,
+            None => self.head = waiter,
+        }
+        self.tail = waiter;
+    }
+
+    unsafe fn push_front(&mut self, waiter: *mut Waiter) {
+        (*waiter).prev = ptr::null_mut();
+        (*waiter).next = self.head;
+        (*waiter).linked = true;
+        match self.head.as_mut() {
+            Some(head) => head.prev = waiter,
+            None => self.tail = waiter,
+        }
+        self.head = waiter;
+    }
+
+    unsafe fn remove(&mut self, waiter: *mut Waiter) {
+        let Waiter { prev, next, .. } = *waiter;
+        match prev.as_mut() {
+            Some(prev) => prev.next = next,
+            None => self.head = next,
+        }
+        match next.as_mut() {
+            Some(next) => next.prev = prev,
+            None => self.tail = prev,
+        }
+        (*waiter).linked = false;
+    }
+
+    fn pop_front(&mut self) -> Option<*mut Waiter> {
+        let head = self.head;
+        if head.is_null() {
+            return None;
+        }
+        unsafe { self.remove(head) };
+        Some(head)
+    }
+}
+
+fn wake(waker: Option<Waker>) {
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        // Nothing panics while the state is half-updated, so a poisoned lock is still fine to use.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T> State<T> {
+    // A slot just opened up: hand it to the first waiting sender. Returns the waker to call once
+    // the lock is released.
+    fn notify_sender(&mut self) -> Option<Waker> {
+        let waiter = self.send_waiters.pop_front()?;
+        unsafe {
+            (*waiter).notified = true;
+            (*waiter).waker.take()
+        }
+    }
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+// A channel that holds at most `cap` values at a time. A capacity of zero would never let anything
+// through, so it's rejected.
+pub fn channel<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(cap > 0, "channel capacity must be at least 1");
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: List::with_capacity(cap),
+            senders: 1,
+            receiver_alive: true,
+            recv_waker: None,
+            send_waiters: WaiterList {
+                head: ptr::null_mut(),
+                tail: ptr::null_mut(),
+            },
+        }),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    Full(T),
+    Disconnected(T),
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("sending on a full channel"),
+            TrySendError::Disconnected(_) => f.write_str("sending on a channel with no receiver"),
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    // Waits while the channel is full. Fails (handing the value back) if the receiver is gone.
+    pub fn send(&self, value: T) -> SendFuture<'_, T> {
+        SendFuture {
+            sender: self,
+            value: Some(value),
+            waiter: UnsafeCell::new(Waiter {
+                waker: None,
+                prev: ptr::null_mut(),
+                next: ptr::null_mut(),
+                linked: false,
+                notified: false,
+            }),
+            _pinned: PhantomPinned,
+        }
+    }
+
+    // Like send, but never waits. Senders already waiting for room go first, so this reports Full
+    // while there are any.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let op = trace::span!("try_send");
+        let mut state = self.shared.lock();
+        let sent = if !state.receiver_alive {
+            Err(TrySendError::Disconnected(value))
+        } else if !state.send_waiters.is_empty() {
+            Err(TrySendError::Full(value))
+        } else {
+            state.queue.try_push(value).map_err(TrySendError::Full)
+        };
+        op.done(Some(state.queue.len()), sent.is_ok() as usize);
+        sent?;
+        let waker = state.recv_waker.take();
+        drop(state);
+        wake(waker);
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            // Last sender: a waiting receiver needs to find out it's disconnected.
+            let waker = state.recv_waker.take();
+            drop(state);
+            wake(waker);
+        }
+    }
+}
+
+// The future returned by Sender::send.
+pub struct SendFuture<'a, T> {
+    sender: &'a Sender<T>,
+    value: Option<T>,
+    // Linked into the channel's waiter list while we wait; only touched under the lock.
+    waiter: UnsafeCell<Waiter>,
+    _pinned: PhantomPinned,
+}
+
+// The waiter is only ever touched under the channel's lock, whichever thread that happens on.
+unsafe impl<T: Send> Send for SendFuture<'_, T> {}
+
+impl<T> Future for SendFuture<'_, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // We never move out of `self`; the pin is what keeps `waiter` where the list expects it.
+        let this = unsafe { self.get_unchecked_mut() };
+        let waiter = this.waiter.get();
+        let op = trace::span!("poll_send");
+        let mut state = this.sender.shared.lock();
+
+        unsafe {
+            if !state.receiver_alive {
+                if (*waiter).linked {
+                    state.send_waiters.remove(waiter);
+                }
+                op.done(Some(state.queue.len()), 0);
+                let value = this
+                    .value
+                    .take()
+                    .expect("SendFuture polled after completion");
+                return Poll::Ready(Err(SendError(value)));
+            }
+
+            // Wait our turn: either the receiver picked us, or nobody else is waiting.
+            let our_turn =
+                (*waiter).notified || (!(*waiter).linked && state.send_waiters.is_empty());
+            if our_turn {
+                let value = this
+                    .value
+                    .take()
+                    .expect("SendFuture polled after completion");
+                match state.queue.try_push(value) {
+                    Ok(()) => {
+                        op.done(Some(state.queue.len()), 1);
+                        (*waiter).notified = false;
+                        let waker = state.recv_waker.take();
+                        drop(state);
+                        wake(waker);
+                        return Poll::Ready(Ok(()));
+                    }
+                    Err(value) => this.value = Some(value),
+                }
+            }
+
+            (*waiter).waker = Some(cx.waker().clone());
+            if (*waiter).notified {
+                // Picked, but a new sender took the room first. Don't lose our place in line.
+                (*waiter).notified = false;
+                state.send_waiters.push_front(waiter);
+            } else if !(*waiter).linked {
+                state.send_waiters.push_back(waiter);
+            }
+        }
+        op.done(Some(state.queue.len()), 0);
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for SendFuture<'_, T> {
+    fn drop(&mut self) {
+        // Finished (or failed): the waiter was already unlinked.
+        if self.value.is_none() {
+            return;
+        }
+        let waiter = self.waiter.get();
+        let mut state = self.sender.shared.lock();
+        let waker = unsafe {
+            if (*waiter).linked {
+                state.send_waiters.remove(waiter);
+                None
+            } else if (*waiter).notified {
+                // We were given a turn we'll never use; pass it on.
+                state.notify_sender()
+            } else {
+                None
+            }
+        };
+        drop(state);
+        wake(waker);
+    }
+}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let op = trace::span!("try_recv");
+        let mut state = self.shared.lock();
+        let value = state.queue.pop();
+        op.done(Some(state.queue.len()), value.is_some() as usize);
+        match value {
+            Some(value) => {
+                let waker = state.notify_sender();
+                drop(state);
+                wake(waker);
+                Ok(value)
+            }
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Result<T, RecvError>> {
+        let op = trace::span!("poll_recv");
+        let mut state = self.shared.lock();
+        let value = state.queue.pop();
+        op.done(Some(state.queue.len()), value.is_some() as usize);
+        if let Some(value) = value {
+            let waker = state.notify_sender();
+            drop(state);
+            wake(waker);
+            return Poll::Ready(Ok(value));
+        }
+        if state.senders == 0 {
+            return Poll::Ready(Err(RecvError));
+        }
+        // Still under the lock, so no send can slip in before the waker is in place.
+        state.recv_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    // Waits while the channel is empty. Fails once every sender is gone and the queue is drained.
+    pub fn recv(&mut self) -> impl Future<Output = Result<T, RecvError>> + '_ {
+        future::poll_fn(move |cx| self.poll_recv(cx))
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.receiver_alive = false;
+        // Every waiting sender gets its value back the next time it's polled.
+        let mut wakers = Vec::new();
+        while let Some(waiter) = state.send_waiters.pop_front() {
+            wakers.extend(unsafe { (*waiter).waker.take() });
+        }
+        drop(state);
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T> futures_core::Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.get_mut().poll_recv(cx).map(Result::ok)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{channel, TrySendError};
+    use crate::mpsc_channel::{RecvError, SendError, TryRecvError};
+    use futures::executor::block_on;
+    use futures::task::noop_waker_ref;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll};
+    use std::thread;
+
+    #[test]
+    fn basics() {
+        let (tx, mut rx) = channel(2);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+        block_on(async {
+            tx.send(1).await.unwrap();
+            tx.send(2).await.unwrap();
+            assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+            assert_eq!(rx.recv().await, Ok(1));
+            assert_eq!(tx.try_send(3), Ok(()));
+            assert_eq!(rx.recv().await, Ok(2));
+            assert_eq!(rx.try_recv(), Ok(3));
+        });
+
+        drop(tx);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(block_on(rx.recv()), Err(RecvError));
+    }
+
+    #[test]
+    fn receiver_gone() {
+        let (tx, rx) = channel(1);
+        tx.try_send(1).unwrap();
+
+        // A sender waiting for room gets its value back when the receiver goes away.
+        let cx = &mut Context::from_waker(noop_waker_ref());
+        let mut waiting = pin!(tx.send(2));
+        assert!(waiting.as_mut().poll(cx).is_pending());
+        drop(rx);
+        assert_eq!(waiting.poll(cx), Poll::Ready(Err(SendError(2))));
+        assert_eq!(tx.try_send(3), Err(TrySendError::Disconnected(3)));
+    }
+
+    #[test]
+    fn waiting_senders_go_in_order() {
+        let (tx, mut rx) = channel(1);
+        let cx = &mut Context::from_waker(noop_waker_ref());
+        tx.try_send(0).unwrap();
+
+        let mut first = pin!(tx.send(1));
+        let mut second = pin!(tx.send(2));
+        assert!(first.as_mut().poll(cx).is_pending());
+        assert!(second.as_mut().poll(cx).is_pending());
+
+        // Room for one: it's the first waiter's, even if the second one is polled first, and a
+        // brand new send has to wait behind both.
+        assert_eq!(rx.try_recv(), Ok(0));
+        assert!(second.as_mut().poll(cx).is_pending());
+        assert_eq!(tx.try_send(9), Err(TrySendError::Full(9)));
+        assert_eq!(first.as_mut().poll(cx), Poll::Ready(Ok(())));
+
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(second.as_mut().poll(cx), Poll::Ready(Ok(())));
+        assert_eq!(rx.try_recv(), Ok(2));
+    }
+
+    #[test]
+    fn cancelled_sender_passes_its_turn_on() {
+        let (tx, mut rx) = channel(1);
+        let cx = &mut Context::from_waker(noop_waker_ref());
+        tx.try_send(0).unwrap();
+
+        let mut second = pin!(tx.send(2));
+        {
+            let mut first = pin!(tx.send(1));
+            assert!(first.as_mut().poll(cx).is_pending());
+            assert!(second.as_mut().poll(cx).is_pending());
+            // The first waiter is picked, then dropped before it gets to send.
+            assert_eq!(rx.try_recv(), Ok(0));
+        }
+        assert_eq!(second.poll(cx), Poll::Ready(Ok(())));
+        assert_eq!(rx.try_recv(), Ok(2));
+    }
+
+    #[test]
+    fn backpressure_across_threads() {
+        let (tx, mut rx) = channel(4);
+        let producers: Vec<_> = (0..3)
+            .map(|t| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    block_on(async {
+                        for i in 0..500 {
+                            tx.send(t * 500 + i).await.unwrap();
+                        }
+                    })
+                })
+            })
+            .collect();
+        drop(tx);
+
+        let mut got = Vec::new();
+        block_on(async {
+            while let Ok(v) = rx.recv().await {
+                got.push(v);
+            }
+        });
+        for p in producers {
+            p.join().unwrap();
+        }
+
+        // Each producer's values arrive in the order it sent them.
+        for t in 0..3 {
+            let mine: Vec<_> = got.iter().filter(|&&v| v / 500 == t).copied().collect();
+            assert_eq!(mine, (t * 500..(t + 1) * 500).collect::<Vec<_>>());
+        }
+        got.sort();
+        assert_eq!(got, (0..1500).collect::<Vec<_>>());
+    }
+}
@@ -1,4 +1,7 @@
+use crate::mem_usage::MemUsage;
+use crate::traits::{Collection, Stack};
 use std::mem;
+use std::ops::Deref;
 
 pub struct List {
     head: Link,
@@ -47,7 +50,52 @@ impl List {
                 self.head = node.next;
             }
         }
-        return result;
+        result
+    }
+
+    // See mem_usage.rs.
+    pub fn mem_usage(&self) -> MemUsage {
+        MemUsage::nodes::<Node, i32>(Collection::len(self), 1)
+    }
+}
+
+impl Default for List {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The crate-wide traits (see traits.rs). There's no length kept here, so len() counts.
+impl Collection for List {
+    fn len(&self) -> usize {
+        let mut len = 0;
+        let mut cur = &self.head;
+        while let Link::More(node) = cur {
+            len += 1;
+            cur = &node.next;
+        }
+        len
+    }
+
+    fn is_empty(&self) -> bool {
+        matches!(self.head, Link::Empty)
+    }
+}
+
+impl Stack<i32> for List {
+    fn push(&mut self, elem: i32) {
+        List::push(self, elem)
+    }
+
+    fn pop(&mut self) -> Option<i32> {
+        List::pop(self)
+    }
+
+    fn peek(&self) -> Option<impl Deref<Target = i32> + '_> {
+        match &self.head {
+            Link::Empty => None,
+            Link::More(node) => Some(&node.elem),
+        }
     }
 }
 
@@ -0,0 +1,391 @@
+/*
+ A blocking producer/consumer queue
+
+No clever atomics here: the unsafe queue from unsafe_single_linked_queue behind a Mutex, plus two
+Condvars so threads can sleep instead of spinning:
+
+- `not_empty` is waited on by pop() while the queue is empty, and notified by every push.
+- `not_full` is waited on by push() while a bounded queue is full, and notified by every pop.
+
+The queue's own capacity (List::with_capacity) is what makes it bounded, so "full" means exactly
+what try_push on the inner list says it means.
+
+With the `async` feature, tasks can wait too (push_async/pop_async, and a Stream over &queue).
+A task can't sleep on a Condvar, so waiting tasks leave their Waker in a list instead, and every
+push or pop wakes all of them. They re-check and go back to waiting if they lost the race, which
+also means a task that is dropped after being woken never swallows a wakeup someone else needed.
+*/
+
+use crate::trace;
+use crate::unsafe_single_linked_queue::List;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+#[cfg(feature = "async")]
+use std::{
+    future::{self, Future},
+    mem,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+pub struct BlockingQueue<T> {
+    queue: Mutex<List<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    // Always locked after `queue`, never before.
+    #[cfg(feature = "async")]
+    waiters: Mutex<Waiters>,
+}
+
+#[cfg(feature = "async")]
+#[derive(Default)]
+struct Waiters {
+    poppers: Vec<Waker>,
+    pushers: Vec<Waker>,
+}
+
+impl<T> BlockingQueue<T> {
+    // Unbounded: push never blocks.
+    pub fn new() -> Self {
+        Self::from_list(List::new())
+    }
+
+    // Holds at most `cap` elements. A capacity of zero would block every push forever, so it's
+    // rejected.
+    pub fn with_capacity(cap: usize) -> Self {
+        assert!(cap > 0, "queue capacity must be at least 1");
+        Self::from_list(List::with_capacity(cap))
+    }
+
+    fn from_list(list: List<T>) -> Self {
+        BlockingQueue {
+            queue: Mutex::new(list),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            #[cfg(feature = "async")]
+            waiters: Mutex::default(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, List<T>> {
+        // The list is never left half-updated by a panic, so a poisoned lock is still fine to use.
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Something was pushed: wake one blocked pop (and every waiting task).
+    fn pushed(&self) {
+        self.not_empty.notify_one();
+        #[cfg(feature = "async")]
+        self.wake_all(|w| &mut w.poppers);
+    }
+
+    // Something was popped: wake one blocked push (and every waiting task).
+    fn popped(&self) {
+        self.not_full.notify_one();
+        #[cfg(feature = "async")]
+        self.wake_all(|w| &mut w.pushers);
+    }
+
+    // Blocks while the queue is full.
+    pub fn push(&self, elem: T) {
+        let op = trace::span!("push", waited);
+        let queue = self.lock();
+        let waited = queue.is_full();
+        let mut queue = self
+            .not_full
+            .wait_while(queue, |q| q.is_full())
+            .unwrap_or_else(|e| e.into_inner());
+        queue.push(elem);
+        op.done(Some(queue.len()), 1);
+        op.flag("waited", waited);
+        drop(queue);
+        self.pushed();
+    }
+
+    // Blocks while the queue is empty.
+    pub fn pop(&self) -> T {
+        let op = trace::span!("pop", waited);
+        let queue = self.lock();
+        let waited = queue.is_empty();
+        let mut queue = self
+            .not_empty
+            .wait_while(queue, |q| q.is_empty())
+            .unwrap_or_else(|e| e.into_inner());
+        let elem = queue.pop().unwrap();
+        op.done(Some(queue.len()), 1);
+        op.flag("waited", waited);
+        drop(queue);
+        self.popped();
+        elem
+    }
+
+    // Hands `elem` back if the queue is full.
+    pub fn try_push(&self, elem: T) -> Result<(), T> {
+        let op = trace::span!("try_push");
+        let mut queue = self.lock();
+        let pushed = queue.try_push(elem);
+        op.done(Some(queue.len()), pushed.is_ok() as usize);
+        drop(queue);
+        pushed?;
+        self.pushed();
+        Ok(())
+    }
+
+    pub fn try_pop(&self) -> Option<T> {
+        let op = trace::span!("try_pop");
+        let mut queue = self.lock();
+        let elem = queue.pop();
+        op.done(Some(queue.len()), elem.is_some() as usize);
+        drop(queue);
+        let elem = elem?;
+        self.popped();
+        Some(elem)
+    }
+
+    // Like push, but gives up (and hands `elem` back) after `timeout`.
+    pub fn push_timeout(&self, elem: T, timeout: Duration) -> Result<(), T> {
+        let op = trace::span!("push_timeout", waited);
+        let queue = self.lock();
+        let waited = queue.is_full();
+        let (mut queue, _) = self
+            .not_full
+            .wait_timeout_while(queue, timeout, |q| q.is_full())
+            .unwrap_or_else(|e| e.into_inner());
+        // Timed out or not, whatever the queue says now is the answer.
+        let pushed = queue.try_push(elem);
+        op.done(Some(queue.len()), pushed.is_ok() as usize);
+        op.flag("waited", waited);
+        pushed?;
+        drop(queue);
+        self.pushed();
+        Ok(())
+    }
+
+    // Like pop, but gives up after `timeout`.
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        let op = trace::span!("pop_timeout", waited);
+        let queue = self.lock();
+        let waited = queue.is_empty();
+        let (mut queue, _) = self
+            .not_empty
+            .wait_timeout_while(queue, timeout, |q| q.is_empty())
+            .unwrap_or_else(|e| e.into_inner());
+        let elem = queue.pop();
+        op.done(Some(queue.len()), elem.is_some() as usize);
+        op.flag("waited", waited);
+        let elem = elem?;
+        drop(queue);
+        self.popped();
+        Some(elem)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.lock().capacity()
+    }
+
+    pub fn into_inner(self) -> List<T> {
+        self.queue.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T> BlockingQueue<T> {
+    fn waiters(&self) -> MutexGuard<'_, Waiters> {
+        self.waiters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn wake_all(&self, list: impl FnOnce(&mut Waiters) -> &mut Vec<Waker>) {
+        let wakers = mem::take(list(&mut self.waiters()));
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    fn poll_pop(&self, cx: &mut Context<'_>) -> Poll<T> {
+        let mut queue = self.lock();
+        if let Some(elem) = queue.pop() {
+            drop(queue);
+            self.popped();
+            return Poll::Ready(elem);
+        }
+        // Still holding `queue`, so no push can slip in before we're on the list.
+        self.waiters().poppers.push(cx.waker().clone());
+        Poll::Pending
+    }
+
+    fn poll_push(&self, elem: &mut Option<T>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut queue = self.lock();
+        match queue.try_push(elem.take().expect("polled after completion")) {
+            Ok(()) => {
+                drop(queue);
+                self.pushed();
+                Poll::Ready(())
+            }
+            Err(back) => {
+                *elem = Some(back);
+                self.waiters().pushers.push(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    // Waits (without blocking the thread) while the queue is full.
+    pub fn push_async(&self, elem: T) -> impl Future<Output = ()> + '_ {
+        let mut elem = Some(elem);
+        future::poll_fn(move |cx| self.poll_push(&mut elem, cx))
+    }
+
+    // Waits (without blocking the thread) while the queue is empty.
+    pub fn pop_async(&self) -> impl Future<Output = T> + '_ {
+        future::poll_fn(move |cx| self.poll_pop(cx))
+    }
+}
+
+// Never ends: an empty queue just means waiting for the next push.
+#[cfg(feature = "async")]
+impl<T> futures_core::Stream for &BlockingQueue<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.poll_pop(cx).map(Some)
+    }
+}
+
+impl<T> Default for BlockingQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BlockingQueue;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn basics() {
+        let queue = BlockingQueue::with_capacity(2);
+        assert_eq!(queue.try_pop(), None);
+        assert_eq!(queue.capacity(), Some(2));
+
+        queue.push(1);
+        assert_eq!(queue.try_push(2), Ok(()));
+        assert_eq!(queue.try_push(3), Err(3));
+        assert_eq!(queue.len(), 2);
+
+        assert_eq!(queue.pop(), 1);
+        assert_eq!(queue.try_pop(), Some(2));
+        assert!(queue.is_empty());
+
+        queue.push(4);
+        assert_eq!(queue.into_inner().into_vec(), vec![4]);
+    }
+
+    #[test]
+    #[should_panic(expected = "queue capacity must be at least 1")]
+    fn zero_capacity() {
+        BlockingQueue::<i32>::with_capacity(0);
+    }
+
+    #[test]
+    fn timeouts() {
+        let queue = BlockingQueue::with_capacity(1);
+        let short = Duration::from_millis(10);
+        assert_eq!(queue.pop_timeout(short), None);
+        assert_eq!(queue.push_timeout(1, short), Ok(()));
+        assert_eq!(queue.push_timeout(2, short), Err(2));
+        assert_eq!(queue.pop_timeout(short), Some(1));
+    }
+
+    #[test]
+    fn blocks_until_woken() {
+        let queue = Arc::new(BlockingQueue::with_capacity(1));
+
+        // The consumer sleeps in pop() until something shows up.
+        let q = queue.clone();
+        let consumer = thread::spawn(move || q.pop());
+        thread::sleep(Duration::from_millis(10));
+        queue.push(7);
+        assert_eq!(consumer.join().unwrap(), 7);
+
+        // The producer sleeps in push() until there's room.
+        queue.push(1);
+        let q = queue.clone();
+        let producer = thread::spawn(move || q.push(2));
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(queue.pop(), 1);
+        producer.join().unwrap();
+        assert_eq!(queue.pop(), 2);
+    }
+
+    #[test]
+    fn producers_and_consumers() {
+        let queue = Arc::new(BlockingQueue::with_capacity(4));
+        let producers: Vec<_> = (0..3)
+            .map(|t| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    for i in 0..500 {
+                        queue.push(t * 500 + i);
+                    }
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..3)
+            .map(|_| {
+                let queue = queue.clone();
+                thread::spawn(move || (0..500).map(|_| queue.pop()).collect::<Vec<_>>())
+            })
+            .collect();
+
+        for p in producers {
+            p.join().unwrap();
+        }
+        let mut all: Vec<i32> = consumers
+            .into_iter()
+            .flat_map(|c| c.join().unwrap())
+            .collect();
+        all.sort();
+        assert_eq!(all, (0..1500).collect::<Vec<_>>());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_push_and_pop() {
+        use futures::executor::block_on;
+        use futures::StreamExt;
+
+        let queue = Arc::new(BlockingQueue::with_capacity(2));
+
+        // The producer task waits for room while the consumer thread drains the queue.
+        let q = queue.clone();
+        let consumer = thread::spawn(move || (0..100).map(|_| q.pop()).collect::<Vec<_>>());
+        block_on(async {
+            for i in 0..100 {
+                queue.push_async(i).await;
+            }
+        });
+        assert_eq!(consumer.join().unwrap(), (0..100).collect::<Vec<_>>());
+
+        // And the other way around: a consumer task waits for a producer thread.
+        let q = queue.clone();
+        let producer = thread::spawn(move || (0..100).for_each(|i| q.push(i)));
+        let got: Vec<i32> = block_on(async {
+            let first = queue.pop_async().await;
+            let rest: Vec<_> = (&*queue).take(99).collect().await;
+            std::iter::once(first).chain(rest).collect()
+        });
+        producer.join().unwrap();
+        assert_eq!(got, (0..100).collect::<Vec<_>>());
+    }
+}
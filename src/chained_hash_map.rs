@@ -0,0 +1,379 @@
+/*
+ A hash map with separate chaining
+
+The oldest way to build a hash table, and still the one in most textbooks: an array of buckets,
+each one a singly linked list of the entries whose hash lands there.
+
+buckets:  0: -> ("cat", 3)
+          1:
+          2: -> ("dog", 1) -> ("emu", 9)     two keys whose hashes collide in bucket 2
+          3: -> ("ant", 4)
+
+A lookup hashes the key, picks the bucket (hash % number of buckets), and scans that one chain.
+As long as the chains stay short, that's O(1). The load factor, entries per bucket, is the
+average chain length, so when it goes over MAX_LOAD the table doubles its buckets and rehashes
+everything. Doubling keeps inserts O(1) amortized, the same argument as Vec::push.
+
+The chains are generic_and_iterators::List, and using it for something real shows what it's
+missing:
+
+- It has no len, which is fine, since the map counts its own entries.
+- It had no way to remove from the middle, so List::remove_first was added for this.
+- Rehashing means moving every entry to a new bucket. Going through pop and push would free and
+  reallocate every node, so rehash relinks the Boxes directly through List's pub(crate) fields
+  instead. Not one allocation besides the new bucket array.
+
+entry() takes two scans of the chain when the key is there: one to find out, and one to get the
+&mut. Doing both in one scan (return the &mut if found, else go on to use the map) is a case the
+borrow checker rejects even though it's sound, so this is the usual workaround.
+*/
+
+use crate::generic_and_iterators::List;
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt::{self, Debug};
+use std::hash::{BuildHasher, Hash};
+
+const INITIAL_BUCKETS: usize = 8;
+// The most entries per bucket, on average, before the table grows.
+const MAX_LOAD: usize = 1;
+
+pub struct ChainedHashMap<K, V> {
+    buckets: Vec<List<(K, V)>>,
+    len: usize,
+    hasher: RandomState,
+}
+
+// How well the keys are spread out.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ChainStats {
+    pub buckets: usize,
+    pub empty_buckets: usize,
+    pub longest_chain: usize,
+    pub load_factor: f64,
+}
+
+fn buckets<K, V>(count: usize) -> Vec<List<(K, V)>> {
+    (0..count).map(|_| List::new()).collect()
+}
+
+impl<K: Hash + Eq, V> ChainedHashMap<K, V> {
+    pub fn new() -> Self {
+        ChainedHashMap {
+            buckets: buckets(INITIAL_BUCKETS),
+            len: 0,
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn bucket<Q: Hash + ?Sized>(&self, key: &Q) -> usize {
+        (self.hasher.hash_one(key) % self.buckets.len() as u64) as usize
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let chain = &self.buckets[self.bucket(key)];
+        chain
+            .iter()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let i = self.bucket(key);
+        self.buckets[i]
+            .iter_mut()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(_, v)| v)
+    }
+
+    // Returns the old value if `key` was already there.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(old) = self.get_mut(&key) {
+            return Some(std::mem::replace(old, value));
+        }
+        self.push_new(key, value);
+        None
+    }
+
+    // Add an entry whose key isn't in the map yet, returning where it went.
+    fn push_new(&mut self, key: K, value: V) -> &mut (K, V) {
+        if self.len >= self.buckets.len() * MAX_LOAD {
+            self.rehash(self.buckets.len() * 2);
+        }
+        let i = self.bucket(&key);
+        self.buckets[i].push((key, value));
+        self.len += 1;
+        self.buckets[i].peek_mut().unwrap()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let i = self.bucket(key);
+        let (_, value) = self.buckets[i].remove_first(|(k, _)| k.borrow() == key)?;
+        self.len -= 1;
+        Some(value)
+    }
+
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        if !self.contains_key(&key) {
+            return Entry::Vacant(VacantEntry { map: self, key });
+        }
+        let i = self.bucket(&key);
+        let elem = self.buckets[i].iter_mut().find(|(k, _)| *k == key).unwrap();
+        Entry::Occupied(OccupiedEntry { elem })
+    }
+
+    // Move every node into a table of `count` buckets, without reallocating any of them.
+    fn rehash(&mut self, count: usize) {
+        let old = std::mem::replace(&mut self.buckets, buckets(count));
+        for mut chain in old {
+            let mut next = chain.head.take();
+            while let Some(mut node) = next {
+                next = node.next.take();
+                let i = self.bucket(&node.elem.0);
+                node.next = self.buckets[i].head.take();
+                self.buckets[i].head = Some(node);
+            }
+        }
+    }
+
+    // Keeps the buckets.
+    pub fn clear(&mut self) {
+        for chain in &mut self.buckets {
+            *chain = List::new();
+        }
+        self.len = 0;
+    }
+
+    pub fn load_factor(&self) -> f64 {
+        self.len as f64 / self.buckets.len() as f64
+    }
+
+    pub fn stats(&self) -> ChainStats {
+        let lengths = self.buckets.iter().map(|chain| chain.iter().count());
+        ChainStats {
+            buckets: self.buckets.len(),
+            empty_buckets: lengths.clone().filter(|&n| n == 0).count(),
+            longest_chain: lengths.max().unwrap_or(0),
+            load_factor: self.load_factor(),
+        }
+    }
+
+    // In no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        self.buckets
+            .iter()
+            .flat_map(|chain| chain.iter())
+            .map(|(k, v)| (k, v))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> + '_ {
+        self.buckets
+            .iter_mut()
+            .flat_map(|chain| chain.iter_mut())
+            .map(|(k, v)| (&*k, v))
+    }
+}
+
+pub enum Entry<'a, K, V> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+pub struct OccupiedEntry<'a, K, V> {
+    elem: &'a mut (K, V),
+}
+
+pub struct VacantEntry<'a, K, V> {
+    map: &'a mut ChainedHashMap<K, V>,
+    key: K,
+}
+
+impl<'a, K: Hash + Eq, V> Entry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with(self, default: impl FnOnce() -> V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    pub fn and_modify(mut self, f: impl FnOnce(&mut V)) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K, V> OccupiedEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.elem.0
+    }
+
+    pub fn get(&self) -> &V {
+        &self.elem.1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.elem.1
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.elem.1
+    }
+
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(&mut self.elem.1, value)
+    }
+}
+
+impl<'a, K: Hash + Eq, V> VacantEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V {
+        &mut self.map.push_new(self.key, value).1
+    }
+}
+
+impl<K: Hash + Eq, V> Default for ChainedHashMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq, V> Extend<(K, V)> for ChainedHashMap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> FromIterator<(K, V)> for ChainedHashMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = ChainedHashMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: Hash + Eq + Debug, V: Debug> Debug for ChainedHashMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ChainedHashMap, Entry};
+
+    #[test]
+    fn basics() {
+        let mut map = ChainedHashMap::new();
+        assert_eq!(map.insert("a", 1), None);
+        assert_eq!(map.insert("b", 2), None);
+        assert_eq!(map.insert("a", 10), Some(1));
+        assert_eq!(map.len(), 2);
+
+        assert_eq!(map.get("a"), Some(&10));
+        assert_eq!(map.get("z"), None);
+        *map.get_mut("b").unwrap() += 1;
+        assert_eq!(map.remove("b"), Some(3));
+        assert_eq!(map.remove("b"), None);
+        assert!(!map.contains_key("b"));
+        assert_eq!(format!("{:?}", map), r#"{"a": 10}"#);
+
+        map.clear();
+        assert!(map.is_empty());
+        assert_eq!(map.get("a"), None);
+    }
+
+    #[test]
+    fn entry() {
+        let mut counts: ChainedHashMap<String, usize> = ChainedHashMap::new();
+        for word in "the cat and the dog and the emu".split(' ') {
+            *counts.entry(word.to_string()).or_default() += 1;
+        }
+        assert_eq!(counts.get("the"), Some(&3));
+        assert_eq!(counts.get("and"), Some(&2));
+        assert_eq!(counts.len(), 5);
+
+        counts
+            .entry("cat".to_string())
+            .and_modify(|n| *n *= 10)
+            .or_insert(0);
+        assert_eq!(counts.get("cat"), Some(&10));
+        match counts.entry("owl".to_string()) {
+            Entry::Vacant(entry) => assert_eq!(entry.key(), "owl"),
+            Entry::Occupied(_) => panic!("owl isn't there"),
+        }
+        if let Entry::Occupied(mut entry) = counts.entry("dog".to_string()) {
+            assert_eq!(entry.insert(7), 1);
+        }
+        assert_eq!(counts.get("dog"), Some(&7));
+        assert_eq!(counts.len(), 5);
+    }
+
+    #[test]
+    fn grows() {
+        let mut map: ChainedHashMap<u32, u32> = (0..1000).map(|i| (i, i * i)).collect();
+        let stats = map.stats();
+        assert!(stats.buckets >= 1000);
+        assert!(stats.load_factor <= 1.0);
+        assert!(stats.longest_chain < 20, "{stats:?}");
+        assert!((0..1000).all(|i| map.get(&i) == Some(&(i * i))));
+
+        for (k, v) in map.iter_mut() {
+            *v -= k * k;
+        }
+        assert!(map.iter().all(|(_, &v)| v == 0));
+        assert_eq!(map.iter().count(), 1000);
+    }
+}
@@ -0,0 +1,478 @@
+/*
+ A circular doubly linked list
+
+Join the back of a doubly linked list to its front and there are no ends any more, just a ring
+and a "current" node we're looking at:
+
+        +-> A <-> B <-> C <-+
+        |                   |
+        +-------- D <-------+      current = B
+
+There's no front or back to push to. Instead everything happens relative to `current`:
+insert_after_current / insert_before_current, remove_current (which moves on to the next node),
+and rotate(k) to step k nodes around the ring in either direction. That's the shape of a
+round-robin scheduler (run current, rotate(1)), a circular buffer of players taking turns, or
+the Josephus problem: n people in a ring, every k-th one is eliminated until one is left.
+eliminate_every(k) plays that out, one rotate(k - 1) and remove_current per round.
+
+- Links are NonNull, same as unsafe_doubly_linked_deque.rs, but never None: a ring of one node
+  points at itself both ways. Only `current` is an Option, None when the ring is empty.
+- rotate takes the shorter way round: rotate(len - 1) is the same as rotate(-1), one step back.
+- iter() goes round exactly once, starting at current. Without that bound an iterator over a
+  ring would never end.
+*/
+
+use crate::mem_usage::MemUsage;
+use crate::panic_safety;
+use crate::validate::{self, Invalid};
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+
+pub struct CircularList<T> {
+    current: Option<Link<T>>,
+    len: usize,
+    _boo: PhantomData<T>,
+}
+
+type Link<T> = NonNull<Node<T>>;
+
+struct Node<T> {
+    elem: T,
+    prev: Link<T>,
+    next: Link<T>,
+}
+
+impl<T> CircularList<T> {
+    pub fn new() -> Self {
+        CircularList {
+            current: None,
+            len: 0,
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // See mem_usage.rs.
+    pub fn mem_usage(&self) -> MemUsage {
+        MemUsage::nodes::<Node<T>, T>(self.len, 1)
+    }
+
+    // Going round from current, `len` nodes and then current again, each node's next linking
+    // back to it. A ring has no ends, so node 0 is current. See validate.rs.
+    pub fn validate(&self) -> Result<(), Invalid> {
+        let Some(first) = self.current else {
+            return match self.len {
+                0 => Ok(()),
+                len => Err(Invalid::Len {
+                    stored: len,
+                    counted: 0,
+                }),
+            };
+        };
+        let (mut at, mut link_err) = (0, None);
+        validate::walk(Some(first), self.len, |node| unsafe {
+            let next = node.as_ref().next;
+            at += 1;
+            if next.as_ref().prev != node && link_err.is_none() {
+                link_err = Some(Invalid::Link { at: at % self.len });
+            }
+            (next != first).then_some(next)
+        })?;
+        link_err.map_or(Ok(()), Err)
+    }
+
+    pub fn current(&self) -> Option<&T> {
+        self.current.map(|node| unsafe { &(*node.as_ptr()).elem })
+    }
+
+    pub fn current_mut(&mut self) -> Option<&mut T> {
+        self.current
+            .map(|node| unsafe { &mut (*node.as_ptr()).elem })
+    }
+
+    // The nodes either side of current.
+    pub fn peek_next(&self) -> Option<&T> {
+        self.current
+            .map(|node| unsafe { &(*(*node.as_ptr()).next.as_ptr()).elem })
+    }
+
+    pub fn peek_prev(&self) -> Option<&T> {
+        self.current
+            .map(|node| unsafe { &(*(*node.as_ptr()).prev.as_ptr()).elem })
+    }
+
+    // Step `k` nodes forward (negative: backward), going whichever way round is shorter.
+    pub fn rotate(&mut self, k: isize) {
+        let Some(mut node) = self.current else {
+            return;
+        };
+        let len = self.len as isize;
+        let mut k = k.rem_euclid(len);
+        if k > len / 2 {
+            k -= len;
+        }
+        unsafe {
+            for _ in 0..k {
+                node = (*node.as_ptr()).next;
+            }
+            for _ in k..0 {
+                node = (*node.as_ptr()).prev;
+            }
+        }
+        self.current = Some(node);
+    }
+
+    // Link a new node in between `prev` and `next` (or as the whole ring, if empty), without
+    // moving current except to give an empty ring one.
+    fn link(&mut self, elem: T, between: Option<(Link<T>, Link<T>)>) {
+        let new = NonNull::from(Box::leak(Box::new(Node {
+            elem,
+            prev: NonNull::dangling(),
+            next: NonNull::dangling(),
+        })));
+        let (prev, next) = between.unwrap_or((new, new));
+        unsafe {
+            (*new.as_ptr()).prev = prev;
+            (*new.as_ptr()).next = next;
+            (*prev.as_ptr()).next = new;
+            (*next.as_ptr()).prev = new;
+        }
+        self.current.get_or_insert(new);
+        self.len += 1;
+        validate::check!(self);
+    }
+
+    // current stays where it is. In an empty ring the new node becomes current.
+    pub fn insert_after_current(&mut self, elem: T) {
+        let between = self
+            .current
+            .map(|cur| (cur, unsafe { (*cur.as_ptr()).next }));
+        self.link(elem, between);
+    }
+
+    pub fn insert_before_current(&mut self, elem: T) {
+        let between = self
+            .current
+            .map(|cur| (unsafe { (*cur.as_ptr()).prev }, cur));
+        self.link(elem, between);
+    }
+
+    // Take current out of the ring; the node after it becomes current.
+    pub fn remove_current(&mut self) -> Option<T> {
+        let cur = self.current?;
+        let elem = unsafe {
+            let node = Box::from_raw(cur.as_ptr());
+            self.len -= 1;
+            if self.len == 0 {
+                self.current = None;
+            } else {
+                (*node.prev.as_ptr()).next = node.next;
+                (*node.next.as_ptr()).prev = node.prev;
+                self.current = Some(node.next);
+            }
+            node.elem
+        };
+        validate::check!(self);
+        Some(elem)
+    }
+
+    pub fn clear(&mut self) {
+        while self.remove_current().is_some() {}
+    }
+
+    // Count round from current, 1 to k, and remove the k-th; counting starts again from the node
+    // after it. Yields the removed elements until one is left, which is then current: the
+    // survivor. Each round is a rotate, so it costs min(k, len) steps, however large k is.
+    pub fn eliminate_every(&mut self, k: usize) -> Eliminate<'_, T> {
+        assert!(k > 0, "k must be at least 1");
+        Eliminate { list: self, k }
+    }
+
+    // Once round, starting at current.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            next: self.current,
+            len: self.len,
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            next: self.current,
+            len: self.len,
+            _boo: PhantomData,
+        }
+    }
+}
+
+// clear(), carrying on past an element whose Drop panics: see panic_safety.rs.
+impl<T> Drop for CircularList<T> {
+    fn drop(&mut self) {
+        panic_safety::pop_all(self, |list| list.remove_current().is_some());
+    }
+}
+
+impl<T> Default for CircularList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Appends just behind current, i.e. at the "end" of a trip round from current, so collecting
+// keeps the order and leaves current on the first element.
+impl<T> Extend<T> for CircularList<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.insert_before_current(elem);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for CircularList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = CircularList::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T: Clone> Clone for CircularList<T> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: Debug> Debug for CircularList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+unsafe impl<T: Send> Send for CircularList<T> {}
+unsafe impl<T: Sync> Sync for CircularList<T> {}
+
+pub struct Iter<'a, T> {
+    next: Option<Link<T>>,
+    len: usize,
+    _boo: PhantomData<&'a T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        self.next.map(|node| unsafe {
+            self.next = Some((*node.as_ptr()).next);
+            &(*node.as_ptr()).elem
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+pub struct IterMut<'a, T> {
+    next: Option<Link<T>>,
+    len: usize,
+    _boo: PhantomData<&'a mut T>,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<&'a mut T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        self.next.map(|node| unsafe {
+            self.next = Some((*node.as_ptr()).next);
+            &mut (*node.as_ptr()).elem
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for IterMut<'_, T> {}
+
+pub struct Eliminate<'a, T> {
+    list: &'a mut CircularList<T>,
+    k: usize,
+}
+
+impl<T> Iterator for Eliminate<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.list.len <= 1 {
+            return None;
+        }
+        // rotate wraps k round the ring itself, so reduce it first to fit in an isize.
+        self.list.rotate(((self.k - 1) % self.list.len) as isize);
+        self.list.remove_current()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.list.len.saturating_sub(1);
+        (left, Some(left))
+    }
+}
+
+impl<T> ExactSizeIterator for Eliminate<'_, T> {}
+
+// Removes from current onwards, so it yields the same order as iter().
+pub struct IntoIter<T>(CircularList<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.remove_current()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for CircularList<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a CircularList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut CircularList<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CircularList;
+
+    fn ring(list: &CircularList<char>) -> String {
+        list.iter().collect()
+    }
+
+    #[test]
+    fn basics() {
+        let mut list = CircularList::new();
+        assert_eq!(list.current(), None);
+        assert_eq!(list.remove_current(), None);
+        list.rotate(3);
+
+        list.insert_after_current('a');
+        assert_eq!(list.current(), Some(&'a'));
+        assert_eq!(list.peek_next(), Some(&'a'));
+        list.insert_after_current('c');
+        list.insert_after_current('b');
+        list.insert_before_current('d');
+        assert_eq!(ring(&list), "abcd");
+        assert_eq!(list.peek_prev(), Some(&'d'));
+
+        assert_eq!(list.remove_current(), Some('a'));
+        assert_eq!(ring(&list), "bcd");
+        *list.current_mut().unwrap() = 'B';
+        for c in &mut list {
+            c.make_ascii_uppercase();
+        }
+        assert_eq!(ring(&list), "BCD");
+        assert_eq!(format!("{:?}", list), "['B', 'C', 'D']");
+    }
+
+    #[test]
+    fn rotate() {
+        let mut list: CircularList<char> = "abcde".chars().collect();
+        assert_eq!(list.current(), Some(&'a'));
+        list.rotate(2);
+        assert_eq!(ring(&list), "cdeab");
+        list.rotate(-3);
+        assert_eq!(ring(&list), "eabcd");
+        list.rotate(11);
+        assert_eq!(list.current(), Some(&'a'));
+        list.rotate(-10);
+        assert_eq!(list.current(), Some(&'a'));
+
+        // Round-robin: serve current, then move on.
+        let mut served = String::new();
+        for _ in 0..7 {
+            served.push(*list.current().unwrap());
+            list.rotate(1);
+        }
+        assert_eq!(served, "abcdeab");
+        assert_eq!(list.into_iter().collect::<String>(), "cdeab");
+    }
+
+    #[test]
+    fn josephus() {
+        // The classic: 7 people, every 3rd eliminated.
+        let mut list: CircularList<i32> = (1..=7).collect();
+        let order: Vec<i32> = list.eliminate_every(3).collect();
+        assert_eq!(order, [3, 6, 2, 7, 5, 1]);
+        assert_eq!(list.len(), 1);
+        assert_eq!(list.current(), Some(&4));
+
+        let mut list: CircularList<i32> = (1..=5).collect();
+        assert!(list.eliminate_every(1).eq(1..=4));
+        assert_eq!(list.current(), Some(&5));
+
+        // Counting wraps round the ring many times over.
+        let mut list: CircularList<i32> = (1..=4).collect();
+        assert!(list.eliminate_every(1_000_001).eq([1, 3, 4]));
+        assert_eq!(list.current(), Some(&2));
+
+        let mut empty: CircularList<i32> = CircularList::new();
+        assert_eq!(empty.eliminate_every(2).next(), None);
+    }
+
+    #[test]
+    fn drops() {
+        let mut list: CircularList<String> = (0..5).map(|i| i.to_string()).collect();
+        list.rotate(2);
+        list.remove_current();
+        let cloned = list.clone();
+        assert_eq!(
+            cloned.iter().cloned().collect::<Vec<_>>(),
+            ["3", "4", "0", "1"]
+        );
+        let mut iter = list.into_iter();
+        assert_eq!(iter.next().as_deref(), Some("3"));
+    }
+}
@@ -0,0 +1,189 @@
+/*
+ Counting allocations in tests
+
+"append is O(1)" and "push allocates one node" are promises the rest of the crate's comments keep
+making, and nothing checks them: a change that makes append copy, or push allocate twice, still
+passes every test that only looks at the contents. CountingAlloc is a global allocator that
+counts what goes through it (and hands it on to the system allocator), and it's the one the
+crate's unit tests run on, so a test can assert the exact numbers:
+
+    let counts = Counts::start();
+    a.append(&mut b);
+    assert_eq!(counts.allocs(), 0);
+
+Counting is per thread. The test harness runs tests in parallel on a thread each, and a global
+count would see every other test's allocations too; a thread's own count only sees what that
+thread did, which for a test is the code it calls. Work a test hands to another thread isn't
+counted.
+
+Counts::start() notes where this thread's counters are and every read is a difference from that,
+so several can be live at once, one per step of a test, and none of them has to be reset.
+
+The crate only installs it for `cargo test`: a #[global_allocator] in a library would be forced
+on every program that uses it, which a linked list has no business doing. A program that wants
+the counts (for instrumented.rs's reports, say) installs it itself:
+
+    #[global_allocator]
+    static ALLOC: CountingAlloc = CountingAlloc;
+
+installed() says whether that happened. Without it the counters just stay at zero.
+
+The counters are const-initialized thread locals of Cell<usize>, which have no destructor, so
+reading one never allocates (which would recurse back into the allocator) and still works while
+the thread is being torn down. try_with covers the last moments after that, when they're gone.
+*/
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub struct CountingAlloc;
+
+#[cfg(test)]
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+// Whether CountingAlloc is the global allocator (or at least, has allocated something).
+pub fn installed() -> bool {
+    INSTALLED.load(Ordering::Relaxed)
+}
+
+thread_local! {
+    static ALLOCS: Cell<usize> = const { Cell::new(0) };
+    static DEALLOCS: Cell<usize> = const { Cell::new(0) };
+    static BYTES: Cell<usize> = const { Cell::new(0) };
+}
+
+// Only ever written once, so the threads aren't all writing the same cache line on every
+// allocation.
+fn mark_installed() {
+    if !INSTALLED.load(Ordering::Relaxed) {
+        INSTALLED.store(true, Ordering::Relaxed);
+    }
+}
+
+fn bump(counter: &'static std::thread::LocalKey<Cell<usize>>, by: usize) {
+    let _ = counter.try_with(|c| c.set(c.get() + by));
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        mark_installed();
+        bump(&ALLOCS, 1);
+        bump(&BYTES, layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        mark_installed();
+        bump(&ALLOCS, 1);
+        bump(&BYTES, layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        bump(&DEALLOCS, 1);
+        System.dealloc(ptr, layout)
+    }
+
+    // A realloc is a new allocation and the old one freed, which is how a test thinks of a Vec
+    // growing. Only the growth counts as bytes.
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        bump(&ALLOCS, 1);
+        bump(&DEALLOCS, 1);
+        bump(&BYTES, new_size.saturating_sub(layout.size()));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+fn read(counter: &'static std::thread::LocalKey<Cell<usize>>) -> usize {
+    counter.with(Cell::get)
+}
+
+// This thread's allocations since start().
+pub struct Counts {
+    allocs: usize,
+    deallocs: usize,
+    bytes: usize,
+}
+
+impl Counts {
+    pub fn start() -> Counts {
+        Counts {
+            allocs: read(&ALLOCS),
+            deallocs: read(&DEALLOCS),
+            bytes: read(&BYTES),
+        }
+    }
+
+    pub fn allocs(&self) -> usize {
+        read(&ALLOCS) - self.allocs
+    }
+
+    pub fn deallocs(&self) -> usize {
+        read(&DEALLOCS) - self.deallocs
+    }
+
+    // Bytes asked for, not bytes live: freeing doesn't take any back.
+    pub fn bytes(&self) -> usize {
+        read(&BYTES) - self.bytes
+    }
+
+    // Allocations not yet freed, which is what a leak check wants.
+    pub fn live(&self) -> isize {
+        self.allocs() as isize - self.deallocs() as isize
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{installed, Counts};
+    use std::sync::Barrier;
+    use std::thread;
+
+    #[test]
+    fn counts() {
+        assert!(installed());
+        let counts = Counts::start();
+        let b = Box::new(1u64);
+        assert_eq!(
+            (counts.allocs(), counts.deallocs(), counts.bytes()),
+            (1, 0, 8)
+        );
+        assert_eq!(counts.live(), 1);
+
+        let inner = Counts::start();
+        drop(b);
+        assert_eq!((inner.allocs(), inner.deallocs()), (0, 1));
+        assert_eq!(counts.live(), 0);
+
+        // Growing is one more allocation and one more free, and 4 more bytes.
+        let counts = Counts::start();
+        let mut v = Vec::<u8>::with_capacity(4);
+        v.extend([0; 8]);
+        drop(v);
+        assert_eq!(
+            (counts.allocs(), counts.deallocs(), counts.bytes()),
+            (2, 2, 8)
+        );
+    }
+
+    #[test]
+    fn per_thread() {
+        let barrier = Barrier::new(2);
+        thread::scope(|s| {
+            s.spawn(|| {
+                barrier.wait();
+                drop(vec![0u8; 100]);
+                barrier.wait();
+            });
+            let counts = Counts::start();
+            barrier.wait();
+            barrier.wait();
+            // The other thread allocated in between, and none of it shows here.
+            assert_eq!(counts.allocs(), 0);
+        });
+    }
+}
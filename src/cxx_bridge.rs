@@ -0,0 +1,166 @@
+/*
+ The deque and queue from C++
+
+In a project that's part Rust and part C++, a list built on one side usually reaches the other as
+a std::vector or a rust::Vec: copied out, worked on, copied back. With the `cxx` feature this
+module hands C++ the list itself instead, through a cxx::bridge. The C++ side owns it through a
+rust::Box, which is RAII already (its destructor drops the list in Rust), and include/linked_list.h
+wraps that in two small classes with the usual C++ names and std::optional for the pops:
+
+    #include "linked_list.h"
+
+    linked_list::Deque deque;      // unsafe_doubly_linked_deque::List<i64>
+    deque.push_back(1);
+    std::optional<int64_t> x = deque.pop_front();
+
+    linked_list::Queue queue;      // unsafe_single_linked_queue::List<i64>
+    queue.push(2);
+
+Going the other way, Deque and Queue are public newtypes over the lists. Rust code can move a list
+it already has into one, Box it and pass it across the bridge, and take the list back out of the
+Box when C++ is done with it. The nodes never move.
+
+The limits all come from cxx:
+
+- No generics across the bridge, so the elements are i64. Another element type is another copy of
+  the bridge, with the same methods.
+- No Option either: the pops return whether there was an element and write it through `out`. The
+  C++ wrappers turn that back into an optional.
+- The build script (build.rs) runs cxx_build on this file, which generates the C++ header for it
+  (target/cxxbridge/linked_list_rc/src/cxx_bridge.rs.h) and compiles the C++ half of each function.
+  A C++ build adds target/cxxbridge and include/ to its include path, and links the Rust library.
+*/
+
+use crate::{unsafe_doubly_linked_deque, unsafe_single_linked_queue};
+
+#[cxx::bridge(namespace = "linked_list::ffi")]
+mod ffi {
+    extern "Rust" {
+        type Deque;
+
+        fn new_deque() -> Box<Deque>;
+        fn len(self: &Deque) -> usize;
+        fn push_front(self: &mut Deque, elem: i64);
+        fn push_back(self: &mut Deque, elem: i64);
+        fn pop_front(self: &mut Deque, out: &mut i64) -> bool;
+        fn pop_back(self: &mut Deque, out: &mut i64) -> bool;
+        fn clear(self: &mut Deque);
+
+        type Queue;
+
+        fn new_queue() -> Box<Queue>;
+        fn len(self: &Queue) -> usize;
+        fn push(self: &mut Queue, elem: i64);
+        fn pop(self: &mut Queue, out: &mut i64) -> bool;
+    }
+}
+
+pub struct Deque(pub unsafe_doubly_linked_deque::List<i64>);
+
+pub struct Queue(pub unsafe_single_linked_queue::List<i64>);
+
+// Write `elem` to `out` if there is one. The pops' half of an Option.
+fn put(elem: Option<i64>, out: &mut i64) -> bool {
+    match elem {
+        Some(elem) => {
+            *out = elem;
+            true
+        }
+        None => false,
+    }
+}
+
+fn new_deque() -> Box<Deque> {
+    Box::new(Deque(unsafe_doubly_linked_deque::List::new()))
+}
+
+impl Deque {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn push_front(&mut self, elem: i64) {
+        self.0.push_front(elem)
+    }
+
+    fn push_back(&mut self, elem: i64) {
+        self.0.push_back(elem)
+    }
+
+    fn pop_front(&mut self, out: &mut i64) -> bool {
+        put(self.0.pop_front(), out)
+    }
+
+    fn pop_back(&mut self, out: &mut i64) -> bool {
+        put(self.0.pop_back(), out)
+    }
+
+    fn clear(&mut self) {
+        self.0.clear()
+    }
+}
+
+fn new_queue() -> Box<Queue> {
+    Box::new(Queue(unsafe_single_linked_queue::List::new()))
+}
+
+impl Queue {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn push(&mut self, elem: i64) {
+        self.0.push(elem)
+    }
+
+    fn pop(&mut self, out: &mut i64) -> bool {
+        put(self.0.pop(), out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{new_deque, new_queue, Deque};
+    use crate::unsafe_doubly_linked_deque;
+
+    // What the C++ side calls, without the C++.
+    #[test]
+    fn deque() {
+        let mut deque = new_deque();
+        deque.push_back(2);
+        deque.push_front(1);
+        assert_eq!(deque.len(), 2);
+        let mut out = 0;
+        assert!(deque.pop_back(&mut out));
+        assert_eq!(out, 2);
+        assert!(deque.pop_front(&mut out));
+        assert_eq!(out, 1);
+        assert!(!deque.pop_front(&mut out));
+        // Left alone when there's nothing to pop.
+        assert_eq!(out, 1);
+    }
+
+    #[test]
+    fn queue() {
+        let mut queue = new_queue();
+        for x in 0..3 {
+            queue.push(x);
+        }
+        let mut out = -1;
+        assert!(queue.pop(&mut out));
+        assert_eq!(out, 0);
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn shared() {
+        // A Rust list goes over as it is, and comes back the same way.
+        let list: unsafe_doubly_linked_deque::List<i64> = (1..=3).collect();
+        let front = list.front().unwrap() as *const i64;
+        let mut deque = Box::new(Deque(list));
+        deque.push_back(4);
+        let list = deque.0;
+        assert_eq!(list.front().unwrap() as *const i64, front);
+        assert!(list.iter().eq(&[1, 2, 3, 4]));
+    }
+}
@@ -0,0 +1,188 @@
+/*
+ Diffing two lists
+
+Given two versions of a list, what's the smallest set of changes that turns one into the other?
+That's an edit script: a walk through both lists at once that keeps the elements they have in
+common and deletes or inserts the rest, the same thing `diff` prints for two files a line at a
+time.
+
+    old: a b c a b b a
+    new: c b a b a c
+
+    - a  - b    c  + b    a    b  - b    a  + c
+
+The fewest deletions and insertions is the same problem as the longest common subsequence (keep
+that, change everything else), and Myers' algorithm finds it in O((N + M) * D) time, where D is
+the number of changes. Picture a grid with the old list along the top and the new one down the
+side. A step right deletes an element, a step down inserts one, and where the two elements are
+equal there's a free diagonal step that keeps it. An edit script is a path from the top-left
+corner to the bottom-right, and the shortest one has the fewest non-diagonal steps.
+
+The search goes outwards one edit at a time. After d edits, a path can only have reached
+diagonals -d..=d (k = x - y), so for each of those it keeps the furthest point any d-edit path
+has got along it, extends it by one step from a neighbouring diagonal, and then follows free
+diagonal steps as far as they go. The first d at which some path reaches the corner is D. Each
+round's furthest points are kept so the path can be traced back from the corner afterwards, which
+makes the memory O(D²) on top of the two lists.
+
+When the lists are mostly the same, D is small, and the diff is close to linear. Persistent lists
+have a shortcut on top: two versions of a persistent_stack::List usually share a tail, which is
+equal without being compared, so its diff only searches the parts in front of it.
+
+The edits borrow the elements, from the old list for Keep and Delete and the new one for Insert.
+*/
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Edit<'a, T> {
+    Keep(&'a T),
+    // From the old list.
+    Delete(&'a T),
+    // From the new one.
+    Insert(&'a T),
+}
+
+// Derived, these would need T: Clone and T: Copy, and the references don't.
+impl<T> Clone for Edit<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Edit<'_, T> {}
+
+pub fn diff<'a, T: PartialEq + 'a>(
+    old: impl IntoIterator<Item = &'a T>,
+    new: impl IntoIterator<Item = &'a T>,
+) -> Vec<Edit<'a, T>> {
+    let old: Vec<&T> = old.into_iter().collect();
+    let new: Vec<&T> = new.into_iter().collect();
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+
+    // furthest[k + max] is how far along diagonal k (in x) the best path so far has got.
+    let mut furthest = vec![0isize; 2 * max as usize + 2];
+    let at = |furthest: &[isize], k: isize| furthest[(k + max) as usize];
+    // Before each round d, the furthest points on diagonals -d..=d, for the way back.
+    let mut rounds: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max {
+        rounds.push(furthest[(max - d) as usize..=(max + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            // Down from diagonal k + 1, or right from k - 1, whichever got further.
+            let mut x = if k == -d || (k != d && at(&furthest, k - 1) < at(&furthest, k + 1)) {
+                at(&furthest, k + 1)
+            } else {
+                at(&furthest, k - 1) + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            furthest[(k + max) as usize] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Back from the corner, one round at a time, making the same choice the search did.
+    let mut edits = Vec::with_capacity(max as usize);
+    let (mut x, mut y) = (n, m);
+    for (d, before) in rounds.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let at = |k: isize| before[(k + d) as usize];
+        let down = d > 0 && (k == -d || (k != d && at(k - 1) < at(k + 1)));
+        // Where this round's path left the previous one's, and the free steps after it.
+        let start = match (d, down) {
+            (0, _) => 0,
+            (_, true) => at(k + 1),
+            (_, false) => at(k - 1) + 1,
+        };
+        while x > start {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Keep(old[x as usize]));
+        }
+        if d == 0 {
+            break;
+        }
+        if down {
+            y -= 1;
+            edits.push(Edit::Insert(new[y as usize]));
+        } else {
+            x -= 1;
+            edits.push(Edit::Delete(old[x as usize]));
+        }
+    }
+    edits.reverse();
+    edits
+}
+
+#[cfg(test)]
+mod test {
+    use super::{diff, Edit};
+
+    // Both lists back out of an edit script: keeps and deletes are the old one, keeps and inserts
+    // the new one.
+    fn sides<'a>(edits: &[Edit<'a, char>]) -> (String, String) {
+        let (mut old, mut new) = (String::new(), String::new());
+        for edit in edits {
+            match *edit {
+                Edit::Keep(&c) => {
+                    old.push(c);
+                    new.push(c);
+                }
+                Edit::Delete(&c) => old.push(c),
+                Edit::Insert(&c) => new.push(c),
+            }
+        }
+        (old, new)
+    }
+
+    fn changes(edits: &[Edit<'_, char>]) -> usize {
+        edits
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Keep(_)))
+            .count()
+    }
+
+    #[test]
+    fn shortest() {
+        let cases = [
+            ("abcabba", "cbabac", 5),
+            ("", "", 0),
+            ("abc", "", 3),
+            ("", "abc", 3),
+            ("abc", "abc", 0),
+            ("abc", "xyz", 6),
+            ("kitten", "sitting", 5),
+        ];
+        for (old, new, d) in cases {
+            let old: Vec<char> = old.chars().collect();
+            let new: Vec<char> = new.chars().collect();
+            let edits = diff(&old, &new);
+            assert_eq!(changes(&edits), d, "{old:?} -> {new:?}");
+            let (back_old, back_new) = sides(&edits);
+            assert_eq!(back_old, old.iter().collect::<String>());
+            assert_eq!(back_new, new.iter().collect::<String>());
+        }
+    }
+
+    #[test]
+    fn script() {
+        let old = ['a', 'b', 'c'];
+        let new = ['a', 'x', 'c', 'd'];
+        assert_eq!(
+            diff(&old, &new),
+            [
+                Edit::Keep(&'a'),
+                Edit::Delete(&'b'),
+                Edit::Insert(&'x'),
+                Edit::Keep(&'c'),
+                Edit::Insert(&'d'),
+            ]
+        );
+    }
+}
@@ -5,8 +5,19 @@
  - fn borrow_mut(&self) -> RefMut<'_, T>    (like &mut, requires exclusivity)
 */
 
+use crate::format::{self, ParseListError};
+use crate::mem_usage::MemUsage;
+use crate::panic_safety;
+use crate::traits::{Collection, Deque, Queue, Stack};
+use crate::unsafe_doubly_linked_deque;
+use crate::validate::{self, Invalid};
 use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Display};
+use std::iter;
+use std::ops::Deref;
 use std::rc::Rc;
+use std::str::FromStr;
 
 pub struct List<T> {
     head: Link<T>,
@@ -56,10 +67,11 @@ impl<T> List<T> {
                 self.head = Some(new_head);
             }
         }
+        validate::check!(self);
     }
 
     pub fn pop_front(&mut self) -> Option<T> {
-        self.head.take().map(|curr_head| {
+        let elem = self.head.take().map(|curr_head| {
             match curr_head.borrow_mut().next.take() {
                 Some(new_head) => {
                     // -1 ref count on old head
@@ -83,7 +95,9 @@ impl<T> List<T> {
                let's just work around it by converting the Result to an Option with ok.
             */
             Rc::try_unwrap(curr_head).ok().unwrap().into_inner().elem
-        })
+        });
+        validate::check!(self);
+        elem
     }
 
     /*
@@ -92,14 +106,14 @@ impl<T> List<T> {
     that's returned is connected to the lifetime of the Ref, and not the actual RefCell. This means
     that the Ref has to be sitting around as long as we keep the reference around.
      */
-    pub fn peek_front(&self) -> Option<Ref<T>> {
+    pub fn peek_front(&self) -> Option<Ref<'_, T>> {
         self.head
             .as_ref()
             /* just like you can map over an Option, you can map over a Ref. */
             .map(|node| Ref::map(node.borrow(), |node| &node.elem))
     }
 
-    pub fn peek_front_mut(&mut self) -> Option<RefMut<T>> {
+    pub fn peek_front_mut(&mut self) -> Option<RefMut<'_, T>> {
         self.head
             .as_ref()
             .map(|node| RefMut::map(node.borrow_mut(), |node| &mut node.elem))
@@ -122,10 +136,11 @@ impl<T> List<T> {
                 self.tail = Some(new_tail);
             }
         }
+        validate::check!(self);
     }
 
     pub fn pop_back(&mut self) -> Option<T> {
-        self.tail.take().map(|curr_tail| {
+        let elem = self.tail.take().map(|curr_tail| {
             match curr_tail.borrow_mut().prev.take() {
                 Some(new_tail) => {
                     new_tail.borrow_mut().next.take();
@@ -136,34 +151,276 @@ impl<T> List<T> {
                 }
             }
             Rc::try_unwrap(curr_tail).ok().unwrap().into_inner().elem
-        })
+        });
+        validate::check!(self);
+        elem
     }
 
-    pub fn peek_back(&self) -> Option<Ref<T>> {
+    pub fn peek_back(&self) -> Option<Ref<'_, T>> {
         self.tail
             .as_ref()
             .map(|node| Ref::map(node.borrow(), |node| &node.elem))
     }
 
-    pub fn peek_back_mut(&mut self) -> Option<RefMut<T>> {
+    pub fn peek_back_mut(&mut self) -> Option<RefMut<'_, T>> {
         self.tail
             .as_ref()
             .map(|node| RefMut::map(node.borrow_mut(), |node| &mut node.elem))
     }
 }
 
-// Destructor.
+impl<T> List<T> {
+    // See mem_usage.rs. Each node is a RefCell in an Rc, so it carries a borrow flag too.
+    pub fn mem_usage(&self) -> MemUsage {
+        MemUsage::rc_nodes::<RefCell<Node<T>>, T>(Collection::len(self))
+    }
+
+    // The rule from "Debugging the links" below: each prev points at the node before, tail is the
+    // last node, and no node has more than its two owners. There's no length to check against, but
+    // a next that loops back gives the node it lands on a third owner, so the walk stops there.
+    // See validate.rs.
+    pub fn validate(&self) -> Result<(), Invalid> {
+        let mut prev: Link<T> = None;
+        let mut cur = self.head.clone();
+        let mut at = 0;
+        while let Some(node) = cur {
+            // `node` is one more.
+            if Rc::strong_count(&node) > 3 {
+                return Err(Invalid::Shared { at });
+            }
+            let borrowed = node.borrow();
+            let prev_ok = match (&borrowed.prev, &prev) {
+                (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+                (a, b) => a.is_none() && b.is_none(),
+            };
+            if !prev_ok {
+                return Err(Invalid::Link { at });
+            }
+            cur = borrowed.next.clone();
+            drop(borrowed);
+            prev = Some(node);
+            at += 1;
+        }
+        match (&prev, &self.tail) {
+            (Some(a), Some(b)) if Rc::ptr_eq(a, b) => Ok(()),
+            (None, None) => Ok(()),
+            _ => Err(Invalid::Ends),
+        }
+    }
+}
+
+// "[1, 2, 3]", front first. See format.rs.
+impl<T: Display> Display for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nodes = iter::successors(self.head.clone(), |node| node.borrow().next.clone());
+        format::write_list(f, nodes.map(ElemOf))
+    }
+}
+
+impl<T: FromStr> FromStr for List<T> {
+    type Err = ParseListError<T::Err>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut list = List::new();
+        for elem in format::parse_list(s)? {
+            list.push_back(elem);
+        }
+        Ok(list)
+    }
+}
+
+// A node's element, borrowed only while it prints, the way peek_front lends it out.
+struct ElemOf<T>(Rc<RefCell<Node<T>>>);
+
+impl<T: Display> Display for ElemOf<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.borrow().elem.fmt(f)
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Every other list converts to the unsafe deque; this is the way back from there.
+impl<T> From<unsafe_doubly_linked_deque::List<T>> for List<T> {
+    fn from(deque: unsafe_doubly_linked_deque::List<T>) -> Self {
+        let mut list = List::new();
+        for elem in deque {
+            list.push_back(elem);
+        }
+        list
+    }
+}
+
+// The crate-wide traits (see traits.rs): a deque is a stack at the front, a queue from back to
+// front, and a deque. There's no length field, so len() walks the list.
+impl<T> Collection for List<T> {
+    fn len(&self) -> usize {
+        let mut len = 0;
+        let mut cur = self.head.clone();
+        while let Some(node) = cur {
+            len += 1;
+            cur = node.borrow().next.clone();
+        }
+        len
+    }
+
+    fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+}
+
+impl<T> Stack<T> for List<T> {
+    fn push(&mut self, elem: T) {
+        self.push_front(elem)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        self.pop_front()
+    }
+
+    fn peek(&self) -> Option<impl Deref<Target = T> + '_> {
+        self.peek_front()
+    }
+}
+
+impl<T> Queue<T> for List<T> {
+    fn push(&mut self, elem: T) {
+        self.push_back(elem)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        self.pop_front()
+    }
+
+    fn peek(&self) -> Option<impl Deref<Target = T> + '_> {
+        self.peek_front()
+    }
+}
+
+impl<T> Deque<T> for List<T> {
+    fn push_front(&mut self, elem: T) {
+        List::push_front(self, elem)
+    }
+
+    fn push_back(&mut self, elem: T) {
+        List::push_back(self, elem)
+    }
+
+    fn pop_front(&mut self) -> Option<T> {
+        List::pop_front(self)
+    }
+
+    fn pop_back(&mut self) -> Option<T> {
+        List::pop_back(self)
+    }
+
+    fn peek_front(&self) -> Option<impl Deref<Target = T> + '_> {
+        List::peek_front(self)
+    }
+
+    fn peek_back(&self) -> Option<impl Deref<Target = T> + '_> {
+        List::peek_back(self)
+    }
+}
+
+/*
+ Debugging the links
+
+Every node here is pointed at twice: by its neighbours' next and prev, or by head or tail at the
+ends. So every strong count should be exactly 2, and a node with any other count is a link that
+was forgotten (1, and it'll be freed too early) or left behind (3, and it'll never be freed: a
+leak, since two nodes that point at each other keep each other alive forever).
+
+{:#?} prints a node per line with its counts, and flags whatever breaks that rule: a count that
+isn't 2, a prev that doesn't point at the node before, a tail that isn't the last node, and a
+next chain that loops back on itself, which would otherwise have Debug walk it forever. {:?}
+prints just the elements (and stops at a loop too).
+
+    [
+        1 (strong: 2, weak: 0),
+        2 (strong: 3, weak: 0) expected strong: 2,
+        3 (strong: 2, weak: 0) prev link broken,
+    ]
+
+The walk holds its own Rc to the node it's on, which the printed count leaves out.
+*/
+
+impl<T: Debug> Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let alternate = f.alternate();
+        let mut list = f.debug_list();
+        let mut seen = HashMap::new();
+        let mut prev: Link<T> = None;
+        let mut cur = self.head.clone();
+
+        while let Some(node) = cur {
+            if let Some(i) = seen.get(&Rc::as_ptr(&node)) {
+                list.entry(&format_args!("<cycle: back to node {i}>"));
+                return list.finish();
+            }
+            seen.insert(Rc::as_ptr(&node), seen.len());
+            let Ok(borrowed) = node.try_borrow() else {
+                list.entry(&format_args!("<borrowed>"));
+                return list.finish();
+            };
+
+            if alternate {
+                let strong = Rc::strong_count(&node) - 1;
+                let prev_ok = match (&borrowed.prev, &prev) {
+                    (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+                    (a, b) => a.is_none() && b.is_none(),
+                };
+                list.entry(&format_args!(
+                    "{:#?} (strong: {strong}, weak: {}){}{}",
+                    borrowed.elem,
+                    Rc::weak_count(&node),
+                    if strong != 2 {
+                        " expected strong: 2"
+                    } else {
+                        ""
+                    },
+                    if prev_ok { "" } else { " prev link broken" },
+                ));
+            } else {
+                list.entry(&borrowed.elem);
+            }
+
+            cur = borrowed.next.clone();
+            drop(borrowed);
+            prev = Some(node);
+        }
+
+        let tail_ok = match (&prev, &self.tail) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        if alternate && !tail_ok {
+            list.entry(&format_args!("<tail is not the last node>"));
+        }
+        list.finish()
+    }
+}
+
+// Destructor. pop_front till list is empty, which also breaks each prev/next cycle. If an
+// element's Drop panics, carry on anyway: stopping would leave the rest of the cycles, and their
+// nodes, leaked (see panic_safety.rs).
 impl<T> Drop for List<T> {
     fn drop(&mut self) {
-        // pop_front till list is empty.
-        while self.pop_front().is_some() {}
+        panic_safety::pop_all(self, |list| list.pop_front().is_some());
     }
 }
 
 pub struct IntoIter<T>(List<T>);
 
-impl<T> List<T> {
-    pub fn into_iter(self) -> IntoIter<T> {
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
         IntoIter(self)
     }
 }
@@ -186,6 +443,7 @@ impl<T> DoubleEndedIterator for IntoIter<T> {
 #[cfg(test)]
 mod test {
     use super::List;
+    use crate::validate::Invalid;
 
     #[test]
     fn basics() {
@@ -260,6 +518,67 @@ mod test {
         assert_eq!(&mut *list.peek_back_mut().unwrap(), &mut 1);
     }
 
+    #[test]
+    fn debug_links() {
+        let mut list = List::new();
+        list.push_back(2);
+        list.push_back(3);
+        list.push_front(1);
+        assert_eq!(format!("{list:?}"), "[1, 2, 3]");
+        assert_eq!(
+            format!("{list:#?}"),
+            "[
+    1 (strong: 2, weak: 0),
+    2 (strong: 2, weak: 0),
+    3 (strong: 2, weak: 0),
+]"
+        );
+
+        // Link the back to the front, as a buggy splice might.
+        list.tail.as_ref().unwrap().borrow_mut().next = list.head.clone();
+        assert_eq!(format!("{list:?}"), "[1, 2, 3, <cycle: back to node 0>]");
+        assert_eq!(
+            format!("{list:#?}"),
+            "[
+    1 (strong: 3, weak: 0) expected strong: 2,
+    2 (strong: 2, weak: 0),
+    3 (strong: 2, weak: 0),
+    <cycle: back to node 0>,
+]"
+        );
+        list.tail.as_ref().unwrap().borrow_mut().next = None;
+
+        // Cut the chain after the front, then put it back so the list can be dropped.
+        let rest = list.head.as_ref().unwrap().borrow_mut().next.take();
+        let out = format!("{list:#?}");
+        assert!(out.ends_with("<tail is not the last node>,\n]"), "{out}");
+        list.head.as_ref().unwrap().borrow_mut().next = rest;
+    }
+
+    #[test]
+    fn validate() {
+        let mut list = List::new();
+        assert_eq!(list.validate(), Ok(()));
+        list.push_back(2);
+        list.push_back(3);
+        list.push_front(1);
+        assert_eq!(list.validate(), Ok(()));
+
+        // The same breakages as debug_links.
+        list.tail.as_ref().unwrap().borrow_mut().next = list.head.clone();
+        assert_eq!(list.validate(), Err(Invalid::Shared { at: 0 }));
+        list.tail.as_ref().unwrap().borrow_mut().next = None;
+
+        let prev = list.tail.as_ref().unwrap().borrow_mut().prev.take();
+        assert_eq!(list.validate(), Err(Invalid::Link { at: 2 }));
+        list.tail.as_ref().unwrap().borrow_mut().prev = prev;
+
+        let rest = list.head.as_ref().unwrap().borrow_mut().next.take();
+        assert_eq!(list.validate(), Err(Invalid::Ends));
+        list.head.as_ref().unwrap().borrow_mut().next = rest;
+        assert_eq!(list.validate(), Ok(()));
+    }
+
     #[test]
     fn into_iter() {
         let mut list = List::new();
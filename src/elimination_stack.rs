@@ -0,0 +1,217 @@
+/*
+ An elimination-backoff stack (Hendler, Shavit & Yerushalmi, 2004)
+
+Under contention a Treiber stack turns into a queue of threads all failing their CAS on the same
+`head`. The observation behind elimination: a push and a pop that happen at the same time don't
+need the stack at all. The pop can just take the pushed value directly, and the stack ends up
+exactly as if the push had gone first and the pop right after.
+
+So when a CAS on `head` fails, instead of retrying straight away, a thread backs off into an
+"elimination array" of slots:
+
+- A push offers its node: it CASes a random empty slot to point at it, then waits a little for a
+  pop to come along.
+- A pop looks at a random slot, and if there's a node on offer it CASes the slot to TAKEN. That
+  node is now the pop's, and the push is done.
+- If nobody shows up in time, the push withdraws its offer (CAS back to empty) and both sides go
+  back to the main stack.
+
+The pushing thread never dereferences its node again once it's on offer, it only compares the
+slot's pointer against it, so the pop can free the node as soon as it has taken the element out.
+Pops stay pinned while looking at slots, so a withdrawn node that ends up popped (and retired)
+off the main stack can't be reused at the same address while anyone still holds its pointer.
+*/
+
+use crate::epoch;
+use crate::sync::atomic::{AtomicPtr, Ordering};
+use crate::sync::hint;
+use crate::treiber_stack::{Node, TreiberStack};
+use std::cell::Cell;
+use std::mem::ManuallyDrop;
+use std::ptr;
+
+const SLOTS: usize = 8;
+// How long a push waits on its offer before withdrawing it.
+const SPINS: usize = 128;
+
+pub struct EliminationStack<T> {
+    stack: TreiberStack<T>,
+    slots: [AtomicPtr<Node<T>>; SLOTS],
+}
+
+// The same reasoning as TreiberStack: elements are only ever handed over by value.
+unsafe impl<T: Send> Send for EliminationStack<T> {}
+unsafe impl<T: Send> Sync for EliminationStack<T> {}
+
+// A slot whose offer has been accepted, until the pushing thread notices and empties it. Never
+// dereferenced, just compared.
+fn taken<T>() -> *mut Node<T> {
+    ptr::dangling_mut()
+}
+
+// A cheap per-thread xorshift, so contending threads spread out over the slots.
+fn random_slot() -> usize {
+    thread_local! {
+        static STATE: Cell<u32> = Cell::new({
+            let local = 0u8;
+            (&local as *const u8).addr() as u32 | 1
+        });
+    }
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        state.set(x);
+        x as usize % SLOTS
+    })
+}
+
+impl<T> EliminationStack<T> {
+    pub fn new() -> Self {
+        EliminationStack {
+            stack: TreiberStack::new(),
+            slots: std::array::from_fn(|_| AtomicPtr::new(ptr::null_mut())),
+        }
+    }
+
+    pub fn push(&self, elem: T) {
+        let node = Node::new(elem);
+        loop {
+            if self.stack.try_push(node) || self.offer(node) {
+                return;
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = epoch::pin();
+        loop {
+            if let Ok(elem) = self.stack.try_pop(&guard) {
+                return elem;
+            }
+            if let Some(elem) = self.take_offer() {
+                return Some(elem);
+            }
+        }
+    }
+
+    // Put `node` up for grabs. True if a pop took it.
+    fn offer(&self, node: *mut Node<T>) -> bool {
+        let slot = &self.slots[random_slot()];
+        if slot
+            .compare_exchange(ptr::null_mut(), node, Ordering::Release, Ordering::Relaxed)
+            .is_err()
+        {
+            return false;
+        }
+
+        for _ in 0..SPINS {
+            if slot.load(Ordering::Acquire) == taken() {
+                slot.store(ptr::null_mut(), Ordering::Release);
+                return true;
+            }
+            hint::spin_loop();
+        }
+
+        // Withdraw, unless a pop got there at the very last moment.
+        match slot.compare_exchange(node, ptr::null_mut(), Ordering::Relaxed, Ordering::Acquire) {
+            Ok(_) => false,
+            Err(_) => {
+                slot.store(ptr::null_mut(), Ordering::Release);
+                true
+            }
+        }
+    }
+
+    // Take whatever a push has on offer, if anything.
+    fn take_offer(&self) -> Option<T> {
+        let slot = &self.slots[random_slot()];
+        let node = slot.load(Ordering::Acquire);
+        if node.is_null() || node == taken() {
+            return None;
+        }
+        slot.compare_exchange(node, taken(), Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        // The push has let go of it, and nobody else can win the same CAS.
+        let mut node = unsafe { Box::from_raw(node) };
+        Some(unsafe { ManuallyDrop::take(&mut node.elem) })
+    }
+
+    // Just a snapshot, and it doesn't count values that are on offer.
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+}
+
+impl<T> Default for EliminationStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::EliminationStack;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn basics() {
+        let stack = EliminationStack::new();
+        assert_eq!(stack.pop(), None);
+        stack.push(1);
+        stack.push(2);
+        assert_eq!(stack.pop(), Some(2));
+        stack.push(3);
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(stack.pop(), Some(1));
+        assert_eq!(stack.pop(), None);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn offers() {
+        // Drive the elimination path directly, without any contention on the stack.
+        let stack = EliminationStack::new();
+        assert_eq!(stack.take_offer(), None);
+
+        // An offer nobody takes gets withdrawn, and the node is still the pusher's.
+        let node = super::Node::new(String::from("unclaimed"));
+        assert!(!stack.offer(node));
+        assert!(stack.stack.try_push(node));
+        assert_eq!(stack.pop().as_deref(), Some("unclaimed"));
+        assert!(stack
+            .slots
+            .iter()
+            .all(|s| s.load(super::Ordering::Relaxed).is_null()));
+    }
+
+    #[test]
+    fn threads() {
+        let stack = Arc::new(EliminationStack::new());
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let stack = stack.clone();
+                thread::spawn(move || {
+                    let mut popped = Vec::new();
+                    for i in 0..1000 {
+                        stack.push(t * 1000 + i);
+                        popped.extend(stack.pop());
+                    }
+                    popped
+                })
+            })
+            .collect();
+
+        let mut all: Vec<i32> = handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect();
+        while let Some(v) = stack.pop() {
+            all.push(v);
+        }
+        all.sort();
+        assert_eq!(all, (0..4000).collect::<Vec<_>>());
+    }
+}
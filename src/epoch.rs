@@ -0,0 +1,310 @@
+/*
+ Epoch-based memory reclamation
+
+The problem with lock-free lists: once a node is unlinked, some other thread may still be holding a
+pointer to it that it loaded a moment earlier. Freeing it right away is a use-after-free, and
+reusing the allocation is the ABA problem. So freeing has to wait until nobody can still be
+looking at it.
+
+Epochs keep track of "nobody" cheaply:
+
+- There is one global epoch counter. A thread that wants to touch shared nodes first pins itself,
+  which just records "I'm active, and I saw epoch e".
+- Unlinked nodes aren't freed; they are deferred, stamped with the global epoch at that time.
+- The global epoch only moves from e to e + 1 once every pinned thread has seen e. So when the
+  global epoch is two steps past a node's stamp, every thread that was pinned when it was unlinked
+  has since unpinned, and the node can go.
+
+Pinning and unpinning are a couple of atomic stores per operation, so the hot paths stay
+lock-free. The bookkeeping that happens rarely (a thread registering for the first time, or trying
+to advance the epoch and collect garbage) takes a lock, which keeps this module short.
+
+    let guard = epoch::pin();
+    // ... load shared pointers, unlink a node ...
+    unsafe { guard.defer_destroy(node) };
+    // `node` is freed some time after every current guard is gone.
+*/
+
+use crate::sync::atomic::{fence, AtomicUsize, Ordering};
+use crate::sync::{thread_local, Arc, Mutex};
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use std::mem;
+
+// How much garbage a thread piles up before it tries to collect some.
+const COLLECT_THRESHOLD: usize = 64;
+
+// A participant's state: 0 when unpinned, otherwise (epoch << 1) | 1.
+struct Participant {
+    state: AtomicUsize,
+}
+
+struct Global {
+    epoch: AtomicUsize,
+    participants: Mutex<Vec<Arc<Participant>>>,
+    // Garbage left behind by threads that exited before it could be freed.
+    orphans: Mutex<Vec<(usize, Deferred)>>,
+}
+
+#[cfg(not(loom))]
+static GLOBAL: Global = Global {
+    epoch: AtomicUsize::new(0),
+    participants: Mutex::new(Vec::new()),
+    orphans: Mutex::new(Vec::new()),
+};
+
+// loom's primitives can't be built in a const, and every model run needs a fresh collector anyway.
+#[cfg(loom)]
+loom::lazy_static! {
+    static ref GLOBAL: Global = Global {
+        epoch: AtomicUsize::new(0),
+        participants: Mutex::new(Vec::new()),
+        orphans: Mutex::new(Vec::new()),
+    };
+}
+
+// A deferred destructor. Orphaned garbage is run on whichever thread collects it; defer_unchecked's
+// contract is what makes that okay.
+struct Deferred(Box<dyn FnOnce()>);
+
+unsafe impl Send for Deferred {}
+
+struct Local {
+    participant: Arc<Participant>,
+    guards: Cell<usize>,
+    bag: RefCell<Vec<(usize, Deferred)>>,
+}
+
+impl Local {
+    fn new() -> Self {
+        let participant = Arc::new(Participant {
+            state: AtomicUsize::new(0),
+        });
+        GLOBAL
+            .participants
+            .lock()
+            .unwrap()
+            .push(participant.clone());
+        Local {
+            participant,
+            guards: Cell::new(0),
+            bag: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+// loom can't schedule other threads from inside a thread-local destructor. Under loom an exited
+// thread just stays registered, unpinned (so it never holds the epoch back), and its leftover
+// garbage is leaked.
+#[cfg(not(loom))]
+impl Drop for Local {
+    fn drop(&mut self) {
+        GLOBAL
+            .participants
+            .lock()
+            .unwrap()
+            .retain(|p| !Arc::ptr_eq(p, &self.participant));
+        let bag = mem::take(self.bag.get_mut());
+        GLOBAL.orphans.lock().unwrap().extend(bag);
+    }
+}
+
+thread_local! {
+    static LOCAL: Local = Local::new();
+}
+
+// Keeps the current thread pinned. Guards nest: the thread stays pinned until the last one drops.
+// Tied to its thread, so it's neither Send nor Sync.
+pub struct Guard {
+    _not_send: PhantomData<*mut ()>,
+}
+
+pub fn pin() -> Guard {
+    LOCAL.with(|local| {
+        let guards = local.guards.get();
+        if guards == 0 {
+            let epoch = GLOBAL.epoch.load(Ordering::Relaxed);
+            local
+                .participant
+                .state
+                .store((epoch << 1) | 1, Ordering::Relaxed);
+            // Pairs with the fence in try_advance(): either it sees us pinned, or we see every
+            // unlink that happened before the epoch moved on.
+            fence(Ordering::SeqCst);
+        }
+        local.guards.set(guards + 1);
+    });
+    Guard {
+        _not_send: PhantomData,
+    }
+}
+
+impl Guard {
+    /// Run `f` once no thread can still hold a pointer it loaded while pinned.
+    ///
+    /// # Safety
+    /// `f` may run on any thread, after any amount of time, so whatever it captures must be
+    /// fine to use from another thread and must outlive that.
+    pub unsafe fn defer_unchecked<F: FnOnce()>(&self, f: F) {
+        let f: Box<dyn FnOnce() + '_> = Box::new(f);
+        // Erase the lifetime: the caller has promised it doesn't matter.
+        let f: Box<dyn FnOnce()> = mem::transmute(f);
+
+        fence(Ordering::SeqCst);
+        let epoch = GLOBAL.epoch.load(Ordering::Relaxed);
+        let full = LOCAL.with(|local| {
+            let mut bag = local.bag.borrow_mut();
+            bag.push((epoch, Deferred(f)));
+            bag.len() >= COLLECT_THRESHOLD
+        });
+        if full {
+            self.flush();
+        }
+    }
+
+    /// Free the Box at `ptr` once it's safe to.
+    ///
+    /// # Safety
+    /// `ptr` came from Box::into_raw, is no longer reachable from the shared structure, and is
+    /// deferred only once. Dropping a T on another thread, after its owner is gone, must be fine:
+    /// whatever T drops has to be Send and 'static.
+    pub unsafe fn defer_destroy<T>(&self, ptr: *mut T) {
+        struct SendPtr<T>(*mut T);
+        let ptr = SendPtr(ptr);
+        self.defer_unchecked(move || {
+            let ptr = ptr;
+            drop(Box::from_raw(ptr.0));
+        });
+    }
+
+    // Try to move the epoch on, and run whatever garbage is old enough.
+    pub fn flush(&self) {
+        let epoch = try_advance();
+
+        let ready = LOCAL.with(|local| {
+            let mut bag = local.bag.borrow_mut();
+            let (ready, keep) = mem::take(&mut *bag)
+                .into_iter()
+                .partition::<Vec<_>, _>(|(stamp, _)| expired(*stamp, epoch));
+            *bag = keep;
+            ready
+        });
+        let orphans = {
+            let mut orphans = GLOBAL.orphans.lock().unwrap();
+            let (ready, keep) = mem::take(&mut *orphans)
+                .into_iter()
+                .partition::<Vec<_>, _>(|(stamp, _)| expired(*stamp, epoch));
+            *orphans = keep;
+            ready
+        };
+
+        // Run them with no borrows or locks held: a destructor may well pin and defer again.
+        for (_, Deferred(f)) in ready.into_iter().chain(orphans) {
+            f();
+        }
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        LOCAL.with(|local| {
+            let guards = local.guards.get() - 1;
+            local.guards.set(guards);
+            if guards == 0 {
+                local.participant.state.store(0, Ordering::Release);
+            }
+        });
+    }
+}
+
+fn expired(stamp: usize, epoch: usize) -> bool {
+    epoch.wrapping_sub(stamp) >= 2
+}
+
+// Bump the global epoch if every pinned thread has caught up with it. Returns the epoch after.
+fn try_advance() -> usize {
+    // Holding the lock means nobody else is advancing at the same time, so the epoch we load is
+    // the one we bump.
+    let participants = GLOBAL.participants.lock().unwrap();
+    let epoch = GLOBAL.epoch.load(Ordering::Relaxed);
+    fence(Ordering::SeqCst);
+
+    for p in participants.iter() {
+        let state = p.state.load(Ordering::Relaxed);
+        if state & 1 == 1 && state >> 1 != epoch {
+            return epoch;
+        }
+    }
+    fence(Ordering::Acquire);
+
+    GLOBAL.epoch.store(epoch.wrapping_add(1), Ordering::Release);
+    epoch.wrapping_add(1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    struct Counted(Arc<AtomicUsize>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn deferred_until_unpinned() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let reader = pin();
+        {
+            let guard = pin();
+            let ptr = Box::into_raw(Box::new(Counted(drops.clone())));
+            unsafe { guard.defer_destroy(ptr) };
+            // This thread is still pinned (through `reader`), so the epoch can move on at most
+            // once and nothing may be freed yet.
+            for _ in 0..4 {
+                guard.flush();
+            }
+            assert_eq!(drops.load(Ordering::Relaxed), 0);
+        }
+        drop(reader);
+
+        // Other tests may pin concurrently, so give the epoch a few chances to advance.
+        for _ in 0..100 {
+            if drops.load(Ordering::Relaxed) == 1 {
+                break;
+            }
+            pin().flush();
+            thread::yield_now();
+        }
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn exiting_threads_leave_garbage_behind() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let d = drops.clone();
+        thread::spawn(move || {
+            let guard = pin();
+            for _ in 0..10 {
+                let ptr = Box::into_raw(Box::new(Counted(d.clone())));
+                unsafe { guard.defer_destroy(ptr) };
+            }
+        })
+        .join()
+        .unwrap();
+
+        for _ in 0..100 {
+            if drops.load(Ordering::Relaxed) == 10 {
+                break;
+            }
+            pin().flush();
+            thread::yield_now();
+        }
+        assert_eq!(drops.load(Ordering::Relaxed), 10);
+    }
+}
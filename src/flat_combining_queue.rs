@@ -0,0 +1,251 @@
+/*
+ A flat-combining queue (Hendler, Incze, Shavit & Tzafrir, 2010)
+
+The obvious way to share the unsafe deque between threads is a Mutex around it. Under contention
+that means every operation pays for its own lock handoff, and the deque's nodes bounce from cache
+to cache as each thread in turn walks up to them.
+
+Flat combining keeps the lock, but makes whoever holds it do everyone's work:
+
+- A thread writes its operation into a request record and publishes it with a CAS onto a shared
+  list of pending requests (a Treiber-style push, so publishing never waits).
+- Then it tries to take the lock. If it gets it, it becomes the combiner: it takes the whole
+  pending list in one swap, applies every request to the deque in the order they arrived, marks
+  each one done, and repeats until no requests are left.
+- If it doesn't, it just waits for its own record to be marked done. Some other combiner is
+  already on it.
+
+requests -> [pop] -> [push 7] -> [push 3] -> null     (newest first)
+
+One thread touches the deque for a whole batch, so it stays hot in that thread's cache, and the
+lock changes hands once per batch instead of once per operation.
+
+A request record lives on its thread's stack. That's fine because the thread doesn't return until
+the record is done, and the combiner is finished with a record the moment it marks it done.
+*/
+
+use crate::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use crate::sync::thread;
+use crate::trace;
+use crate::unsafe_doubly_linked_deque::List;
+use std::cell::{Cell, UnsafeCell};
+use std::ptr;
+
+pub struct FlatCombiningQueue<T> {
+    // Only ever touched by the thread holding `locked`.
+    deque: UnsafeCell<List<T>>,
+    locked: AtomicBool,
+    requests: AtomicPtr<Request<T>>,
+}
+
+// A pending operation. `run` knows the real type behind `op` and applies it to the deque.
+struct Request<T> {
+    run: unsafe fn(*mut (), &mut List<T>),
+    op: *mut (),
+    next: Cell<*mut Request<T>>,
+    done: AtomicBool,
+}
+
+// Elements move between threads through push and pop (the combiner runs everyone's operations),
+// which is all T: Send is needed for.
+unsafe impl<T: Send> Send for FlatCombiningQueue<T> {}
+unsafe impl<T: Send> Sync for FlatCombiningQueue<T> {}
+
+impl<T> FlatCombiningQueue<T> {
+    pub fn new() -> Self {
+        FlatCombiningQueue {
+            deque: UnsafeCell::new(List::new()),
+            locked: AtomicBool::new(false),
+            requests: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    pub fn push(&self, elem: T) {
+        let op = trace::span!("push");
+        let len = self.apply(move |deque| {
+            deque.push_back(elem);
+            deque.len()
+        });
+        op.done(Some(len), 1);
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let op = trace::span!("pop");
+        let (elem, len) = self.apply(|deque| (deque.pop_front(), deque.len()));
+        op.done(Some(len), elem.is_some() as usize);
+        elem
+    }
+
+    // Just a snapshot: other threads may push or pop right after.
+    pub fn len(&self) -> usize {
+        self.apply(|deque| deque.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn into_inner(self) -> List<T> {
+        self.deque.into_inner()
+    }
+
+    // Have `f` run against the deque, by us or by whoever is combining, and return its result.
+    //
+    // `f` must not panic: it may run on the combiner's thread, and a combiner that unwinds would
+    // leave the lock held and everyone else waiting forever. That's why this stays private and
+    // only ever gets handed the deque's own (non-panicking) methods.
+    fn apply<R, F: FnOnce(&mut List<T>) -> R>(&self, f: F) -> R {
+        // The closure goes in, the result comes out, both in a slot on our own stack.
+        let mut slot: (Option<F>, Option<R>) = (Some(f), None);
+        unsafe fn run<T, F: FnOnce(&mut List<T>) -> R, R>(op: *mut (), deque: &mut List<T>) {
+            let slot = &mut *(op as *mut (Option<F>, Option<R>));
+            let f = slot.0.take().unwrap();
+            slot.1 = Some(f(deque));
+        }
+        let request = Request {
+            run: run::<T, F, R>,
+            op: &mut slot as *mut (Option<F>, Option<R>) as *mut (),
+            next: Cell::new(ptr::null_mut()),
+            done: AtomicBool::new(false),
+        };
+        let ours = &request as *const Request<T> as *mut Request<T>;
+
+        // Publish it.
+        let mut head = self.requests.load(Ordering::Relaxed);
+        loop {
+            request.next.set(head);
+            match self
+                .requests
+                .compare_exchange(head, ours, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+
+        // Wait for it to be done, combining ourselves whenever nobody else is.
+        while !request.done.load(Ordering::Acquire) {
+            if !self.locked.load(Ordering::Relaxed)
+                && self
+                    .locked
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                // Ours was published before we took the lock, so this is sure to run it.
+                unsafe { self.combine() };
+                self.locked.store(false, Ordering::Release);
+            } else {
+                // Let the combiner run; spinning only helps if it's on another core.
+                thread::yield_now();
+            }
+        }
+        slot.1.take().unwrap()
+    }
+
+    // Safety: the caller holds `locked`.
+    unsafe fn combine(&self) {
+        let deque = &mut *self.deque.get();
+        loop {
+            let mut batch = self.requests.swap(ptr::null_mut(), Ordering::Acquire);
+            if batch.is_null() {
+                return;
+            }
+
+            // Newest first on the list; flip it so requests run in the order they came in.
+            let mut oldest = ptr::null_mut();
+            while !batch.is_null() {
+                let next = (*batch).next.get();
+                (*batch).next.set(oldest);
+                oldest = batch;
+                batch = next;
+            }
+
+            while !oldest.is_null() {
+                let request = &*oldest;
+                // Once `done` is set the record's owner may return and its stack frame is gone,
+                // so read `next` first.
+                oldest = request.next.get();
+                (request.run)(request.op, deque);
+                request.done.store(true, Ordering::Release);
+            }
+        }
+    }
+}
+
+impl<T> Default for FlatCombiningQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FlatCombiningQueue;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn basics() {
+        let queue = FlatCombiningQueue::new();
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+
+        queue.push(1);
+        queue.push(2);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.pop(), Some(1));
+        queue.push(3);
+        assert_eq!(queue.pop(), Some(2));
+
+        queue.push(4);
+        assert_eq!(
+            queue.into_inner().into_iter().collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+    }
+
+    #[test]
+    fn threads() {
+        let queue = Arc::new(FlatCombiningQueue::new());
+        let producers: Vec<_> = (0..3)
+            .map(|t| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        queue.push(t * 1000 + i);
+                    }
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..3)
+            .map(|_| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    let mut got = Vec::new();
+                    while got.len() < 1000 {
+                        if let Some(v) = queue.pop() {
+                            got.push(v);
+                        }
+                    }
+                    got
+                })
+            })
+            .collect();
+        for p in producers {
+            p.join().unwrap();
+        }
+
+        let got: Vec<Vec<i32>> = consumers.into_iter().map(|c| c.join().unwrap()).collect();
+        // Still FIFO per producer, whoever happened to combine.
+        for mine in &got {
+            for t in 0..3 {
+                let from_t: Vec<_> = mine.iter().filter(|&&v| v / 1000 == t).collect();
+                assert!(from_t.windows(2).all(|w| w[0] < w[1]));
+            }
+        }
+        let mut all: Vec<i32> = got.into_iter().flatten().collect();
+        all.sort();
+        assert_eq!(all, (0..3000).collect::<Vec<_>>());
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,173 @@
+/*
+ Printing and parsing lists
+
+{:?} prints a list for whoever is debugging it. Display is for everyone else: a command-line tool
+printing its state, a doctest's expected output, an error message. Every list prints the same
+way, its elements in iteration order (top first for a stack, front first for a queue) between
+brackets:
+
+    [1, 2, 3]
+
+The formatter is handed on to each element, so `{:>3}` pads each element to 3 and `{:.1}` prints
+each f64 to one decimal place, rather than applying to the list as a whole.
+
+FromStr reads the same format back, for any T: FromStr, so "[1, 2, 3]".parse() is a list, and a
+list printed with Display parses back to one equal to it. The elements are split at commas and
+trimmed of whitespace. Commas inside a nested pair of brackets don't split, so a list of lists
+round-trips too:
+
+    [[1, 2], [], [3]]
+
+A string element containing a comma or a bracket of its own won't: there's no quoting. Something
+like serde_json is the way to store arbitrary data; this is for reading what people type.
+
+Errors say what went wrong and where: no brackets round the list (or brackets inside it that
+don't pair up), or the index of the first element that didn't parse, along with T's own error.
+*/
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseListError<E> {
+    Brackets,
+    Elem { index: usize, error: E },
+}
+
+impl<E: Display> Display for ParseListError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseListError::Brackets => f.write_str("expected a list in matching [ ]"),
+            ParseListError::Elem { index, error } => write!(f, "element {index}: {error}"),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for ParseListError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseListError::Brackets => None,
+            ParseListError::Elem { error, .. } => Some(error),
+        }
+    }
+}
+
+pub(crate) fn write_list<D: Display>(
+    f: &mut fmt::Formatter<'_>,
+    elems: impl IntoIterator<Item = D>,
+) -> fmt::Result {
+    f.write_str("[")?;
+    for (i, elem) in elems.into_iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        elem.fmt(f)?;
+    }
+    f.write_str("]")
+}
+
+// The elements, in order, for each list's FromStr to build itself from.
+pub(crate) fn parse_list<T: FromStr>(s: &str) -> Result<Vec<T>, ParseListError<T::Err>> {
+    let inner = s
+        .trim()
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .ok_or(ParseListError::Brackets)?;
+    let mut elems = Vec::new();
+    if inner.trim().is_empty() {
+        return Ok(elems);
+    }
+
+    let mut parse = |elem: &str| {
+        let index = elems.len();
+        let elem = elem
+            .trim()
+            .parse()
+            .map_err(|error| ParseListError::Elem { index, error })?;
+        elems.push(elem);
+        Ok(())
+    };
+    let (mut depth, mut start) = (0usize, 0);
+    for (i, c) in inner.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth = depth.checked_sub(1).ok_or(ParseListError::Brackets)?,
+            ',' if depth == 0 => {
+                parse(&inner[start..i])?;
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(ParseListError::Brackets);
+    }
+    parse(&inner[start..])?;
+    Ok(elems)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_list, ParseListError};
+    use crate::{
+        arc_persistent_stack, doubly_linked_deque, generic_and_iterators, persistent_stack,
+        unsafe_doubly_linked_deque, unsafe_single_linked_queue,
+    };
+    use std::num::IntErrorKind;
+
+    #[test]
+    fn display() {
+        let deque: unsafe_doubly_linked_deque::List<i32> = (1..=3).collect();
+        assert_eq!(deque.to_string(), "[1, 2, 3]");
+        assert_eq!(format!("{deque:>2}"), "[ 1,  2,  3]");
+        assert_eq!(
+            unsafe_doubly_linked_deque::List::<i32>::new().to_string(),
+            "[]"
+        );
+
+        let mut stack = generic_and_iterators::List::new();
+        stack.push(1.26);
+        stack.push(0.5);
+        assert_eq!(format!("{stack:.1}"), "[0.5, 1.3]");
+    }
+
+    // Each list parses what it prints.
+    #[test]
+    fn round_trip() {
+        let text = "[3, 1, 4, 1, 5]";
+        let stack: generic_and_iterators::List<u8> = text.parse().unwrap();
+        assert_eq!(stack.to_string(), text);
+        let persistent: persistent_stack::List<u8> = text.parse().unwrap();
+        assert_eq!(persistent.to_string(), text);
+        let arc: arc_persistent_stack::List<u8> = text.parse().unwrap();
+        assert_eq!(arc.to_string(), text);
+        let deque: doubly_linked_deque::List<u8> = text.parse().unwrap();
+        assert_eq!(deque.to_string(), text);
+        let queue: unsafe_single_linked_queue::List<u8> = text.parse().unwrap();
+        assert_eq!(queue.to_string(), text);
+
+        let nested: unsafe_doubly_linked_deque::List<unsafe_doubly_linked_deque::List<u8>> =
+            " [[1,2] , [], [3]] ".parse().unwrap();
+        assert_eq!(nested.to_string(), "[[1, 2], [], [3]]");
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse_list::<u8>("1, 2"), Err(ParseListError::Brackets));
+        assert_eq!(parse_list::<u8>("[1, 2]]"), Err(ParseListError::Brackets));
+        assert_eq!(parse_list::<u8>("[[1, 2]"), Err(ParseListError::Brackets));
+        match parse_list::<u8>("[1, 300]") {
+            Err(ParseListError::Elem { index: 1, error }) => {
+                assert_eq!(*error.kind(), IntErrorKind::PosOverflow)
+            }
+            other => panic!("{other:?}"),
+        }
+        // A trailing comma is an empty last element.
+        assert!(parse_list::<u8>("[1,]").is_err());
+        assert_eq!(
+            parse_list::<String>("[a, b c]").unwrap(),
+            ["a".to_string(), "b c".to_string()]
+        );
+    }
+}
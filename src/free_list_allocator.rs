@@ -0,0 +1,263 @@
+/*
+ A free-list allocator
+
+Where does Box::new get its memory from? Underneath, an allocator hands out pieces of a big
+region of memory and takes them back, and the classic way to keep track of the pieces that are
+free is a linked list threaded through the free pieces themselves. That's the free list, and it's
+probably the most common linked list in systems code.
+
+This module manages a plain byte buffer that way. Offsets into the buffer stand in for pointers,
+so it's all safe code, but the layout is the real thing:
+
+heap:  [hdr|   used   ][hdr|next| free ...][hdr|  used  ][hdr|next|   free   ]
+                        ^ free list --------------------->^ -> NIL
+
+- Every block starts with an 8-byte header: the block's total size, with the low bit set while
+  it's allocated (sizes are multiples of 8, so that bit is otherwise always 0).
+- A free block also stores the offset of the next free block right after its header. That's the
+  intrusive link: the list costs no memory of its own, it lives in the space nobody's using.
+  It's also why a block is never smaller than 16 bytes.
+- The free list is kept in address order, which makes coalescing cheap (see free).
+
+alloc(n) rounds n up to a multiple of 8, adds the header, and looks for a free block that fits:
+
+- First fit takes the first one. Fast, but tends to chop up the blocks near the start.
+- Best fit takes the smallest one that fits, scanning the whole list, to keep big blocks big.
+
+If the block is bigger than needed by at least a minimal block, it's split and the rest stays on
+the free list. alloc returns the offset of the payload, just past the header.
+
+free(p) marks the block free and links it back in at its place in address order. Its neighbours
+in the list are then the nearest free blocks on either side. If either one ends exactly where
+this one starts (or starts where it ends), the two are merged into one block. Without this, the
+heap would end up as a list of small blocks, none large enough for a big request, even with
+plenty of memory free in total.
+*/
+
+const HEADER: usize = 8;
+const MIN_BLOCK: usize = 16;
+const ALLOCATED: usize = 1;
+const NIL: usize = usize::MAX;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Fit {
+    First,
+    Best,
+}
+
+pub struct FreeListAllocator {
+    heap: Box<[u8]>,
+    // Offset of the first free block, in address order.
+    free: usize,
+    fit: Fit,
+}
+
+impl FreeListAllocator {
+    // A heap of `size` bytes (rounded down to a multiple of 8), all of it one free block.
+    pub fn new(size: usize, fit: Fit) -> Self {
+        let size = size & !7;
+        assert!(size >= MIN_BLOCK, "heap too small for even one block");
+        let mut this = FreeListAllocator {
+            heap: vec![0; size].into_boxed_slice(),
+            free: 0,
+            fit,
+        };
+        this.set_size(0, size);
+        this.set_next(0, NIL);
+        this
+    }
+
+    fn word(&self, at: usize) -> usize {
+        let bytes = self.heap[at..at + 8].try_into().unwrap();
+        u64::from_le_bytes(bytes) as usize
+    }
+
+    fn set_word(&mut self, at: usize, value: usize) {
+        self.heap[at..at + 8].copy_from_slice(&(value as u64).to_le_bytes());
+    }
+
+    fn size(&self, block: usize) -> usize {
+        self.word(block) & !ALLOCATED
+    }
+
+    fn is_allocated(&self, block: usize) -> bool {
+        self.word(block) & ALLOCATED != 0
+    }
+
+    fn set_size(&mut self, block: usize, size: usize) {
+        self.set_word(block, size);
+    }
+
+    fn next(&self, block: usize) -> usize {
+        self.word(block + HEADER)
+    }
+
+    fn set_next(&mut self, block: usize, next: usize) {
+        self.set_word(block + HEADER, next);
+    }
+
+    // Point `prev`'s link (or the list head, if NIL) at `to`.
+    fn relink(&mut self, prev: usize, to: usize) {
+        match prev {
+            NIL => self.free = to,
+            _ => self.set_next(prev, to),
+        }
+    }
+
+    // Allocate `n` bytes and return the payload's offset, or None if no free block is big enough.
+    pub fn alloc(&mut self, n: usize) -> Option<usize> {
+        let need = ((n + 7) & !7).max(MIN_BLOCK - HEADER) + HEADER;
+
+        // Find the block, and the free block before it (whose link has to change).
+        let (mut prev, mut cur) = (NIL, self.free);
+        let mut found: Option<(usize, usize)> = None;
+        while cur != NIL {
+            let size = self.size(cur);
+            if size >= need && found.is_none_or(|(_, best)| size < self.size(best)) {
+                found = Some((prev, cur));
+                if self.fit == Fit::First || size == need {
+                    break;
+                }
+            }
+            prev = cur;
+            cur = self.next(cur);
+        }
+        let (prev, block) = found?;
+
+        let size = self.size(block);
+        let next = self.next(block);
+        if size - need >= MIN_BLOCK {
+            // Split: the tail end stays free and takes the block's place in the list.
+            let rest = block + need;
+            self.set_size(rest, size - need);
+            self.set_next(rest, next);
+            self.relink(prev, rest);
+            self.set_size(block, need | ALLOCATED);
+        } else {
+            self.relink(prev, next);
+            self.set_size(block, size | ALLOCATED);
+        }
+        Some(block + HEADER)
+    }
+
+    // Give back a payload offset returned by alloc. Panics on a double free.
+    pub fn free(&mut self, p: usize) {
+        let block = p - HEADER;
+        assert!(self.is_allocated(block), "double free of {p}");
+        let mut size = self.size(block);
+
+        // The free blocks either side of us, in address order.
+        let (mut prev, mut next) = (NIL, self.free);
+        while next != NIL && next < block {
+            prev = next;
+            next = self.next(next);
+        }
+
+        // Merge with the next block if it starts right where we end...
+        if next != NIL && block + size == next {
+            size += self.size(next);
+            next = self.next(next);
+        }
+        // ...and into the previous one if it ends right where we start.
+        if prev != NIL && prev + self.size(prev) == block {
+            let merged = self.size(prev) + size;
+            self.set_size(prev, merged);
+            self.set_next(prev, next);
+        } else {
+            self.set_size(block, size);
+            self.set_next(block, next);
+            self.relink(prev, block);
+        }
+    }
+
+    // The payload of an allocated block.
+    pub fn memory(&mut self, p: usize) -> &mut [u8] {
+        let block = p - HEADER;
+        assert!(self.is_allocated(block), "{p} isn't allocated");
+        let size = self.size(block);
+        &mut self.heap[p..block + size]
+    }
+
+    // (offset, size) of every free block, in address order.
+    pub fn free_blocks(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut cur = self.free;
+        std::iter::from_fn(move || {
+            let block = (cur != NIL).then_some(cur)?;
+            cur = self.next(block);
+            Some((block, self.size(block)))
+        })
+    }
+
+    pub fn free_bytes(&self) -> usize {
+        self.free_blocks().map(|(_, size)| size).sum()
+    }
+
+    // The biggest block alloc could hand out right now. Much smaller than free_bytes means the
+    // free space is fragmented.
+    pub fn largest_free(&self) -> usize {
+        self.free_blocks().map(|(_, size)| size).max().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Fit, FreeListAllocator};
+
+    #[test]
+    fn alloc_free_coalesce() {
+        let mut heap = FreeListAllocator::new(256, Fit::First);
+        let a = heap.alloc(10).unwrap();
+        let b = heap.alloc(24).unwrap();
+        let c = heap.alloc(8).unwrap();
+        // 10 rounds up to 16; every block also has an 8-byte header.
+        assert_eq!((a, b, c), (8, 32, 64));
+        assert_eq!(heap.free_blocks().collect::<Vec<_>>(), [(72, 184)]);
+
+        heap.memory(a).fill(0xaa);
+        heap.memory(b).copy_from_slice(&[7; 24]);
+        assert_eq!(heap.memory(a), [0xaa; 16]);
+        assert!(heap.memory(b).iter().all(|&x| x == 7));
+
+        // Freeing `a` and `c` leaves two holes; freeing `b` merges all of it back into one.
+        heap.free(a);
+        heap.free(c);
+        assert_eq!(heap.free_blocks().count(), 2);
+        assert_eq!(heap.free_bytes(), 24 + 16 + 184);
+        heap.free(b);
+        assert_eq!(heap.free_blocks().collect::<Vec<_>>(), [(0, 256)]);
+
+        assert_eq!(heap.alloc(249), None);
+        assert_eq!(heap.alloc(248), Some(8));
+    }
+
+    #[test]
+    fn first_fit_and_best_fit() {
+        // Holes of 64 bytes then 32 bytes, separated by allocated blocks.
+        fn holes(fit: Fit) -> FreeListAllocator {
+            let mut heap = FreeListAllocator::new(512, fit);
+            let big = heap.alloc(56).unwrap();
+            heap.alloc(8).unwrap();
+            let small = heap.alloc(24).unwrap();
+            heap.alloc(8).unwrap();
+            heap.free(big);
+            heap.free(small);
+            heap
+        }
+
+        // First fit splits the 64-byte hole; best fit uses the 32-byte one exactly.
+        let mut first = holes(Fit::First);
+        assert_eq!(first.alloc(24), Some(8));
+        let mut best = holes(Fit::Best);
+        assert_eq!(best.alloc(24), Some(88));
+        assert_eq!(best.largest_free(), 512 - 128);
+    }
+
+    #[test]
+    #[should_panic(expected = "double free")]
+    fn double_free() {
+        let mut heap = FreeListAllocator::new(64, Fit::First);
+        let a = heap.alloc(8).unwrap();
+        heap.free(a);
+        heap.free(a);
+    }
+}
@@ -1,74 +1,283 @@
+use crate::allocator::{Allocator, Global};
+use crate::diff::{self, Edit};
+use crate::pointer_list::{self, BoxKind};
+use crate::{persistent_stack, unsafe_doubly_linked_deque};
+#[cfg(feature = "nightly")]
+use std::alloc;
+use std::alloc::{handle_alloc_error, Layout};
 use std::mem;
+use std::ptr::{self, NonNull};
 
-pub struct List<T> {
-    head: Link<T>,
-}
+// The list itself is pointer_list's, with Box as the pointer: push, pop, peek, the iterators and
+// Drop are written there once for this list and the persistent ones. What's here is what only a
+// list of Boxes can do.
+// The fields are pub(crate) so other modules (sorted_list.rs, chained_hash_map.rs) can build on
+// the links directly.
+// T can be unsized (see push_boxed below, nightly only, and push_slice and push_str).
+pub type List<T, A = Global> = pointer_list::List<T, BoxKind<A>>;
 
 // Type aliasing
-type Link<T> = Option<Box<Node<T>>>;
+pub(crate) type Link<T, A = Global> = pointer_list::Link<T, BoxKind<A>>;
+
+pub(crate) type Node<T, A = Global> = pointer_list::Node<T, BoxKind<A>>;
+
+/*
+ Allocators
+
+A List<T> gets its nodes from the global allocator, one malloc per node, like Box::new.
+List<T, A> gets them from A instead (see allocator.rs): new_in(alloc) builds one, and try_push
+gives the element back when A has run out, where push would abort. With a Bump the whole list is
+freed in one go when the Bump is, and each node costs no more than its size plus alignment:
+
+    let bump = Bump::with_capacity(4096);
+    let mut list = List::new_in(&bump);
+
+Box<T, A> is what would do this, but it's nightly only, so the links are pointer_list's Ptrs and
+BoxKind<A> allocates and frees the nodes itself, the way Box would. The nodes are the same
+wherever they come from, so everything below works on any A.
+*/
+
+/*
+ Push and pop
+
+These were the first methods written here, and they're pointer_list's now, but the steps are
+worth keeping. pop started as a match on mem::replace(&mut self.head, None):
+
+    match mem::replace(&mut self.head, None) {
+        None => None,
+        Some(node) => {
+            self.head = node.next;
+            Some(node.elem)
+        }
+    }
+
+x = mem::replace(option, None) can be x = option.take(), and
+match option { None => None, Some(x) => Some(y) } can be option.map(|x| y), with a closure:
 
-struct Node<T> {
-    elem: T,
-    next: Link<T>,
+    self.head.take().map(|node| {
+        self.head = node.next;
+        node.elem
+    })
+
+peek takes a shared reference and returns one, and peek_mut is the mutable version. They go
+through head.as_ref() and head.as_mut(): a map on the Option itself would move the Box out of
+the list, where as_ref() and as_mut() turn an &Option<Box<Node>> into an Option<&Box<Node>>
+(and the same for &mut) to map over instead.
+
+Drop is a loop rather than the default recursive one: take() each node's next before the node
+itself goes, so that dropping a long list doesn't overflow the stack.
+*/
+
+/*
+ Unsized elements
+
+A List<dyn Shape> or a List<str> can't push(elem: T): an unsized value can't be passed around by
+itself, only behind a pointer. But a node can hold one, as long as it's the last field, and then
+a pointer to a Node<dyn Shape> is a fat pointer like Box<dyn Shape> is, with the vtable (or the
+length, for str and slices) alongside the address.
+
+push_boxed takes the element already boxed, which is how an unsized value usually arrives
+(Box::new(circle) coerces to Box<dyn Shape> at the call), and moves it into a node:
+
+Layout::for_value gives the element's size and alignment, Layout::extend puts `next` in front of
+it the way repr(C) would, then we allocate that, copy the element's bytes over, and free the old
+box without dropping the element in it (it's moved, not gone). pop_boxed does it in reverse.
+
+The fiddly part is making the fat pointer for the new node: the new address, with the element's
+metadata. ptr::from_raw_parts_mut does exactly that, but it isn't stable yet, and nothing stable
+can attach metadata to an address in a different allocation: ptr.with_addr() and byte_offset()
+keep the old pointer's provenance, so the result can't be used to reach the new node, and
+writing the address over the front of a fat pointer assumes a layout rustc doesn't promise. So
+push_boxed and pop_boxed need the `nightly` feature.
+
+Slices and strs don't need any of that. Their metadata is only a length, and a cast from
+*mut [U] to *mut Node<[U]> keeps it, so push_slice and push_str build the node on stable: lay it
+out the same way, clone (or, for a str, copy) the elements straight into it, and cast. Getting
+them back out as a Box is still pop_boxed's job, so on stable these lists are pushed, peeked,
+iterated and dropped, but not popped. A List<dyn Shape> is nightly only.
+
+So a List<dyn Shape> holds heterogeneous shapes with one allocation per element, the node itself,
+rather than a node plus a Box<dyn Shape> inside it.
+
+Only this list takes unsized elements. The others move elements in and out by value: the deques'
+pop_front and into_iter return a T, doubly_linked_deque's nodes are Rc<RefCell<Node<T>>>s that
+pop takes apart with Rc::try_unwrap and RefCell::into_inner (both need T: Sized), the persistent
+lists hand the last node back with into_inner (see pointer_list.rs), and the unsafe queue's nodes
+are built with Box::new. Each would need its own push_boxed and pop_boxed like the ones here, and
+basic_impl only holds i32s anyway.
+*/
+
+// `ptr`'s metadata (vtable or length, if any) with `addr` as the address.
+// Safety: the result is only valid if `addr` really holds a value with that metadata.
+#[cfg(feature = "nightly")]
+unsafe fn with_addr_of<U: ?Sized>(ptr: *mut U, addr: *mut u8) -> *mut U {
+    ptr::from_raw_parts_mut(addr, ptr::metadata(ptr))
 }
 
-impl<T> List<T> {
-    pub fn new() -> Self {
-        List { head: None }
+#[cfg(feature = "nightly")]
+impl<T: ?Sized, A: Allocator> List<T, A> {
+    pub fn push_boxed(&mut self, elem: Box<T>) {
+        let elem_layout = Layout::for_value(&*elem);
+        let (layout, offset) = Layout::new::<Link<T, A>>().extend(elem_layout).unwrap();
+        let raw = self.allocate(layout.pad_to_align()).as_ptr();
+        unsafe {
+            let elem = Box::into_raw(elem);
+            ptr::copy_nonoverlapping(elem as *const u8, raw.add(offset), elem_layout.size());
+            if elem_layout.size() != 0 {
+                alloc::dealloc(elem as *mut u8, elem_layout);
+            }
+            self.link_in(with_addr_of(elem as *mut Node<T, A>, raw));
+        }
     }
 
-    pub fn push(&mut self, elem: T) {
-        let new_node = Box::new(Node {
-            elem,
-            next: mem::replace(&mut self.head, None),
-        });
-        self.head = Some(new_node);
+    pub fn pop_boxed(&mut self) -> Option<Box<T>> {
+        let node = self.head.take()?.into_raw().as_ptr();
+        unsafe {
+            let node_layout = Layout::for_value(&*node);
+            self.head = ptr::addr_of_mut!((*node).next).read();
+            let elem = ptr::addr_of_mut!((*node).elem);
+            let elem_layout = Layout::for_value(&*elem);
+            let raw = if elem_layout.size() == 0 {
+                // What Box uses for zero-sized values: any non-null, aligned address.
+                ptr::without_provenance_mut(elem_layout.align())
+            } else {
+                let raw = alloc::alloc(elem_layout);
+                if raw.is_null() {
+                    handle_alloc_error(elem_layout);
+                }
+                raw
+            };
+            ptr::copy_nonoverlapping(elem as *const u8, raw, elem_layout.size());
+            let node = NonNull::new_unchecked(node as *mut u8);
+            self.allocator().deallocate(node, node_layout);
+            Some(Box::from_raw(with_addr_of(elem, raw)))
+        }
     }
+}
 
-    // pub fn pop(&mut self) -> Option<i32> {
-    //     match mem::replace(self.head, None) {
-    //         None => None,
-    //         Link::Some(node) => {
-    //             self.head = node.next;
-    //             Some(node.elem)
-    //         }
-    //     }
-    // }
+impl<U: Clone, A: Allocator> List<[U], A> {
+    pub fn push_slice(&mut self, elems: &[U]) {
+        let (layout, offset) = Layout::new::<Link<[U], A>>()
+            .extend(Layout::array::<U>(elems.len()).unwrap())
+            .unwrap();
+        let layout = layout.pad_to_align();
+        let raw = self.allocate(layout);
+
+        // If a clone panics, the ones already made are dropped and the node freed.
+        struct Partial<'a, U, A: Allocator> {
+            alloc: &'a A,
+            raw: NonNull<u8>,
+            layout: Layout,
+            elems: *mut U,
+            done: usize,
+        }
 
-    // x = mem::replace(option, None) can be x = option.take()
+        impl<U, A: Allocator> Drop for Partial<'_, U, A> {
+            fn drop(&mut self) {
+                unsafe {
+                    ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.elems, self.done));
+                    self.alloc.deallocate(self.raw, self.layout);
+                }
+            }
+        }
 
-    /*
-    match option {None => None, Some(x) => Some(y)} can be
+        let mut partial = Partial {
+            alloc: self.allocator(),
+            raw,
+            layout,
+            elems: unsafe { raw.as_ptr().add(offset).cast::<U>() },
+            done: 0,
+        };
+        for elem in elems {
+            unsafe { partial.elems.add(partial.done).write(elem.clone()) };
+            partial.done += 1;
+        }
+        mem::forget(partial);
 
-    using Rust closure
-    */
-    pub fn pop(&mut self) -> Option<T> {
-        self.head.take().map(|node| {
-            self.head = node.next;
-            node.elem
-        })
+        let node = ptr::slice_from_raw_parts_mut(raw.as_ptr().cast::<U>(), elems.len());
+        unsafe { self.link_in(node as *mut Node<[U], A>) };
+    }
+}
+
+impl<A: Allocator> List<str, A> {
+    pub fn push_str(&mut self, elem: &str) {
+        let (layout, offset) = Layout::new::<Link<str, A>>()
+            .extend(Layout::for_value(elem))
+            .unwrap();
+        let raw = self.allocate(layout.pad_to_align()).as_ptr();
+        unsafe { ptr::copy_nonoverlapping(elem.as_ptr(), raw.add(offset), elem.len()) };
+
+        let node = ptr::slice_from_raw_parts_mut(raw, elem.len());
+        unsafe { self.link_in(node as *mut Node<str, A>) };
+    }
+}
+
+impl<T: ?Sized, A: Allocator> List<T, A> {
+    // Memory for a node built by hand. Never zero-sized: there's always `next`.
+    fn allocate(&self, layout: Layout) -> NonNull<u8> {
+        match self.allocator().allocate(layout) {
+            Ok(raw) => raw.cast(),
+            Err(_) => handle_alloc_error(layout),
+        }
     }
 
-    // Taking a shared reference and returning a shared reference.
-    pub fn peek(&self) -> Option<&T> {
-        // NOTE: as_ref()
-        self.head.as_ref().map(|node| &node.elem)
+    // Put a node built by hand on top, with everything but its `next` already written.
+    // Safety: `node` is from allocate, with the layout Layout::for_value gives it, so BoxKind
+    // frees it like any other.
+    unsafe fn link_in(&mut self, node: *mut Node<T, A>) {
+        ptr::addr_of_mut!((*node).next).write(self.head.take());
+        self.head = Some(pointer_list::Ptr::from_raw(NonNull::new_unchecked(node)));
+    }
+}
+
+impl<T, A: Allocator> List<T, A> {
+    // The edits that turn this list into `other`, front to back. See diff.rs.
+    pub fn diff<'a>(&'a self, other: &'a List<T, A>) -> Vec<Edit<'a, T>>
+    where
+        T: PartialEq,
+    {
+        diff::diff(self.iter(), other.iter())
     }
 
-    // Mutable version of peek.
-    // Taking a mutable reference and returning a mutable reference.
-    pub fn peek_mut(&mut self) -> Option<&mut T> {
-        // NOTE: as_mut()
-        self.head.as_mut().map(|node| &mut node.elem)
+    // Unlink and return the first element `pred` is true of.
+    // `link` points at the link to change; see sorted_list.rs for why the loop looks like this.
+    pub fn remove_first(&mut self, mut pred: impl FnMut(&T) -> bool) -> Option<T> {
+        let mut link = &mut self.head;
+        while link.as_ref().is_some_and(|node| !pred(&node.elem)) {
+            link = &mut link.as_mut().unwrap().next;
+        }
+        let node = self.kind.unboxed(link.take()?);
+        *link = node.next;
+        Some(node.elem)
     }
 }
 
-impl<T> Drop for List<T> {
-    fn drop(&mut self) {
-        let mut cur_link = mem::replace(&mut self.head, None);
-        while let Some(mut boxed_node) = cur_link {
-            cur_link = mem::replace(&mut boxed_node.next, None);
+impl<T> List<T> {
+    // A list whose head is the first element of `iter`: the opposite order to pushing them one at
+    // a time. Each node goes on the end, through the link `tail` points at.
+    pub(crate) fn from_front<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = List::new();
+        let mut tail = &mut list.head;
+        for elem in iter {
+            tail = &mut tail.insert(list.kind.boxed(Node { next: None, elem })).next;
         }
+        list
+    }
+}
+
+// Conversions keep the order: the front of a deque, or the head of a persistent list, becomes the
+// top of the stack. The unsafe queue's conversions are in unsafe_single_linked_queue.rs, since
+// they reuse the nodes.
+impl<T> From<unsafe_doubly_linked_deque::List<T>> for List<T> {
+    fn from(deque: unsafe_doubly_linked_deque::List<T>) -> Self {
+        List::from_front(deque)
+    }
+}
+
+// The nodes may be shared, so the elements are cloned.
+impl<T: Clone> From<persistent_stack::List<T>> for List<T> {
+    fn from(list: persistent_stack::List<T>) -> Self {
+        List::from_front(list.iter().cloned())
     }
 }
 
@@ -84,96 +293,168 @@ every implementation of Iterator has an associated type called Item.
     IntoIter - T
     IterMut - &mut T
     Iter - &T
-*/
 
-// Tuple structs are an alternative form of struct,
-// useful for trivial wrappers around other types.
+All three are pointer_list's, written once for every pointer (IterMut and IntoIter only for
+Box, since an Rc node can be in another list too):
 
-// IntoIter<T> is a wrapper for List<T> and we are implementing the Iterator for this.
-pub struct IntoIter<T>(List<T>);
+- IntoIter<T> is a tuple struct, an alternative form of struct useful for trivial wrappers,
+  around the List, and next() is just pop().
+- Iter<'a, T> holds an Option<&'a Node>. It's generic over *some* lifetime, and iter() declares
+  a fresh one for the *exact* borrow that creates it, so &self needs to be valid as long as the
+  Iter is around (lifetime elision spells that out for us). next() is then
 
-impl<T> List<T> {
-    pub fn into_iter(self) -> IntoIter<T> {
-        IntoIter(self)
-    }
-}
+      impl<'a, T> Iterator for Iter<'a, T> {
+          type Item = &'a T;
 
-impl<T> Iterator for IntoIter<T> {
-    type Item = T;
+          fn next<'b>(&'b mut self) -> Option<&'a T> { /* stuff */ }
+      }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        // 0 is indexing the first item in the struct (?) i.e., List<T>
-        self.0.pop()
+- IterMut<'a, T> is the same with &'a mut Node, except that next() has to take() it: a mutable
+  reference isn't Copy, and take() gives us exclusive ownership of it.
+*/
+pub type IntoIter<T, A = Global> = pointer_list::IntoIter<T, BoxKind<A>>;
+pub type Iter<'a, T, A = Global> = pointer_list::Iter<'a, T, BoxKind<A>>;
+pub type IterMut<'a, T, A = Global> = pointer_list::IterMut<'a, T, BoxKind<A>>;
+
+// With the `serde` feature: a sequence, top first, the same as a Vec of the elements in iteration
+// order. Deserializing appends each element through the tail link (from_front's loop), so there's
+// no recursion however long the list is.
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::{List, Node};
+    use serde::de::{SeqAccess, Visitor};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::fmt;
+    use std::marker::PhantomData;
+
+    impl<T: Serialize> Serialize for List<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(self.iter())
+        }
     }
-}
 
-// Generic over *some* lifetime, it doesn't care
-pub struct Iter<'a, T> {
-    next: Option<&'a Node<T>>,
-}
+    struct ListVisitor<T>(PhantomData<T>);
 
-// No need for lifetimes here
-impl<T> List<T> {
-    // declare fresh lifetime for the *exact* borrow that creates the iter.
-    // Now &self needs to be valid as long as the Iter is around.
-    // But lifetime elision can occur so no need to be explicit about it.
-    pub fn iter<'a>(&'a self) -> Iter<'a, T> {
-        Iter {
-            next: self.head.as_deref(),
-        }
-    }
-}
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for ListVisitor<T> {
+        type Value = List<T>;
 
-// we do have a lifetime here, because Iter has one that we need to define
-impl<'a, T> Iterator for Iter<'a, T> {
-    type Item = &'a T;
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a sequence")
+        }
 
-    // No need to change this because the above handles it
-    fn next(&mut self) -> Option<Self::Item> {
-        self.next.map(|node| {
-            self.next = node.next.as_deref();
-            &node.elem
-        })
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<List<T>, A::Error> {
+            let mut list = List::new();
+            let mut tail = &mut list.head;
+            while let Some(elem) = seq.next_element()? {
+                tail = &mut tail.insert(list.kind.boxed(Node { next: None, elem })).next;
+            }
+            Ok(list)
+        }
     }
-    /*
-    This is basically
-
-    impl<'a, T> Iterator for Iter<'a, T> {
-        type Item = &'a T;
 
-        fn next<'b>(&'b mut self) -> Option<&'a T> { /* stuff */ }
+    impl<'de, T: Deserialize<'de>> Deserialize<'de> for List<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_seq(ListVisitor(PhantomData))
+        }
     }
-    */
 }
 
-pub struct IterMut<'a, T> {
-    next: Option<&'a mut Node<T>>,
-}
+// With the `rkyv` feature: archived as an ArchivedVec of the elements, top first, so an archive
+// (a file, an mmap) can be read in place as a plain slice with no links to chase. Deserializing
+// rebuilds the nodes, appending through the tail link like the serde impl.
+#[cfg(feature = "rkyv")]
+mod rkyv_impl {
+    use super::{List, Node};
+    use rkyv::rancor::Fallible;
+    use rkyv::ser::{Allocator, Writer};
+    use rkyv::vec::{ArchivedVec, VecResolver};
+    use rkyv::{Archive, Deserialize, Place, Serialize};
+
+    impl<T: Archive> Archive for List<T> {
+        type Archived = ArchivedVec<T::Archived>;
+        type Resolver = VecResolver;
+
+        fn resolve(&self, resolver: VecResolver, out: Place<Self::Archived>) {
+            ArchivedVec::resolve_from_len(self.len(), resolver, out);
+        }
+    }
 
-impl<T> List<T> {
-    pub fn iter_mut<'a>(&'a mut self) -> IterMut<'a, T> {
-        IterMut {
-            next: self.head.as_deref_mut(),
+    impl<T, S> Serialize<S> for List<T>
+    where
+        T: Serialize<S>,
+        S: Fallible + Allocator + Writer + ?Sized,
+    {
+        fn serialize(&self, serializer: &mut S) -> Result<VecResolver, S::Error> {
+            // serialize_from_iter needs the length up front, which only a walk can tell us.
+            let elems: Vec<&T> = self.iter().collect();
+            ArchivedVec::serialize_from_iter::<T, _, _>(elems.into_iter(), serializer)
         }
     }
-}
 
-impl<'a, T> Iterator for IterMut<'a, T> {
-    type Item = &'a mut T;
+    impl<T, D> Deserialize<List<T>, D> for ArchivedVec<T::Archived>
+    where
+        T: Archive,
+        T::Archived: Deserialize<T, D>,
+        D: Fallible + ?Sized,
+    {
+        fn deserialize(&self, deserializer: &mut D) -> Result<List<T>, D::Error> {
+            let mut list = List::new();
+            let mut tail = &mut list.head;
+            for elem in self.iter() {
+                let elem = elem.deserialize(deserializer)?;
+                tail = &mut tail.insert(list.kind.boxed(Node { next: None, elem })).next;
+            }
+            Ok(list)
+        }
+    }
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        // Need to use take() because this is mutable reference which is not Copy.
-        // take() gives us exclusive ownership of the mut reference.
-        self.next.take().map(|node| {
-            self.next = node.next.as_deref_mut();
-            &mut node.elem
-        })
+// With the `arbitrary` feature: a list built the way a program would build one, by a run of pushes,
+// pops and removals from the middle that the fuzzer's bytes choose, not just collected from
+// arbitrary elements. Each step starts with a bool (false once the bytes run out), so the list
+// stops where it should when it's one field of a bigger Arbitrary value.
+#[cfg(feature = "arbitrary")]
+mod arbitrary_impl {
+    use super::List;
+    use arbitrary::{Arbitrary, Result, Unstructured};
+
+    impl<'a, T: Arbitrary<'a>> Arbitrary<'a> for List<T> {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            let mut list = List::new();
+            let mut len = 0usize;
+            while u.arbitrary()? {
+                match u.int_in_range(0..=3u8)? {
+                    0 | 1 => {
+                        list.push(T::arbitrary(u)?);
+                        len += 1;
+                    }
+                    2 => {
+                        list.pop();
+                        len = len.saturating_sub(1);
+                    }
+                    _ if len > 0 => {
+                        let at = u.choose_index(len)?;
+                        let mut i = 0;
+                        list.remove_first(|_| {
+                            i += 1;
+                            i > at
+                        });
+                        len -= 1;
+                    }
+                    _ => {}
+                }
+            }
+            Ok(list)
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::List;
+    use crate::allocator::Bump;
+    use std::mem;
+    use std::rc::Rc;
 
     #[test]
     fn test_linked_list() {
@@ -191,7 +472,9 @@ mod test {
         assert_eq!(list.peek(), Some(&3));
         assert_eq!(list.peek_mut(), Some(&mut 3));
         assert_eq!(list.pop(), Some(3));
-        list.peek_mut().map(|value| *value = 4);
+        if let Some(value) = list.peek_mut() {
+            *value = 4;
+        }
         assert_eq!(list.pop(), Some(4));
 
         list.push(4);
@@ -204,6 +487,18 @@ mod test {
         assert_eq!(list.pop(), None);
     }
 
+    #[test]
+    fn remove_first() {
+        let mut list = List::new();
+        for x in [1, 2, 3, 2] {
+            list.push(x);
+        }
+        assert_eq!(list.remove_first(|&x| x == 2), Some(2));
+        assert_eq!(list.remove_first(|&x| x == 9), None);
+        assert_eq!(list.remove_first(|&x| x == 1), Some(1));
+        assert_eq!(list.iter().collect::<Vec<_>>(), [&3, &2]);
+    }
+
     #[test]
     fn into_iter() {
         let mut list = List::new();
@@ -243,4 +538,222 @@ mod test {
         assert_eq!(iter.next(), Some(&mut 2));
         assert_eq!(iter.next(), Some(&mut 1));
     }
+
+    #[test]
+    fn bump() {
+        let bump = Bump::with_capacity(1024);
+        let mut a = List::new_in(&bump);
+        let mut b = List::new_in(&bump);
+        for x in 0..10u64 {
+            a.push(x);
+            b.push(x * 10);
+        }
+        // Two words a node, one after the other.
+        assert_eq!(bump.used(), 20 * mem::size_of::<[u64; 2]>());
+        assert_eq!(a.iter().sum::<u64>(), 45);
+        assert_eq!(b.pop(), Some(90));
+        assert_eq!(b.len(), 9);
+        assert_eq!(b.remove_first(|&x| x == 40), Some(40));
+        b.iter_mut().for_each(|x| *x += 1);
+        assert_eq!(
+            b.into_iter().collect::<Vec<_>>(),
+            [81, 71, 61, 51, 31, 21, 11, 1]
+        );
+
+        // Popping doesn't give the memory back, so the bump fills up.
+        let small = Bump::with_capacity(3 * mem::size_of::<[u64; 2]>());
+        let mut list = List::new_in(&small);
+        for x in 0..3 {
+            assert_eq!(list.try_push(x), Ok(()));
+        }
+        assert_eq!(list.pop(), Some(2));
+        assert_eq!(list.try_push(3), Err(3));
+        assert_eq!(list.iter().collect::<Vec<_>>(), [&1, &0]);
+    }
+
+    #[test]
+    fn bump_drops() {
+        let rc = Rc::new(());
+        let bump = Bump::with_capacity(256);
+        {
+            let mut list = List::new_in(&bump);
+            for _ in 0..5 {
+                list.push(rc.clone());
+            }
+            assert_eq!(Rc::strong_count(&rc), 6);
+        }
+        assert_eq!(Rc::strong_count(&rc), 1);
+
+        // The list can own its allocator too.
+        let mut list = List::new_in(Bump::with_capacity(64));
+        list.push("owned");
+        assert_eq!(list.allocator().used(), mem::size_of::<(&str, usize)>());
+        assert_eq!(list.pop(), Some("owned"));
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn arbitrary() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let bytes: Vec<u8> = (0..4096u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let mut u = Unstructured::new(&bytes);
+        let mut lens = Vec::new();
+        while !u.is_empty() {
+            let list = List::<u16>::arbitrary(&mut u).unwrap();
+            lens.push(list.iter().count());
+        }
+        assert!(lens.len() > 1);
+        assert!(lens.iter().any(|&len| len > 1));
+        // Out of bytes: an empty list, not an error.
+        let empty = List::<u16>::arbitrary(&mut Unstructured::new(&[])).unwrap();
+        assert!(empty.peek().is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let mut list = List::new();
+        for i in 0..100_000 {
+            list.push(i);
+        }
+        let json = serde_json::to_string(&list).unwrap();
+        assert!(json.starts_with("[99999,99998,"));
+        // Long enough to overflow the stack if anything recursed.
+        let mut back: List<i32> = serde_json::from_str(&json).unwrap();
+        assert!(back.iter().eq(list.iter()));
+        assert_eq!(back.pop(), Some(99_999));
+        assert_eq!(serde_json::to_string(&List::<i32>::new()).unwrap(), "[]");
+    }
+
+    #[cfg(feature = "rkyv")]
+    #[test]
+    fn rkyv() {
+        use rkyv::rancor::Error;
+        use rkyv::vec::ArchivedVec;
+        use rkyv::Archived;
+
+        let mut list = List::new();
+        for word in ["c", "b", "a"] {
+            list.push(word.to_string());
+        }
+        let bytes = rkyv::to_bytes::<Error>(&list).unwrap();
+        // Read straight out of the bytes, as a slice.
+        let archived = rkyv::access::<ArchivedVec<Archived<String>>, Error>(&bytes).unwrap();
+        assert_eq!(archived.len(), 3);
+        assert_eq!(archived[0], "a");
+        let back: List<String> = rkyv::deserialize::<_, Error>(archived).unwrap();
+        assert!(back.iter().eq(list.iter()));
+    }
+
+    #[test]
+    fn slices_and_strs() {
+        let mut words: List<str> = List::new();
+        words.push_str("hello");
+        words.push_str("");
+        words.push_str("world");
+        words.peek_mut().unwrap().make_ascii_uppercase();
+        assert_eq!(words.iter().collect::<Vec<_>>(), ["WORLD", "", "hello"]);
+
+        let rc = Rc::new(());
+        let mut slices: List<[Rc<()>]> = List::new();
+        slices.push_slice(&[rc.clone(), rc.clone()]);
+        slices.push_slice(&[]);
+        assert_eq!(slices.iter().map(<[_]>::len).collect::<Vec<_>>(), [0, 2]);
+        assert_eq!(Rc::strong_count(&rc), 3);
+        drop(slices);
+        assert_eq!(Rc::strong_count(&rc), 1);
+
+        // A clone that panics halfway: the clones made so far are dropped, and the list is as
+        // it was.
+        struct Bomb(Rc<()>);
+        impl Clone for Bomb {
+            fn clone(&self) -> Self {
+                assert!(Rc::strong_count(&self.0) < 4, "boom");
+                Bomb(self.0.clone())
+            }
+        }
+        let bombs = [Bomb(rc.clone()), Bomb(rc.clone()), Bomb(rc.clone())];
+        let mut list: List<[Bomb]> = List::new();
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            list.push_slice(&bombs);
+        }));
+        assert!(panicked.is_err());
+        assert!(list.peek().is_none());
+        assert_eq!(Rc::strong_count(&rc), 4);
+
+        // From the list's allocator, like any other node.
+        let bump = Bump::with_capacity(256);
+        let mut on_bump: List<str, &Bump> = List::new_in(&bump);
+        on_bump.push_str("bump");
+        assert!(bump.used() > 0);
+        assert_eq!(on_bump.peek(), Some("bump"));
+    }
+
+    #[cfg(feature = "nightly")]
+    #[test]
+    fn unsized_elements() {
+        trait Shape {
+            fn area(&self) -> f64;
+        }
+
+        struct Square(f64);
+        struct Circle(f64);
+        struct Point;
+
+        impl Shape for Square {
+            fn area(&self) -> f64 {
+                self.0 * self.0
+            }
+        }
+
+        impl Shape for Circle {
+            fn area(&self) -> f64 {
+                3.0 * self.0 * self.0
+            }
+        }
+
+        impl Shape for Point {
+            fn area(&self) -> f64 {
+                0.0
+            }
+        }
+
+        let mut shapes: List<dyn Shape> = List::new();
+        shapes.push_boxed(Box::new(Square(2.0)));
+        shapes.push_boxed(Box::new(Point));
+        shapes.push_boxed(Box::new(Circle(1.0)));
+        let areas: Vec<_> = shapes.iter().map(|s| s.area()).collect();
+        assert_eq!(areas, [3.0, 0.0, 4.0]);
+        assert_eq!(shapes.pop_boxed().map(|s| s.area()), Some(3.0));
+        assert_eq!(shapes.pop_boxed().map(|s| s.area()), Some(0.0));
+
+        let mut words: List<str> = List::new();
+        words.push_boxed("hello".into());
+        words.push_boxed(String::from("world").into_boxed_str());
+        words.peek_mut().unwrap().make_ascii_uppercase();
+        assert_eq!(words.iter().collect::<Vec<_>>(), ["WORLD", "hello"]);
+        assert_eq!(words.pop_boxed().as_deref(), Some("WORLD"));
+
+        let mut slices: List<[u8]> = List::new();
+        slices.push_boxed(Box::new([1, 2, 3]));
+        slices.push_boxed(Box::new([]));
+        assert_eq!(slices.iter().map(<[u8]>::len).collect::<Vec<_>>(), [0, 3]);
+
+        // The nodes come from the list's allocator, unsized or not.
+        let bump = Bump::with_capacity(256);
+        let mut on_bump: List<str, &Bump> = List::new_in(&bump);
+        on_bump.push_boxed("bump".into());
+        assert!(bump.used() > 0);
+        assert_eq!(on_bump.pop_boxed().as_deref(), Some("bump"));
+
+        // The rest are dropped with the lists.
+        let rc = std::rc::Rc::new(());
+        let mut debugs: List<dyn std::fmt::Debug> = List::new();
+        debugs.push_boxed(Box::new(rc.clone()));
+        debugs.push_boxed(Box::new(rc.clone()));
+        assert_eq!(std::rc::Rc::strong_count(&rc), 3);
+        drop(debugs);
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+    }
 }
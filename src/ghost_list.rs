@@ -0,0 +1,372 @@
+/*
+ A GhostCell doubly linked list
+
+There have been two doubly linked deques so far, at opposite ends of a trade-off:
+
+- doubly_linked_deque.rs: Rc<RefCell<Node>>. Safe, but every access is a runtime borrow check,
+  and peek can't hand out a plain &T, only a Ref guard.
+- unsafe_doubly_linked_deque.rs: raw pointers. Fast and flexible, and all of it unsafe.
+
+The problem in both cases is the same: a node is reachable from two places (its neighbours),
+so the compiler can't see that a &mut to it is unique. RefCell checks that at runtime, node by
+node. GhostCell (Yanovski et al., "GhostCell: Separating Permissions from Data in Rust", 2021)
+checks it at compile time instead, by moving the permission out of the nodes into a single
+token:
+
+- Every GhostCell<'id, T> is branded with a lifetime 'id, and so is one GhostToken<'id>.
+- cell.borrow(&token) gives a &T; cell.borrow_mut(&mut token) gives a &mut T.
+- So borrowing the token mutably, which the borrow checker makes sure happens in one place at a
+  time, is the proof that no other cell of that brand is borrowed at all.
+- The brand comes from GhostToken::scope(|token| ...): the closure has to work for *any* lifetime
+  'id, so each call's token is a type of its own, and can't unlock cells from another.
+
+The permission is for the whole list at once, not per node: coarser than RefCell, but free at
+runtime, and front() returns a real &T that lives as long as the borrow of the token.
+
+The list itself has no unsafe code and no RefCell. Links are Rc<GhostCell<Node>> forwards and
+Weak backwards, so there are no cycles for Rc to leak (the list's own head and tail are both Rc,
+so front and back can lend out references). The cell's two unsafe lines are the
+whole trusted base, and they're the part the paper proves sound.
+*/
+
+use crate::mem_usage::MemUsage;
+use crate::validate::{self, Invalid};
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::rc::{Rc, Weak};
+
+/* ---- the cell ---- */
+
+// fn(&'id ()) -> &'id () makes 'id invariant: it can't be stretched or shrunk to match another
+// brand.
+type Brand<'id> = PhantomData<fn(&'id ()) -> &'id ()>;
+
+pub struct GhostToken<'id> {
+    _brand: Brand<'id>,
+}
+
+impl GhostToken<'_> {
+    // Run `f` with a token of a brand new brand. (The paper calls this GhostToken::new, but it
+    // returns whatever `f` does, not a token.)
+    pub fn scope<R>(f: impl for<'new> FnOnce(GhostToken<'new>) -> R) -> R {
+        f(GhostToken {
+            _brand: PhantomData,
+        })
+    }
+}
+
+pub struct GhostCell<'id, T> {
+    _brand: Brand<'id>,
+    value: UnsafeCell<T>,
+}
+
+impl<'id, T> GhostCell<'id, T> {
+    pub fn new(value: T) -> Self {
+        GhostCell {
+            _brand: PhantomData,
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    pub fn borrow<'a>(&'a self, _token: &'a GhostToken<'id>) -> &'a T {
+        // Safety: a &mut T from borrow_mut needs the token borrowed mutably, which can't happen
+        // while we hold it shared.
+        unsafe { &*self.value.get() }
+    }
+
+    pub fn borrow_mut<'a>(&'a self, _token: &'a mut GhostToken<'id>) -> &'a mut T {
+        // Safety: there's only one token of this brand, and we have it exclusively, so no other
+        // borrow of any cell of this brand can exist.
+        unsafe { &mut *self.value.get() }
+    }
+}
+
+/* ---- the list ---- */
+
+pub struct GhostList<'id, T> {
+    head: Link<'id, T>,
+    tail: Link<'id, T>,
+    len: usize,
+}
+
+type NodeRef<'id, T> = Rc<GhostCell<'id, Node<'id, T>>>;
+type Link<'id, T> = Option<NodeRef<'id, T>>;
+type WeakLink<'id, T> = Weak<GhostCell<'id, Node<'id, T>>>;
+
+struct Node<'id, T> {
+    elem: T,
+    next: Link<'id, T>,
+    prev: Option<WeakLink<'id, T>>,
+}
+
+impl<'id, T> GhostList<'id, T> {
+    pub fn new() -> Self {
+        GhostList {
+            head: None,
+            tail: None,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // See mem_usage.rs. No token needed: it only counts.
+    pub fn mem_usage(&self) -> MemUsage {
+        MemUsage::rc_nodes::<GhostCell<'id, Node<'id, T>>, T>(self.len)
+    }
+
+    // `len` nodes from head, the last of them tail, each prev pointing at the node before, and no
+    // node with more strong references than its own link (and tail's, for the last one). It takes
+    // the token like any other read of the nodes. See validate.rs.
+    pub fn validate(&self, token: &GhostToken<'id>) -> Result<(), Invalid> {
+        let (mut prev, mut cur, mut at) = (None, self.head.as_ref(), 0);
+        while let Some(node) = cur {
+            if at == self.len {
+                return Err(Invalid::Len {
+                    stored: self.len,
+                    counted: at + 1,
+                });
+            }
+            let is_tail = self
+                .tail
+                .as_ref()
+                .is_some_and(|tail| Rc::ptr_eq(tail, node));
+            if Rc::strong_count(node) > 1 + usize::from(is_tail) {
+                return Err(Invalid::Shared { at });
+            }
+            let node_ref = node.borrow(token);
+            if node_ref.prev.as_ref().map(Weak::as_ptr) != prev.map(Rc::as_ptr) {
+                return Err(Invalid::Link { at });
+            }
+            (prev, cur, at) = (Some(node), node_ref.next.as_ref(), at + 1);
+        }
+        if at != self.len {
+            return Err(Invalid::Len {
+                stored: self.len,
+                counted: at,
+            });
+        }
+        if prev.map(Rc::as_ptr) != self.tail.as_ref().map(Rc::as_ptr) {
+            return Err(Invalid::Ends);
+        }
+        Ok(())
+    }
+
+    pub fn push_front(&mut self, elem: T, token: &mut GhostToken<'id>) {
+        let new = Rc::new(GhostCell::new(Node {
+            elem,
+            next: None,
+            prev: None,
+        }));
+        match self.head.take() {
+            Some(old) => {
+                old.borrow_mut(token).prev = Some(Rc::downgrade(&new));
+                new.borrow_mut(token).next = Some(old);
+            }
+            None => self.tail = Some(new.clone()),
+        }
+        self.head = Some(new);
+        self.len += 1;
+        validate::check!(self, token);
+    }
+
+    pub fn push_back(&mut self, elem: T, token: &mut GhostToken<'id>) {
+        let old = self.tail.take();
+        let new = Rc::new(GhostCell::new(Node {
+            elem,
+            next: None,
+            prev: old.as_ref().map(Rc::downgrade),
+        }));
+        self.tail = Some(new.clone());
+        match old {
+            Some(old) => old.borrow_mut(token).next = Some(new),
+            None => self.head = Some(new),
+        }
+        self.len += 1;
+        validate::check!(self, token);
+    }
+
+    // Once it's unlinked, the node's last strong reference is the one passed in.
+    fn into_elem(node: NodeRef<'id, T>) -> T {
+        match Rc::try_unwrap(node) {
+            Ok(cell) => cell.into_inner().elem,
+            Err(_) => unreachable!("unlinked node is still referenced"),
+        }
+    }
+
+    pub fn pop_front(&mut self, token: &mut GhostToken<'id>) -> Option<T> {
+        let node = self.head.take()?;
+        self.head = node.borrow_mut(token).next.take();
+        match &self.head {
+            Some(next) => next.borrow_mut(token).prev = None,
+            None => self.tail = None,
+        }
+        self.len -= 1;
+        validate::check!(self, token);
+        Some(Self::into_elem(node))
+    }
+
+    pub fn pop_back(&mut self, token: &mut GhostToken<'id>) -> Option<T> {
+        let node = self.tail.take()?;
+        match node
+            .borrow_mut(token)
+            .prev
+            .take()
+            .and_then(|prev| prev.upgrade())
+        {
+            Some(prev) => {
+                prev.borrow_mut(token).next = None;
+                self.tail = Some(prev);
+            }
+            None => self.head = None,
+        }
+        self.len -= 1;
+        validate::check!(self, token);
+        Some(Self::into_elem(node))
+    }
+
+    pub fn front<'a>(&'a self, token: &'a GhostToken<'id>) -> Option<&'a T> {
+        self.head.as_ref().map(|node| &node.borrow(token).elem)
+    }
+
+    pub fn front_mut<'a>(&'a self, token: &'a mut GhostToken<'id>) -> Option<&'a mut T> {
+        self.head
+            .as_ref()
+            .map(|node| &mut node.borrow_mut(token).elem)
+    }
+
+    pub fn back<'a>(&'a self, token: &'a GhostToken<'id>) -> Option<&'a T> {
+        self.tail.as_ref().map(|node| &node.borrow(token).elem)
+    }
+
+    pub fn back_mut<'a>(&'a self, token: &'a mut GhostToken<'id>) -> Option<&'a mut T> {
+        self.tail
+            .as_ref()
+            .map(|node| &mut node.borrow_mut(token).elem)
+    }
+
+    pub fn iter<'a>(&'a self, token: &'a GhostToken<'id>) -> Iter<'a, 'id, T> {
+        Iter {
+            next: self.head.as_deref(),
+            len: self.len,
+            token,
+        }
+    }
+
+    // &mut to each element in turn. An IterMut would have to hold the token mutably and hand
+    // out many &mut at once, which is exactly what the token is there to prevent.
+    pub fn for_each_mut(&self, token: &mut GhostToken<'id>, mut f: impl FnMut(&mut T)) {
+        let mut cur = self.head.clone();
+        while let Some(node) = cur {
+            let node = node.borrow_mut(token);
+            f(&mut node.elem);
+            cur = node.next.clone();
+        }
+    }
+}
+
+impl<T> Default for GhostList<'_, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Dropping can't borrow the token, but it doesn't need to: each node is owned outright once its
+// predecessor is gone, so it can be unwrapped and taken apart without one. Iterative, so a long
+// list doesn't recurse.
+impl<T> Drop for GhostList<'_, T> {
+    fn drop(&mut self) {
+        self.tail = None;
+        let mut cur = self.head.take();
+        while let Some(node) = cur {
+            cur = match Rc::try_unwrap(node) {
+                Ok(cell) => cell.into_inner().next,
+                Err(_) => None,
+            };
+        }
+    }
+}
+
+pub struct Iter<'a, 'id, T> {
+    next: Option<&'a GhostCell<'id, Node<'id, T>>>,
+    len: usize,
+    token: &'a GhostToken<'id>,
+}
+
+impl<'a, T> Iterator for Iter<'a, '_, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.next?.borrow(self.token);
+        self.next = node.next.as_deref();
+        self.len -= 1;
+        Some(&node.elem)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, '_, T> {}
+
+#[cfg(test)]
+mod test {
+    use super::{GhostList, GhostToken};
+
+    #[test]
+    fn basics() {
+        GhostToken::scope(|mut token| {
+            let mut list = GhostList::new();
+            assert_eq!(list.pop_front(&mut token), None);
+            assert_eq!(list.pop_back(&mut token), None);
+
+            list.push_back(2, &mut token);
+            list.push_back(3, &mut token);
+            list.push_front(1, &mut token);
+            assert_eq!(list.iter(&token).copied().collect::<Vec<_>>(), [1, 2, 3]);
+            assert_eq!(list.front(&token), Some(&1));
+            assert_eq!(list.back(&token), Some(&3));
+            assert_eq!(list.len(), 3);
+
+            // Plain references, held side by side.
+            let (a, b) = (
+                list.front(&token).unwrap(),
+                list.iter(&token).nth(1).unwrap(),
+            );
+            assert_eq!(a + b, 3);
+
+            *list.front_mut(&mut token).unwrap() = 10;
+            *list.back_mut(&mut token).unwrap() = 30;
+            list.for_each_mut(&mut token, |x| *x *= 2);
+            assert_eq!(list.pop_back(&mut token), Some(60));
+            assert_eq!(list.pop_front(&mut token), Some(20));
+            assert_eq!(list.pop_back(&mut token), Some(4));
+            assert!(list.is_empty());
+            list.push_front(5, &mut token);
+            assert_eq!(list.pop_back(&mut token), Some(5));
+        });
+    }
+
+    #[test]
+    #[cfg_attr(feature = "debug-validate", ignore = "quadratic under debug-validate")]
+    fn drops() {
+        GhostToken::scope(|mut token| {
+            let mut list = GhostList::new();
+            for i in 0..100_000 {
+                list.push_back(i.to_string(), &mut token);
+            }
+            assert_eq!(list.pop_back(&mut token).as_deref(), Some("99999"));
+        });
+    }
+}
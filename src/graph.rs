@@ -0,0 +1,244 @@
+/*
+ An adjacency-list graph
+
+Every list in this crate so far has been the data structure itself. Here they're the parts of
+one: a directed graph stored as one linked list per vertex, holding the vertices its edges go to.
+
+vertex 0: -> 2 -> 1
+vertex 1: -> 3                 edges 0->1, 0->2, 1->3, 2->3, 3->1
+vertex 2: -> 3
+vertex 3: -> 1
+
+That's the classic sparse-graph representation: O(V + E) space, where an adjacency matrix takes
+O(V^2) whether there are edges or not. Adding an edge is a push at the head of the list. Removing
+one, or checking whether it's there, scans one vertex's list, which is short in a sparse graph.
+Edges are a set, so add_edge won't add the same edge twice. (For an undirected graph, add each
+edge both ways.)
+
+Vertices are numbered from 0 in the order they're added. neighbours() yields a vertex's edges
+newest first, since that's the order the list keeps them in.
+
+The traversals use the crate's lists as well:
+
+- bfs() keeps its frontier in unsafe_single_linked_queue::List, a FIFO queue, so it visits
+  vertices in order of distance from the start.
+- dfs() keeps a stack in generic_and_iterators::List instead, so it follows each path as deep as
+  it goes before backing up.
+
+Both are iterators yielding each vertex reachable from the start exactly once, and both mark
+vertices as seen, so cycles are fine.
+*/
+
+use crate::generic_and_iterators::{self, List};
+use crate::unsafe_single_linked_queue::List as Queue;
+use std::fmt;
+
+pub struct Graph {
+    adjacency: Vec<List<usize>>,
+    edges: usize,
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        Self::with_vertices(0)
+    }
+
+    pub fn with_vertices(count: usize) -> Self {
+        Graph {
+            adjacency: (0..count).map(|_| List::new()).collect(),
+            edges: 0,
+        }
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.adjacency.len()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.edges
+    }
+
+    pub fn add_vertex(&mut self) -> usize {
+        self.adjacency.push(List::new());
+        self.adjacency.len() - 1
+    }
+
+    fn check(&self, v: usize) {
+        assert!(v < self.adjacency.len(), "no vertex {v}");
+    }
+
+    pub fn has_edge(&self, from: usize, to: usize) -> bool {
+        self.neighbours(from).any(|v| v == to)
+    }
+
+    // False if the edge was already there.
+    pub fn add_edge(&mut self, from: usize, to: usize) -> bool {
+        self.check(to);
+        if self.has_edge(from, to) {
+            return false;
+        }
+        self.adjacency[from].push(to);
+        self.edges += 1;
+        true
+    }
+
+    // False if there was no such edge.
+    pub fn remove_edge(&mut self, from: usize, to: usize) -> bool {
+        self.check(from);
+        let removed = self.adjacency[from].remove_first(|&v| v == to).is_some();
+        self.edges -= removed as usize;
+        removed
+    }
+
+    pub fn neighbours(&self, v: usize) -> Neighbours<'_> {
+        self.check(v);
+        Neighbours(self.adjacency[v].iter())
+    }
+
+    pub fn bfs(&self, start: usize) -> Bfs<'_> {
+        self.check(start);
+        let mut seen = vec![false; self.vertex_count()];
+        seen[start] = true;
+        let mut queue = Queue::new();
+        queue.push(start);
+        Bfs {
+            graph: self,
+            queue,
+            seen,
+        }
+    }
+
+    pub fn dfs(&self, start: usize) -> Dfs<'_> {
+        self.check(start);
+        let mut stack = List::new();
+        stack.push(start);
+        Dfs {
+            graph: self,
+            stack,
+            seen: vec![false; self.vertex_count()],
+        }
+    }
+}
+
+impl Default for Graph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Graph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries((0..self.vertex_count()).map(|v| (v, self.neighbours(v).collect::<Vec<_>>())))
+            .finish()
+    }
+}
+
+pub struct Neighbours<'a>(generic_and_iterators::Iter<'a, usize>);
+
+impl Iterator for Neighbours<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        self.0.next().copied()
+    }
+}
+
+pub struct Bfs<'a> {
+    graph: &'a Graph,
+    queue: Queue<usize>,
+    // Marked when queued, so nothing is queued twice.
+    seen: Vec<bool>,
+}
+
+impl Iterator for Bfs<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let v = self.queue.pop()?;
+        for next in self.graph.neighbours(v) {
+            if !self.seen[next] {
+                self.seen[next] = true;
+                self.queue.push(next);
+            }
+        }
+        Some(v)
+    }
+}
+
+pub struct Dfs<'a> {
+    graph: &'a Graph,
+    stack: List<usize>,
+    // Marked when visited: a vertex can be on the stack more than once, if it's reached by
+    // another path before its turn comes, and only the first one off the stack counts.
+    seen: Vec<bool>,
+}
+
+impl Iterator for Dfs<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            let v = self.stack.pop()?;
+            if self.seen[v] {
+                continue;
+            }
+            self.seen[v] = true;
+            for next in self.graph.neighbours(v) {
+                if !self.seen[next] {
+                    self.stack.push(next);
+                }
+            }
+            return Some(v);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Graph;
+
+    // 0 -> 1 -> 3 -> 4 -> 1, 0 -> 2 -> 3, and 5 -> 0, which nothing reaches.
+    fn graph() -> Graph {
+        let mut graph = Graph::with_vertices(6);
+        for (from, to) in [(0, 1), (0, 2), (1, 3), (2, 3), (3, 4), (4, 1), (5, 0)] {
+            assert!(graph.add_edge(from, to));
+        }
+        graph
+    }
+
+    #[test]
+    fn edges() {
+        let mut graph = graph();
+        assert_eq!(graph.edge_count(), 7);
+        assert!(!graph.add_edge(0, 1));
+        assert!(graph.has_edge(4, 1));
+        assert!(!graph.has_edge(1, 4));
+        assert_eq!(graph.neighbours(0).collect::<Vec<_>>(), [2, 1]);
+
+        assert!(graph.remove_edge(0, 2));
+        assert!(!graph.remove_edge(0, 2));
+        assert_eq!(graph.edge_count(), 6);
+        let v = graph.add_vertex();
+        graph.add_edge(v, v);
+        assert_eq!(
+            format!("{:?}", graph),
+            "{0: [1], 1: [3], 2: [3], 3: [4], 4: [1], 5: [0], 6: [6]}"
+        );
+    }
+
+    #[test]
+    fn traversals() {
+        let graph = graph();
+        assert_eq!(graph.bfs(0).collect::<Vec<_>>(), [0, 2, 1, 3, 4]);
+        assert_eq!(graph.dfs(0).collect::<Vec<_>>(), [0, 1, 3, 4, 2]);
+        assert_eq!(graph.bfs(5).count(), 6);
+        assert_eq!(graph.dfs(3).collect::<Vec<_>>(), [3, 4, 1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "no vertex 9")]
+    fn missing_vertex() {
+        graph().add_edge(0, 9);
+    }
+}
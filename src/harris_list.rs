@@ -0,0 +1,328 @@
+/*
+ A lock-free sorted set on a linked list (Harris, 2001; with Michael's unlinking, 2002)
+
+The list is kept sorted, and any number of threads can insert, remove and look up keys through a
+shared reference. Inserting is just the Treiber-stack trick done in the middle of the list: find
+the spot, point the new node at its successor, and CAS the predecessor's `next` over to it.
+
+Removing is where it gets subtle. Unlinking `B` from `A -> B -> C` with one CAS on `A.next` isn't
+enough, because someone may be inserting `X` after `B` at the same moment:
+
+A -> B -> X -> C      (insert CASes B.next, from C to X)
+A ------> C           (remove CASes A.next, from B to C)
+
+Both CASes succeed and X is lost. Harris's fix is to delete in two steps:
+
+1. Logically delete B by setting a mark bit in B's own `next` pointer. A marked pointer never
+   changes again, so an insert after B now fails its CAS and retries.
+2. Physically unlink B by CASing A.next from B to C. Anyone who walks past a marked node does
+   this for it, so a remove doesn't have to succeed at step 2 itself.
+
+Nodes are aligned to at least 2 bytes, so the lowest bit of a node pointer is always free to use
+as the mark: `next` is a tagged_ptr::AtomicTaggedPtr, with tag 1 for marked. Unlinked nodes go to
+the epoch collector, since other threads may still be walking over them.
+*/
+
+use crate::epoch::{self, Guard};
+use crate::sync::atomic::Ordering;
+use crate::tagged_ptr::{AtomicTaggedPtr, TaggedPtr};
+use std::marker::PhantomData;
+
+pub struct HarrisList<T> {
+    // Never marked; it's a tagged pointer only so that search() can treat it like any `next`.
+    head: AtomicTaggedPtr<Node<T>>,
+    _boo: PhantomData<T>,
+}
+
+struct Node<T> {
+    key: T,
+    // Tagged MARK: this node has been removed.
+    next: AtomicTaggedPtr<Node<T>>,
+}
+
+const MARK: usize = 1;
+
+fn is_marked<T>(ptr: TaggedPtr<Node<T>>) -> bool {
+    ptr.tag() == MARK
+}
+
+// Keys are shared between threads (contains() reads them from anywhere) and dropped by whichever
+// thread ends up freeing the node.
+unsafe impl<T: Send + Sync> Send for HarrisList<T> {}
+unsafe impl<T: Send + Sync> Sync for HarrisList<T> {}
+
+// Unlike the Treiber stack and the Michael-Scott queue, a removed node's key can't be moved out
+// before the node is retired: other threads may still be comparing against it. So the node goes
+// to the collector with its key, and the key is dropped by whichever thread frees it, whenever
+// that is, possibly after the list itself is gone. Hence Send + 'static.
+impl<T: Ord + Send + 'static> HarrisList<T> {
+    pub fn new() -> Self {
+        HarrisList {
+            head: AtomicTaggedPtr::null(),
+            _boo: PhantomData,
+        }
+    }
+
+    // Find the first node whose key is >= `key`, and the link pointing at it. Marked nodes found
+    // along the way get unlinked.
+    //
+    // Safety: the caller stays pinned (`guard`) for as long as it uses the result.
+    unsafe fn search(
+        &self,
+        key: &T,
+        guard: &Guard,
+    ) -> (*const AtomicTaggedPtr<Node<T>>, *mut Node<T>) {
+        'retry: loop {
+            let mut prev: *const AtomicTaggedPtr<Node<T>> = &self.head;
+            let mut curr = (*prev).load(Ordering::Acquire).ptr();
+            loop {
+                if curr.is_null() {
+                    return (prev, curr);
+                }
+                let next = (*curr).next.load(Ordering::Acquire);
+                if is_marked(next) {
+                    // `curr` is already removed: help unlink it. If `prev` changed (or was itself
+                    // marked), our view is stale and we start over.
+                    let succ = next.with_tag(0);
+                    match (*prev).compare_exchange(
+                        curr.into(),
+                        succ,
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                    ) {
+                        Ok(_) => {
+                            guard.defer_destroy(curr);
+                            curr = succ.ptr();
+                        }
+                        Err(_) => continue 'retry,
+                    }
+                } else {
+                    if (*curr).key >= *key {
+                        return (prev, curr);
+                    }
+                    prev = &(*curr).next;
+                    curr = next.ptr();
+                }
+            }
+        }
+    }
+
+    // Returns false (and drops `key`) if it was already in the set.
+    pub fn insert(&self, key: T) -> bool {
+        let guard = epoch::pin();
+        let node = Box::into_raw(Box::new(Node {
+            key,
+            next: AtomicTaggedPtr::null(),
+        }));
+        unsafe {
+            loop {
+                let (prev, curr) = self.search(&(*node).key, &guard);
+                if !curr.is_null() && (*curr).key == (*node).key {
+                    // Never published, so it's still ours to free right away.
+                    drop(Box::from_raw(node));
+                    return false;
+                }
+                (*node).next.store(curr.into(), Ordering::Relaxed);
+                if (*prev)
+                    .compare_exchange(
+                        curr.into(),
+                        node.into(),
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+                {
+                    return true;
+                }
+            }
+        }
+    }
+
+    pub fn remove(&self, key: &T) -> bool {
+        let guard = epoch::pin();
+        unsafe {
+            loop {
+                let (prev, curr) = self.search(key, &guard);
+                if curr.is_null() || (*curr).key != *key {
+                    return false;
+                }
+                let next = (*curr).next.load(Ordering::Acquire);
+                if is_marked(next) {
+                    // Someone else is removing it; search again, which will also unlink it.
+                    continue;
+                }
+                // Step 1: the logical delete. Whoever sets the mark is the one who removed it.
+                if (*curr)
+                    .next
+                    .compare_exchange(
+                        next,
+                        next.with_tag(MARK),
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                    )
+                    .is_err()
+                {
+                    continue;
+                }
+                // Step 2: try to unlink it ourselves. If that fails, a later search will.
+                if (*prev)
+                    .compare_exchange(curr.into(), next, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    guard.defer_destroy(curr);
+                } else {
+                    self.search(key, &guard);
+                }
+                return true;
+            }
+        }
+    }
+
+    // Never writes anything, never retries: just walk past the smaller keys.
+    pub fn contains(&self, key: &T) -> bool {
+        let _guard = epoch::pin();
+        unsafe {
+            let mut curr = self.head.load(Ordering::Acquire).ptr();
+            while !curr.is_null() && (*curr).key < *key {
+                curr = (*curr).next.load(Ordering::Acquire).ptr();
+            }
+            !curr.is_null()
+                && (*curr).key == *key
+                && !is_marked((*curr).next.load(Ordering::Acquire))
+        }
+    }
+
+    // Just a snapshot: other threads may insert or remove right after.
+    pub fn is_empty(&self) -> bool {
+        let _guard = epoch::pin();
+        let mut curr = self.head.load(Ordering::Acquire).ptr();
+        unsafe {
+            while !curr.is_null() {
+                let next = (*curr).next.load(Ordering::Acquire);
+                if !is_marked(next) {
+                    return false;
+                }
+                curr = next.ptr();
+            }
+        }
+        true
+    }
+
+    // The keys present during the walk, in order. Keys inserted or removed concurrently may or
+    // may not show up.
+    pub fn to_vec(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        let _guard = epoch::pin();
+        let mut keys = Vec::new();
+        let mut curr = self.head.load(Ordering::Acquire).ptr();
+        unsafe {
+            while !curr.is_null() {
+                let next = (*curr).next.load(Ordering::Acquire);
+                if !is_marked(next) {
+                    keys.push((*curr).key.clone());
+                }
+                curr = next.ptr();
+            }
+        }
+        keys
+    }
+}
+
+impl<T: Ord + Send + 'static> Default for HarrisList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for HarrisList<T> {
+    fn drop(&mut self) {
+        // Unlinked nodes belong to the epoch collector; everything still linked (marked or not)
+        // is ours.
+        let mut cur = self.head.load(Ordering::Relaxed).ptr();
+        while !cur.is_null() {
+            let node = unsafe { Box::from_raw(cur) };
+            cur = node.next.load(Ordering::Relaxed).ptr();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HarrisList;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn basics() {
+        let set = HarrisList::new();
+        assert!(set.is_empty());
+        assert!(!set.contains(&1));
+
+        assert!(set.insert(3));
+        assert!(set.insert(1));
+        assert!(set.insert(2));
+        assert!(!set.insert(2));
+        assert_eq!(set.to_vec(), vec![1, 2, 3]);
+
+        assert!(set.contains(&2));
+        assert!(set.remove(&2));
+        assert!(!set.remove(&2));
+        assert!(!set.contains(&2));
+        assert!(!set.remove(&10));
+        assert_eq!(set.to_vec(), vec![1, 3]);
+
+        assert!(set.remove(&1));
+        assert!(set.remove(&3));
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn drop_remaining() {
+        let set = HarrisList::new();
+        for word in ["b", "a", "c"] {
+            set.insert(String::from(word));
+        }
+        set.remove(&String::from("a"));
+        drop(set);
+    }
+
+    #[test]
+    fn threads() {
+        let set = Arc::new(HarrisList::new());
+        // Every thread inserts the same keys and removes its own share of them, so inserts and
+        // removes on the same key race all the time.
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let set = set.clone();
+                thread::spawn(move || {
+                    for i in 0..200 {
+                        set.insert(i);
+                    }
+                    for i in (0..200).filter(|i| i % 4 == t) {
+                        if i % 8 < 4 {
+                            set.remove(&i);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        // A removed key may have been re-inserted by a slower thread, so only the survivors are
+        // certain: the ones nobody removes.
+        let keys = set.to_vec();
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        for i in (0..200).filter(|i| i % 8 >= 4) {
+            assert!(set.contains(&i));
+        }
+
+        for i in 0..200 {
+            set.remove(&i);
+        }
+        assert!(set.is_empty());
+    }
+}
@@ -0,0 +1,275 @@
+/*
+ A heapless list
+
+Every list so far has allocated its nodes: Box::new per push, or a Vec for the arena lists. On a
+microcontroller with no allocator there's no Box to call, but a linked list still works fine. The
+nodes just have to live somewhere the caller provides, and the list only links them together.
+
+    let mut a = Node::new(1);
+    let mut b = Node::new(2);
+    let mut list = HeaplessList::new();
+    list.push(&mut a);
+    list.push(&mut b);                    list.head -> b -> a
+
+The links are plain &'a mut references, so this is all safe code, and the borrow checker does the
+bookkeeping that the unsafe lists do by hand:
+
+- A node can only be pushed through a &'a mut, and the list holds that borrow until the node is
+  popped, so nothing else can touch a node while it's linked, and it can't be in two lists.
+- The storage has to outlive 'a, so the list can't outlive its nodes.
+- pop hands the &'a mut back, so a node can be read, or pushed onto another list with the same 'a.
+  Its storage stays borrowed for all of 'a, though: that's the price of &'a mut Node<'a, T>,
+  where the node's own type mentions the lifetime of the borrow of it.
+
+Nodes can come from locals, as above, or from an array: from_nodes links a whole
+&'a mut [Node<'a, T>] at once, which is the usual way to carve a fixed pool out of a static or a
+stack frame.
+
+Frame is the other classic heapless list, the one that lives on the call stack. Each recursive
+call pushes a frame that points at its caller's, `Option<&'a Frame<'a, T>>`, and the frame is
+popped just by returning. It can only be read, never changed, but it's a handy way to give a
+recursive search the whole path it took to get where it is.
+*/
+
+use crate::validate::{self, Invalid};
+
+pub struct Node<'a, T> {
+    elem: T,
+    next: Option<&'a mut Node<'a, T>>,
+}
+
+impl<T> Node<'_, T> {
+    pub const fn new(elem: T) -> Self {
+        Node { elem, next: None }
+    }
+
+    pub fn elem(&self) -> &T {
+        &self.elem
+    }
+
+    pub fn elem_mut(&mut self) -> &mut T {
+        &mut self.elem
+    }
+}
+
+pub struct HeaplessList<'a, T> {
+    head: Option<&'a mut Node<'a, T>>,
+    len: usize,
+}
+
+impl<'a, T> HeaplessList<'a, T> {
+    pub const fn new() -> Self {
+        HeaplessList { head: None, len: 0 }
+    }
+
+    // A list of every node in `nodes`, in the same order.
+    pub fn from_nodes(nodes: &'a mut [Node<'a, T>]) -> Self {
+        let mut list = Self::new();
+        for node in nodes.iter_mut().rev() {
+            list.push(node);
+        }
+        list
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    // The borrow checker keeps the links honest, so all there is to check is `len`. See
+    // validate.rs.
+    pub fn validate(&self) -> Result<(), Invalid> {
+        let counted = self.iter().take(self.len + 1).count();
+        if counted != self.len {
+            return Err(Invalid::Len {
+                stored: self.len,
+                counted,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn push(&mut self, node: &'a mut Node<'a, T>) {
+        node.next = self.head.take();
+        self.head = Some(node);
+        self.len += 1;
+        validate::check!(self);
+    }
+
+    // The node comes back unlinked, ready to go on another list.
+    pub fn pop(&mut self) -> Option<&'a mut Node<'a, T>> {
+        let node = self.head.take()?;
+        self.head = node.next.take();
+        self.len -= 1;
+        validate::check!(self);
+        Some(node)
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.head.as_ref().map(|node| &node.elem)
+    }
+
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        self.head.as_mut().map(|node| &mut node.elem)
+    }
+
+    pub fn reverse(&mut self) {
+        let mut rest = self.head.take();
+        while let Some(node) = rest {
+            rest = node.next.take();
+            node.next = self.head.take();
+            self.head = Some(node);
+        }
+        validate::check!(self);
+    }
+
+    pub fn iter(&self) -> Iter<'_, 'a, T> {
+        Iter {
+            next: self.head.as_deref(),
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, 'a, T> {
+        IterMut {
+            next: self.head.as_deref_mut(),
+        }
+    }
+}
+
+impl<T> Default for HeaplessList<'_, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Iter<'b, 'a, T> {
+    next: Option<&'b Node<'a, T>>,
+}
+
+impl<'b, T> Iterator for Iter<'b, '_, T> {
+    type Item = &'b T;
+
+    fn next(&mut self) -> Option<&'b T> {
+        let node = self.next?;
+        self.next = node.next.as_deref();
+        Some(&node.elem)
+    }
+}
+
+pub struct IterMut<'b, 'a, T> {
+    next: Option<&'b mut Node<'a, T>>,
+}
+
+impl<'b, T> Iterator for IterMut<'b, '_, T> {
+    type Item = &'b mut T;
+
+    fn next(&mut self) -> Option<&'b mut T> {
+        let node = self.next.take()?;
+        self.next = node.next.as_deref_mut();
+        Some(&mut node.elem)
+    }
+}
+
+/* ---- on the call stack ---- */
+
+pub struct Frame<'a, T> {
+    elem: T,
+    prev: Option<&'a Frame<'a, T>>,
+}
+
+impl<'a, T> Frame<'a, T> {
+    // Call `f` with a list of just `elem`.
+    pub fn root<R>(elem: T, f: impl FnOnce(&Frame<'_, T>) -> R) -> R {
+        f(&Frame { elem, prev: None })
+    }
+
+    // Call `f` with `elem` pushed on top of this list. It's popped again when `f` returns.
+    pub fn with<R>(&self, elem: T, f: impl FnOnce(&Frame<'_, T>) -> R) -> R {
+        f(&Frame {
+            elem,
+            prev: Some(self),
+        })
+    }
+
+    pub fn elem(&self) -> &T {
+        &self.elem
+    }
+
+    // Newest first.
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        std::iter::successors(Some(self), |frame| frame.prev).map(|frame| &frame.elem)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Frame, HeaplessList, Node};
+
+    #[test]
+    fn basics() {
+        let mut a = Node::new(1);
+        let mut b = Node::new(2);
+        let mut c = Node::new(String::from("not Copy"));
+        let mut list = HeaplessList::new();
+        assert!(list.pop().is_none());
+        list.push(&mut a);
+        list.push(&mut b);
+        assert_eq!(list.peek(), Some(&2));
+        *list.peek_mut().unwrap() = 20;
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), [20, 1]);
+
+        // Move a node from one list to another.
+        let mut other = HeaplessList::new();
+        let node = list.pop().unwrap();
+        *node.elem_mut() += 1;
+        other.push(node);
+        assert_eq!(other.peek(), Some(&21));
+        assert_eq!((list.len(), other.len()), (1, 1));
+        assert_eq!(list.pop().map(|node| *node.elem()), Some(1));
+        assert!(list.is_empty());
+
+        let mut strings = HeaplessList::new();
+        strings.push(&mut c);
+        assert_eq!(strings.peek().map(String::as_str), Some("not Copy"));
+    }
+
+    #[test]
+    fn from_array() {
+        let mut pool = [Node::new(0), Node::new(1), Node::new(2), Node::new(3)];
+        let mut list = HeaplessList::from_nodes(&mut pool);
+        assert_eq!(list.len(), 4);
+        list.iter_mut().for_each(|x| *x *= 10);
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), [0, 10, 20, 30]);
+        list.reverse();
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), [30, 20, 10, 0]);
+        assert_eq!(list.pop().map(|node| *node.elem()), Some(30));
+    }
+
+    // Every path from `v` to a leaf of the tree 0 -> {1, 2}, 1 -> {3, 4}, found without
+    // allocating anything but the results.
+    fn paths(frame: &Frame<'_, usize>, out: &mut Vec<Vec<usize>>) {
+        let children: &[usize] = match frame.elem() {
+            0 => &[1, 2],
+            1 => &[3, 4],
+            _ => &[],
+        };
+        if children.is_empty() {
+            let mut path: Vec<_> = frame.iter().copied().collect();
+            path.reverse();
+            out.push(path);
+        }
+        for &child in children {
+            frame.with(child, |frame| paths(frame, out));
+        }
+    }
+
+    #[test]
+    fn call_stack() {
+        let mut out = Vec::new();
+        Frame::root(0, |frame| paths(frame, &mut out));
+        assert_eq!(out, [vec![0, 1, 3], vec![0, 1, 4], vec![0, 2]]);
+    }
+}
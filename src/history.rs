@@ -0,0 +1,169 @@
+/*
+ Undo/redo history
+
+An editor's undo history is a line of states with a cursor in it: undo moves the cursor back,
+redo moves it forward, and making a change after some undos throws away everything ahead of the
+cursor and starts a new branch from there.
+
+The functional way to hold "a list with a cursor" is a zipper: two stacks, one for each side of
+the cursor, with the newest entry on top of each, and the focus in between.
+
+states:  s1  s2  s3 [s4]  s5  s6          undo: s3 becomes current, s4 goes onto future
+past:    s3 -> s2 -> s1                    redo: the other way round
+current: s4                                push: current goes onto past, future is emptied
+future:  s5 -> s6
+
+Every move is a push on one stack and a pop off the other, O(1). The stacks are
+persistent_stack::List, so a History is a value: clone() is three refcount bumps, and the clone
+and the original share every state. Keep a clone before an operation and you have a snapshot of
+the whole history to go back to, at the cost of a few pointers.
+
+That's also what happens to a discarded branch. push replaces `future` with an empty list, and
+any clone taken before still holds the old future, so nothing is lost unless nobody refers to
+it any more. Then it's freed, once no other history shares it.
+
+States are kept in Rc, so moving one between stacks never clones the state itself.
+*/
+
+use crate::persistent_stack::List;
+use std::rc::Rc;
+
+#[derive(Clone)]
+pub struct History<T> {
+    // Newest first: the state undo goes back to is on top.
+    past: List<Rc<T>>,
+    current: Rc<T>,
+    // Next first: the state redo goes forward to is on top.
+    future: List<Rc<T>>,
+    undos: usize,
+    redos: usize,
+}
+
+impl<T> History<T> {
+    pub fn new(initial: T) -> Self {
+        History {
+            past: List::new(),
+            current: Rc::new(initial),
+            future: List::new(),
+            undos: 0,
+            redos: 0,
+        }
+    }
+
+    pub fn current(&self) -> &T {
+        &self.current
+    }
+
+    // Make `state` current, dropping any states that could have been redone.
+    pub fn push(&mut self, state: T) {
+        let old = std::mem::replace(&mut self.current, Rc::new(state));
+        self.past = self.past.prepend(old);
+        self.future = List::new();
+        self.undos += 1;
+        self.redos = 0;
+    }
+
+    // Step back a state, returning the new current one. None (and nothing changes) if there's
+    // nothing to undo.
+    pub fn undo(&mut self) -> Option<&T> {
+        let (prev, rest) = self.past.uncons()?;
+        let prev = Rc::clone(prev);
+        let old = std::mem::replace(&mut self.current, prev);
+        self.past = rest;
+        self.future = self.future.prepend(old);
+        self.undos -= 1;
+        self.redos += 1;
+        Some(&self.current)
+    }
+
+    pub fn redo(&mut self) -> Option<&T> {
+        let (next, rest) = self.future.uncons()?;
+        let next = Rc::clone(next);
+        let old = std::mem::replace(&mut self.current, next);
+        self.future = rest;
+        self.past = self.past.prepend(old);
+        self.redos -= 1;
+        self.undos += 1;
+        Some(&self.current)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.undos > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.redos > 0
+    }
+
+    // How many undos (redos) in a row would succeed.
+    pub fn undo_depth(&self) -> usize {
+        self.undos
+    }
+
+    pub fn redo_depth(&self) -> usize {
+        self.redos
+    }
+
+    // The states undo would go through, newest first.
+    pub fn past(&self) -> impl Iterator<Item = &T> + '_ {
+        self.past.iter().map(|state| &**state)
+    }
+
+    // The states redo would go through, next first.
+    pub fn future(&self) -> impl Iterator<Item = &T> + '_ {
+        self.future.iter().map(|state| &**state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::History;
+
+    #[test]
+    fn undo_redo() {
+        let mut history = History::new(String::new());
+        for word in ["a", "ab", "abc"] {
+            history.push(word.to_string());
+        }
+        assert_eq!(history.current(), "abc");
+        assert_eq!(history.undo().map(String::as_str), Some("ab"));
+        assert_eq!(history.undo().map(String::as_str), Some("a"));
+        assert_eq!((history.undo_depth(), history.redo_depth()), (1, 2));
+        assert_eq!(history.future().collect::<Vec<_>>(), ["ab", "abc"]);
+
+        assert_eq!(history.redo().map(String::as_str), Some("ab"));
+        assert_eq!(history.undo().map(String::as_str), Some("a"));
+        assert_eq!(history.undo().map(String::as_str), Some(""));
+        assert_eq!(history.undo(), None);
+        assert!(!history.can_undo());
+        assert_eq!(history.current(), "");
+
+        while history.redo().is_some() {}
+        assert_eq!(history.current(), "abc");
+        assert!(!history.can_redo());
+        assert_eq!(history.past().collect::<Vec<_>>(), ["ab", "a", ""]);
+    }
+
+    #[test]
+    fn branches() {
+        let mut history = History::new(0);
+        for i in 1..=5 {
+            history.push(i);
+        }
+        history.undo();
+        history.undo();
+        let snapshot = history.clone();
+
+        // A new edit after undoing starts a new branch; 4 and 5 can't be redone any more...
+        history.push(30);
+        assert!(!history.can_redo());
+        assert_eq!(history.past().copied().collect::<Vec<_>>(), [3, 2, 1, 0]);
+
+        // ...except from the snapshot, which shares everything up to 3 with it.
+        let mut snapshot = snapshot;
+        assert_eq!(snapshot.redo(), Some(&4));
+        assert_eq!(snapshot.redo(), Some(&5));
+        assert_eq!(history.undo(), Some(&3));
+        assert_eq!(history.redo(), Some(&30));
+    }
+}
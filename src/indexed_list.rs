@@ -0,0 +1,471 @@
+/*
+ An indexable skip list
+
+Indexing is the one thing every list in this crate is bad at: list[i] walks i nodes. Vec is O(1)
+there, but O(n) to insert or remove in the middle. IndexedList is O(log n) for all of them:
+get(i), insert(i, x) and remove(i).
+
+It's the skip list from skip_list.rs with one thing added to each link: its width, the number of
+level-0 steps it jumps over.
+
+level 2: head -------------------4-------------------> d ------------2------------> None
+level 1: head -------2--------> b ---------2---------> d --------1--------> e --1--> None
+level 0: head --1--> a --1--> b --1--> c --1--> d --1--> e --1--> None
+index:               0        1        2        3        4
+
+There are no keys to compare. Instead, a search for index i keeps a running position (head is
+position 0, element i is at position i + 1) and runs along each level for as long as the next
+node's position, position + width, doesn't overshoot. That's the skip list search with "key < k"
+replaced by "position <= i", and it's O(log n) for the same reason.
+
+- insert(i) finds, on each level, the last node before the new position, just like the sorted
+  skip list's insert. On the levels the new node is on, its predecessor's link is split in two
+  widths around it. On the levels above, the new node is somewhere underneath the link, so the
+  link just gets one wider.
+- remove(i) is the reverse: merge the widths on the levels the node was on, and narrow the
+  links above it by one.
+- Links to the end (None) have widths too, as if the end were a node after the last element.
+  That way they need no special cases: they widen and narrow like the rest.
+
+Nodes live in a slab::Slab, and links are slab keys rather than pointers, so none of this needs
+unsafe code. Node heights are coin flips, as in skip_list.rs.
+*/
+
+use crate::mem_usage::MemUsage;
+use crate::slab::Slab;
+use crate::validate::{self, Invalid};
+use std::fmt::{self, Debug};
+use std::mem::size_of;
+use std::ops::{Index, IndexMut};
+
+// Enough for 2^24 elements at p = 1/2.
+const MAX_LEVEL: usize = 24;
+
+#[derive(Clone, Copy)]
+struct Link {
+    // A slab key, or None for the end.
+    next: Option<usize>,
+    // Position of `next` minus position of this node.
+    width: usize,
+}
+
+struct Node<T> {
+    elem: T,
+    // One link per level the node is on; never empty.
+    links: Vec<Link>,
+}
+
+pub struct IndexedList<T> {
+    head: [Link; MAX_LEVEL],
+    nodes: Slab<Node<T>>,
+    // xorshift state for picking node heights.
+    rng: u64,
+}
+
+impl<T> IndexedList<T> {
+    pub fn new() -> Self {
+        IndexedList {
+            head: [Link {
+                next: None,
+                width: 1,
+            }; MAX_LEVEL],
+            nodes: Slab::new(),
+            rng: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // The slab's slots, plus each node's tower of links, which is a Vec of its own and as tall
+    // as the node's level. See mem_usage.rs.
+    pub fn mem_usage(&self) -> MemUsage {
+        let slab = self.nodes.mem_usage();
+        let towers: usize = self
+            .nodes
+            .iter()
+            .map(|(_, node)| node.links.capacity() * size_of::<Link>())
+            .sum();
+        MemUsage {
+            node_overhead: slab.node_overhead + size_of::<Node<T>>() - size_of::<T>(),
+            ..slab
+        }
+        .plus(towers)
+    }
+
+    // Level 0 is a node for each element in the slab, and each level above links exactly the
+    // nodes that are on it, as in SkipMap::validate. On top of that, every link's width has to
+    // land on the position of the node it links to, or on len + 1 for the end. One that doesn't
+    // is a Node at the node it links to (or at len, for a link to the end). See validate.rs.
+    pub fn validate(&self) -> Result<(), Invalid> {
+        let len = self.len();
+        let (mut at, mut stray) = (0, None);
+        let walked = validate::walk(self.head[0].next, len, |key| match self.nodes.get(key) {
+            Some(node) if !node.links.is_empty() => {
+                at += 1;
+                node.links[0].next
+            }
+            _ => {
+                stray.get_or_insert(at);
+                None
+            }
+        });
+        if let Some(at) = stray {
+            return Err(Invalid::Node { at });
+        }
+        walked?;
+
+        // For each level, where its last link went: the key, and the position it claimed.
+        let mut expected = self.head.map(|link| (link.next, link.width));
+        let mut cur = self.head[0].next;
+        for at in 0..len {
+            let key = cur.unwrap();
+            let links = &self.nodes[key].links;
+            if links.len() > MAX_LEVEL {
+                return Err(Invalid::Node { at });
+            }
+            for (level, link) in links.iter().enumerate() {
+                let (next, pos) = expected[level];
+                if next != Some(key) {
+                    return Err(Invalid::Link { at });
+                }
+                if pos != at + 1 {
+                    return Err(Invalid::Node { at });
+                }
+                expected[level] = (link.next, at + 1 + link.width);
+            }
+            cur = links[0].next;
+        }
+        for (next, pos) in expected {
+            if next.is_some() {
+                return Err(Invalid::Link { at: len });
+            }
+            if pos != len + 1 {
+                return Err(Invalid::Node { at: len });
+            }
+        }
+        Ok(())
+    }
+
+    // Coin flips: level 1 with probability 1/2, level 2 with 1/4, ...
+    fn random_level(&mut self) -> usize {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng = x;
+        (x.trailing_zeros() as usize + 1).min(MAX_LEVEL)
+    }
+
+    // The links of a node, or of the head for None.
+    fn links(&self, at: Option<usize>) -> &[Link] {
+        match at {
+            Some(key) => &self.nodes[key].links,
+            None => &self.head,
+        }
+    }
+
+    fn links_mut(&mut self, at: Option<usize>) -> &mut [Link] {
+        match at {
+            Some(key) => &mut self.nodes[key].links,
+            None => &mut self.head,
+        }
+    }
+
+    // For each level, the last node at or before position `pos`, and its position.
+    fn search(&self, pos: usize) -> [(Option<usize>, usize); MAX_LEVEL] {
+        let mut preds = [(None, 0); MAX_LEVEL];
+        let (mut at, mut at_pos) = (None, 0);
+        for level in (0..MAX_LEVEL).rev() {
+            // A node reached on `level` is at least `level + 1` high.
+            while let Link {
+                next: Some(next),
+                width,
+            } = self.links(at)[level]
+            {
+                if at_pos + width > pos {
+                    break;
+                }
+                at = Some(next);
+                at_pos += width;
+            }
+            preds[level] = (at, at_pos);
+        }
+        preds
+    }
+
+    fn key_of(&self, index: usize) -> Option<usize> {
+        if index >= self.len() {
+            return None;
+        }
+        // Element `index` is the last node at or before its own position.
+        self.search(index + 1)[0].0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let key = self.key_of(index)?;
+        Some(&self.nodes[key].elem)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let key = self.key_of(index)?;
+        Some(&mut self.nodes[key].elem)
+    }
+
+    // Insert `elem` so that it's at `index`, shifting everything from there on up by one. Panics
+    // if `index > len`, like Vec::insert.
+    pub fn insert(&mut self, index: usize, elem: T) {
+        let len = self.len();
+        assert!(
+            index <= len,
+            "insertion index (is {index}) should be <= len (is {len})"
+        );
+        let height = self.random_level();
+        let preds = self.search(index);
+        let links = preds[..height]
+            .iter()
+            .enumerate()
+            .map(|(level, &(at, at_pos))| {
+                let old = self.links(at)[level];
+                // `old.next` was at at_pos + old.width, and is about to move up one; the new
+                // node goes in at index + 1.
+                Link {
+                    next: old.next,
+                    width: at_pos + old.width - index,
+                }
+            })
+            .collect();
+        let key = self.nodes.insert(Node { elem, links });
+        for (level, &(at, at_pos)) in preds.iter().enumerate() {
+            let link = &mut self.links_mut(at)[level];
+            if level < height {
+                *link = Link {
+                    next: Some(key),
+                    width: index + 1 - at_pos,
+                };
+            } else {
+                link.width += 1;
+            }
+        }
+        validate::check!(self);
+    }
+
+    // Remove and return the element at `index`, shifting everything after it down by one.
+    // Panics if `index >= len`, like Vec::remove.
+    pub fn remove(&mut self, index: usize) -> T {
+        let len = self.len();
+        assert!(
+            index < len,
+            "removal index (is {index}) should be < len (is {len})"
+        );
+        let preds = self.search(index);
+        let key = self.links(preds[0].0)[0].next.unwrap();
+        let node = self.nodes.remove(key).unwrap();
+        for (level, &(at, _)) in preds.iter().enumerate() {
+            let link = &mut self.links_mut(at)[level];
+            match node.links.get(level) {
+                Some(skipped) => {
+                    link.next = skipped.next;
+                    link.width += skipped.width - 1;
+                }
+                None => link.width -= 1,
+            }
+        }
+        validate::check!(self);
+        node.elem
+    }
+
+    pub fn push_back(&mut self, elem: T) {
+        self.insert(self.len(), elem);
+    }
+
+    pub fn push_front(&mut self, elem: T) {
+        self.insert(0, elem);
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        let len = self.len();
+        (len > 0).then(|| self.remove(len - 1))
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        (!self.is_empty()).then(|| self.remove(0))
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        for link in &mut self.head {
+            *link = Link {
+                next: None,
+                width: 1,
+            };
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            list: self,
+            next: self.head[0].next,
+            len: self.len(),
+        }
+    }
+}
+
+impl<T> Default for IndexedList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Index<usize> for IndexedList<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        let len = self.len();
+        self.get(index)
+            .unwrap_or_else(|| panic!("index {index} out of bounds for length {len}"))
+    }
+}
+
+impl<T> IndexMut<usize> for IndexedList<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        let len = self.len();
+        self.get_mut(index)
+            .unwrap_or_else(|| panic!("index {index} out of bounds for length {len}"))
+    }
+}
+
+impl<T> FromIterator<T> for IndexedList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = Self::new();
+        for elem in iter {
+            list.push_back(elem);
+        }
+        list
+    }
+}
+
+impl<T: Debug> Debug for IndexedList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+pub struct Iter<'a, T> {
+    list: &'a IndexedList<T>,
+    next: Option<usize>,
+    len: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = &self.list.nodes[self.next?];
+        self.next = node.links[0].next;
+        self.len -= 1;
+        Some(&node.elem)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+#[cfg(test)]
+mod test {
+    use super::IndexedList;
+    use crate::validate::Invalid;
+
+    #[test]
+    fn basics() {
+        let mut list: IndexedList<_> = "bd".chars().collect();
+        list.insert(0, 'a');
+        list.insert(2, 'c');
+        list.push_back('e');
+        assert_eq!(list.iter().collect::<String>(), "abcde");
+        assert_eq!((list[0], list[2], list[4]), ('a', 'c', 'e'));
+        assert_eq!(list.get(5), None);
+
+        list[1] = 'B';
+        assert_eq!(list.remove(2), 'c');
+        assert_eq!(list.pop_front(), Some('a'));
+        assert_eq!(list.pop_back(), Some('e'));
+        assert_eq!(format!("{:?}", list), "['B', 'd']");
+        list.clear();
+        assert!(list.is_empty());
+        list.push_front('z');
+        assert_eq!(list.iter().len(), 1);
+    }
+
+    // Random inserts and removes at random indexes, checked against a Vec.
+    #[test]
+    fn matches_vec() {
+        let mut list = IndexedList::new();
+        let mut vec = Vec::new();
+        let mut rng = 12345u64;
+        for i in 0..20_000usize {
+            rng = rng
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let r = (rng >> 33) as usize;
+            if vec.is_empty() || !r.is_multiple_of(3) {
+                let at = r % (vec.len() + 1);
+                list.insert(at, i);
+                vec.insert(at, i);
+            } else {
+                let at = r % vec.len();
+                assert_eq!(list.remove(at), vec.remove(at));
+            }
+            if i.is_multiple_of(1000) {
+                assert!(list.iter().eq(vec.iter()));
+            }
+        }
+        assert_eq!(list.len(), vec.len());
+        for (i, x) in vec.iter().enumerate() {
+            assert_eq!(list.get(i), Some(x));
+        }
+    }
+
+    #[test]
+    fn validate() {
+        let mut list: IndexedList<_> = (0..50).collect();
+        assert_eq!(list.validate(), Ok(()));
+
+        list.head[0].width = 2;
+        assert_eq!(list.validate(), Err(Invalid::Node { at: 0 }));
+        list.head[0].width = 1;
+
+        // A link on a higher level that's one too wide: the node it leads to is where it's found.
+        let tall = list.head[1].next.unwrap();
+        list.head[1].width += 1;
+        let at = list.nodes[tall].elem;
+        assert_eq!(list.validate(), Err(Invalid::Node { at }));
+        list.head[1].width -= 1;
+
+        let first = list.head[0].next.unwrap();
+        list.head[0].next = list.nodes[first].links[0].next;
+        assert_eq!(
+            list.validate(),
+            Err(Invalid::Len {
+                stored: 50,
+                counted: 49
+            })
+        );
+        list.head[0].next = Some(first);
+        assert_eq!(list.validate(), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "should be <= len")]
+    fn insert_out_of_bounds() {
+        IndexedList::new().insert(1, ());
+    }
+}
@@ -0,0 +1,311 @@
+/*
+ Instrumented lists
+
+Complexity analysis says what an operation should cost; Instrumented<L> counts what it does cost.
+It wraps any list that implements the traits in traits.rs, implements the same traits itself, and
+counts everything that goes through it:
+
+    let mut list = Instrumented::new(unsafe_doubly_linked_deque::List::new());
+    for x in 0..1000 {
+        list.push_back(x);
+    }
+    list.iter().find(|&&x| x == 500);
+    println!("{}", list.report());
+
+    pushes: 1000, pops: 0, peeks: 0, steps: 501, allocations: 1000, frees: 0
+
+- pushes, pops, peeks: calls to the trait methods, at either end.
+- steps: elements visited through iter(), so a search or scan done through the wrapper. An O(n)
+  lookup shows up as up to n steps per call; push and pop, being O(1), add none.
+- allocations and frees: what the global allocator saw during each push and pop, on this thread.
+  A push into a node-based list should be exactly one allocation; one that's two, or a pop that
+  frees nothing (an arena keeps its slots), shows up here. That needs counting_alloc's
+  CountingAlloc installed as the program's global allocator. Without it the counts aren't known,
+  and the report says so rather than showing zeros.
+
+The counters are in a Cell, so that iter() and peek, which only borrow the list, can count too.
+That makes an Instrumented list !Sync, like a RefCell, which is no loss for something meant to be
+watched from one thread.
+
+get() lends out the list itself, for its inherent methods the traits don't cover, uncounted.
+reset() zeroes the counters, to measure one phase of a workload at a time.
+*/
+
+use crate::counting_alloc::{self, Counts};
+use crate::traits::{Collection, Deque, Queue, Stack};
+use std::cell::Cell;
+use std::fmt;
+use std::ops::Deref;
+
+pub struct Instrumented<L> {
+    list: L,
+    counters: Cell<Counters>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Counters {
+    pushes: u64,
+    pops: u64,
+    peeks: u64,
+    steps: u64,
+    allocs: u64,
+    frees: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    pub pushes: u64,
+    pub pops: u64,
+    pub peeks: u64,
+    pub steps: u64,
+    // None if CountingAlloc isn't the global allocator.
+    pub allocs: Option<u64>,
+    pub frees: Option<u64>,
+}
+
+impl<L> Instrumented<L> {
+    pub fn new(list: L) -> Self {
+        Instrumented {
+            list,
+            counters: Cell::default(),
+        }
+    }
+
+    pub fn get(&self) -> &L {
+        &self.list
+    }
+
+    pub fn into_inner(self) -> L {
+        self.list
+    }
+
+    pub fn reset(&self) {
+        self.counters.take();
+    }
+
+    pub fn report(&self) -> Report {
+        let counters = self.counters.get();
+        let installed = counting_alloc::installed();
+        Report {
+            pushes: counters.pushes,
+            pops: counters.pops,
+            peeks: counters.peeks,
+            steps: counters.steps,
+            allocs: installed.then_some(counters.allocs),
+            frees: installed.then_some(counters.frees),
+        }
+    }
+
+    pub fn iter<'a>(&'a self) -> Steps<'a, <&'a L as IntoIterator>::IntoIter>
+    where
+        &'a L: IntoIterator,
+    {
+        Steps {
+            iter: (&self.list).into_iter(),
+            counters: &self.counters,
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Counters)) {
+        let mut counters = self.counters.get();
+        f(&mut counters);
+        self.counters.set(counters);
+    }
+
+    // Run `op` on the list, adding one to the counter `which` picks, and what op allocated and
+    // freed to the others.
+    fn counted<R>(
+        &mut self,
+        which: fn(&mut Counters) -> &mut u64,
+        op: impl FnOnce(&mut L) -> R,
+    ) -> R {
+        let allocs = Counts::start();
+        let result = op(&mut self.list);
+        self.update(|counters| {
+            *which(counters) += 1;
+            counters.allocs += allocs.allocs() as u64;
+            counters.frees += allocs.deallocs() as u64;
+        });
+        result
+    }
+
+    fn peeked(&self) {
+        self.update(|counters| counters.peeks += 1);
+    }
+}
+
+impl<L: Default> Default for Instrumented<L> {
+    fn default() -> Self {
+        Self::new(L::default())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "pushes: {}, pops: {}, peeks: {}, steps: {}",
+            self.pushes, self.pops, self.peeks, self.steps
+        )?;
+        match (self.allocs, self.frees) {
+            (Some(allocs), Some(frees)) => write!(f, ", allocations: {allocs}, frees: {frees}"),
+            _ => f.write_str(", allocations: unknown (CountingAlloc isn't installed)"),
+        }
+    }
+}
+
+// iter()'s iterator: the list's own, counting a step per element.
+pub struct Steps<'a, I> {
+    iter: I,
+    counters: &'a Cell<Counters>,
+}
+
+impl<I: Iterator> Iterator for Steps<'_, I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let item = self.iter.next()?;
+        let mut counters = self.counters.get();
+        counters.steps += 1;
+        self.counters.set(counters);
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<L: Collection> Collection for Instrumented<L> {
+    fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+}
+
+impl<T, L: Stack<T>> Stack<T> for Instrumented<L> {
+    fn push(&mut self, elem: T) {
+        self.counted(|c| &mut c.pushes, |list| Stack::push(list, elem))
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        self.counted(|c| &mut c.pops, Stack::pop)
+    }
+
+    fn peek(&self) -> Option<impl Deref<Target = T> + '_> {
+        self.peeked();
+        Stack::peek(&self.list)
+    }
+}
+
+impl<T, L: Queue<T>> Queue<T> for Instrumented<L> {
+    fn push(&mut self, elem: T) {
+        self.counted(|c| &mut c.pushes, |list| Queue::push(list, elem))
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        self.counted(|c| &mut c.pops, Queue::pop)
+    }
+
+    fn peek(&self) -> Option<impl Deref<Target = T> + '_> {
+        self.peeked();
+        Queue::peek(&self.list)
+    }
+}
+
+impl<T, L: Deque<T>> Deque<T> for Instrumented<L> {
+    fn push_front(&mut self, elem: T) {
+        self.counted(|c| &mut c.pushes, |list| list.push_front(elem))
+    }
+
+    fn push_back(&mut self, elem: T) {
+        self.counted(|c| &mut c.pushes, |list| list.push_back(elem))
+    }
+
+    fn pop_front(&mut self) -> Option<T> {
+        self.counted(|c| &mut c.pops, L::pop_front)
+    }
+
+    fn pop_back(&mut self) -> Option<T> {
+        self.counted(|c| &mut c.pops, L::pop_back)
+    }
+
+    fn peek_front(&self) -> Option<impl Deref<Target = T> + '_> {
+        self.peeked();
+        self.list.peek_front()
+    }
+
+    fn peek_back(&self) -> Option<impl Deref<Target = T> + '_> {
+        self.peeked();
+        self.list.peek_back()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Instrumented, Report};
+    use crate::traits::{Deque, Queue, Stack};
+    use crate::{generic_and_iterators, persistent_stack, unsafe_doubly_linked_deque};
+
+    #[test]
+    fn counts() {
+        let mut list = Instrumented::new(unsafe_doubly_linked_deque::List::new());
+        for x in 0..1000 {
+            list.push_back(x);
+        }
+        assert_eq!(list.iter().find(|&&x| x == 500), Some(&500));
+        assert_eq!(list.peek_front().as_deref(), Some(&0));
+        assert_eq!(list.pop_back(), Some(999));
+        assert_eq!(
+            list.report(),
+            Report {
+                pushes: 1000,
+                pops: 1,
+                peeks: 1,
+                steps: 501,
+                allocs: Some(1000),
+                frees: Some(1),
+            }
+        );
+        assert_eq!(
+            list.report().to_string(),
+            "pushes: 1000, pops: 1, peeks: 1, steps: 501, allocations: 1000, frees: 1"
+        );
+
+        list.reset();
+        assert_eq!(list.get().len(), 999);
+        assert_eq!(list.report().pushes, 0);
+    }
+
+    // The same workload through the same trait, on two representations.
+    #[test]
+    fn compare() {
+        fn drain<S: Stack<u32>>(stack: S) -> Report {
+            let mut stack = Instrumented::new(stack);
+            while stack.pop().is_some() {}
+            stack.report()
+        }
+
+        // Popping frees a node.
+        let mut boxed = generic_and_iterators::List::new();
+        for x in 0..100 {
+            boxed.push(x);
+        }
+        let report = drain(boxed);
+        assert_eq!((report.pops, report.frees), (101, Some(100)));
+
+        // Popping a persistent stack only moves a handle while another list shares the nodes.
+        let shared = persistent_stack::List::new().cons_all(0..100);
+        let report = drain(shared.clone());
+        assert_eq!((report.pops, report.frees), (101, Some(0)));
+        assert_eq!(shared.head(), Some(&0));
+
+        let mut queue = Instrumented::<unsafe_doubly_linked_deque::List<u32>>::default();
+        Queue::push(&mut queue, 1);
+        assert_eq!(Queue::peek(&queue).as_deref(), Some(&1));
+        assert_eq!(queue.report().peeks, 1);
+    }
+}
@@ -0,0 +1,462 @@
+/*
+ An intrusive doubly linked list
+
+Every other list in this crate owns its elements: push moves the value into a node the list
+allocated. An intrusive list turns that around. The links live inside the user's own struct,
+
+struct Timer {
+    deadline: u64,
+    link: ListLink,
+}
+
+and the list only threads pointers through those fields. Pushing and removing never allocate, an
+element can sit on a stack frame or in a static, and given a reference to an element you can take
+it out of its list in O(1) without searching for it. That's how kernels and async runtimes keep
+their wait queues and timer lists.
+
+The list has to find the struct from its ListLink field. A Linked impl says where the field is,
+and `impl_linked!(Timer, link)` writes it with offset_of!. From a pointer to the whole element we
+step forward to the link, and from a link we step back to the element.
+
+What makes this hard in Rust is that the list holds pointers into memory it doesn't own:
+
+- Elements must not move while linked, or their neighbours' pointers dangle. So push takes a
+  Pin<&'a T>, and the `'a` borrow also keeps the element alive as long as the list is usable.
+- The list mustn't move either: its own ListLink is the sentinel of a ring,
+  sentinel <-> front <-> ... <-> back <-> sentinel, and front and back point back at it. So the
+  methods take Pin<&Self> or Pin<&mut Self>; make one with pin!(IntrusiveList::new()).
+- Links are Cells, so the whole thing is !Send and !Sync and can't race.
+- Each link records which list it's in. remove(elem) checks that instead of trusting the caller,
+  and pushing an element that's already linked somewhere panics.
+- Dropping either side unlinks. A dropped list clears every element's link, so they can be pushed
+  somewhere else. A dropped element takes itself out of its list: the borrow normally rules that
+  out while the list can still be used, but a list that's leaked (a Pin<Box<_>> that's forgotten) would
+  otherwise be left pointing at freed elements.
+*/
+
+use crate::validate::{self, Invalid};
+use std::cell::Cell;
+use std::fmt::{self, Debug};
+use std::marker::{PhantomData, PhantomPinned};
+use std::pin::Pin;
+use std::ptr::{self, NonNull};
+
+pub struct ListLink {
+    prev: Cell<Option<NonNull<ListLink>>>,
+    next: Cell<Option<NonNull<ListLink>>>,
+    // The sentinel of the list we're in, or null.
+    owner: Cell<*const ListLink>,
+    _pin: PhantomPinned,
+}
+
+impl ListLink {
+    pub const fn new() -> Self {
+        ListLink {
+            prev: Cell::new(None),
+            next: Cell::new(None),
+            owner: Cell::new(ptr::null()),
+            _pin: PhantomPinned,
+        }
+    }
+
+    pub fn is_linked(&self) -> bool {
+        !self.owner.get().is_null()
+    }
+
+    // Splice ourselves out of the ring and forget it. Neighbours are either linked elements or
+    // the sentinel, which are pinned and alive for as long as we're in the ring.
+    fn unlink(&self) {
+        if let (Some(prev), Some(next)) = (self.prev.take(), self.next.take()) {
+            unsafe {
+                (*prev.as_ptr()).next.set(Some(next));
+                (*next.as_ptr()).prev.set(Some(prev));
+            }
+        }
+        self.owner.set(ptr::null());
+    }
+}
+
+impl Default for ListLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for ListLink {
+    fn drop(&mut self) {
+        self.unlink();
+    }
+}
+
+impl Debug for ListLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ListLink")
+            .field("linked", &self.is_linked())
+            .finish()
+    }
+}
+
+/// # Safety
+///
+/// OFFSET must be the offset of a ListLink field of Self.
+pub unsafe trait Linked {
+    const OFFSET: usize;
+}
+
+// impl_linked!(Timer, link) marks `Timer.link` as the ListLink an IntrusiveList<Timer> threads
+// through.
+#[macro_export]
+macro_rules! impl_linked {
+    ($ty:ty, $field:ident) => {
+        unsafe impl $crate::intrusive_list::Linked for $ty {
+            const OFFSET: usize = {
+                // Fails to compile unless the field really is a ListLink.
+                fn _check(t: &$ty) -> &$crate::intrusive_list::ListLink {
+                    &t.$field
+                }
+                ::std::mem::offset_of!($ty, $field)
+            };
+        }
+    };
+}
+
+// Both directions go through pointers to the whole element, never a reference to just the link,
+// so stepping from the link back to the element stays within what the pointer may access.
+fn link_of<T: Linked>(elem: NonNull<T>) -> NonNull<ListLink> {
+    unsafe { elem.byte_add(T::OFFSET).cast() }
+}
+
+unsafe fn elem_of<T: Linked>(link: NonNull<ListLink>) -> NonNull<T> {
+    link.byte_sub(T::OFFSET).cast()
+}
+
+pub struct IntrusiveList<'a, T: Linked> {
+    // prev is the back, next is the front. Both None until the first push, since `new` can't
+    // know where the list will end up pinned.
+    sentinel: ListLink,
+    len: Cell<usize>,
+    _boo: PhantomData<Pin<&'a T>>,
+}
+
+impl<'a, T: Linked> IntrusiveList<'a, T> {
+    pub const fn new() -> Self {
+        IntrusiveList {
+            sentinel: ListLink::new(),
+            len: Cell::new(0),
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Round the ring from the sentinel: `len` links, each owned by this list, each prev pointing
+    // at the link before (the sentinel, for the front), and the sentinel's prev at the back. A
+    // link that says it's in some other list is a Node. See validate.rs.
+    pub fn validate(&self) -> Result<(), Invalid> {
+        let s = NonNull::from(&self.sentinel);
+        let Some(front) = self.sentinel.next.get() else {
+            // Never pushed to.
+            return match self.len() {
+                0 => Ok(()),
+                len => Err(Invalid::Len {
+                    stored: len,
+                    counted: 0,
+                }),
+            };
+        };
+        let (mut prev, mut at, mut err) = (s, 0, None);
+        let last = validate::walk((front != s).then_some(front), self.len(), |link| unsafe {
+            let l = link.as_ref();
+            if l.prev.get() != Some(prev) {
+                err.get_or_insert(Invalid::Link { at });
+            }
+            if !ptr::eq(l.owner.get(), &self.sentinel) {
+                err.get_or_insert(Invalid::Node { at });
+            }
+            (prev, at) = (link, at + 1);
+            l.next.get().filter(|&next| next != s)
+        })?;
+        if let Some(err) = err {
+            return Err(err);
+        }
+        if self.sentinel.prev.get() != Some(last.unwrap_or(s)) {
+            return Err(Invalid::Ends);
+        }
+        Ok(())
+    }
+
+    fn sentinel(&self) -> NonNull<ListLink> {
+        let s = NonNull::from(&self.sentinel);
+        if self.sentinel.next.get().is_none() {
+            self.sentinel.next.set(Some(s));
+            self.sentinel.prev.set(Some(s));
+        }
+        s
+    }
+
+    // Link `elem` in just before `at` (the sentinel, for the back).
+    fn link_before(&self, at: NonNull<ListLink>, elem: Pin<&'a T>) {
+        let link = link_of(NonNull::from(elem.get_ref()));
+        unsafe {
+            let l = &*link.as_ptr();
+            assert!(!l.is_linked(), "element is already in a list");
+            let prev = (*at.as_ptr()).prev.get().unwrap();
+            l.prev.set(Some(prev));
+            l.next.set(Some(at));
+            l.owner.set(&self.sentinel);
+            (*prev.as_ptr()).next.set(Some(link));
+            (*at.as_ptr()).prev.set(Some(link));
+        }
+        self.len.set(self.len() + 1);
+        validate::check!(self);
+    }
+
+    fn take(&self, link: NonNull<ListLink>) -> Pin<&'a T> {
+        unsafe {
+            (*link.as_ptr()).unlink();
+            self.len.set(self.len() - 1);
+            validate::check!(self);
+            Pin::new_unchecked(&*elem_of(link).as_ptr())
+        }
+    }
+
+    // The first or last element's link, if there is one.
+    fn end(&self, front: bool) -> Option<NonNull<ListLink>> {
+        if self.is_empty() {
+            return None;
+        }
+        let end = if front {
+            &self.sentinel.next
+        } else {
+            &self.sentinel.prev
+        };
+        end.get()
+    }
+
+    pub fn push_back(self: Pin<&mut Self>, elem: Pin<&'a T>) {
+        let this = self.into_ref().get_ref();
+        this.link_before(this.sentinel(), elem);
+    }
+
+    pub fn push_front(self: Pin<&mut Self>, elem: Pin<&'a T>) {
+        let this = self.into_ref().get_ref();
+        let front = unsafe { (*this.sentinel().as_ptr()).next.get().unwrap() };
+        this.link_before(front, elem);
+    }
+
+    pub fn pop_front(self: Pin<&mut Self>) -> Option<Pin<&'a T>> {
+        let this = self.into_ref().get_ref();
+        this.end(true).map(|link| this.take(link))
+    }
+
+    pub fn pop_back(self: Pin<&mut Self>) -> Option<Pin<&'a T>> {
+        let this = self.into_ref().get_ref();
+        this.end(false).map(|link| this.take(link))
+    }
+
+    // Take `elem` out of this list in O(1). False if it wasn't in this list.
+    pub fn remove(self: Pin<&mut Self>, elem: Pin<&T>) -> bool {
+        let this = self.into_ref().get_ref();
+        let link = link_of(NonNull::from(elem.get_ref()));
+        let ours = unsafe { ptr::eq((*link.as_ptr()).owner.get(), &this.sentinel) };
+        if ours {
+            this.take(link);
+        }
+        ours
+    }
+
+    pub fn front(self: Pin<&Self>) -> Option<Pin<&'a T>> {
+        self.get_ref()
+            .end(true)
+            .map(|link| unsafe { Pin::new_unchecked(&*elem_of(link).as_ptr()) })
+    }
+
+    pub fn back(self: Pin<&Self>) -> Option<Pin<&'a T>> {
+        self.get_ref()
+            .end(false)
+            .map(|link| unsafe { Pin::new_unchecked(&*elem_of(link).as_ptr()) })
+    }
+
+    pub fn iter(self: Pin<&Self>) -> Iter<'_, 'a, T> {
+        let this = self.get_ref();
+        Iter {
+            next: this.end(true),
+            sentinel: &this.sentinel,
+            len: this.len(),
+            _boo: PhantomData,
+        }
+    }
+}
+
+impl<T: Linked> Default for IntrusiveList<'_, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Linked> Drop for IntrusiveList<'_, T> {
+    fn drop(&mut self) {
+        // Free every element to go into another list.
+        let mut cur = self.end(true);
+        while let Some(link) = cur {
+            if ptr::eq(link.as_ptr(), &self.sentinel) {
+                break;
+            }
+            unsafe {
+                cur = (*link.as_ptr()).next.get();
+                let l = &*link.as_ptr();
+                l.prev.set(None);
+                l.next.set(None);
+                l.owner.set(ptr::null());
+            }
+        }
+        self.sentinel.prev.set(None);
+        self.sentinel.next.set(None);
+    }
+}
+
+impl<T: Linked + Debug> Debug for IntrusiveList<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Nothing's linked to a list that was never pinned, and pinning doesn't move it.
+        let this = unsafe { Pin::new_unchecked(self) };
+        f.debug_list().entries(this.iter()).finish()
+    }
+}
+
+// Borrows the list shared, so it can't change underneath us.
+pub struct Iter<'l, 'a, T> {
+    next: Option<NonNull<ListLink>>,
+    sentinel: &'l ListLink,
+    len: usize,
+    _boo: PhantomData<Pin<&'a T>>,
+}
+
+impl<'a, T: Linked> Iterator for Iter<'_, 'a, T> {
+    type Item = Pin<&'a T>;
+
+    fn next(&mut self) -> Option<Pin<&'a T>> {
+        let link = self.next.filter(|l| !ptr::eq(l.as_ptr(), self.sentinel))?;
+        unsafe {
+            self.next = (*link.as_ptr()).next.get();
+            self.len -= 1;
+            Some(Pin::new_unchecked(&*elem_of(link).as_ptr()))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T: Linked> ExactSizeIterator for Iter<'_, '_, T> {}
+
+#[cfg(test)]
+mod test {
+    use super::{IntrusiveList, ListLink};
+    use std::mem::ManuallyDrop;
+    use std::pin::pin;
+
+    #[derive(Debug)]
+    struct Timer {
+        deadline: u64,
+        link: ListLink,
+    }
+
+    impl Timer {
+        fn new(deadline: u64) -> Self {
+            Timer {
+                deadline,
+                link: ListLink::new(),
+            }
+        }
+    }
+
+    crate::impl_linked!(Timer, link);
+
+    fn deadlines(list: std::pin::Pin<&IntrusiveList<Timer>>) -> Vec<u64> {
+        list.iter().map(|t| t.deadline).collect()
+    }
+
+    #[test]
+    fn basics() {
+        let a = pin!(Timer::new(1));
+        let a = a.into_ref();
+        let b = pin!(Timer::new(2));
+        let b = b.into_ref();
+        let c = pin!(Timer::new(3));
+        let c = c.into_ref();
+        let mut list = pin!(IntrusiveList::new());
+        assert!(list.as_mut().pop_front().is_none());
+
+        list.as_mut().push_back(b);
+        list.as_mut().push_back(c);
+        list.as_mut().push_front(a);
+        assert_eq!(list.len(), 3);
+        assert!(a.link.is_linked());
+        assert_eq!(deadlines(list.as_ref()), [1, 2, 3]);
+        assert_eq!(list.as_ref().front().unwrap().deadline, 1);
+        assert_eq!(list.as_ref().back().unwrap().deadline, 3);
+
+        // Out of the middle, by reference.
+        assert!(list.as_mut().remove(b));
+        assert!(!list.as_mut().remove(b));
+        assert!(!b.link.is_linked());
+        assert_eq!(deadlines(list.as_ref()), [1, 3]);
+
+        assert_eq!(list.as_mut().pop_back().unwrap().deadline, 3);
+        assert_eq!(list.as_mut().pop_front().unwrap().deadline, 1);
+        assert!(list.is_empty());
+        assert_eq!(format!("{:?}", list), "[]");
+    }
+
+    #[test]
+    fn elements_outlive_lists() {
+        let a = pin!(Timer::new(1));
+        let a = a.into_ref();
+        let b = pin!(Timer::new(2));
+        let b = b.into_ref();
+        {
+            let mut first = pin!(IntrusiveList::new());
+            first.as_mut().push_back(a);
+            first.as_mut().push_back(b);
+
+            // Only the list an element is in can remove it.
+            let mut other = pin!(IntrusiveList::new());
+            assert!(!other.as_mut().remove(a));
+        }
+        // Dropping `first` let go of both.
+        assert!(!a.link.is_linked());
+        let mut second = pin!(IntrusiveList::new());
+        second.as_mut().push_back(b);
+        second.as_mut().push_back(a);
+        assert_eq!(deadlines(second.as_ref()), [2, 1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "already in a list")]
+    fn no_double_linking() {
+        let a = pin!(Timer::new(1));
+        let a = a.into_ref();
+        let mut first = pin!(IntrusiveList::new());
+        let mut second = pin!(IntrusiveList::new());
+        first.as_mut().push_back(a);
+        second.as_mut().push_back(a);
+    }
+
+    #[test]
+    fn leaked_list() {
+        {
+            let mut list = ManuallyDrop::new(Box::pin(IntrusiveList::new()));
+            let a = pin!(Timer::new(1));
+            let a = a.into_ref();
+            list.as_mut().push_back(a);
+            // `a` unlinks itself from the leaked list here, rather than leaving it dangling.
+        }
+    }
+}
@@ -0,0 +1,253 @@
+/*
+ A persistent leftist heap
+
+pairing_heap.rs is ephemeral: push and pop change the heap in place. This is the persistent
+counterpart, in the style of persistent_stack.rs: nodes are immutable and shared through Rc,
+every operation returns a new heap, and the old one stays valid and unchanged.
+
+h1 = [1, 4, 7]
+h2 = h1.insert(3)        h1 is still [1, 4, 7]
+h3 = h2.delete_min()     [3, 4, 7], sharing most of its nodes with h1 and h2
+
+A leftist heap is a binary tree, heap-ordered (every node is no larger than its children), plus
+one more rule. A node's rank is the length of its right spine, the path you get by following
+right children until you run out, and every left child has at least the rank of its sibling.
+So the right spine is always the shortest path down, at most log2(n + 1) nodes long, and all the
+work happens along it:
+
+- merge walks down the right spines of both heaps, always continuing from the smaller root,
+  then swaps children on the way back up wherever that's needed to restore the rank rule.
+  O(log n), because both spines are.
+- insert merges in a one-node heap; delete_min merges the root's two children.
+
+Persistence is where copying comes in. Merging changes the children of every node on the path,
+and those nodes may belong to older heaps, so the path is rebuilt with new nodes (which is why
+T: Clone; use Rc<T> for elements that are expensive to clone) and everything off the path is
+shared. That's O(log n) new nodes per operation.
+
+Why leftist rather than skew? A skew heap drops the ranks and swaps children unconditionally on
+every merge. Its O(log n) bound is only amortized, and amortization doesn't survive persistence:
+an expensive operation can be repeated on the same old version as many times as you like.
+*/
+
+use std::rc::Rc;
+
+pub struct LeftistHeap<T> {
+    root: Link<T>,
+}
+
+type Link<T> = Option<Rc<Node<T>>>;
+
+struct Node<T> {
+    elem: T,
+    // Length of the right spine, counting this node.
+    rank: usize,
+    len: usize,
+    left: Link<T>,
+    right: Link<T>,
+}
+
+fn rank<T>(link: &Link<T>) -> usize {
+    link.as_ref().map_or(0, |node| node.rank)
+}
+
+fn len<T>(link: &Link<T>) -> usize {
+    link.as_ref().map_or(0, |node| node.len)
+}
+
+// A new node over `a` and `b`, with the higher-ranked one on the left.
+fn make<T>(elem: T, a: Link<T>, b: Link<T>) -> Rc<Node<T>> {
+    let (left, right) = if rank(&a) >= rank(&b) { (a, b) } else { (b, a) };
+    Rc::new(Node {
+        elem,
+        rank: rank(&right) + 1,
+        len: len(&left) + len(&right) + 1,
+        left,
+        right,
+    })
+}
+
+// Recurses once per node on the two right spines, so O(log n) deep.
+fn merge<T: Clone + Ord>(a: &Link<T>, b: &Link<T>) -> Link<T> {
+    match (a, b) {
+        (None, _) => b.clone(),
+        (_, None) => a.clone(),
+        (Some(x), Some(y)) => {
+            let (small, big) = if y.elem < x.elem { (y, x) } else { (x, y) };
+            let right = merge(&small.right, &Some(big.clone()));
+            Some(make(small.elem.clone(), small.left.clone(), right))
+        }
+    }
+}
+
+impl<T> LeftistHeap<T> {
+    pub fn new() -> Self {
+        LeftistHeap { root: None }
+    }
+
+    pub fn len(&self) -> usize {
+        len(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn find_min(&self) -> Option<&T> {
+        self.root.as_ref().map(|node| &node.elem)
+    }
+
+    // The same nodes, not just equal elements.
+    pub fn ptr_eq(&self, other: &LeftistHeap<T>) -> bool {
+        match (&self.root, &other.root) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        }
+    }
+}
+
+impl<T: Clone + Ord> LeftistHeap<T> {
+    pub fn insert(&self, elem: T) -> LeftistHeap<T> {
+        self.merge(&LeftistHeap {
+            root: Some(make(elem, None, None)),
+        })
+    }
+
+    pub fn merge(&self, other: &LeftistHeap<T>) -> LeftistHeap<T> {
+        LeftistHeap {
+            root: merge(&self.root, &other.root),
+        }
+    }
+
+    // Everything but the minimum. Empty if empty.
+    pub fn delete_min(&self) -> LeftistHeap<T> {
+        LeftistHeap {
+            root: self
+                .root
+                .as_ref()
+                .and_then(|node| merge(&node.left, &node.right)),
+        }
+    }
+
+    // find_min and delete_min in one go.
+    pub fn pop_min(&self) -> Option<(&T, LeftistHeap<T>)> {
+        self.find_min().map(|min| (min, self.delete_min()))
+    }
+
+    // Smallest first. Each step is a delete_min, so this leaves `self` alone.
+    pub fn iter_sorted(&self) -> IterSorted<T> {
+        IterSorted { heap: self.clone() }
+    }
+}
+
+impl<T> Clone for LeftistHeap<T> {
+    fn clone(&self) -> Self {
+        LeftistHeap {
+            root: self.root.clone(),
+        }
+    }
+}
+
+impl<T> Default for LeftistHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone + Ord> FromIterator<T> for LeftistHeap<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(LeftistHeap::new(), |heap, elem| heap.insert(elem))
+    }
+}
+
+// The left spine isn't bounded like the right one (inserting in descending order makes it as long
+// as the heap), so dropping has to be iterative, as in persistent_stack.rs. Children we held the
+// last reference to go on a stack instead of being dropped recursively.
+impl<T> Drop for Node<T> {
+    fn drop(&mut self) {
+        let mut stack: Vec<Rc<Node<T>>> = Vec::new();
+        stack.extend(self.left.take());
+        stack.extend(self.right.take());
+        while let Some(node) = stack.pop() {
+            if let Some(mut node) = Rc::into_inner(node) {
+                stack.extend(node.left.take());
+                stack.extend(node.right.take());
+            }
+        }
+    }
+}
+
+pub struct IterSorted<T> {
+    heap: LeftistHeap<T>,
+}
+
+impl<T: Clone + Ord> Iterator for IterSorted<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let min = self.heap.find_min()?.clone();
+        self.heap = self.heap.delete_min();
+        Some(min)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+impl<T: Clone + Ord> ExactSizeIterator for IterSorted<T> {}
+
+#[cfg(test)]
+mod test {
+    use super::LeftistHeap;
+
+    #[test]
+    fn basics() {
+        let empty = LeftistHeap::new();
+        assert_eq!(empty.find_min(), None);
+        assert!(empty.delete_min().is_empty());
+
+        let h1 = empty.insert(4).insert(1).insert(7);
+        let h2 = h1.insert(3);
+        let h3 = h2.delete_min();
+        assert_eq!(h1.iter_sorted().collect::<Vec<_>>(), [1, 4, 7]);
+        assert_eq!(h2.iter_sorted().collect::<Vec<_>>(), [1, 3, 4, 7]);
+        assert_eq!(h3.iter_sorted().collect::<Vec<_>>(), [3, 4, 7]);
+        assert_eq!((h1.len(), h2.len(), h3.len()), (3, 4, 3));
+
+        let (min, rest) = h3.pop_min().unwrap();
+        assert_eq!(*min, 3);
+        assert_eq!(rest.find_min(), Some(&4));
+        assert!(h1.clone().ptr_eq(&h1));
+    }
+
+    #[test]
+    fn merge_and_sort() {
+        let elems: Vec<u32> = (0..500u32)
+            .map(|i| i.wrapping_mul(2654435761) % 200)
+            .collect();
+        let (a, b) = elems.split_at(200);
+        let a: LeftistHeap<u32> = a.iter().copied().collect();
+        let b: LeftistHeap<u32> = b.iter().copied().collect();
+        let merged = a.merge(&b);
+
+        let mut sorted = elems.clone();
+        sorted.sort();
+        assert!(merged.iter_sorted().eq(sorted));
+        // Merging didn't disturb either input.
+        assert_eq!(a.len(), 200);
+        assert_eq!(b.len(), 300);
+        assert!(a.merge(&LeftistHeap::new()).ptr_eq(&a));
+    }
+
+    #[test]
+    fn long_shared_drop() {
+        // Descending inserts give a left spine as long as the heap.
+        let heap: LeftistHeap<i32> = (0..100_000).rev().collect();
+        let older = heap.delete_min().delete_min();
+        drop(heap);
+        assert_eq!(older.find_min(), Some(&2));
+        drop(older);
+    }
+}
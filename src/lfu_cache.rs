@@ -0,0 +1,359 @@
+/*
+ An LFU cache
+
+lru_cache.rs evicts whatever was used least recently. An LFU cache evicts whatever was used least
+often, which holds on to the entries that are popular in the long run even through a burst of
+one-off lookups. The trouble is keeping "least often" cheap to find. A heap keyed on use counts
+is O(log n) per get, since every get changes a count.
+
+The O(1) design (Shah, Mitra and Matani, 2010) is a list of lists. The outer list has a node per
+use count that some entry currently has, in increasing order, and each of those holds the list
+of entries with that count, most recently used first:
+
+freqs:  [1] ---------- [2] ---------- [5]
+         |              |              |
+         d <-> e        a              b <-> c
+
+map:    { a -> ([2], a's node), b -> ([5], b's node), ... }
+
+- get moves the entry from its count's list to the front of the list for count + 1. That's the
+  next node of the outer list, if it exists, or a new node linked in right after. If the old
+  list is left empty, its node is unlinked. All O(1), because each step is next to where we
+  already are.
+- Evicting takes the back (least recently used) entry of the first (least used) list. Ties in
+  use count go to recency, as in the LRU cache.
+- A new entry has been used once, so it goes in the list for count 1, which is the first node
+  if it exists.
+
+Both levels are arena_list::ArenaList, so the map holds a pair of handles: the count's node, and
+the entry's node in that count's list. Moving an entry to another list gives it a new handle, so
+the map entry is updated as it moves.
+
+As with the LRU cache, the key is kept in both the map and the entry, hence K: Clone.
+*/
+
+use crate::arena_list::{ArenaList, Handle};
+use crate::mem_usage::{self, MemUsage};
+use crate::trace;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::hash::Hash;
+
+pub struct LfuCache<K, V> {
+    // The entry's count node in `freqs`, and its node in that count's list.
+    map: HashMap<K, (Handle, Handle)>,
+    // Increasing use count.
+    freqs: ArenaList<Bucket<K, V>>,
+    capacity: usize,
+}
+
+struct Bucket<K, V> {
+    count: u64,
+    // Front is the most recently used.
+    entries: ArenaList<(K, V)>,
+}
+
+impl<K: Hash + Eq + Clone, V> LfuCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(
+            capacity > 0,
+            "an LFU cache needs room for at least one entry"
+        );
+        LfuCache {
+            map: HashMap::with_capacity(capacity),
+            freqs: ArenaList::new(),
+            capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // Each count's entries are a list of their own, and their slots are the nodes. The counts'
+    // list and the map are side tables. See mem_usage.rs.
+    pub fn mem_usage(&self) -> MemUsage {
+        // Starting from an empty list gets the slot overhead right when there are no entries.
+        let entries = self.freqs.iter().map(|bucket| bucket.entries.mem_usage());
+        let entries = entries.fold(ArenaList::<(K, V)>::new().mem_usage(), |sum, usage| {
+            MemUsage {
+                nodes: sum.nodes + usage.nodes,
+                heap_bytes: sum.heap_bytes + usage.heap_bytes,
+                ..sum
+            }
+        });
+        entries.plus(self.freqs.mem_usage().heap_bytes + mem_usage::hash_map(&self.map))
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    // How many times `key` has been used (put counts as the first use).
+    pub fn frequency<Q>(&self, key: &Q) -> Option<u64>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (bucket, _) = *self.map.get(key)?;
+        self.freqs.get(bucket).map(|bucket| bucket.count)
+    }
+
+    // Move `key`'s entry up to the next count, returning its new handles.
+    fn touch<Q>(&mut self, key: &Q) -> Option<(Handle, Handle)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (bucket, entry) = *self.map.get(key)?;
+        let from = self.freqs.get_mut(bucket).unwrap();
+        let count = from.count + 1;
+        let elem = from.entries.remove(entry).unwrap();
+        let emptied = from.entries.is_empty();
+
+        let next = self
+            .freqs
+            .next(bucket)
+            .filter(|&next| self.freqs.get(next).unwrap().count == count);
+        let to = next.unwrap_or_else(|| {
+            let new = Bucket {
+                count,
+                entries: ArenaList::new(),
+            };
+            self.freqs.insert_after(bucket, new).ok().unwrap()
+        });
+        let entry = self.freqs.get_mut(to).unwrap().entries.push_front(elem);
+        if emptied {
+            self.freqs.remove(bucket);
+        }
+        *self.map.get_mut(key).unwrap() = (to, entry);
+        Some((to, entry))
+    }
+
+    // Look `key` up and count it as a use.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_mut(key).map(|v| &*v)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let op = trace::span!("get", hit);
+        let handles = self.touch(key);
+        op.done(Some(self.len()), 0);
+        op.flag("hit", handles.is_some());
+        let (bucket, entry) = handles?;
+        let bucket = self.freqs.get_mut(bucket).unwrap();
+        bucket.entries.get_mut(entry).map(|(_, v)| v)
+    }
+
+    // Look `key` up without counting it as a use.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (bucket, entry) = *self.map.get(key)?;
+        let bucket = self.freqs.get(bucket).unwrap();
+        bucket.entries.get(entry).map(|(_, v)| v)
+    }
+
+    // Insert or overwrite `key`. Overwriting counts as a use. Returns the entry that made room
+    // for it: the old value under `key`, or the least frequently used entry if the cache was
+    // full.
+    pub fn put(&mut self, key: K, value: V) -> Option<(K, V)> {
+        let op = trace::span!("put", evicted);
+        if let Some((bucket, entry)) = self.touch(&key) {
+            let bucket = self.freqs.get_mut(bucket).unwrap();
+            let slot = bucket.entries.get_mut(entry).unwrap();
+            let old = std::mem::replace(slot, (key, value));
+            op.done(Some(self.len()), 1);
+            op.flag("evicted", false);
+            return Some(old);
+        }
+        let evicted = if self.len() == self.capacity {
+            self.pop_lfu()
+        } else {
+            None
+        };
+        let ones = self
+            .freqs
+            .front_handle()
+            .filter(|&front| self.freqs.get(front).unwrap().count == 1);
+        let bucket = ones.unwrap_or_else(|| {
+            self.freqs.push_front(Bucket {
+                count: 1,
+                entries: ArenaList::new(),
+            })
+        });
+        let entries = &mut self.freqs.get_mut(bucket).unwrap().entries;
+        let entry = entries.push_front((key.clone(), value));
+        self.map.insert(key, (bucket, entry));
+        op.done(Some(self.len()), 1);
+        op.flag("evicted", evicted.is_some());
+        evicted
+    }
+
+    // Unlink an entry, and its count's node too if that leaves it empty.
+    fn unlink(&mut self, bucket: Handle, entry: Handle) -> (K, V) {
+        let entries = &mut self.freqs.get_mut(bucket).unwrap().entries;
+        let elem = entries.remove(entry).unwrap();
+        if entries.is_empty() {
+            self.freqs.remove(bucket);
+        }
+        elem
+    }
+
+    pub fn pop<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let op = trace::span!("pop");
+        let value = self
+            .map
+            .remove(key)
+            .map(|(bucket, entry)| self.unlink(bucket, entry).1);
+        op.done(Some(self.len()), value.is_some() as usize);
+        value
+    }
+
+    // Evict the least frequently used entry (the least recently used, among equals).
+    pub fn pop_lfu(&mut self) -> Option<(K, V)> {
+        let op = trace::span!("pop_lfu");
+        let entry = self.freqs.front_handle().map(|bucket| {
+            let entry = self
+                .freqs
+                .get(bucket)
+                .unwrap()
+                .entries
+                .back_handle()
+                .unwrap();
+            let (key, value) = self.unlink(bucket, entry);
+            self.map.remove(&key);
+            (key, value)
+        });
+        op.done(Some(self.len()), entry.is_some() as usize);
+        entry
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.freqs.clear();
+    }
+
+    // In eviction order: least frequently used first.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        self.freqs
+            .iter()
+            .flat_map(|bucket| bucket.entries.iter().rev())
+            .map(|(k, v)| (k, v))
+    }
+}
+
+impl<K: Hash + Eq + Clone + Debug, V: Debug> Debug for LfuCache<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LfuCache;
+
+    fn keys(cache: &LfuCache<&'static str, i32>) -> Vec<&'static str> {
+        cache.iter().map(|(k, _)| *k).collect()
+    }
+
+    #[test]
+    fn evicts_least_frequently_used() {
+        let mut cache = LfuCache::new(3);
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+        for _ in 0..3 {
+            cache.get("a");
+        }
+        cache.get("b");
+        assert_eq!(cache.frequency("a"), Some(4));
+        assert_eq!(cache.frequency("b"), Some(2));
+        assert_eq!(keys(&cache), ["c", "b", "a"]);
+
+        // "c" has been used least.
+        assert_eq!(cache.put("d", 4), Some(("c", 3)));
+        // Now "d" has: it's new, with a count of 1.
+        assert_eq!(cache.put("e", 5), Some(("d", 4)));
+
+        // Equal counts go by recency: "b" and "e" are both at 2, and "b" was used longer ago.
+        cache.get("e");
+        assert_eq!(cache.put("f", 6), Some(("b", 2)));
+        assert_eq!(keys(&cache), ["f", "e", "a"]);
+        cache.get("f");
+        assert_eq!(keys(&cache), ["e", "f", "a"]);
+        assert_eq!(cache.pop_lfu(), Some(("e", 5)));
+        assert!(!cache.contains_key("e"));
+    }
+
+    #[test]
+    fn overwrite_and_remove() {
+        let mut cache = LfuCache::new(2);
+        cache.put("a", 1);
+        // Overwriting counts as a use, and doesn't evict anything.
+        assert_eq!(cache.put("a", 10), Some(("a", 1)));
+        assert_eq!(cache.frequency("a"), Some(2));
+        cache.put("b", 2);
+
+        // peek doesn't count.
+        assert_eq!(cache.peek("b"), Some(&2));
+        assert_eq!(cache.frequency("b"), Some(1));
+        *cache.get_mut("b").unwrap() += 1;
+        // Both at 2 now, and "a" was used longer ago, so it'd go first.
+        assert_eq!(format!("{:?}", cache), r#"{"a": 10, "b": 3}"#);
+
+        assert_eq!(cache.pop("a"), Some(10));
+        assert_eq!(cache.pop("a"), None);
+        assert_eq!(cache.len(), 1);
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.get("b"), None);
+        cache.put("c", 3);
+        assert_eq!(cache.frequency("c"), Some(1));
+    }
+
+    #[test]
+    fn mem_usage() {
+        let mut cache = LfuCache::new(3);
+        let empty = cache.mem_usage();
+        assert_eq!(empty.nodes, 0);
+        assert!(empty.node_overhead > 0);
+
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+        cache.get("a");
+        // Three entries, in two counts' lists.
+        let usage = cache.mem_usage();
+        assert_eq!(usage.nodes, 3);
+        assert_eq!(usage.node_overhead, empty.node_overhead);
+        assert!(usage.heap_bytes >= empty.heap_bytes + 3 * (16 + usage.node_overhead));
+    }
+}
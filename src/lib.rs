@@ -1,8 +1,78 @@
 // in lib.rs
 
+#![cfg_attr(
+    feature = "nightly",
+    feature(dropck_eyepatch, allocator_api, ptr_metadata)
+)]
+
+pub mod allocator;
+pub mod arc_persistent_stack;
+pub mod arena_list;
+mod assertions;
+pub mod async_channel;
 pub mod basic_impl;
+pub mod blocking_queue;
+pub mod chained_hash_map;
+pub mod circular_list;
+pub mod counting_alloc;
+#[cfg(feature = "cxx")]
+pub mod cxx_bridge;
+pub mod diff;
 pub mod doubly_linked_deque;
+pub mod elimination_stack;
+pub mod epoch;
+pub mod flat_combining_queue;
+pub mod format;
+pub mod free_list_allocator;
 pub mod generic_and_iterators;
+pub mod ghost_list;
+pub mod graph;
+pub mod harris_list;
+pub mod heapless_list;
+pub mod history;
+pub mod indexed_list;
+pub mod instrumented;
+pub mod intrusive_list;
+pub mod leftist_heap;
+pub mod lfu_cache;
+pub mod linked_hash_map;
+pub mod lru_cache;
+pub mod mem_usage;
+pub mod mpsc_channel;
+pub mod ms_queue;
+pub mod observer;
+pub mod pairing_heap;
+mod panic_safety;
 pub mod persistent_stack;
+pub mod piece_table;
+pub mod pointer_list;
+pub mod polynomial;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod recorder;
+pub mod rope;
+pub mod seg_queue;
+pub mod self_organizing_list;
+pub mod skip_list;
+pub mod slab;
+pub mod small_list;
+pub mod sorted_list;
+pub mod spsc_queue;
 pub mod stacked_borrows;
+mod sync;
+pub mod tagged_ptr;
+pub mod timer_wheel;
+mod trace;
+pub mod traits;
+pub mod treiber_stack;
+pub mod unrolled_list;
+pub mod unsafe_doubly_linked_deque;
 pub mod unsafe_single_linked_queue;
+pub mod validate;
+pub mod vlist;
+pub mod wait_list;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod xor_list;
+
+pub use traits::{Collection, Deque, Queue, Stack};
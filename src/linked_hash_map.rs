@@ -0,0 +1,318 @@
+/*
+ A LinkedHashMap
+
+A HashMap that remembers the order entries went in. The entries are chained through a doubly
+linked list in insertion order, and the map points each key at its node:
+
+map:  { "x" -> h2, "a" -> h0, "m" -> h1 }
+list: front (oldest) [a] <-> [m] <-> [x] back (newest)
+
+So iteration is in insertion order and doesn't depend on the hasher, and both ends can be popped
+in O(1): pop_front removes the oldest entry, pop_back the newest. Removing by key is O(1) too,
+since the map hands us the node directly. Re-inserting a key that's already present replaces the
+value in place; it doesn't move the entry.
+
+It's the same HashMap-of-handles-into-an-ArenaList layout as lru_cache.rs, and with move on
+access switched on (with_move_on_access) it behaves like one: get moves the entry to the back, so
+the front is always the least recently used and pop_front is eviction. Without it, get leaves the
+order alone. Either way get takes &mut self; peek is the read that never moves anything.
+*/
+
+use crate::arena_list::{self, ArenaList, Handle};
+use crate::mem_usage::{self, MemUsage};
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::hash::Hash;
+
+pub struct LinkedHashMap<K, V> {
+    map: HashMap<K, Handle>,
+    // Front is the oldest entry (or least recently used, with move_on_access).
+    list: ArenaList<(K, V)>,
+    move_on_access: bool,
+}
+
+impl<K: Hash + Eq + Clone, V> LinkedHashMap<K, V> {
+    pub fn new() -> Self {
+        LinkedHashMap {
+            map: HashMap::new(),
+            list: ArenaList::new(),
+            move_on_access: false,
+        }
+    }
+
+    // get, get_mut and insert of an existing key move the entry to the back.
+    pub fn with_move_on_access() -> Self {
+        LinkedHashMap {
+            move_on_access: true,
+            ..Self::new()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    // The list's, with the map as a side table. See mem_usage.rs.
+    pub fn mem_usage(&self) -> MemUsage {
+        self.list.mem_usage().plus(mem_usage::hash_map(&self.map))
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    fn accessed(&mut self, handle: Handle) {
+        if self.move_on_access {
+            self.list.move_to_back(handle);
+        }
+    }
+
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_mut(key).map(|v| &*v)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let handle = *self.map.get(key)?;
+        self.accessed(handle);
+        self.list.get_mut(handle).map(|(_, v)| v)
+    }
+
+    // Never counts as an access.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let handle = *self.map.get(key)?;
+        self.list.get(handle).map(|(_, v)| v)
+    }
+
+    // New keys go at the back. Returns the old value if `key` was already there.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(&handle) = self.map.get(&key) {
+            self.accessed(handle);
+            let (_, v) = self.list.get_mut(handle).unwrap();
+            return Some(std::mem::replace(v, value));
+        }
+        let handle = self.list.push_back((key.clone(), value));
+        self.map.insert(key, handle);
+        None
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let handle = self.map.remove(key)?;
+        self.list.remove(handle).map(|(_, v)| v)
+    }
+
+    // Move `key`'s entry to the back, as if it had just been inserted. False if it isn't there.
+    pub fn move_to_back<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.map.get(key) {
+            Some(&handle) => self.list.move_to_back(handle),
+            None => false,
+        }
+    }
+
+    pub fn front(&self) -> Option<(&K, &V)> {
+        self.list.front().map(|(k, v)| (k, v))
+    }
+
+    pub fn back(&self) -> Option<(&K, &V)> {
+        self.list.back().map(|(k, v)| (k, v))
+    }
+
+    pub fn pop_front(&mut self) -> Option<(K, V)> {
+        let (key, value) = self.list.pop_front()?;
+        self.map.remove(&key);
+        Some((key, value))
+    }
+
+    pub fn pop_back(&mut self) -> Option<(K, V)> {
+        let (key, value) = self.list.pop_back()?;
+        self.map.remove(&key);
+        Some((key, value))
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.list.clear();
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.list.iter(),
+        }
+    }
+
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &K> + '_ {
+        self.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> + '_ {
+        self.iter().map(|(_, v)| v)
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Default for LinkedHashMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Extend<(K, V)> for LinkedHashMap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> FromIterator<(K, V)> for LinkedHashMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = LinkedHashMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: Hash + Eq + Clone + Debug, V: Debug> Debug for LinkedHashMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    inner: arena_list::Iter<'a, (K, V)>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, v)| (k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(k, v)| (k, v))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+// Oldest first.
+pub struct IntoIter<K, V> {
+    inner: arena_list::IntoIter<(K, V)>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back()
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+impl<K, V> IntoIterator for LinkedHashMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            inner: self.list.into_iter(),
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq + Clone, V> IntoIterator for &'a LinkedHashMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LinkedHashMap;
+
+    #[test]
+    fn insertion_order() {
+        let mut map: LinkedHashMap<_, _> = [("x", 1), ("a", 2), ("m", 3)].into_iter().collect();
+        assert_eq!(map.keys().copied().collect::<Vec<_>>(), ["x", "a", "m"]);
+
+        // Overwriting and reading leave the order alone.
+        assert_eq!(map.insert("x", 10), Some(1));
+        assert_eq!(map.get("a"), Some(&2));
+        assert_eq!(format!("{:?}", map), r#"{"x": 10, "a": 2, "m": 3}"#);
+
+        // Removing from the middle, then re-inserting, puts it at the back.
+        assert_eq!(map.remove("a"), Some(2));
+        map.insert("a", 20);
+        assert_eq!(map.values().copied().collect::<Vec<_>>(), [10, 3, 20]);
+
+        assert_eq!(map.front(), Some((&"x", &10)));
+        assert_eq!(map.pop_front(), Some(("x", 10)));
+        assert_eq!(map.pop_back(), Some(("a", 20)));
+        assert!(!map.contains_key("a"));
+        assert!(map.move_to_back("m"));
+        assert_eq!(map.into_iter().collect::<Vec<_>>(), [("m", 3)]);
+    }
+
+    #[test]
+    fn move_on_access() {
+        let mut map = LinkedHashMap::with_move_on_access();
+        map.extend([(1, 'a'), (2, 'b'), (3, 'c')]);
+
+        assert_eq!(map.get(&1), Some(&'a'));
+        map.insert(2, 'B');
+        assert_eq!(map.keys().copied().collect::<Vec<_>>(), [3, 1, 2]);
+
+        // peek isn't an access, so 3 is still the least recently used.
+        assert_eq!(map.peek(&3), Some(&'c'));
+        assert_eq!(map.pop_front(), Some((3, 'c')));
+        assert_eq!(map.len(), 2);
+    }
+}
@@ -0,0 +1,256 @@
+/*
+ An LRU cache
+
+The textbook real-world use of a doubly linked list. A cache with room for N entries that, when
+full, evicts the entry that was used least recently. It needs two things at once:
+
+- lookup by key, which is a HashMap;
+- the entries in order of last use, with "move this one to the front" and "drop the one at the
+  back" both O(1). A Vec can't do that, since moving an entry to the front shifts everything
+  else, but a doubly linked list can, given a way to get at the entry's node directly.
+
+So the map doesn't hold the values. It holds a handle to the entry's node in the list:
+
+map:  { "b" -> h1, "a" -> h0, "c" -> h2 }
+list: front (most recent) [c] <-> [a] <-> [b] back (least recent)
+
+get looks the key up, moves its node to the front, and returns the value; put at capacity pops
+the back node and removes its key from the map. Both are O(1).
+
+The list is arena_list::ArenaList, whose Handles stay valid while nodes move around and can't be
+confused with a later entry that reuses the same slot. With a pointer-based list, the map would
+hold raw node pointers and all of this would be unsafe.
+
+The key lives in both the map and the node (the node needs it to find the map entry on
+eviction), hence K: Clone. Cheap for the usual integer or Rc<str> keys.
+*/
+
+use crate::arena_list::{self, ArenaList, Handle};
+use crate::mem_usage::{self, MemUsage};
+use crate::trace;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::hash::Hash;
+
+pub struct LruCache<K, V> {
+    map: HashMap<K, Handle>,
+    // Front is the most recently used.
+    list: ArenaList<(K, V)>,
+    capacity: usize,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(
+            capacity > 0,
+            "an LRU cache needs room for at least one entry"
+        );
+        LruCache {
+            map: HashMap::with_capacity(capacity),
+            list: ArenaList::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // The list's, with the map as a side table. See mem_usage.rs.
+    pub fn mem_usage(&self) -> MemUsage {
+        self.list.mem_usage().plus(mem_usage::hash_map(&self.map))
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    // Look `key` up and mark it as just used.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_mut(key).map(|v| &*v)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let op = trace::span!("get", hit);
+        let handle = self.map.get(key).copied();
+        op.done(Some(self.len()), 0);
+        op.flag("hit", handle.is_some());
+        let handle = handle?;
+        self.list.move_to_front(handle);
+        self.list.get_mut(handle).map(|(_, v)| v)
+    }
+
+    // Look `key` up without changing its place in line.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let handle = *self.map.get(key)?;
+        self.list.get(handle).map(|(_, v)| v)
+    }
+
+    // Insert or overwrite `key` as the most recently used entry. Returns the entry that made
+    // room for it: the old value under `key`, or the least recently used entry if the cache was
+    // full.
+    pub fn put(&mut self, key: K, value: V) -> Option<(K, V)> {
+        let op = trace::span!("put", evicted);
+        if let Some(&handle) = self.map.get(&key) {
+            self.list.move_to_front(handle);
+            let entry = self.list.get_mut(handle).unwrap();
+            let old = std::mem::replace(entry, (key, value));
+            op.done(Some(self.len()), 1);
+            op.flag("evicted", false);
+            return Some(old);
+        }
+        let evicted = if self.len() == self.capacity {
+            self.pop_lru()
+        } else {
+            None
+        };
+        let handle = self.list.push_front((key.clone(), value));
+        self.map.insert(key, handle);
+        op.done(Some(self.len()), 1);
+        op.flag("evicted", evicted.is_some());
+        evicted
+    }
+
+    pub fn pop<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let op = trace::span!("pop");
+        let value = self
+            .map
+            .remove(key)
+            .and_then(|handle| self.list.remove(handle))
+            .map(|(_, v)| v);
+        op.done(Some(self.len()), value.is_some() as usize);
+        value
+    }
+
+    // Evict the least recently used entry.
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        let op = trace::span!("pop_lru");
+        let entry = self.list.pop_back();
+        if let Some((key, _)) = &entry {
+            self.map.remove(key);
+        }
+        op.done(Some(self.len()), entry.is_some() as usize);
+        entry
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.list.clear();
+    }
+
+    // Most recently used first.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.list.iter(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone + Debug, V: Debug> Debug for LruCache<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    inner: arena_list::Iter<'a, (K, V)>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, v)| (k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(k, v)| (k, v))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+#[cfg(test)]
+mod test {
+    use super::LruCache;
+
+    fn keys(cache: &LruCache<&'static str, i32>) -> Vec<&'static str> {
+        cache.iter().map(|(k, _)| *k).collect()
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        assert_eq!(cache.put("a", 1), None);
+        assert_eq!(cache.put("b", 2), None);
+        assert_eq!(keys(&cache), ["b", "a"]);
+
+        // Using "a" makes "b" the one to go.
+        assert_eq!(cache.get("a"), Some(&1));
+        assert_eq!(cache.put("c", 3), Some(("b", 2)));
+        assert_eq!(keys(&cache), ["c", "a"]);
+        assert!(!cache.contains_key("b"));
+
+        // peek doesn't count as a use.
+        assert_eq!(cache.peek("a"), Some(&1));
+        assert_eq!(cache.put("d", 4), Some(("a", 1)));
+        assert_eq!(format!("{:?}", cache), r#"{"d": 4, "c": 3}"#);
+    }
+
+    #[test]
+    fn overwrite_and_remove() {
+        let mut cache = LruCache::new(3);
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+
+        // Overwriting doesn't evict, but does count as a use.
+        assert_eq!(cache.put("a", 10), Some(("a", 1)));
+        assert_eq!(keys(&cache), ["a", "c", "b"]);
+        *cache.get_mut("b").unwrap() += 1;
+        assert_eq!(cache.iter().next_back(), Some((&"c", &3)));
+
+        assert_eq!(cache.pop("c"), Some(3));
+        assert_eq!(cache.pop("c"), None);
+        assert_eq!(cache.pop_lru(), Some(("a", 10)));
+        assert_eq!(cache.len(), 1);
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.get("b"), None);
+    }
+}
@@ -0,0 +1,139 @@
+/*
+ Memory footprint
+
+"A linked list has a pointer of overhead per element" is the textbook line, and it's rarely the
+whole story. A Box<Node<u8>> is 16 bytes for one byte of element, an Rc node carries two counts
+on top, an arena slot carries a generation, and an unrolled list's overhead depends on how full
+its chunks are. mem_usage() on each list says what that list actually costs with the data it
+actually has, so two representations can be compared on real data rather than guessed at:
+
+    let usage = list.mem_usage();
+    usage.nodes          // allocations (or arena slots) holding elements
+    usage.node_overhead  // bytes of each that aren't element: links, counts, padding
+    usage.heap_bytes     // everything the list has allocated
+
+heap_bytes is the list's own memory: nodes, and for the lists that have them, spare capacity
+(an arena's vacant slots, an unrolled chunk's empty slots, a pooled queue's free nodes), side
+tables and skip-list towers. It doesn't follow pointers inside the elements, so a list of Strings
+counts the Strings but not their text. It counts what was asked of the allocator, not what the
+allocator kept for itself on top.
+
+Nodes shared between lists are the Rc-based lists' whole point, and are where adding up
+mem_usage() over several lists goes wrong: the shared part is counted once per list. So
+persistent_stack::ListSet has its own, which counts each node once however many lists it's in.
+
+The caches and linked_hash_map keep a HashMap beside their list, and count it as a side table of
+capacity() entries with a control byte each. That's a little low: std doesn't say how big its
+table really is, only how many entries fit before it grows, and it rounds the table up from that.
+
+Not every list has one. intrusive_list's nodes belong to the caller, and heapless_list doesn't
+allocate at all, so for those size_of says everything. Neither do the concurrent queues and
+stacks, where the count would be stale before it was returned.
+*/
+
+use std::alloc::Layout;
+use std::collections::HashMap;
+use std::mem::size_of;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemUsage {
+    pub nodes: usize,
+    pub node_overhead: usize,
+    pub heap_bytes: usize,
+}
+
+impl MemUsage {
+    // `nodes` allocations of N each, each holding `per_node` Ts.
+    pub(crate) fn nodes<N, T>(nodes: usize, per_node: usize) -> MemUsage {
+        MemUsage::sized::<T>(nodes, size_of::<N>(), per_node)
+    }
+
+    // The same, with each N behind an Rc or Arc, which puts its two counts in front of it.
+    pub(crate) fn rc_nodes<N, T>(nodes: usize) -> MemUsage {
+        MemUsage::sized::<T>(nodes, rc_size::<N>(), 1)
+    }
+
+    pub(crate) fn sized<T>(nodes: usize, node_bytes: usize, per_node: usize) -> MemUsage {
+        MemUsage {
+            nodes,
+            node_overhead: node_bytes - per_node * size_of::<T>(),
+            heap_bytes: nodes * node_bytes,
+        }
+    }
+
+    // Plus memory that isn't in the nodes.
+    pub(crate) fn plus(self, heap_bytes: usize) -> MemUsage {
+        MemUsage {
+            heap_bytes: self.heap_bytes + heap_bytes,
+            ..self
+        }
+    }
+}
+
+// An Rc<T>'s allocation (and an Arc's): the strong and weak counts, then T.
+pub(crate) fn rc_layout<T>() -> Layout {
+    let (layout, _) = Layout::new::<[usize; 2]>()
+        .extend(Layout::new::<T>())
+        .unwrap();
+    layout.pad_to_align()
+}
+
+pub(crate) fn rc_size<T>() -> usize {
+    rc_layout::<T>().size()
+}
+
+// A HashMap's table, at least: see above.
+pub(crate) fn hash_map<K, V, S>(map: &HashMap<K, V, S>) -> usize {
+    map.capacity() * (size_of::<(K, V)>() + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::rc_size;
+    use crate::arena_list::ArenaList;
+    use crate::unrolled_list::UnrolledList;
+    use crate::{generic_and_iterators, persistent_stack, unsafe_doubly_linked_deque};
+    use std::mem::size_of;
+
+    #[test]
+    fn rc_counts() {
+        assert_eq!(rc_size::<u8>(), 3 * size_of::<usize>());
+        assert_eq!(rc_size::<[u64; 2]>(), 2 * size_of::<usize>() + 16);
+    }
+
+    #[test]
+    fn compare() {
+        const WORD: usize = size_of::<usize>();
+        let elems = 0..100u64;
+
+        // One pointer a node for the stack, two for the deque, and two counts on top of the stack's
+        // for the Rc version.
+        let mut stack = generic_and_iterators::List::new();
+        for x in elems.clone() {
+            stack.push(x);
+        }
+        let usage = stack.mem_usage();
+        assert_eq!(usage.nodes, 100);
+        assert_eq!(usage.node_overhead, WORD);
+        assert_eq!(usage.heap_bytes, 100 * (8 + WORD));
+
+        let deque: unsafe_doubly_linked_deque::List<u64> = elems.clone().collect();
+        assert_eq!(deque.mem_usage().node_overhead, 2 * WORD);
+
+        let persistent = persistent_stack::List::new().cons_all(elems.clone());
+        assert_eq!(persistent.mem_usage().node_overhead, 3 * WORD);
+
+        // An arena's slots are its nodes, and the spare capacity counts.
+        let mut arena = ArenaList::with_capacity(128);
+        arena.extend(elems.clone());
+        let usage = arena.mem_usage();
+        assert_eq!(usage.nodes, 100);
+        assert_eq!(usage.heap_bytes, 128 * (8 + usage.node_overhead));
+
+        // 16 elements a chunk amortise two links and a length between them.
+        let unrolled: UnrolledList<u64, 16> = elems.collect();
+        let usage = unrolled.mem_usage();
+        assert_eq!(usage.node_overhead, 3 * WORD);
+        assert!(usage.heap_bytes < stack.mem_usage().heap_bytes);
+    }
+}
@@ -0,0 +1,439 @@
+/*
+ A multi-producer, single-consumer channel on a linked list (Vyukov's MPSC queue)
+
+The queue is singly linked from oldest to newest, with a "stub" node at the oldest end whose value
+has already been taken:
+
+tail -> stub -> A -> B -> C <- head
+(consumer)                  (producers)
+
+- Producers push with a single atomic swap on `head`, then link the previous head to the new
+  node. No CAS loops, so a producer always finishes in a bounded number of steps.
+- The one consumer pops by following `tail.next`: that node's value is taken out and the node
+  becomes the new stub, and the old stub is freed. Only the consumer ever touches `tail`, so it
+  needs no atomics at all.
+- Between a producer's swap and its link, the chain is briefly broken (head moved on, but the old
+  head's `next` is still null). The consumer sees that as "inconsistent" and just waits for the
+  link to appear, since the producer is guaranteed to be about to write it.
+
+channel() wraps that in Sender/Receiver handles. Senders are counted so the receiver can tell
+"empty for now" from "empty forever", and a blocked recv() parks the receiving thread until a
+sender wakes it up. That wakeup is a plain Waker, so with the `async` feature the Receiver is
+also a Stream: an async task registers its own Waker in exactly the same slot.
+*/
+
+use crate::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use crate::sync::thread::{self, Thread};
+use crate::sync::{hint, Arc, Mutex};
+use crate::trace;
+use std::cell::{Cell, UnsafeCell};
+use std::fmt;
+use std::marker::PhantomData;
+use std::ptr;
+use std::task::{Wake, Waker};
+#[cfg(feature = "async")]
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+struct Node<T> {
+    next: AtomicPtr<Node<T>>,
+    value: Option<T>,
+}
+
+impl<T> Node<T> {
+    fn new(value: Option<T>) -> *mut Node<T> {
+        Box::into_raw(Box::new(Node {
+            next: AtomicPtr::new(ptr::null_mut()),
+            value,
+        }))
+    }
+}
+
+enum Pop<T> {
+    Data(T),
+    Empty,
+    // A producer has swapped `head` but not linked its node in yet.
+    Inconsistent,
+}
+
+struct Inner<T> {
+    head: AtomicPtr<Node<T>>,
+    // Only the receiver reads or writes this.
+    tail: UnsafeCell<*mut Node<T>>,
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool,
+    // Set while the receiver is (about to be) parked in recv(), or waiting as a Stream.
+    waiting: AtomicBool,
+    receiver: Mutex<Option<Waker>>,
+}
+
+unsafe impl<T: Send> Send for Inner<T> {}
+unsafe impl<T: Send> Sync for Inner<T> {}
+
+impl<T> Inner<T> {
+    fn push(&self, value: T) {
+        let node = Node::new(Some(value));
+        let prev = self.head.swap(node, Ordering::AcqRel);
+        // Until this store, the consumer can't see `node` (see Pop::Inconsistent).
+        unsafe { (*prev).next.store(node, Ordering::Release) };
+    }
+
+    // Safety: only the single consumer may call this.
+    unsafe fn pop(&self) -> Pop<T> {
+        let tail = *self.tail.get();
+        let next = (*tail).next.load(Ordering::Acquire);
+        if next.is_null() {
+            return if self.head.load(Ordering::Acquire) == tail {
+                Pop::Empty
+            } else {
+                Pop::Inconsistent
+            };
+        }
+
+        *self.tail.get() = next;
+        let value = (*next).value.take();
+        drop(Box::from_raw(tail));
+        Pop::Data(value.expect("non-stub node without a value"))
+    }
+
+    // Safety: only the single consumer may call this.
+    unsafe fn pop_consistent(&self) -> Option<T> {
+        loop {
+            match self.pop() {
+                Pop::Data(value) => return Some(value),
+                Pop::Empty => return None,
+                Pop::Inconsistent => hint::spin_loop(),
+            }
+        }
+    }
+
+    // Nothing pushed, and no push in progress either.
+    fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire) == unsafe { *self.tail.get() }
+    }
+
+    // Announce that the receiver is about to wait, and how to wake it. The caller must check for
+    // values once more afterwards, so a send that raced with us isn't lost.
+    fn register(&self, waker: Waker) {
+        *self.receiver.lock().unwrap() = Some(waker);
+        self.waiting.store(true, Ordering::Relaxed);
+        // Pairs with the fence in send(): either the sender sees `waiting`, or we see its node.
+        fence(Ordering::SeqCst);
+    }
+
+    fn wake_receiver(&self) {
+        if let Some(waker) = self.receiver.lock().unwrap().as_ref() {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+// Lets a parked thread stand in for a Waker, so recv() and the Stream impl share one wakeup path.
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: std::sync::Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &std::sync::Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        // Nobody else is left, so just walk from the stub and free everything.
+        let mut cur = *self.tail.get_mut();
+        while !cur.is_null() {
+            let node = unsafe { Box::from_raw(cur) };
+            cur = node.next.load(Ordering::Relaxed);
+        }
+    }
+}
+
+pub struct Sender<T> {
+    inner: Arc<Inner<T>>,
+}
+
+// The receiver is the consumer side (the unsynchronized `tail`), so it may move between threads
+// but must never be shared: the Cell marker makes it !Sync.
+pub struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let stub = Node::new(None);
+    let inner = Arc::new(Inner {
+        head: AtomicPtr::new(stub),
+        tail: UnsafeCell::new(stub),
+        senders: AtomicUsize::new(1),
+        receiver_alive: AtomicBool::new(true),
+        waiting: AtomicBool::new(false),
+        receiver: Mutex::new(None),
+    });
+    (
+        Sender {
+            inner: inner.clone(),
+        },
+        Receiver {
+            inner,
+            _not_sync: PhantomData,
+        },
+    )
+}
+
+// The value couldn't be sent because the receiver is gone; here it is back.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TryRecvError {
+    Empty,
+    Disconnected,
+}
+
+// All senders are gone and the queue is drained.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RecvError;
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sending on a channel with no receiver")
+    }
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => f.write_str("receiving on an empty channel"),
+            TryRecvError::Disconnected => f.write_str("receiving on a closed channel"),
+        }
+    }
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("receiving on a closed channel")
+    }
+}
+
+impl<T> Sender<T> {
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let op = trace::span!("send");
+        if !self.inner.receiver_alive.load(Ordering::Acquire) {
+            op.done(None, 0);
+            return Err(SendError(value));
+        }
+        self.inner.push(value);
+        op.done(None, 1);
+
+        // Pairs with the fence in register(): either we see `waiting` and wake the receiver, or
+        // the receiver sees our node before it goes to sleep.
+        fence(Ordering::SeqCst);
+        if self.inner.waiting.load(Ordering::Relaxed) {
+            self.inner.wake_receiver();
+        }
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.inner.senders.fetch_add(1, Ordering::Relaxed);
+        Sender {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.inner.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // Last sender: a parked receiver needs to find out it's disconnected.
+            self.inner.wake_receiver();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let op = trace::span!("try_recv");
+        // Receiver is !Sync, so `&self` here means we're the only consumer.
+        let value = match unsafe { self.inner.pop_consistent() } {
+            Some(value) => Ok(value),
+            // The last sender may have pushed right before dropping; look once more.
+            None if self.inner.senders.load(Ordering::Acquire) == 0 => {
+                unsafe { self.inner.pop_consistent() }.ok_or(TryRecvError::Disconnected)
+            }
+            None => Err(TryRecvError::Empty),
+        };
+        op.done(None, value.is_ok() as usize);
+        value
+    }
+
+    // Block until a value arrives, or every sender is gone.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let op = trace::span!("recv", waited);
+        let mut waited = false;
+        let value = loop {
+            match self.try_recv() {
+                Ok(value) => break Ok(value),
+                Err(TryRecvError::Disconnected) => break Err(RecvError),
+                Err(TryRecvError::Empty) => {}
+            }
+
+            // The receiver may have moved threads since last time, so always re-register.
+            let waker = std::sync::Arc::new(ThreadWaker(thread::current()));
+            self.inner.register(Waker::from(waker));
+
+            let ready = !self.inner.is_empty() || self.inner.senders.load(Ordering::Acquire) == 0;
+            if !ready {
+                // A stale unpark token only means one extra trip around the loop.
+                waited = true;
+                thread::park();
+            }
+            self.inner.waiting.store(false, Ordering::Relaxed);
+        };
+        op.done(None, value.is_ok() as usize);
+        op.flag("waited", waited);
+        value
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(move || self.recv().ok())
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.inner.receiver_alive.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T> futures_core::Stream for Receiver<T> {
+    type Item = T;
+
+    // Ends once every sender is gone and the queue is drained, just like iter().
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        match self.try_recv() {
+            Ok(value) => return Poll::Ready(Some(value)),
+            Err(TryRecvError::Disconnected) => return Poll::Ready(None),
+            Err(TryRecvError::Empty) => {}
+        }
+
+        // The same dance as recv(), except that instead of parking we return Pending and get
+        // polled again once a sender wakes the task.
+        self.inner.register(cx.waker().clone());
+        match self.try_recv() {
+            Ok(value) => Poll::Ready(Some(value)),
+            Err(TryRecvError::Disconnected) => Poll::Ready(None),
+            Err(TryRecvError::Empty) => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{channel, RecvError, SendError, TryRecvError};
+    use std::thread;
+
+    #[test]
+    fn basics() {
+        let (tx, rx) = channel();
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+        tx.send(3).unwrap();
+        drop(tx);
+        // Values sent before the disconnect are still delivered.
+        assert_eq!(rx.recv(), Ok(3));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(rx.recv(), Err(RecvError));
+    }
+
+    #[test]
+    fn receiver_gone() {
+        let (tx, rx) = channel();
+        tx.send(String::from("dropped with the queue")).unwrap();
+        drop(rx);
+        assert_eq!(tx.send("late".into()), Err(SendError("late".into())));
+    }
+
+    #[test]
+    fn many_producers() {
+        let (tx, rx) = channel();
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        tx.send(t * 1000 + i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+
+        // recv() blocks while producers are still running and ends once they've all hung up.
+        let mut got: Vec<i32> = rx.iter().collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(got.len(), 4000);
+
+        // Each producer's values arrive in the order it sent them.
+        for t in 0..4 {
+            let mine: Vec<_> = got.iter().filter(|&&v| v / 1000 == t).copied().collect();
+            assert_eq!(mine, (t * 1000..(t + 1) * 1000).collect::<Vec<_>>());
+        }
+        got.sort();
+        assert_eq!(got, (0..4000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn receiver_moves_threads() {
+        let (tx, rx) = channel();
+        let consumer = thread::spawn(move || rx.recv());
+        thread::sleep(std::time::Duration::from_millis(10));
+        tx.send(7).unwrap();
+        assert_eq!(consumer.join().unwrap(), Ok(7));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn stream() {
+        use futures::executor::block_on;
+        use futures::StreamExt;
+
+        let (tx, rx) = channel();
+        let producers: Vec<_> = (0..2)
+            .map(|t| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        tx.send(t * 100 + i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+
+        // The task sleeps whenever the queue runs dry, and the stream ends when the senders do.
+        let mut got: Vec<i32> = block_on(rx.collect());
+        for p in producers {
+            p.join().unwrap();
+        }
+        got.sort();
+        assert_eq!(got, (0..200).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,222 @@
+/*
+ A lock-free FIFO queue (Michael & Scott, 1996)
+
+The classic multi-producer, multi-consumer queue: one node per element, with a dummy node at the
+front so `head` and `tail` never have to be null:
+
+head -> dummy -> A -> B -> C <- tail
+
+- push CASes the last node's `next` from null to the new node, then swings `tail` over to it.
+- pop CASes `head` one node forward. The node it lands on becomes the new dummy, and its value is
+  the one we return. The old dummy is unlinked and goes to the epoch collector.
+- `tail` can lag one node behind the real end (after the first CAS of a push, before the
+  second). Anyone who notices that finishes the push's job and swings `tail` forward themselves,
+  so nobody ever waits on another thread.
+*/
+
+use crate::epoch;
+use crate::sync::atomic::{AtomicPtr, Ordering};
+use crate::trace;
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr;
+
+pub struct MsQueue<T> {
+    head: AtomicPtr<Node<T>>,
+    tail: AtomicPtr<Node<T>>,
+    _boo: PhantomData<T>,
+}
+
+struct Node<T> {
+    // Uninitialized in the dummy: its value was already taken by the pop that made it the dummy.
+    value: MaybeUninit<T>,
+    next: AtomicPtr<Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn new(value: MaybeUninit<T>) -> *mut Node<T> {
+        Box::into_raw(Box::new(Node {
+            value,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+unsafe impl<T: Send> Send for MsQueue<T> {}
+unsafe impl<T: Send> Sync for MsQueue<T> {}
+
+impl<T> MsQueue<T> {
+    pub fn new() -> Self {
+        let dummy = Node::new(MaybeUninit::uninit());
+        MsQueue {
+            head: AtomicPtr::new(dummy),
+            tail: AtomicPtr::new(dummy),
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn push(&self, value: T) {
+        let op = trace::span!("push");
+        let node = Node::new(MaybeUninit::new(value));
+        let _guard = epoch::pin();
+        loop {
+            let tail = self.tail.load(Ordering::Acquire);
+            let next = unsafe { (*tail).next.load(Ordering::Acquire) };
+            if !next.is_null() {
+                // `tail` is lagging; help it along and try again.
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+            if unsafe { &(*tail).next }
+                .compare_exchange(next, node, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                // Linked in. If this fails, someone already helped.
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, node, Ordering::Release, Ordering::Relaxed);
+                op.done(None, 1);
+                return;
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let op = trace::span!("pop");
+        let guard = epoch::pin();
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            let next = unsafe { (*head).next.load(Ordering::Acquire) };
+            if next.is_null() {
+                op.done(None, 0);
+                return None;
+            }
+            // Never let `head` pass `tail`: the old dummy is about to be freed, and `tail` must
+            // not be left pointing at it.
+            let tail = self.tail.load(Ordering::Acquire);
+            if head == tail {
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                unsafe {
+                    // `next` is the new dummy; we're the only one who gets to take its value.
+                    let value = (*next).value.assume_init_read();
+                    guard.defer_destroy(head);
+                    op.done(None, 1);
+                    return Some(value);
+                }
+            }
+        }
+    }
+
+    // Just a snapshot: other threads may push or pop right after.
+    pub fn is_empty(&self) -> bool {
+        let _guard = epoch::pin();
+        let head = self.head.load(Ordering::Acquire);
+        unsafe { (*head).next.load(Ordering::Acquire).is_null() }
+    }
+}
+
+impl<T> Default for MsQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for MsQueue<T> {
+    fn drop(&mut self) {
+        // The dummy's value is gone; every node after it still has one.
+        let dummy = unsafe { Box::from_raw(self.head.load(Ordering::Relaxed)) };
+        let mut cur = dummy.next.load(Ordering::Relaxed);
+        while !cur.is_null() {
+            let mut node = unsafe { Box::from_raw(cur) };
+            unsafe { node.value.assume_init_drop() };
+            cur = node.next.load(Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MsQueue;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn basics() {
+        let queue = MsQueue::new();
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+
+        queue.push(1);
+        queue.push(2);
+        assert_eq!(queue.pop(), Some(1));
+        queue.push(3);
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(3));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn drop_remaining() {
+        let queue = MsQueue::new();
+        for word in ["a", "b", "c"] {
+            queue.push(String::from(word));
+        }
+        assert_eq!(queue.pop().as_deref(), Some("a"));
+        drop(queue);
+    }
+
+    #[test]
+    fn threads() {
+        let queue = Arc::new(MsQueue::new());
+        let producers: Vec<_> = (0..2)
+            .map(|t| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        queue.push(t * 1000 + i);
+                    }
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..2)
+            .map(|_| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    let mut got = Vec::new();
+                    while got.len() < 1000 {
+                        if let Some(v) = queue.pop() {
+                            got.push(v);
+                        }
+                    }
+                    got
+                })
+            })
+            .collect();
+        for p in producers {
+            p.join().unwrap();
+        }
+
+        let got: Vec<Vec<i32>> = consumers.into_iter().map(|c| c.join().unwrap()).collect();
+        // FIFO per producer: each consumer sees a producer's values in increasing order.
+        for mine in &got {
+            for t in 0..2 {
+                let from_t: Vec<_> = mine.iter().filter(|&&v| v / 1000 == t).collect();
+                assert!(from_t.windows(2).all(|w| w[0] < w[1]));
+            }
+        }
+        let mut all: Vec<i32> = got.into_iter().flatten().collect();
+        all.sort();
+        assert_eq!(all, (0..2000).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,262 @@
+/*
+ Observing pushes and pops
+
+A cache that keeps a byte count of what it holds, a metrics layer that counts what went through a
+queue, an index kept beside a list: each wants to hear about every element that goes in or comes
+out. Without a hook, that's code at every call site that pushes or pops, and a call site that
+forgets. Observed<L, O> wraps a list, implements the traits in traits.rs like the list does, and
+tells its observer about each element as it goes in and as it comes out:
+
+    let mut list = Observed::with_hooks(
+        unsafe_doubly_linked_deque::List::new(),
+        |x: &u32| println!("in: {x}"),
+        |x: &u32| println!("out: {x}"),
+    );
+
+An observer is anything implementing Observer<T>, whose two methods default to doing nothing, so
+one only writes the hooks it wants. with_hooks is the shortcut for two closures. The observer is
+the wrapper's to keep, so a running total is just a field on it, read through observer():
+
+    struct Bytes(usize);
+
+    impl Observer<String> for Bytes {
+        fn on_insert(&mut self, s: &String) { self.0 += s.len(); }
+        fn on_remove(&mut self, s: &String) { self.0 -= s.len(); }
+    }
+
+on_insert sees an element just before the push, and on_remove just after the pop, before it's
+handed back. Either way it's a borrow, so the hooks can look but not keep. A hook that panics
+leaves the list as it was before the push, or after the pop: the element is the one thing lost.
+
+It's opt-in and costs nothing when it isn't used: the lists themselves have no hooks, and a list
+that isn't wrapped runs exactly the code it did before. Peeks, iteration and the inherent methods
+reached through get() aren't observed. instrumented.rs and recorder.rs are wrappers in the same
+style, for counting and for recording, with what they need built in.
+*/
+
+use crate::traits::{Collection, Deque, Queue, Stack};
+use std::ops::Deref;
+
+pub trait Observer<T> {
+    fn on_insert(&mut self, _elem: &T) {}
+
+    fn on_remove(&mut self, _elem: &T) {}
+}
+
+// Two closures as an observer, which is what with_hooks makes.
+pub struct Hooks<I, R> {
+    on_insert: I,
+    on_remove: R,
+}
+
+impl<T, I: FnMut(&T), R: FnMut(&T)> Observer<T> for Hooks<I, R> {
+    fn on_insert(&mut self, elem: &T) {
+        (self.on_insert)(elem)
+    }
+
+    fn on_remove(&mut self, elem: &T) {
+        (self.on_remove)(elem)
+    }
+}
+
+pub struct Observed<L, O> {
+    list: L,
+    observer: O,
+}
+
+impl<L, O> Observed<L, O> {
+    pub fn new(list: L, observer: O) -> Self {
+        Observed { list, observer }
+    }
+
+    pub fn get(&self) -> &L {
+        &self.list
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    pub fn into_parts(self) -> (L, O) {
+        (self.list, self.observer)
+    }
+
+    // Tell the observer about what `pop` took out, if anything.
+    fn removed<T>(&mut self, elem: Option<T>) -> Option<T>
+    where
+        O: Observer<T>,
+    {
+        if let Some(elem) = &elem {
+            self.observer.on_remove(elem);
+        }
+        elem
+    }
+}
+
+impl<L, I, R> Observed<L, Hooks<I, R>> {
+    pub fn with_hooks<T>(list: L, on_insert: I, on_remove: R) -> Self
+    where
+        I: FnMut(&T),
+        R: FnMut(&T),
+    {
+        Observed::new(
+            list,
+            Hooks {
+                on_insert,
+                on_remove,
+            },
+        )
+    }
+}
+
+impl<L: Collection, O> Collection for Observed<L, O> {
+    fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+}
+
+impl<T, L: Stack<T>, O: Observer<T>> Stack<T> for Observed<L, O> {
+    fn push(&mut self, elem: T) {
+        self.observer.on_insert(&elem);
+        Stack::push(&mut self.list, elem);
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        let elem = Stack::pop(&mut self.list);
+        self.removed(elem)
+    }
+
+    fn peek(&self) -> Option<impl Deref<Target = T> + '_> {
+        Stack::peek(&self.list)
+    }
+}
+
+impl<T, L: Queue<T>, O: Observer<T>> Queue<T> for Observed<L, O> {
+    fn push(&mut self, elem: T) {
+        self.observer.on_insert(&elem);
+        Queue::push(&mut self.list, elem);
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        let elem = Queue::pop(&mut self.list);
+        self.removed(elem)
+    }
+
+    fn peek(&self) -> Option<impl Deref<Target = T> + '_> {
+        Queue::peek(&self.list)
+    }
+}
+
+impl<T, L: Deque<T>, O: Observer<T>> Deque<T> for Observed<L, O> {
+    fn push_front(&mut self, elem: T) {
+        self.observer.on_insert(&elem);
+        self.list.push_front(elem);
+    }
+
+    fn push_back(&mut self, elem: T) {
+        self.observer.on_insert(&elem);
+        self.list.push_back(elem);
+    }
+
+    fn pop_front(&mut self) -> Option<T> {
+        let elem = self.list.pop_front();
+        self.removed(elem)
+    }
+
+    fn pop_back(&mut self) -> Option<T> {
+        let elem = self.list.pop_back();
+        self.removed(elem)
+    }
+
+    fn peek_front(&self) -> Option<impl Deref<Target = T> + '_> {
+        self.list.peek_front()
+    }
+
+    fn peek_back(&self) -> Option<impl Deref<Target = T> + '_> {
+        self.list.peek_back()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Observed, Observer};
+    use crate::traits::{Deque, Queue, Stack};
+    use crate::{generic_and_iterators, unsafe_doubly_linked_deque, unsafe_single_linked_queue};
+    use std::cell::RefCell;
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[test]
+    fn hooks() {
+        let log = RefCell::new(Vec::new());
+        let mut list = Observed::with_hooks(
+            unsafe_doubly_linked_deque::List::new(),
+            |x: &u32| log.borrow_mut().push(format!("+{x}")),
+            |x: &u32| log.borrow_mut().push(format!("-{x}")),
+        );
+        list.push_back(1);
+        list.push_front(0);
+        Stack::push(&mut list, 2);
+        assert_eq!(list.pop_back(), Some(1));
+        assert_eq!(Queue::pop(&mut list), Some(2));
+        // Nothing to pop, nothing to tell.
+        list.pop_front();
+        list.pop_front();
+        drop(list);
+        assert_eq!(*log.borrow(), ["+1", "+0", "+2", "-1", "-2", "-0"]);
+    }
+
+    // A byte count kept beside a queue of strings.
+    #[test]
+    fn observer() {
+        #[derive(Default)]
+        struct Bytes(usize);
+
+        impl Observer<String> for Bytes {
+            fn on_insert(&mut self, s: &String) {
+                self.0 += s.len();
+            }
+
+            fn on_remove(&mut self, s: &String) {
+                self.0 -= s.len();
+            }
+        }
+
+        let mut queue = Observed::new(unsafe_single_linked_queue::List::new(), Bytes::default());
+        for word in ["one", "three", "four"] {
+            queue.push(word.to_string());
+        }
+        assert_eq!(queue.observer().0, 12);
+        assert_eq!(queue.pop().as_deref(), Some("one"));
+        assert_eq!(queue.observer().0, 9);
+        queue.observer_mut().0 = 0;
+        let (list, bytes) = queue.into_parts();
+        assert_eq!((list.len(), bytes.0), (2, 0));
+    }
+
+    #[test]
+    fn panicking_hook() {
+        struct NoSevens;
+
+        impl Observer<u8> for NoSevens {
+            fn on_insert(&mut self, x: &u8) {
+                assert_ne!(*x, 7);
+            }
+        }
+
+        let mut stack = Observed::new(generic_and_iterators::List::new(), NoSevens);
+        stack.push(1);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| stack.push(7)));
+        assert!(result.is_err());
+        // The push never happened.
+        assert_eq!(stack.pop(), Some(1));
+        assert_eq!(stack.pop(), None);
+    }
+}
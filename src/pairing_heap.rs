@@ -0,0 +1,242 @@
+/*
+ A pairing heap
+
+Every structure so far has been a line of nodes. A pairing heap is a tree of them (a min-heap:
+each node is no larger than any of its children) with any number of children per node. It
+still only needs two links per node, because the children are kept in a singly linked list:
+
+        1                    node.child   -> its first child
+      / | \                  node.sibling -> the next child of its parent
+     4  2  7
+     |     |             is stored as   1
+     9     8                            |
+                                        4 -> 2 -> 7
+                                        |         |
+                                        9         8
+
+The whole thing rests on one operation, meld, which joins two heaps in O(1): the root with the
+larger element becomes the first child of the other root.
+
+- push melds in a one-node heap: O(1).
+- merge melds two whole heaps: O(1). A binary heap in a Vec can't do that; it has to copy one
+  into the other.
+- peek_min is the root: O(1).
+- pop_min removes the root and has to turn its list of children back into one heap. Melding
+  them all in one go, left to right, would leave a long chain for the next pop; the "pairing"
+  does it in two passes instead: meld neighbours in pairs left to right, then meld the pairs
+  right to left. That's O(log n) amortized.
+
+The first pass threads the melded pairs onto a stack through their own sibling links, so pop_min
+doesn't allocate either. Drop is iterative, since the child and sibling lists can both be as long
+as the heap.
+*/
+
+pub struct PairingHeap<T: Ord> {
+    root: Link<T>,
+    len: usize,
+}
+
+type Link<T> = Option<Box<Node<T>>>;
+
+struct Node<T> {
+    elem: T,
+    child: Link<T>,
+    sibling: Link<T>,
+}
+
+// The larger root goes under the smaller. Both must have no siblings.
+fn meld<T: Ord>(mut a: Box<Node<T>>, mut b: Box<Node<T>>) -> Box<Node<T>> {
+    if b.elem < a.elem {
+        std::mem::swap(&mut a, &mut b);
+    }
+    b.sibling = a.child.take();
+    a.child = Some(b);
+    a
+}
+
+// Two-pass pairing of a list of sibling heaps into one heap.
+fn combine<T: Ord>(mut first: Link<T>) -> Link<T> {
+    // Left to right: meld pairs, pushing each result onto `pairs` (linked through `sibling`,
+    // so the last pair ends up on top).
+    let mut pairs: Link<T> = None;
+    while let Some(mut a) = first {
+        first = a.sibling.take();
+        let mut pair = match first {
+            Some(mut b) => {
+                first = b.sibling.take();
+                meld(a, b)
+            }
+            None => a,
+        };
+        pair.sibling = pairs;
+        pairs = Some(pair);
+    }
+    // Right to left: meld each pair into the running result.
+    let mut result = pairs?;
+    let mut rest = result.sibling.take();
+    while let Some(mut pair) = rest {
+        rest = pair.sibling.take();
+        result = meld(pair, result);
+    }
+    Some(result)
+}
+
+impl<T: Ord> PairingHeap<T> {
+    pub fn new() -> Self {
+        PairingHeap { root: None, len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, elem: T) {
+        let node = Box::new(Node {
+            elem,
+            child: None,
+            sibling: None,
+        });
+        self.root = Some(match self.root.take() {
+            Some(root) => meld(root, node),
+            None => node,
+        });
+        self.len += 1;
+    }
+
+    pub fn peek_min(&self) -> Option<&T> {
+        self.root.as_ref().map(|root| &root.elem)
+    }
+
+    pub fn pop_min(&mut self) -> Option<T> {
+        let mut root = self.root.take()?;
+        self.root = combine(root.child.take());
+        self.len -= 1;
+        Some(root.elem)
+    }
+
+    // Takes all of `other`'s elements, in O(1).
+    pub fn merge(&mut self, mut other: PairingHeap<T>) {
+        self.root = match (self.root.take(), other.root.take()) {
+            (Some(a), Some(b)) => Some(meld(a, b)),
+            (a, b) => a.or(b),
+        };
+        self.len += std::mem::take(&mut other.len);
+    }
+
+    pub fn clear(&mut self) {
+        *self = PairingHeap::new();
+    }
+
+    // Smallest first.
+    pub fn into_sorted_iter(self) -> IntoSortedIter<T> {
+        IntoSortedIter(self)
+    }
+}
+
+impl<T: Ord> Drop for PairingHeap<T> {
+    fn drop(&mut self) {
+        // Every node's child and sibling go on the stack before the node itself is dropped, so
+        // no Box is ever dropped with anything still hanging off it.
+        let mut stack: Vec<Box<Node<T>>> = self.root.take().into_iter().collect();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.child.take());
+            stack.extend(node.sibling.take());
+        }
+    }
+}
+
+impl<T: Ord> Default for PairingHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> Extend<T> for PairingHeap<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T: Ord> FromIterator<T> for PairingHeap<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut heap = PairingHeap::new();
+        heap.extend(iter);
+        heap
+    }
+}
+
+pub struct IntoSortedIter<T: Ord>(PairingHeap<T>);
+
+impl<T: Ord> Iterator for IntoSortedIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.pop_min()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<T: Ord> ExactSizeIterator for IntoSortedIter<T> {}
+
+#[cfg(test)]
+mod test {
+    use super::PairingHeap;
+
+    #[test]
+    fn basics() {
+        let mut heap = PairingHeap::new();
+        assert_eq!(heap.pop_min(), None);
+        assert_eq!(heap.peek_min(), None);
+
+        heap.extend([5, 3, 8, 1, 9]);
+        assert_eq!(heap.len(), 5);
+        assert_eq!(heap.peek_min(), Some(&1));
+        assert_eq!(heap.pop_min(), Some(1));
+        heap.push(2);
+        assert_eq!(heap.pop_min(), Some(2));
+        assert_eq!(heap.pop_min(), Some(3));
+        assert_eq!(heap.len(), 3);
+    }
+
+    #[test]
+    fn sorts() {
+        // Scrambled, with duplicates.
+        let elems: Vec<u32> = (0..1000u32)
+            .map(|i| i.wrapping_mul(2654435761) % 300)
+            .collect();
+        let heap: PairingHeap<u32> = elems.iter().copied().collect();
+        let mut sorted = elems.clone();
+        sorted.sort();
+        assert!(heap.into_sorted_iter().eq(sorted));
+    }
+
+    #[test]
+    fn merge() {
+        let mut evens: PairingHeap<i32> = (0..10).map(|i| i * 2).collect();
+        let odds: PairingHeap<i32> = (0..10).map(|i| i * 2 + 1).collect();
+        evens.merge(odds);
+        evens.merge(PairingHeap::new());
+        assert_eq!(evens.len(), 20);
+        assert!(evens.into_sorted_iter().eq(0..20));
+    }
+
+    #[test]
+    fn long_drop() {
+        // Pushing in descending order makes every push a new root, and one long child chain.
+        let mut heap: PairingHeap<i32> = (0..200_000).rev().collect();
+        heap.pop_min();
+        drop(heap);
+        // And ascending makes one root with a very long list of children.
+        let heap: PairingHeap<i32> = (0..200_000).collect();
+        drop(heap);
+    }
+}
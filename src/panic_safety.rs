@@ -0,0 +1,53 @@
+/*
+ Panic safety
+
+Every list here calls code it didn't write: an element's Clone and Drop, the iterator handed to
+extend, the closure handed to retain, Ord::cmp while keeping itself sorted. Any of those can
+panic, and a panic is an early return from the middle of a list operation. Whatever state the
+list was in at that point is the state it's left in, and it'll still be dropped (or, under
+catch_unwind, used again) afterwards. So the rule for the unsafe lists is the one std follows:
+
+- Get the list back into a valid state (links, len, head and tail all agreeing) before every call
+  into code that might panic. Unlink a node, then drop its element, never the other way round.
+  Clone and Extend push one element at a time, and a push is complete before the next element is
+  asked for, so a panic leaves a shorter list, not a broken one.
+- A panic may leak, but only the one element it was in the middle of. Leaking isn't unsafe, but a
+  destructor that stops at the first element that panics leaks everything after it.
+
+Drop is where the second point takes work. "Pop until empty" stops at the first element whose
+Drop panics, and the nodes after it are never freed. pop_all is the fix std's LinkedList uses: a
+guard whose own Drop carries on popping while the panic unwinds, so every element still gets
+dropped. If another one panics during that, it's a panic while already panicking, which aborts;
+there's no way to carry on past that, for any collection.
+
+The safe lists mostly get this for free. Box and Vec drop what they own even when something in
+them panics, and the persistent lists' iterative Node drop carries on where it left off. The
+exceptions are links that only the list's own Drop knows how to walk: a doubly linked list's Rc
+cycles, which leak if the loop breaking them stops, and links whose default drop recurses, which
+would overflow the stack on a long list if it ran while unwinding.
+
+tests/panics.rs checks each list with elements that panic on cue, in Drop, in Clone and in the
+middle of an extend.
+*/
+
+use std::mem;
+
+// Call `pop` until it says there was nothing left. Each call should take one element off `list`,
+// leaving it valid, and drop it. If a drop panics, the guard pops (and drops) the rest while the
+// panic unwinds.
+pub(crate) fn pop_all<L: ?Sized>(list: &mut L, pop: impl FnMut(&mut L) -> bool) {
+    struct Guard<'a, L: ?Sized, P: FnMut(&mut L) -> bool> {
+        list: &'a mut L,
+        pop: P,
+    }
+
+    impl<L: ?Sized, P: FnMut(&mut L) -> bool> Drop for Guard<'_, L, P> {
+        fn drop(&mut self) {
+            while (self.pop)(self.list) {}
+        }
+    }
+
+    let mut guard = Guard { list, pop };
+    while (guard.pop)(guard.list) {}
+    mem::forget(guard);
+}
@@ -18,71 +18,300 @@ Can't use Box because ownership is shared. If we drop list2, should B,C,D be fre
 Garbage collection would have saved us in high-level PLs.
 Rust has reference counting (Rc which is like Box) but we can only take shared reference
 to its interals.
+
+The list itself is pointer_list's with Rc as the pointer (see pointer_list.rs): new, prepend,
+tail, head, iter, Clone, Display and the rest are there, written once for this list, the Box one
+and the Arc one. What's here is what only this list does.
+
+Two notes from when this module had its own Node. prepend built the new node with
+`next: self.head.clone()`: clone() is the generic way to get "another one like this one" that is
+logically disjoint, given only a shared reference, like a copy constructor in C++, and Rc in
+particular uses Clone as the way to increment the reference count. pointer_list's Clone for a
+list is that same bump, on the head. And head() is peek under its persistent name.
 */
 
-use std::rc::Rc;
+use crate::allocator::Allocator;
+use crate::diff::{self, Edit};
+use crate::mem_usage::MemUsage;
+use crate::pointer_list::{self, RcKind};
+use crate::{generic_and_iterators, unsafe_doubly_linked_deque, unsafe_single_linked_queue};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+pub type List<T> = pointer_list::List<T, RcKind>;
+
+type Node<T> = pointer_list::Node<T, RcKind>;
+
+impl<T> List<T> {
+    // head() and tail() in one go, for `while let Some((x, rest)) = list.uncons()` style recursion.
+    pub fn uncons(&self) -> Option<(&T, List<T>)> {
+        Some((self.head()?, self.tail()))
+    }
 
-pub struct List<T> {
-    head: Link<T>,
+    // Prepend everything in `elems` so that the result starts with them in iteration order:
+    // [4].cons_all([1, 2, 3]) == [1, 2, 3, 4].
+    pub fn cons_all<I: IntoIterator<Item = T>>(&self, elems: I) -> List<T> {
+        self.prepend_rev(elems.into_iter().collect())
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.iter().last()
+    }
+
+    // Same as calling tail() `n` times, but only bumps the refcount of the node we end up on.
+    // Stepping past the end gives the empty list, just like tail() does.
+    pub fn nth_tail(&self, n: usize) -> List<T> {
+        let mut cur = self.head.as_ref();
+        for _ in 0..n {
+            cur = cur.and_then(|node| node.next.as_ref());
+        }
+        self.sharing(cur)
+    }
 }
 
-type Link<T> = Option<Rc<Node<T>>>;
+// From the mutable lists, which give up their elements: the top of the stack, or the front of the
+// deque or queue, becomes the head.
+impl<T> From<generic_and_iterators::List<T>> for List<T> {
+    fn from(list: generic_and_iterators::List<T>) -> Self {
+        List::new().cons_all(list)
+    }
+}
 
-struct Node<T> {
-    elem: T,
-    next: Link<T>,
+impl<T> From<unsafe_doubly_linked_deque::List<T>> for List<T> {
+    fn from(deque: unsafe_doubly_linked_deque::List<T>) -> Self {
+        List::new().cons_all(deque)
+    }
 }
 
-impl<T> List<T> {
-    pub fn new() -> List<T> {
-        List { head: None }
+impl<T, A: Allocator> From<unsafe_single_linked_queue::List<T, A>> for List<T> {
+    fn from(queue: unsafe_single_linked_queue::List<T, A>) -> Self {
+        List::new().cons_all(queue)
     }
+}
+
+/*
+ Sorted lists
 
-    pub fn prepend(&self, elem: T) -> List<T> {
-        List {
-            head: Some(Rc::new(Node {
-                elem,
-                // clone() trait: generic way to get "another one like this one" that is logically
-                // disjoint, given only a shared reference. It's like a copy constructor in C++.
-                // Rc in particular uses Clone as the way to increment the reference count.
-                next: self.head.clone(),
-            })),
+Inserting into a sorted persistent list only has to copy the nodes *before* the insertion point.
+Everything after it is shared with the original list:
+
+list1 -> 1 -> 3 -> 5 -> 7
+                   ^
+                   |
+list2 -> 1 -> 3 -> 4
+
+The copied prefix needs `T: Clone` because the original nodes are still owned by `list1`.
+*/
+
+impl<T: Clone + Ord> List<T> {
+    pub fn insert_sorted(&self, elem: T) -> List<T> {
+        let mut prefix = Vec::new();
+        let mut cur = &self.head;
+        // Insert after equal elements so the insert is stable.
+        while let Some(node) = cur {
+            if node.elem > elem {
+                break;
+            }
+            prefix.push(node.elem.clone());
+            cur = &node.next;
         }
+
+        let suffix = self.sharing(cur.as_ref()).prepend(elem);
+        suffix.prepend_rev(prefix)
     }
 
-    pub fn tail(&self) -> List<T> {
-        List {
-            head: self.head.as_ref().and_then(|node| node.next.clone()),
+    // Merge two sorted lists. Once one side runs out, the rest of the other side is shared.
+    pub fn merge(&self, other: &List<T>) -> List<T> {
+        let mut prefix = Vec::new();
+        let mut left = &self.head;
+        let mut right = &other.head;
+        loop {
+            match (left, right) {
+                (Some(l), Some(r)) => {
+                    if r.elem < l.elem {
+                        prefix.push(r.elem.clone());
+                        right = &r.next;
+                    } else {
+                        prefix.push(l.elem.clone());
+                        left = &l.next;
+                    }
+                }
+                (Some(_), None) => break,
+                (None, _) => {
+                    left = right;
+                    break;
+                }
+            }
         }
-    }
 
-    // Like peek
-    pub fn head(&self) -> Option<&T> {
-        self.head.as_ref().map(|node| &node.elem)
+        self.sharing(left.as_ref()).prepend_rev(prefix)
     }
 }
 
-pub struct Iter<'a, T> {
-    next: Option<&'a Node<T>>,
+impl<T> List<T> {
+    // Prepend `elems` so that they end up in the same order as the Vec in front of `self`.
+    fn prepend_rev(&self, elems: Vec<T>) -> List<T> {
+        let mut list = self.clone();
+        for elem in elems.into_iter().rev() {
+            list.push(elem);
+        }
+        list
+    }
 }
 
+/*
+ Shared suffixes
+
+Two versions derived from the same list share every node after the point where they diverged.
+Walking both lists in lockstep and remembering every node pointer seen so far finds that point
+after O(divergence) steps, without walking the (possibly huge) shared part.
+
+That also makes diffing two versions cheap. The shared part is equal by construction, so diff()
+only has to search the nodes in front of it for the changes, however long the tail is.
+*/
+
 impl<T> List<T> {
-    pub fn iter(&self) -> Iter<'_, T> {
-        Iter {
-            next: self.head.as_deref(),
+    // The longest suffix shared (by pointer, not by value) between `self` and `other`.
+    pub fn shares_tail_with(&self, other: &List<T>) -> Option<List<T>> {
+        let mut seen = HashSet::new();
+        let mut left = self.head.as_ref();
+        let mut right = other.head.as_ref();
+
+        while left.is_some() || right.is_some() {
+            for node in [left, right].into_iter().flatten() {
+                if !seen.insert(node.as_ptr()) {
+                    return Some(self.sharing(Some(node)));
+                }
+            }
+            left = left.and_then(|node| node.next.as_ref());
+            right = right.and_then(|node| node.next.as_ref());
         }
+        None
+    }
+
+    // Is `self` literally one of the tails of `other`? The empty list is a suffix of everything.
+    pub fn is_suffix_of(&self, other: &List<T>) -> bool {
+        let Some(head) = self.head.as_ref() else {
+            return true;
+        };
+        let mut cur = other.head.as_ref();
+        while let Some(node) = cur {
+            if node.as_ptr() == head.as_ptr() {
+                return true;
+            }
+            cur = node.next.as_ref();
+        }
+        false
+    }
+
+    // The edits that turn this list into `other`, front to back. A tail the two share is kept
+    // without being compared, or searched: see diff.rs.
+    pub fn diff<'a>(&'a self, other: &'a List<T>) -> Vec<Edit<'a, T>>
+    where
+        T: PartialEq,
+    {
+        // An element's address is its node's, so it marks where the shared tail starts.
+        let shared = self.shares_tail_with(other);
+        let start = shared
+            .as_ref()
+            .and_then(List::head)
+            .map(|elem| elem as *const T);
+        let unshared = |elem: &&T| Some(*elem as *const T) != start;
+
+        let mut edits = diff::diff(
+            self.iter().take_while(unshared),
+            other.iter().take_while(unshared),
+        );
+        edits.extend(self.iter().skip_while(unshared).map(Edit::Keep));
+        edits
+    }
+}
+
+/*
+ Seeing the sharing
+
+{:?} prints a list as its elements, like any other collection. {:#?} prints a node per line with
+its reference counts, which is what's worth seeing when sharing goes wrong: a node kept alive by
+a list that should have been dropped, or a version that copied nodes it could have shared.
+
+    let xs = List::new().prepend(3).prepend(2);
+    let ys = xs.prepend(1);
+    println!("{ys:#?}");
+
+    [
+        1 (strong: 1, weak: 0),
+        2 (strong: 2, weak: 0) shared,
+        3 (strong: 1, weak: 0) shared,
+    ]
+
+A node's strong count is the lists and nodes pointing at it: here 2 is the head of xs and the
+next of ys's 1. Everything from the first node with more than one is reachable from more than one
+list, so it's all marked shared, even where the count is back to 1 (3 is only pointed at by 2,
+but 2 is in both lists).
+
+The impl is pointer_list's, since the Arc list prints the same way.
+*/
+
+// zip/unzip walk both spines at once. Unlike insert_sorted nothing can be shared: every node of
+// the result holds a different element type than the inputs.
+impl<T: Clone> List<T> {
+    pub fn zip<U: Clone>(&self, other: &List<U>) -> List<(T, U)> {
+        let pairs: Vec<_> = self
+            .iter()
+            .zip(other.iter())
+            .map(|(a, b)| (a.clone(), b.clone()))
+            .collect();
+        List::new().prepend_rev(pairs)
+    }
+}
+
+impl<A: Clone, B: Clone> List<(A, B)> {
+    pub fn unzip(&self) -> (List<A>, List<B>) {
+        let (left, right): (Vec<_>, Vec<_>) = self.iter().cloned().unzip();
+        (
+            List::new().prepend_rev(left),
+            List::new().prepend_rev(right),
+        )
     }
 }
 
 // Iter like mutable list
-impl<'a, T> Iterator for Iter<'a, T> {
-    type Item = &'a T;
+pub type Iter<'a, T> = pointer_list::Iter<'a, T, RcKind>;
+
+// Iter borrows the list, so it can't outlive it. IterShared instead holds its own Rc to the next
+// node, so it keeps the rest of the list alive by itself and can be returned or moved around
+// freely. Since it can't hand out references into itself, each item is a handle to the node that
+// derefs to the element. Both are Lists underneath, so they let go of their nodes the same way a
+// List does (see below).
+pub struct IterShared<T> {
+    rest: List<T>,
+}
+
+// Never empty.
+pub struct SharedElem<T>(List<T>);
+
+impl<T> List<T> {
+    pub fn iter_shared(&self) -> IterShared<T> {
+        IterShared { rest: self.clone() }
+    }
+}
+
+impl<T> Iterator for IterShared<T> {
+    type Item = SharedElem<T>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.next.map(|node| {
-            self.next = node.next.as_deref();
-            &node.elem
-        })
+        let rest = self.rest.tail();
+        let node = std::mem::replace(&mut self.rest, rest);
+        (!node.is_empty()).then_some(SharedElem(node))
+    }
+}
+
+impl<T> std::ops::Deref for SharedElem<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.0.head().unwrap()
     }
 }
 
@@ -97,15 +326,255 @@ impl<'a, T> Iterator for Iter<'a, T> {
 //     }
 // }
 
-// Use try_unwrap
-impl<T> Drop for List<T> {
-    fn drop(&mut self) {
-        let mut head = self.head.take(); // instead of mem::replace
-        while let Some(node) = head {
-            if let Ok(mut node) = Rc::try_unwrap(node) {
-                head = node.next.take();
-            } else {
-                break;
+// Then we used try_unwrap, which stops at the first shared node.
+// impl<T> Drop for List<T> {
+//     fn drop(&mut self) {
+//         let mut head = self.head.take(); // instead of mem::replace
+//         while let Some(node) = head {
+//             if let Ok(mut node) = Rc::try_unwrap(node) {
+//                 head = node.next.take();
+//             } else {
+//                 break;
+//             }
+//         }
+//     }
+// }
+//
+// That only unwinds when the drop starts at a List. If the last reference to a long shared suffix
+// is released anywhere else (a node handed out by an iterator, or with Arc, two threads that both
+// fail try_unwrap and then both let go), the default drop of Rc<Node> recurses down the whole
+// chain and overflows the stack.
+//
+// So nothing else holds a node: the links are pointer_list's Ptrs, which don't drop anything
+// themselves, and a node handed out by an iterator (SharedElem above) is a List too. Every drop
+// starts at a List, and pointer_list's Drop unwinds with Rc::into_inner, which only gives the node
+// back if we held the last reference: also the race-free check once this is Arc.
+
+/*
+ Cached hashes
+
+Hashing or comparing two lists normally walks both of them. When lists are used as memoization
+keys that gets expensive, so HashedList stores, in every node, a hash of that node's element
+combined with the hash already cached in the next node. The whole list's hash is then just the
+head's cached value:
+
+- Hash is O(1).
+- Lists with different hashes are definitely different, so == can bail out in O(1).
+- Lists that share a node are equal from that node on, so == can stop there too.
+
+It's a plain List of (hash, elem) pairs underneath, so sharing and the iterative drop come for
+free. The price is a u64 per node and hashing each element once when it's prepended.
+*/
+
+pub struct HashedList<T> {
+    list: List<(u64, T)>,
+}
+
+impl<T: Hash> HashedList<T> {
+    pub fn new() -> Self {
+        HashedList { list: List::new() }
+    }
+
+    pub fn prepend(&self, elem: T) -> HashedList<T> {
+        let mut hasher = DefaultHasher::new();
+        elem.hash(&mut hasher);
+        self.cached_hash().hash(&mut hasher);
+        HashedList {
+            list: self.list.prepend((hasher.finish(), elem)),
+        }
+    }
+}
+
+impl<T> HashedList<T> {
+    pub fn tail(&self) -> HashedList<T> {
+        HashedList {
+            list: self.list.tail(),
+        }
+    }
+
+    pub fn head(&self) -> Option<&T> {
+        self.list.head().map(|(_, elem)| elem)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.list.iter().map(|(_, elem)| elem)
+    }
+
+    // Hash of the whole list; 0 for the empty list.
+    pub fn cached_hash(&self) -> u64 {
+        self.list.head().map_or(0, |(hash, _)| *hash)
+    }
+}
+
+impl<T: Hash> Default for HashedList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Hash for HashedList<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.cached_hash());
+    }
+}
+
+impl<T: PartialEq> PartialEq for HashedList<T> {
+    fn eq(&self, other: &Self) -> bool {
+        let mut left = self.list.head.as_ref();
+        let mut right = other.list.head.as_ref();
+        loop {
+            match (left, right) {
+                (Some(l), Some(r)) => {
+                    if l.as_ptr() == r.as_ptr() {
+                        return true;
+                    }
+                    if l.elem.0 != r.elem.0 || l.elem.1 != r.elem.1 {
+                        return false;
+                    }
+                    left = l.next.as_ref();
+                    right = r.next.as_ref();
+                }
+                (None, None) => return true,
+                _ => return false,
+            }
+        }
+    }
+}
+
+impl<T: Eq> Eq for HashedList<T> {}
+
+/*
+ Serde
+
+Writing each list out as a plain sequence of elements loses the sharing: a history of 1000
+versions that each prepend one element to the previous one would serialize ~500k elements.
+Instead, write a node table where every node appears exactly once:
+
+    nodes: [(elem, next index), ...]
+    heads: [index of each list's first node, ...]
+
+Nodes are numbered so that `next` always points at a smaller index, so deserializing can build
+the Rc nodes front to back and every `next` already exists.
+*/
+
+// A set of lists taken together, so tails shared between them are written only once (with the
+// `serde` feature) and counted only once by mem_usage.
+pub struct ListSet<T>(pub Vec<List<T>>);
+
+impl<T> ListSet<T> {
+    // Every node reachable from any of the lists, once. See mem_usage.rs.
+    pub fn mem_usage(&self) -> MemUsage {
+        let mut seen = HashSet::new();
+        for list in &self.0 {
+            let mut cur = list.head.as_ref();
+            // Once one node has been seen, so has everything after it.
+            while let Some(node) = cur.filter(|node| seen.insert(node.as_ptr())) {
+                cur = node.next.as_ref();
+            }
+        }
+        MemUsage::rc_nodes::<Node<T>, T>(seen.len())
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::{List, ListSet, Node};
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::HashMap;
+
+    #[derive(Serialize)]
+    struct TableRef<'a, T> {
+        nodes: Vec<(&'a T, Option<usize>)>,
+        heads: Vec<Option<usize>>,
+    }
+
+    #[derive(Deserialize)]
+    struct Table<T> {
+        nodes: Vec<(T, Option<usize>)>,
+        heads: Vec<Option<usize>>,
+    }
+
+    fn to_table<'a, T>(lists: impl IntoIterator<Item = &'a List<T>>) -> TableRef<'a, T> {
+        let mut nodes = Vec::new();
+        let mut heads = Vec::new();
+        let mut seen: HashMap<*const Node<T>, usize> = HashMap::new();
+
+        for list in lists {
+            // Collect the nodes this list doesn't share with anything written so far.
+            let mut fresh = Vec::new();
+            let mut cur = list.head.as_ref();
+            let mut next = None;
+            while let Some(node) = cur {
+                if let Some(&idx) = seen.get(&node.as_ptr()) {
+                    next = Some(idx);
+                    break;
+                }
+                fresh.push(node);
+                cur = node.next.as_ref();
+            }
+
+            // Number them from the back so that `next` always points backwards.
+            for node in fresh.into_iter().rev() {
+                let idx = nodes.len();
+                nodes.push((&node.elem, next));
+                seen.insert(node.as_ptr(), idx);
+                next = Some(idx);
+            }
+            heads.push(next);
+        }
+
+        TableRef { nodes, heads }
+    }
+
+    fn from_table<T, E: Error>(table: Table<T>) -> Result<Vec<List<T>>, E> {
+        // The list starting at each node.
+        let mut built: Vec<List<T>> = Vec::with_capacity(table.nodes.len());
+        for (idx, (elem, next)) in table.nodes.into_iter().enumerate() {
+            let next = match next {
+                Some(n) if n < idx => built[n].clone(),
+                Some(n) => return Err(E::custom(format!("node {idx} links forward to {n}"))),
+                None => List::new(),
+            };
+            built.push(next.prepend(elem));
+        }
+
+        table
+            .heads
+            .into_iter()
+            .map(|head| match head {
+                Some(h) if h < built.len() => Ok(built[h].clone()),
+                Some(h) => Err(E::custom(format!("head {h} out of range"))),
+                None => Ok(List::new()),
+            })
+            .collect()
+    }
+
+    impl<T: Serialize> Serialize for ListSet<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            to_table(&self.0).serialize(serializer)
+        }
+    }
+
+    impl<'de, T: Deserialize<'de>> Deserialize<'de> for ListSet<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            from_table(Table::deserialize(deserializer)?).map(ListSet)
+        }
+    }
+
+    // A single list uses the same format with one head.
+    impl<T: Serialize> Serialize for List<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            to_table([self]).serialize(serializer)
+        }
+    }
+
+    impl<'de, T: Deserialize<'de>> Deserialize<'de> for List<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let mut lists = from_table(Table::deserialize(deserializer)?)?;
+            match lists.len() {
+                1 => Ok(lists.pop().unwrap()),
+                n => Err(D::Error::custom(format!("expected 1 list, found {n}"))),
             }
         }
     }
@@ -162,4 +631,282 @@ mod test {
         assert_eq!(iter.next(), Some(&2));
         assert_eq!(iter.next(), Some(&1));
     }
+
+    #[test]
+    fn insert_sorted() {
+        let list = List::new().prepend(7).prepend(5).prepend(3).prepend(1);
+        let inserted = list.insert_sorted(4);
+
+        assert_eq!(
+            inserted.iter().collect::<Vec<_>>(),
+            vec![&1, &3, &4, &5, &7]
+        );
+        // Original is untouched.
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![&1, &3, &5, &7]);
+
+        // The suffix after the insertion point is shared, not copied.
+        let old_five = list.tail().tail();
+        let new_five = inserted.tail().tail().tail();
+        assert!(old_five.ptr_eq(&new_five));
+
+        let list = List::new()
+            .insert_sorted(2)
+            .insert_sorted(1)
+            .insert_sorted(3);
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![&1, &2, &3]);
+    }
+
+    #[test]
+    fn merge() {
+        let a = List::new().prepend(9).prepend(8).prepend(5).prepend(1);
+        let b = List::new().prepend(6).prepend(3).prepend(2);
+
+        let merged = a.merge(&b);
+        assert_eq!(
+            merged.iter().collect::<Vec<_>>(),
+            vec![&1, &2, &3, &5, &6, &8, &9]
+        );
+
+        // The leftover run of `a` is shared.
+        let eight = a.tail().tail();
+        let merged_eight = merged.tail().tail().tail().tail().tail();
+        assert!(eight.ptr_eq(&merged_eight));
+
+        assert_eq!(a.merge(&List::new()).iter().count(), 4);
+        assert_eq!(List::new().merge(&b).iter().count(), 3);
+    }
+
+    #[test]
+    fn zip() {
+        let nums = List::new().prepend(3).prepend(2).prepend(1);
+        let chars = List::new().prepend('b').prepend('a');
+
+        let zipped = nums.zip(&chars);
+        assert_eq!(
+            zipped.iter().collect::<Vec<_>>(),
+            vec![&(1, 'a'), &(2, 'b')]
+        );
+
+        let (left, right) = zipped.unzip();
+        assert_eq!(left.iter().collect::<Vec<_>>(), vec![&1, &2]);
+        assert_eq!(right.iter().collect::<Vec<_>>(), vec![&'a', &'b']);
+
+        assert_eq!(nums.zip(&List::<char>::new()).head(), None);
+    }
+
+    #[test]
+    fn last_and_nth_tail() {
+        let list = List::new().prepend(1).prepend(2).prepend(3);
+        assert_eq!(list.last(), Some(&1));
+        assert_eq!(List::<i32>::new().last(), None);
+
+        assert_eq!(list.nth_tail(0).head(), Some(&3));
+        assert_eq!(list.nth_tail(2).head(), Some(&1));
+        assert_eq!(list.nth_tail(3).head(), None);
+        assert_eq!(list.nth_tail(10).head(), None);
+
+        let tail = list.nth_tail(1);
+        assert!(tail.ptr_eq(&list.tail()));
+    }
+
+    #[test]
+    fn long_shared_drop() {
+        let mut list = List::new();
+        for i in 0..1_000_000 {
+            list = list.prepend(i);
+        }
+
+        // Dropping `list` stops at the shared node; the suffix then becomes uniquely owned by
+        // `suffix` and must still unwind without recursing.
+        let suffix = list.nth_tail(10);
+        drop(list);
+        drop(suffix);
+
+        // Same, but the last reference is a node handed out by an iterator rather than a List.
+        let mut list = List::new();
+        for i in 0..1_000_000 {
+            list = list.prepend(i);
+        }
+        let node = list.iter_shared().next().unwrap();
+        drop(list);
+        drop(node);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_keeps_sharing() {
+        use super::ListSet;
+
+        let base = List::new().prepend(1).prepend(2).prepend(3);
+        let a = base.prepend(10);
+        let b = base.prepend(20).prepend(21);
+
+        let json = serde_json::to_string(&ListSet(vec![a, b, base])).unwrap();
+        // 3 shared nodes + 1 + 2, each written once.
+        let table: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(table["nodes"].as_array().unwrap().len(), 6);
+
+        let ListSet(lists) = serde_json::from_str::<ListSet<i32>>(&json).unwrap();
+        assert_eq!(lists[0].iter().collect::<Vec<_>>(), vec![&10, &3, &2, &1]);
+        assert_eq!(
+            lists[1].iter().collect::<Vec<_>>(),
+            vec![&21, &20, &3, &2, &1]
+        );
+        assert_eq!(lists[2].iter().collect::<Vec<_>>(), vec![&3, &2, &1]);
+
+        let shared = &lists[2];
+        assert!(lists[0].tail().ptr_eq(shared));
+        assert!(lists[1].nth_tail(2).ptr_eq(shared));
+
+        let single: List<i32> =
+            serde_json::from_str(&serde_json::to_string(&lists[1]).unwrap()).unwrap();
+        assert_eq!(
+            single.iter().collect::<Vec<_>>(),
+            vec![&21, &20, &3, &2, &1]
+        );
+
+        assert!(serde_json::from_str::<List<i32>>(r#"{"nodes":[[1,0]],"heads":[0]}"#).is_err());
+    }
+
+    #[test]
+    fn shared_suffix() {
+        let base = List::new().prepend(1).prepend(2);
+        let a = base.prepend(3).prepend(4).prepend(5);
+        let b = base.prepend(6);
+
+        let common = a.shares_tail_with(&b).unwrap();
+        assert!(common.ptr_eq(&base));
+        assert!(b.shares_tail_with(&a).is_some());
+        assert_eq!(a.shares_tail_with(&a).unwrap().head(), Some(&5));
+
+        // Equal values are not sharing.
+        let copy = List::new().prepend(1).prepend(2);
+        assert!(copy.shares_tail_with(&base).is_none());
+        assert!(List::<i32>::new().shares_tail_with(&a).is_none());
+
+        assert!(base.is_suffix_of(&a));
+        assert!(a.tail().is_suffix_of(&a));
+        assert!(!a.is_suffix_of(&base));
+        assert!(!copy.is_suffix_of(&a));
+        assert!(List::new().is_suffix_of(&a));
+    }
+
+    #[test]
+    fn diff_versions() {
+        use crate::diff::Edit::{Delete, Insert, Keep};
+
+        // NaN isn't equal to itself, so the shared tail can only be kept by not comparing it.
+        let base = List::new().cons_all([1.0, f64::NAN]);
+        let a = base.prepend(2.0).prepend(3.0);
+        let b = base.prepend(4.0).prepend(3.0);
+        let edits = a.diff(&b);
+        assert_eq!(edits[..3], [Keep(&3.0), Delete(&2.0), Insert(&4.0)]);
+        assert!(matches!(edits[3..], [Keep(&1.0), Keep(nan)] if nan.is_nan()));
+
+        // A copy shares nothing, and is compared all the way.
+        let copy = List::new().cons_all([1.0, f64::NAN]);
+        let edits = copy.diff(&base);
+        assert!(matches!(edits[..], [Keep(&1.0), Delete(_), Insert(_)]));
+    }
+
+    #[test]
+    fn debug_counts() {
+        let xs = List::new().prepend(3).prepend(2);
+        let ys = xs.prepend(1);
+        assert_eq!(format!("{ys:?}"), "[1, 2, 3]");
+        assert_eq!(
+            format!("{ys:#?}"),
+            "[
+    1 (strong: 1, weak: 0),
+    2 (strong: 2, weak: 0) shared,
+    3 (strong: 1, weak: 0) shared,
+]"
+        );
+        drop(ys);
+        assert_eq!(
+            format!("{xs:#?}"),
+            "[
+    2 (strong: 1, weak: 0),
+    3 (strong: 1, weak: 0),
+]"
+        );
+    }
+
+    #[test]
+    fn mem_usage_counts_shared_once() {
+        use super::ListSet;
+
+        let base = List::new().cons_all(0..10);
+        let a = base.prepend(10);
+        let b = base.prepend(20).prepend(30);
+        assert_eq!(a.mem_usage().nodes, 11);
+        assert_eq!(b.mem_usage().nodes, 12);
+
+        let set = ListSet(vec![a, b, base]).mem_usage();
+        assert_eq!(set.nodes, 13);
+        assert_eq!(
+            set.heap_bytes,
+            13 * crate::mem_usage::rc_size::<super::Node<i32>>()
+        );
+    }
+
+    #[test]
+    fn uncons_and_cons_all() {
+        let list = List::new().prepend(4).cons_all(vec![1, 2, 3]);
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![&1, &2, &3, &4]);
+
+        let mut sum = 0;
+        let mut rest = list;
+        while let Some((x, tail)) = rest.uncons() {
+            sum += x;
+            rest = tail;
+        }
+        assert_eq!(sum, 10);
+        assert!(List::<i32>::new().uncons().is_none());
+        assert_eq!(List::new().cons_all(0..0).head(), None::<&i32>);
+    }
+
+    #[test]
+    fn iter_shared() {
+        fn make_iter() -> super::IterShared<String> {
+            let list = List::new()
+                .prepend("b".to_string())
+                .prepend("a".to_string());
+            list.iter_shared()
+            // `list` is dropped here, the iterator keeps the nodes alive.
+        }
+
+        let mut iter = make_iter();
+        let a = iter.next().unwrap();
+        assert_eq!(&*a, "a");
+        assert_eq!(iter.next().map(|s| s.len()), Some(1));
+        assert!(iter.next().is_none());
+        drop(iter);
+        assert_eq!(&*a, "a");
+    }
+
+    #[test]
+    fn hashed_list() {
+        use super::HashedList;
+        use std::collections::HashMap;
+
+        let a = HashedList::new().prepend(1).prepend(2).prepend(3);
+        let b = HashedList::new().prepend(1).prepend(2).prepend(3);
+        let c = HashedList::new().prepend(1).prepend(2).prepend(4);
+
+        assert_eq!(a.cached_hash(), b.cached_hash());
+        assert_ne!(a.cached_hash(), c.cached_hash());
+        assert!(a == b);
+        assert!(a != c);
+        assert!(a != a.tail());
+        assert!(a.tail() == b.tail());
+        assert!(HashedList::<i32>::new() == HashedList::new());
+        assert_eq!(a.iter().collect::<Vec<_>>(), vec![&3, &2, &1]);
+        assert_eq!(a.head(), Some(&3));
+
+        let mut memo = HashMap::new();
+        memo.insert(a, "a");
+        assert_eq!(memo.get(&b), Some(&"a"));
+        assert_eq!(memo.get(&c), None);
+    }
 }
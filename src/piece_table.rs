@@ -0,0 +1,284 @@
+/*
+ A piece table
+
+How does a text editor keep a large file in memory and still insert in the middle of it cheaply?
+A String would shift everything after the cursor on every keystroke. A piece table never moves
+text at all. It keeps two buffers:
+
+- original: the file as it was loaded. Never modified.
+- add: everything typed since, appended to in order. Never modified except at the end.
+
+and the document itself is a linked list of pieces, each one a span of one of the buffers:
+
+original: "the quick fox"          add: "brown "
+pieces:   [orig 0..10 "the quick "] -> [add 0..6 "brown "] -> [orig 10..13 "fox"]
+text:     "the quick brown fox"
+
+- insert(pos, text) appends text to the add buffer, finds the piece that pos falls in, splits it
+  in two if pos is in its middle, and links a new piece in between. At most 3 pieces change.
+- delete(range) trims the pieces at either end of the range and unlinks the ones in between.
+  Deleted text stays in the buffers, which is what makes undo easy in a real editor: put the
+  old pieces back.
+
+Typing a word one character at a time would make a piece per character, so insert first checks
+whether the piece just before pos ends exactly where the add buffer does. If so, that piece just
+grows. Continuous typing stays one piece.
+
+The list is arena_list::ArenaList, which can insert next to and remove any piece by handle.
+Finding the piece for a position is a walk from the front, so O(pieces), not O(length of the
+text): that's the price of the list over a tree of pieces, which real editors use for large
+files.
+
+Positions are byte offsets, as in String, and have to be on char boundaries.
+*/
+
+use crate::arena_list::{ArenaList, Handle};
+use std::fmt;
+use std::ops::Range;
+
+pub struct PieceTable {
+    original: String,
+    add: String,
+    pieces: ArenaList<Piece>,
+    len: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Source {
+    Original,
+    Add,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Piece {
+    source: Source,
+    start: usize,
+    len: usize,
+}
+
+impl PieceTable {
+    pub fn new(original: &str) -> Self {
+        let mut pieces = ArenaList::new();
+        if !original.is_empty() {
+            pieces.push_back(Piece {
+                source: Source::Original,
+                start: 0,
+                len: original.len(),
+            });
+        }
+        PieceTable {
+            original: original.to_string(),
+            add: String::new(),
+            pieces,
+            len: original.len(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn piece_count(&self) -> usize {
+        self.pieces.len()
+    }
+
+    fn text(&self, piece: &Piece) -> &str {
+        let buffer = match piece.source {
+            Source::Original => &self.original,
+            Source::Add => &self.add,
+        };
+        &buffer[piece.start..piece.start + piece.len]
+    }
+
+    // The piece that byte `pos` is in, and how far into it. None at the end of the text.
+    fn find(&self, pos: usize) -> Option<(Handle, usize)> {
+        let mut at = 0;
+        let mut cur = self.pieces.front_handle();
+        while let Some(h) = cur {
+            let len = self.pieces.get(h).unwrap().len;
+            if pos < at + len {
+                return Some((h, pos - at));
+            }
+            at += len;
+            cur = self.pieces.next(h);
+        }
+        None
+    }
+
+    fn check_boundary(&self, pos: usize) {
+        assert!(pos <= self.len, "position {pos} is past the end");
+        if let Some((h, offset)) = self.find(pos) {
+            let piece = self.pieces.get(h).unwrap();
+            assert!(
+                self.text(piece).is_char_boundary(offset),
+                "position {pos} isn't on a char boundary"
+            );
+        }
+    }
+
+    pub fn insert(&mut self, pos: usize, text: &str) {
+        self.check_boundary(pos);
+        if text.is_empty() {
+            return;
+        }
+        let piece = Piece {
+            source: Source::Add,
+            start: self.add.len(),
+            len: text.len(),
+        };
+        self.add.push_str(text);
+        self.len += text.len();
+
+        let (at, offset) = match self.find(pos) {
+            Some(found) => (Some(found.0), found.1),
+            None => (None, 0),
+        };
+        if offset > 0 {
+            // In the middle of a piece: split it, and put the new piece between the halves.
+            let h = at.unwrap();
+            let head = self.pieces.get_mut(h).unwrap();
+            let tail = Piece {
+                start: head.start + offset,
+                len: head.len - offset,
+                ..*head
+            };
+            head.len = offset;
+            let tail = self.pieces.insert_after(h, tail).ok().unwrap();
+            self.pieces.insert_before(tail, piece).ok().unwrap();
+            return;
+        }
+
+        // At a boundary between pieces. Grow the one before if we're typing onto its end.
+        let before = match at {
+            Some(h) => self.pieces.prev(h),
+            None => self.pieces.back_handle(),
+        };
+        if let Some(before) = before {
+            let prev = self.pieces.get_mut(before).unwrap();
+            if prev.source == Source::Add && prev.start + prev.len == piece.start {
+                prev.len += piece.len;
+                return;
+            }
+        }
+        match at {
+            Some(h) => {
+                self.pieces.insert_before(h, piece).ok().unwrap();
+            }
+            None => {
+                self.pieces.push_back(piece);
+            }
+        }
+    }
+
+    pub fn delete(&mut self, range: Range<usize>) {
+        assert!(range.start <= range.end, "range starts after it ends");
+        self.check_boundary(range.start);
+        self.check_boundary(range.end);
+
+        let mut at = 0;
+        let mut cur = self.pieces.front_handle();
+        while let Some(h) = cur.filter(|_| at < range.end) {
+            cur = self.pieces.next(h);
+            let piece = *self.pieces.get(h).unwrap();
+            let end = at + piece.len;
+            if end > range.start {
+                // The part of this piece to cut, relative to its start.
+                let lo = range.start.max(at) - at;
+                let hi = range.end.min(end) - at;
+                if lo == 0 && hi == piece.len {
+                    self.pieces.remove(h);
+                } else if lo == 0 {
+                    let kept = self.pieces.get_mut(h).unwrap();
+                    kept.start += hi;
+                    kept.len -= hi;
+                } else {
+                    self.pieces.get_mut(h).unwrap().len = lo;
+                    if hi < piece.len {
+                        // Cut out of the middle: what's after the cut becomes its own piece.
+                        let tail = Piece {
+                            start: piece.start + hi,
+                            len: piece.len - hi,
+                            ..piece
+                        };
+                        self.pieces.insert_after(h, tail).ok().unwrap();
+                    }
+                }
+            }
+            at = end;
+        }
+        self.len -= range.end - range.start;
+    }
+
+    // The text, one piece at a time.
+    pub fn chunks(&self) -> impl DoubleEndedIterator<Item = &str> + '_ {
+        self.pieces.iter().map(|piece| self.text(piece))
+    }
+
+    pub fn chars(&self) -> impl DoubleEndedIterator<Item = char> + '_ {
+        self.chunks().flat_map(str::chars)
+    }
+}
+
+impl fmt::Display for PieceTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chunks().try_for_each(|chunk| f.write_str(chunk))
+    }
+}
+
+impl fmt::Debug for PieceTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.chunks()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PieceTable;
+
+    #[test]
+    fn edits() {
+        let mut doc = PieceTable::new("the quick fox");
+        doc.insert(10, "brown ");
+        assert_eq!(doc.to_string(), "the quick brown fox");
+        assert_eq!(doc.piece_count(), 3);
+        assert_eq!(format!("{:?}", doc), r#"["the quick ", "brown ", "fox"]"#);
+
+        doc.insert(0, ">> ");
+        doc.insert(doc.len(), "!");
+        assert_eq!(doc.to_string(), ">> the quick brown fox!");
+
+        // Across three pieces, trimming the ones at either end.
+        doc.delete(7..19);
+        assert_eq!(doc.to_string(), ">> the fox!");
+        // Out of the middle of one piece.
+        doc.delete(1..4);
+        assert_eq!(doc.to_string(), ">he fox!");
+        doc.delete(0..doc.len());
+        assert!(doc.is_empty());
+        assert_eq!(doc.piece_count(), 0);
+        doc.insert(0, "again");
+        assert_eq!(doc.to_string(), "again");
+    }
+
+    #[test]
+    fn typing_grows_one_piece() {
+        let mut doc = PieceTable::new("ab");
+        for (i, c) in "hello".chars().enumerate() {
+            doc.insert(1 + i, &c.to_string());
+        }
+        assert_eq!(doc.to_string(), "ahellob");
+        assert_eq!(doc.piece_count(), 3);
+        assert_eq!(doc.chars().rev().collect::<String>(), "bolleha");
+    }
+
+    #[test]
+    #[should_panic(expected = "char boundary")]
+    fn splitting_a_char() {
+        let mut doc = PieceTable::new("né");
+        doc.insert(2, "x");
+    }
+}
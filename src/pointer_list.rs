@@ -0,0 +1,830 @@
+/*
+ One list, generic over its pointer
+
+Three modules in this crate are the same singly linked list with a different pointer in the link:
+
+- generic_and_iterators.rs: Box. Each node has one owner, and the list can change.
+- persistent_stack.rs:      Rc. Nodes are shared between lists, and never change.
+- arc_persistent_stack.rs:  Arc. The same, between threads.
+
+Each one started as a copy of the one before with the pointer changed, and they drifted apart.
+This module is the list written once: List<T, P>, where P picks the pointer, and each of those
+modules' List is this one with its pointer filled in:
+
+    generic_and_iterators::List<T, A> = List<T, BoxKind<A>>
+    persistent_stack::List<T>         = List<T, RcKind>
+    arc_persistent_stack::List<T>     = List<T, ArcKind>
+
+push, pop, peek, the iterators, Drop, Debug and the rest are here, once. What each module adds
+on top (push_boxed and the serde impls for the Box list, cons_all and the sorted operations for
+the Rc one, SharedHead for the Arc one) is in that module, as impls on its own pointer.
+
+A "kind" is a marker type that names a pointer family: Box, not Box<X>. The link can't just be a
+P<Node<T, P>>, since Rust has no type parameters that take type parameters, and a generic
+associated type that did (`type Pointer<X>`) would make every list invariant in T: the compiler
+can't see through an associated type, so it assumes the worst. A List<&'static str> couldn't be
+used as a List<&'a str> any more, which all three could before.
+
+So the links are the same raw pointer whatever the kind (Ptr, a NonNull to the node), and the
+kind is what made it and knows how to let it go. The list holds its kind by value, which for
+BoxKind<A> is an allocator (see allocator.rs): a Box<T, A> is only on nightly, but a BoxKind
+allocates its nodes through A the way one would, with Global as the default. That's all a list
+asks of it:
+
+- try_new: put a node behind a new pointer (Box::new_in, Rc::new, Arc::new), or give it back
+  if the allocator is out of memory.
+- release: give a pointer up, and if it was the last one, the node goes too.
+- try_unwrap: the node itself, if nobody else points at it.
+- get_mut: a &mut to the node, under the same condition.
+
+What each list can do then falls out of the pointer:
+
+- Every kind can push, peek, iterate, and pop. pop moves the element out when the node isn't
+  shared, which a Box never is; a shared node has to be cloned out of instead, so popping an
+  Rc or Arc list needs T: Clone.
+- peek_mut needs the head to be unshared: always for Box, sometimes for the others.
+- Clone, prepend and tail, the persistent operations, need a pointer that can be cloned: a
+  SharedKind, which Rc and Arc are and Box isn't. A BoxList doesn't have them.
+- Send and Sync are what the pointer's would be: a BoxList is Send like a Box, an ArcList like an
+  Arc, and an RcList not at all.
+- Only a Box can hold an unsized element (see generic_and_iterators' push_boxed, push_slice and
+  push_str). Rc and Arc hand a node back to the last owner with into_inner, the race-free way to
+  find out who that is, and it needs a sized value.
+
+Dropping is one loop for all three: release the head, and if that freed it, carry on with its
+next; stop at the first node another list still holds. Nothing is ever dropped by a pointer's
+own destructor, which would recurse down the list (Ptr doesn't have one), so a node kept alive by
+some other handle (persistent_stack's iter_shared, SharedHead's old versions) is held as a List
+too, and let go of the same way.
+*/
+
+use crate::allocator::{Allocator, Global};
+use crate::format::{self, ParseListError};
+use crate::mem_usage::{self, MemUsage};
+use crate::panic_safety;
+use crate::traits::{Collection, Stack};
+use std::alloc::{handle_alloc_error, Layout};
+use std::fmt::{self, Debug, Display};
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+use std::ptr::{self, NonNull};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// How a list with elements of type T holds its nodes.
+///
+/// # Safety
+///
+/// A Ptr from `try_new` must point at a live node until `release` (or `try_unwrap`) has been
+/// called on it and on every copy `SharedKind::clone_ptr` made of it. `get_mut` and
+/// `try_unwrap` may only succeed when no other Ptr points at the node.
+pub unsafe trait PointerKind<T: ?Sized>: Sized {
+    // A pointer to `node`, or `node` back if there's no memory for it.
+    fn try_new(&self, node: Node<T, Self>) -> Result<Ptr<T, Self>, Node<T, Self>>
+    where
+        T: Sized;
+
+    // Give `ptr` up. If it was the last pointer to its node, `last` gets the node, and then the
+    // node is dropped and freed.
+    fn release(&self, ptr: Ptr<T, Self>, last: impl FnOnce(&mut Node<T, Self>));
+
+    // The node, if `ptr` was the only pointer to it; otherwise `ptr` back.
+    fn try_unwrap(&self, ptr: Ptr<T, Self>) -> Result<Node<T, Self>, Ptr<T, Self>>
+    where
+        T: Sized;
+
+    fn get_mut(ptr: &mut Ptr<T, Self>) -> Option<&mut Node<T, Self>>;
+
+    // What try_new asks its allocator for, per node: just the node for a Box.
+    fn node_layout() -> Layout
+    where
+        T: Sized,
+    {
+        Layout::new::<Node<T, Self>>()
+    }
+
+    // The strong and weak counts, for the kinds that have them. See Debug below.
+    fn counts(_ptr: &Ptr<T, Self>) -> Option<(usize, usize)> {
+        None
+    }
+}
+
+/// A kind whose pointers can be cloned, so that one node can be in many lists.
+///
+/// # Safety
+///
+/// `clone_ptr` must return another pointer to the same node, which keeps it alive on its own.
+pub unsafe trait SharedKind: Clone {
+    fn clone_ptr<T>(ptr: &Ptr<T, Self>) -> Ptr<T, Self>
+    where
+        Self: PointerKind<T>;
+}
+
+// Box, with the allocator the nodes come from.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BoxKind<A: Allocator = Global>(pub(crate) A);
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RcKind;
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ArcKind;
+
+pub struct List<T: ?Sized, P: PointerKind<T>> {
+    pub(crate) head: Link<T, P>,
+    pub(crate) kind: P,
+}
+
+pub(crate) type Link<T, P> = Option<Ptr<T, P>>;
+
+// An unsized field has to come last, and repr(C) pins the layout down, so that push_boxed can
+// build a node by hand and the unsafe queue can take a chain of them over (see
+// unsafe_single_linked_queue.rs).
+#[repr(C)]
+pub struct Node<T: ?Sized, P> {
+    pub(crate) next: Link<T, P>,
+    pub(crate) elem: T,
+}
+
+// A pointer to a node, made by the kind P, and the only way to one: there's no public
+// constructor. It doesn't free anything when dropped; only P::release does.
+#[repr(transparent)]
+pub struct Ptr<T: ?Sized, P> {
+    node: NonNull<Node<T, P>>,
+    _kind: PhantomData<P>,
+}
+
+impl<T: ?Sized, P> Ptr<T, P> {
+    // Safety: `node` has to be what P would have made, and this takes over its count.
+    pub(crate) unsafe fn from_raw(node: NonNull<Node<T, P>>) -> Self {
+        Ptr {
+            node,
+            _kind: PhantomData,
+        }
+    }
+
+    pub(crate) fn into_raw(self) -> NonNull<Node<T, P>> {
+        self.node
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const Node<T, P> {
+        self.node.as_ptr()
+    }
+}
+
+impl<T: ?Sized, P> Deref for Ptr<T, P> {
+    type Target = Node<T, P>;
+
+    fn deref(&self) -> &Node<T, P> {
+        // Safety: a Ptr keeps its node alive.
+        unsafe { self.node.as_ref() }
+    }
+}
+
+// A Box is the node's only owner, so its pointer can lend the node out mutably.
+impl<T: ?Sized, A: Allocator> DerefMut for Ptr<T, BoxKind<A>> {
+    fn deref_mut(&mut self) -> &mut Node<T, BoxKind<A>> {
+        unsafe { self.node.as_mut() }
+    }
+}
+
+impl<A: Allocator> BoxKind<A> {
+    pub(crate) fn allocator(&self) -> &A {
+        &self.0
+    }
+
+    // Box::new_in and *box, for the Box list's own modules (sorted_list.rs, chained_hash_map.rs),
+    // which relink nodes by hand. Out of memory is an abort, as with Box.
+    pub(crate) fn boxed<T>(&self, node: Node<T, BoxKind<A>>) -> Ptr<T, BoxKind<A>> {
+        self.try_new(node)
+            .unwrap_or_else(|_| handle_alloc_error(Layout::new::<Node<T, BoxKind<A>>>()))
+    }
+
+    pub(crate) fn unboxed<T>(&self, ptr: Ptr<T, BoxKind<A>>) -> Node<T, BoxKind<A>> {
+        let node = ptr.into_raw();
+        unsafe {
+            let value = node.read();
+            self.0
+                .deallocate(node.cast(), Layout::new::<Node<T, BoxKind<A>>>());
+            value
+        }
+    }
+}
+
+// What a Box<Node, A> would do, by hand: the layout is the node's own, which for an unsized
+// element (push_boxed) is only known from the pointer.
+unsafe impl<T: ?Sized, A: Allocator> PointerKind<T> for BoxKind<A> {
+    fn try_new(&self, node: Node<T, Self>) -> Result<Ptr<T, Self>, Node<T, Self>>
+    where
+        T: Sized,
+    {
+        let Ok(raw) = self.0.allocate(Layout::new::<Node<T, Self>>()) else {
+            return Err(node);
+        };
+        let raw = raw.cast::<Node<T, Self>>();
+        unsafe {
+            raw.write(node);
+            Ok(Ptr::from_raw(raw))
+        }
+    }
+
+    fn release(&self, ptr: Ptr<T, Self>, last: impl FnOnce(&mut Node<T, Self>)) {
+        // Frees the node when dropped, so it's freed even if the element's Drop panics.
+        struct Dealloc<'a, A: Allocator>(&'a A, NonNull<u8>, Layout);
+
+        impl<A: Allocator> Drop for Dealloc<'_, A> {
+            fn drop(&mut self) {
+                unsafe { self.0.deallocate(self.1, self.2) }
+            }
+        }
+
+        let node = ptr.into_raw();
+        unsafe {
+            let _dealloc = Dealloc(&self.0, node.cast(), Layout::for_value(node.as_ref()));
+            last(&mut *node.as_ptr());
+            ptr::drop_in_place(node.as_ptr());
+        }
+    }
+
+    fn try_unwrap(&self, ptr: Ptr<T, Self>) -> Result<Node<T, Self>, Ptr<T, Self>>
+    where
+        T: Sized,
+    {
+        Ok(self.unboxed(ptr))
+    }
+
+    fn get_mut(ptr: &mut Ptr<T, Self>) -> Option<&mut Node<T, Self>> {
+        Some(ptr)
+    }
+}
+
+impl<T> Ptr<T, RcKind> {
+    fn from_rc(rc: Rc<Node<T, RcKind>>) -> Self {
+        unsafe { Ptr::from_raw(NonNull::new_unchecked(Rc::into_raw(rc).cast_mut())) }
+    }
+}
+
+unsafe impl<T> PointerKind<T> for RcKind {
+    fn try_new(&self, node: Node<T, Self>) -> Result<Ptr<T, Self>, Node<T, Self>> {
+        Ok(Ptr::from_rc(Rc::new(node)))
+    }
+
+    fn release(&self, ptr: Ptr<T, Self>, last: impl FnOnce(&mut Node<T, Self>)) {
+        let rc = unsafe { Rc::from_raw(ptr.as_ptr()) };
+        if let Some(mut node) = Rc::into_inner(rc) {
+            last(&mut node);
+        }
+    }
+
+    fn try_unwrap(&self, ptr: Ptr<T, Self>) -> Result<Node<T, Self>, Ptr<T, Self>> {
+        let rc = unsafe { Rc::from_raw(ptr.as_ptr()) };
+        Rc::try_unwrap(rc).map_err(Ptr::from_rc)
+    }
+
+    fn get_mut(ptr: &mut Ptr<T, Self>) -> Option<&mut Node<T, Self>> {
+        // Borrowed, not taken over: the count stays with `ptr`.
+        let mut rc = ManuallyDrop::new(unsafe { Rc::from_raw(ptr.as_ptr()) });
+        let node: *mut Node<T, Self> = Rc::get_mut(&mut rc)?;
+        Some(unsafe { &mut *node })
+    }
+
+    fn node_layout() -> Layout {
+        mem_usage::rc_layout::<Node<T, Self>>()
+    }
+
+    fn counts(ptr: &Ptr<T, Self>) -> Option<(usize, usize)> {
+        let rc = ManuallyDrop::new(unsafe { Rc::from_raw(ptr.as_ptr()) });
+        Some((Rc::strong_count(&rc), Rc::weak_count(&rc)))
+    }
+}
+
+unsafe impl SharedKind for RcKind {
+    fn clone_ptr<T>(ptr: &Ptr<T, Self>) -> Ptr<T, Self> {
+        unsafe {
+            Rc::increment_strong_count(ptr.as_ptr());
+            Ptr::from_raw(ptr.node)
+        }
+    }
+}
+
+// The same as RcKind, with Arc::into_inner as the one place that decides who drops a node: two
+// threads letting go of the same node at once can both fail try_unwrap, but only one of them
+// gets Some from into_inner.
+impl<T> Ptr<T, ArcKind> {
+    fn from_arc(arc: Arc<Node<T, ArcKind>>) -> Self {
+        unsafe { Ptr::from_raw(NonNull::new_unchecked(Arc::into_raw(arc).cast_mut())) }
+    }
+}
+
+unsafe impl<T> PointerKind<T> for ArcKind {
+    fn try_new(&self, node: Node<T, Self>) -> Result<Ptr<T, Self>, Node<T, Self>> {
+        Ok(Ptr::from_arc(Arc::new(node)))
+    }
+
+    fn release(&self, ptr: Ptr<T, Self>, last: impl FnOnce(&mut Node<T, Self>)) {
+        let arc = unsafe { Arc::from_raw(ptr.as_ptr()) };
+        if let Some(mut node) = Arc::into_inner(arc) {
+            last(&mut node);
+        }
+    }
+
+    fn try_unwrap(&self, ptr: Ptr<T, Self>) -> Result<Node<T, Self>, Ptr<T, Self>> {
+        let arc = unsafe { Arc::from_raw(ptr.as_ptr()) };
+        Arc::try_unwrap(arc).map_err(Ptr::from_arc)
+    }
+
+    fn get_mut(ptr: &mut Ptr<T, Self>) -> Option<&mut Node<T, Self>> {
+        let mut arc = ManuallyDrop::new(unsafe { Arc::from_raw(ptr.as_ptr()) });
+        let node: *mut Node<T, Self> = Arc::get_mut(&mut arc)?;
+        Some(unsafe { &mut *node })
+    }
+
+    fn node_layout() -> Layout {
+        mem_usage::rc_layout::<Node<T, Self>>()
+    }
+
+    fn counts(ptr: &Ptr<T, Self>) -> Option<(usize, usize)> {
+        let arc = ManuallyDrop::new(unsafe { Arc::from_raw(ptr.as_ptr()) });
+        Some((Arc::strong_count(&arc), Arc::weak_count(&arc)))
+    }
+}
+
+unsafe impl SharedKind for ArcKind {
+    fn clone_ptr<T>(ptr: &Ptr<T, Self>) -> Ptr<T, Self> {
+        unsafe {
+            Arc::increment_strong_count(ptr.as_ptr());
+            Ptr::from_raw(ptr.node)
+        }
+    }
+}
+
+pub type BoxList<T, A = Global> = List<T, BoxKind<A>>;
+pub type RcList<T> = List<T, RcKind>;
+pub type ArcList<T> = List<T, ArcKind>;
+
+// The links are raw pointers, so the auto traits are spelled out: each list is Send and Sync
+// when its pointer would be. For a Box that's when the allocator can go along with the nodes.
+unsafe impl<T: ?Sized + Send, A: Allocator + Send> Send for List<T, BoxKind<A>> {}
+unsafe impl<T: ?Sized + Sync, A: Allocator + Sync> Sync for List<T, BoxKind<A>> {}
+unsafe impl<T: Send + Sync> Send for List<T, ArcKind> {}
+unsafe impl<T: Send + Sync> Sync for List<T, ArcKind> {}
+
+// A new() for each kind, not one for every P: Default, so that List::new() through an alias
+// with a default parameter (generic_and_iterators::List<T, A = Global>) knows which list it's
+// making, as with HashMap::new. Default is the generic one.
+impl<T: ?Sized> List<T, BoxKind> {
+    pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+}
+
+impl<T> List<T, RcKind> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<T> List<T, ArcKind> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<T: ?Sized, P: PointerKind<T>> List<T, P> {
+    // No length is kept, so this walks the list.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.head.as_deref().map(|node| &node.elem)
+    }
+
+    // None if the head node is shared with another list.
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        let node = P::get_mut(self.head.as_mut()?)?;
+        Some(&mut node.elem)
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, P> {
+        Iter {
+            next: self.head.as_deref(),
+        }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> List<T, BoxKind<A>> {
+    pub fn new_in(alloc: A) -> Self {
+        List {
+            head: None,
+            kind: BoxKind(alloc),
+        }
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.kind.allocator()
+    }
+}
+
+impl<T, P: PointerKind<T>> List<T, P> {
+    // Err(elem) if the allocator has no room for another node: a Bump with a fixed block, an
+    // arena with a limit. Only a BoxKind's allocator can run out; Rc and Arc abort.
+    pub fn try_push(&mut self, elem: T) -> Result<(), T> {
+        let node = Node {
+            elem,
+            next: self.head.take(),
+        };
+        match self.kind.try_new(node) {
+            Ok(ptr) => {
+                self.head = Some(ptr);
+                Ok(())
+            }
+            Err(node) => {
+                self.head = node.next;
+                Err(node.elem)
+            }
+        }
+    }
+
+    pub fn push(&mut self, elem: T) {
+        if self.try_push(elem).is_err() {
+            // Same as what Box::new does on failure, with the layout that was asked for: for Rc
+            // and Arc that's the counts and the node together.
+            handle_alloc_error(P::node_layout());
+        }
+    }
+
+    // See mem_usage.rs. An Rc or Arc node shared with other lists is counted here too.
+    pub fn mem_usage(&self) -> MemUsage {
+        MemUsage::sized::<T>(self.len(), P::node_layout().size(), 1)
+    }
+}
+
+// Popping moves the element out of an unshared node, and clones it out of a shared one (which
+// other lists still need).
+impl<T, A: Allocator> List<T, BoxKind<A>> {
+    pub fn pop(&mut self) -> Option<T> {
+        let node = self.kind.unboxed(self.head.take()?);
+        self.head = node.next;
+        Some(node.elem)
+    }
+}
+
+impl<T: Clone, P: PointerKind<T> + SharedKind> List<T, P> {
+    pub fn pop(&mut self) -> Option<T> {
+        let node = self.head.take()?;
+        match self.kind.try_unwrap(node) {
+            Ok(node) => {
+                self.head = node.next;
+                Some(node.elem)
+            }
+            Err(shared) => {
+                self.head = Some(shared);
+                let elem = self.peek()?.clone();
+                *self = self.tail();
+                Some(elem)
+            }
+        }
+    }
+}
+
+// The persistent operations. They share structure, so they need a pointer that can be shared.
+impl<T, P: PointerKind<T> + SharedKind> List<T, P> {
+    // A new list with `elem` in front of this one, which is left as it was.
+    pub fn prepend(&self, elem: T) -> Self {
+        let mut list = self.clone();
+        list.push(elem);
+        list
+    }
+
+    // This list without its head, sharing the rest.
+    pub fn tail(&self) -> Self {
+        self.sharing(self.head.as_ref().and_then(|node| node.next.as_ref()))
+    }
+
+    // What the persistent lists call peek.
+    pub fn head(&self) -> Option<&T> {
+        self.peek()
+    }
+
+    // The same nodes, not just equal elements.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.head.as_ref().map(Ptr::as_ptr) == other.head.as_ref().map(Ptr::as_ptr)
+    }
+
+    // Another list starting at `ptr`, a node in this one.
+    pub(crate) fn sharing(&self, ptr: Option<&Ptr<T, P>>) -> Self {
+        List {
+            head: ptr.map(P::clone_ptr),
+            kind: self.kind.clone(),
+        }
+    }
+}
+
+impl<T, P: PointerKind<T> + SharedKind> Clone for List<T, P> {
+    fn clone(&self) -> Self {
+        self.sharing(self.head.as_ref())
+    }
+}
+
+impl<T: ?Sized, P: PointerKind<T> + Default> Default for List<T, P> {
+    fn default() -> Self {
+        List {
+            head: None,
+            kind: P::default(),
+        }
+    }
+}
+
+// Iteratively, stopping at the first node someone else still holds. head moves on before each
+// node is dropped, so an element whose Drop panics doesn't stop the rest (see panic_safety.rs).
+impl<T: ?Sized, P: PointerKind<T>> Drop for List<T, P> {
+    fn drop(&mut self) {
+        panic_safety::pop_all(self, |list| {
+            let Some(ptr) = list.head.take() else {
+                return false;
+            };
+            list.kind.release(ptr, |node| list.head = node.next.take());
+            true
+        });
+    }
+}
+
+/*
+{:?} is the elements, like any other collection. {:#?} is a node per line, and for Rc and Arc
+nodes their reference counts, marking everything from the first node with more than one as
+shared: see persistent_stack.rs. For an Arc list, other threads can be taking and dropping
+references while it prints, so each count is only what it was when its line was written.
+*/
+impl<T: ?Sized + Debug, P: PointerKind<T>> Debug for List<T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !f.alternate() {
+            return f.debug_list().entries(self.iter()).finish();
+        }
+
+        let mut list = f.debug_list();
+        let mut shared = false;
+        let mut cur = self.head.as_ref();
+        while let Some(ptr) = cur {
+            match P::counts(ptr) {
+                Some((strong, weak)) => {
+                    shared |= strong > 1;
+                    list.entry(&format_args!(
+                        "{:#?} (strong: {strong}, weak: {weak}){}",
+                        &ptr.elem,
+                        if shared { " shared" } else { "" }
+                    ));
+                }
+                None => {
+                    list.entry(&&ptr.elem);
+                }
+            }
+            cur = ptr.next.as_ref();
+        }
+        list.finish()
+    }
+}
+
+// "[1, 2, 3]", top first. See format.rs.
+impl<T: Display, P: PointerKind<T>> Display for List<T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format::write_list(f, self.iter())
+    }
+}
+
+impl<T: FromStr, P: PointerKind<T> + Default> FromStr for List<T, P> {
+    type Err = ParseListError<T::Err>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut list = Self::default();
+        for elem in format::parse_list(s)?.into_iter().rev() {
+            list.push(elem);
+        }
+        Ok(list)
+    }
+}
+
+impl<T, P: PointerKind<T> + Default> FromIterator<T> for List<T, P> {
+    // The first element ends up at the back, as with pushing them one at a time.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = Self::default();
+        for elem in iter {
+            list.push(elem);
+        }
+        list
+    }
+}
+
+// The crate-wide traits (see traits.rs). There's no length field, so len() walks the list.
+impl<T: ?Sized, P: PointerKind<T>> Collection for List<T, P> {
+    fn len(&self) -> usize {
+        List::len(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        List::is_empty(self)
+    }
+}
+
+impl<T, A: Allocator> Stack<T> for List<T, BoxKind<A>> {
+    fn push(&mut self, elem: T) {
+        List::<T, BoxKind<A>>::push(self, elem)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        List::<T, BoxKind<A>>::pop(self)
+    }
+
+    fn peek(&self) -> Option<impl Deref<Target = T> + '_> {
+        List::<T, BoxKind<A>>::peek(self)
+    }
+}
+
+// For a shared list it's an adapter: push and pop move this handle and never change a node
+// another list may share, which is why popping needs T: Clone (see pop above).
+impl<T: Clone, P: PointerKind<T> + SharedKind> Stack<T> for List<T, P> {
+    fn push(&mut self, elem: T) {
+        List::<T, P>::push(self, elem)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        List::<T, P>::pop(self)
+    }
+
+    fn peek(&self) -> Option<impl Deref<Target = T> + '_> {
+        List::<T, P>::peek(self)
+    }
+}
+
+pub struct Iter<'a, T: ?Sized, P> {
+    next: Option<&'a Node<T, P>>,
+}
+
+impl<'a, T: ?Sized, P> Iterator for Iter<'a, T, P> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.next?;
+        self.next = node.next.as_deref();
+        Some(&node.elem)
+    }
+}
+
+// A shared borrow of the list, so Send and Sync when a &List would be.
+unsafe impl<T: ?Sized, P: PointerKind<T>> Send for Iter<'_, T, P> where List<T, P>: Sync {}
+unsafe impl<T: ?Sized, P: PointerKind<T>> Sync for Iter<'_, T, P> where List<T, P>: Sync {}
+
+// Only a Box list can be iterated mutably, or by value: an Rc or Arc node may be in another list
+// too.
+pub struct IterMut<'a, T: ?Sized, P> {
+    next: Option<&'a mut Node<T, P>>,
+}
+
+impl<T: ?Sized, A: Allocator> List<T, BoxKind<A>> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, BoxKind<A>> {
+        IterMut {
+            next: self.head.as_deref_mut(),
+        }
+    }
+}
+
+impl<'a, T: ?Sized, A: Allocator> Iterator for IterMut<'a, T, BoxKind<A>> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<&'a mut T> {
+        self.next.take().map(|node| {
+            self.next = node.next.as_deref_mut();
+            &mut node.elem
+        })
+    }
+}
+
+// A mutable borrow of the list: Send and Sync when a &mut List would be.
+unsafe impl<T: ?Sized, P: PointerKind<T>> Send for IterMut<'_, T, P> where List<T, P>: Send {}
+unsafe impl<T: ?Sized, P: PointerKind<T>> Sync for IterMut<'_, T, P> where List<T, P>: Sync {}
+
+pub struct IntoIter<T, P: PointerKind<T>>(List<T, P>);
+
+impl<T, A: Allocator> IntoIterator for List<T, BoxKind<A>> {
+    type Item = T;
+    type IntoIter = IntoIter<T, BoxKind<A>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+impl<T, A: Allocator> Iterator for IntoIter<T, BoxKind<A>> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.pop()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ArcKind, ArcList, BoxKind, BoxList, List, Node, PointerKind, RcKind, RcList};
+    use std::alloc::Layout;
+    use std::mem;
+    use std::thread;
+
+    // Works the same whatever the pointer.
+    fn fill<P: PointerKind<i32> + Default>() -> List<i32, P> {
+        let mut list = List::default();
+        for x in 1..=3 {
+            list.push(x);
+        }
+        *list.peek_mut().unwrap() *= 10;
+        list
+    }
+
+    #[test]
+    fn unique() {
+        let mut list: BoxList<_> = fill();
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), [30, 2, 1]);
+        assert_eq!(list.pop(), Some(30));
+        assert_eq!(list.peek(), Some(&2));
+        assert_eq!(list.len(), 2);
+
+        // No Clone needed to pop a BoxList.
+        struct NotClone;
+        let mut list = BoxList::new();
+        list.push(NotClone);
+        assert!(list.pop().is_some());
+        assert!(list.pop().is_none());
+    }
+
+    #[test]
+    fn shared() {
+        let base: RcList<_> = fill();
+        let a = base.prepend(4);
+        let b = base.tail().prepend(5);
+        assert_eq!(
+            format!("{:?} {:?} {:?}", base, a, b),
+            "[30, 2, 1] [4, 30, 2, 1] [5, 2, 1]"
+        );
+
+        // `a`'s head is its own, but the rest is shared with `base`.
+        let mut a = a;
+        *a.peek_mut().unwrap() += 1;
+        assert_eq!(a.pop(), Some(5));
+        assert!(a.peek_mut().is_none());
+        assert_eq!(a.pop(), Some(30));
+        assert_eq!(base.iter().count(), 3);
+        assert!(a.ptr_eq(&base.tail()));
+
+        let long: RcList<_> = (0..100_000).collect();
+        let longer = long.prepend(-1);
+        drop(long);
+        assert_eq!(longer.len(), 100_001);
+    }
+
+    #[test]
+    fn threads() {
+        let list: ArcList<_> = (0..100).collect();
+        let sums: Vec<i32> = thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|i| {
+                    let list = list.prepend(i);
+                    s.spawn(move || list.iter().sum())
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert_eq!(sums, [4950, 4951, 4952, 4953]);
+
+        // Every thread lets go of the shared nodes at once, and exactly one of them frees each.
+        let list: ArcList<_> = (0..10_000).map(|x| x.to_string()).collect();
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let list = list.clone();
+                s.spawn(move || drop(list));
+            }
+        });
+        assert_eq!(list.len(), 10_000);
+    }
+
+    // An Rc or Arc node's allocation has the two counts in front of it; a Box's is the node.
+    #[test]
+    fn node_layouts() {
+        let node = Layout::new::<Node<u64, BoxKind>>();
+        let word = mem::size_of::<usize>();
+        assert_eq!(<BoxKind as PointerKind<u64>>::node_layout(), node);
+        assert_eq!(
+            <RcKind as PointerKind<u64>>::node_layout().size(),
+            2 * word + node.size()
+        );
+        assert_eq!(
+            <ArcKind as PointerKind<u64>>::node_layout().size(),
+            2 * word + node.size()
+        );
+
+        let list: RcList<u64> = (0..3).collect();
+        assert_eq!(list.mem_usage().heap_bytes, 3 * (2 * word + node.size()));
+    }
+}
@@ -0,0 +1,268 @@
+/*
+ Polynomials as sorted lists of terms
+
+The exercise that's in every data structures textbook's linked list chapter: a sparse polynomial
+is a list of (coefficient, exponent) terms in order of exponent, with no zero terms stored.
+
+3x^100 - 2x + 5    is    [3, 100] -> [-2, 1] -> [5, 0]
+
+An array indexed by exponent would need 101 slots for that; the list needs 3. And keeping the
+terms sorted makes the arithmetic a series of merges, which is exactly what sorted_list.rs is
+good at:
+
+- Adding is a merge of the two term lists (relinking nodes, not copying them), then one pass to
+  combine the neighbours that have the same exponent and drop any that cancel to 0. O(n + m).
+- Multiplying a polynomial by a single term keeps its terms in order, so the product is one such
+  row per term of the first factor, each merged into the result in turn: O(n * m) terms to merge,
+  and each merge is linear. Then the same combining pass at the end.
+- evaluate uses Horner's rule, skipping the missing powers: x^k for a gap of k, instead of
+  k multiply-by-x steps.
+
+Terms are ordered by exponent only, highest first, so that SortedList's ascending order is the
+usual way of writing a polynomial down.
+*/
+
+use crate::sorted_list::SortedList;
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul};
+
+#[derive(Clone)]
+pub struct Polynomial {
+    terms: SortedList<Term>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Term {
+    coeff: i64,
+    exp: u32,
+}
+
+// Higher exponents sort first. The coefficient doesn't take part, so two terms are "equal" when
+// they're terms in the same power of x.
+impl PartialEq for Term {
+    fn eq(&self, other: &Self) -> bool {
+        self.exp == other.exp
+    }
+}
+
+impl Eq for Term {}
+
+impl PartialOrd for Term {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Term {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.exp.cmp(&self.exp)
+    }
+}
+
+// Combine terms with the same exponent (they're next to each other) and drop the zeros.
+fn normalize(terms: &mut SortedList<Term>) {
+    terms.dedup_by(|next, kept| {
+        let same = next.exp == kept.exp;
+        if same {
+            kept.coeff += next.coeff;
+        }
+        same
+    });
+    terms.retain(|term| term.coeff != 0);
+}
+
+impl Polynomial {
+    // The zero polynomial, which has no terms at all.
+    pub fn zero() -> Self {
+        Polynomial {
+            terms: SortedList::new(),
+        }
+    }
+
+    // From (coefficient, exponent) pairs in any order. Repeated exponents are added together.
+    pub fn from_terms(terms: impl IntoIterator<Item = (i64, u32)>) -> Self {
+        let mut terms = terms
+            .into_iter()
+            .map(|(coeff, exp)| Term { coeff, exp })
+            .collect();
+        normalize(&mut terms);
+        Polynomial { terms }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    // None for the zero polynomial.
+    pub fn degree(&self) -> Option<u32> {
+        self.terms.first().map(|term| term.exp)
+    }
+
+    // (coefficient, exponent), highest power first.
+    pub fn terms(&self) -> impl Iterator<Item = (i64, u32)> + '_ {
+        self.terms.iter().map(|term| (term.coeff, term.exp))
+    }
+
+    pub fn evaluate(&self, x: i64) -> i64 {
+        let Some(degree) = self.degree() else {
+            return 0;
+        };
+        let (mut acc, mut last) = (0, degree);
+        for term in self.terms.iter() {
+            acc = acc * x.pow(last - term.exp) + term.coeff;
+            last = term.exp;
+        }
+        acc * x.pow(last)
+    }
+}
+
+impl Default for Polynomial {
+    fn default() -> Self {
+        Self::zero()
+    }
+}
+
+impl PartialEq for Polynomial {
+    fn eq(&self, other: &Self) -> bool {
+        self.terms().eq(other.terms())
+    }
+}
+
+impl Eq for Polynomial {}
+
+// By value, so the two term lists are merged in place.
+impl Add for Polynomial {
+    type Output = Polynomial;
+
+    fn add(mut self, other: Polynomial) -> Polynomial {
+        self.terms.merge(other.terms);
+        normalize(&mut self.terms);
+        self
+    }
+}
+
+impl Add for &Polynomial {
+    type Output = Polynomial;
+
+    fn add(self, other: &Polynomial) -> Polynomial {
+        self.clone() + other.clone()
+    }
+}
+
+impl Mul for &Polynomial {
+    type Output = Polynomial;
+
+    fn mul(self, other: &Polynomial) -> Polynomial {
+        let mut product = SortedList::new();
+        for a in self.terms.iter() {
+            let row: SortedList<Term> = other
+                .terms
+                .iter()
+                .map(|b| Term {
+                    coeff: a.coeff * b.coeff,
+                    exp: a.exp + b.exp,
+                })
+                .collect();
+            product.merge(row);
+        }
+        normalize(&mut product);
+        Polynomial { terms: product }
+    }
+}
+
+impl Mul for Polynomial {
+    type Output = Polynomial;
+
+    fn mul(self, other: Polynomial) -> Polynomial {
+        &self * &other
+    }
+}
+
+// The usual way: 3x^2 - x + 5
+impl fmt::Display for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        for (i, (coeff, exp)) in self.terms().enumerate() {
+            let sign = if coeff < 0 { "-" } else { "+" };
+            match i {
+                0 if coeff < 0 => write!(f, "-")?,
+                0 => {}
+                _ => write!(f, " {sign} ")?,
+            }
+            let abs = coeff.unsigned_abs();
+            if abs != 1 || exp == 0 {
+                write!(f, "{abs}")?;
+            }
+            match exp {
+                0 => {}
+                1 => write!(f, "x")?,
+                _ => write!(f, "x^{exp}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Polynomial({self})")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Polynomial;
+
+    #[test]
+    fn build_and_print() {
+        let p = Polynomial::from_terms([(5, 0), (-2, 1), (3, 100), (1, 1)]);
+        assert_eq!(p.to_string(), "3x^100 - x + 5");
+        assert_eq!(p.degree(), Some(100));
+        assert_eq!(p.terms().collect::<Vec<_>>(), [(3, 100), (-1, 1), (5, 0)]);
+
+        let zero = Polynomial::from_terms([(2, 3), (-2, 3)]);
+        assert!(zero.is_zero());
+        assert_eq!(zero, Polynomial::zero());
+        assert_eq!(zero.degree(), None);
+        assert_eq!(zero.to_string(), "0");
+        assert_eq!(
+            Polynomial::from_terms([(-1, 2), (-4, 0)]).to_string(),
+            "-x^2 - 4"
+        );
+    }
+
+    #[test]
+    fn arithmetic() {
+        let p = Polynomial::from_terms([(1, 1), (1, 0)]); // x + 1
+        let q = Polynomial::from_terms([(1, 1), (-1, 0)]); // x - 1
+        assert_eq!((&p + &q).to_string(), "2x");
+        assert_eq!((&p * &q).to_string(), "x^2 - 1");
+
+        // Adding the negation cancels everything.
+        let neg = Polynomial::from_terms(p.terms().map(|(c, e)| (-c, e)));
+        assert!((p.clone() + neg).is_zero());
+
+        // (x + 1)^5, by repeated multiplication.
+        let mut power = Polynomial::from_terms([(1, 0)]);
+        for _ in 0..5 {
+            power = power * p.clone();
+        }
+        assert_eq!(power.to_string(), "x^5 + 5x^4 + 10x^3 + 10x^2 + 5x + 1");
+        assert!((&power * &Polynomial::zero()).is_zero());
+    }
+
+    #[test]
+    fn evaluate() {
+        let p = Polynomial::from_terms([(3, 4), (-2, 1), (7, 0)]);
+        for x in -5..=5 {
+            assert_eq!(p.evaluate(x), 3 * x.pow(4) - 2 * x + 7);
+        }
+        // No constant term, so the last power of x is still applied.
+        let q = Polynomial::from_terms([(1, 3), (2, 2)]);
+        assert_eq!(q.evaluate(2), 16);
+        assert_eq!(Polynomial::zero().evaluate(9), 0);
+    }
+}
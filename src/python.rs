@@ -0,0 +1,404 @@
+/*
+ The lists from Python
+
+With the `python` feature this module is a Python extension (PyO3) with three of the crate's
+lists as Python classes, so they can be poked at from a REPL or a notebook:
+
+    >>> from linked_list_rc import Deque, LruCache, PersistentList
+    >>> d = Deque([1, 2, 3])
+    >>> d.appendleft(0); d.pop()
+    3
+    >>> len(d), d[0], d[-1], list(d)
+    (3, 0, 2, [0, 1, 2])
+
+- Deque is unsafe_doubly_linked_deque::List, with collections.deque's method names: append,
+  appendleft, pop, popleft.
+- LruCache is lru_cache::LruCache, used like a dict: cache[key] = value, cache[key] (which counts
+  as a use), `key in cache` (which doesn't), del cache[key]. Iterating gives the keys, most
+  recently used first.
+- PersistentList is persistent_stack::List: prepend and tail return new lists that share nodes
+  with the old one, which never changes.
+
+The elements are Python objects, held as PyObject. LruCache's keys are too, wrapped in Key, which
+hashes with Python's hash() and compares with ==, like a dict's keys.
+
+Two things are different from the Rust side:
+
+- d[i] works, and walks from whichever end is nearer, so it's O(n) as on any linked list. The
+  point of these classes is to see that, not to hide it.
+- Iterating takes a snapshot of the elements first. A Python loop can change the list it's
+  iterating over, which a Rust iterator borrowing the list would make impossible, and which a raw
+  pointer into the list would make unsound.
+
+To build the module: `maturin develop` in the crate root (see pyproject.toml), which turns on
+this feature and pyo3's extension-module.
+*/
+
+use crate::lru_cache::LruCache;
+use crate::{persistent_stack, unsafe_doubly_linked_deque};
+use pyo3::exceptions::{PyIndexError, PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use std::hash::{Hash, Hasher};
+
+#[pyclass(name = "Deque", module = "linked_list_rc")]
+pub struct PyDeque(unsafe_doubly_linked_deque::List<PyObject>);
+
+#[pyclass(name = "LruCache", module = "linked_list_rc")]
+pub struct PyLruCache(LruCache<Key, PyObject>);
+
+// Rc nodes, so it has to stay on the thread that made it.
+#[pyclass(name = "PersistentList", module = "linked_list_rc", unsendable)]
+pub struct PyPersistentList(persistent_stack::List<PyObject>);
+
+// What `iter()` on any of them returns: a snapshot, see above.
+#[pyclass(module = "linked_list_rc")]
+pub struct Snapshot(std::vec::IntoIter<PyObject>);
+
+// A Python object as a HashMap key. The hash is taken once, when the Key is made, and that's
+// also where an unhashable key (a list, say) is turned away with Python's TypeError.
+pub struct Key {
+    hash: isize,
+    obj: PyObject,
+}
+
+impl Key {
+    fn new(obj: &Bound<'_, PyAny>) -> PyResult<Key> {
+        Ok(Key {
+            hash: obj.hash()?,
+            obj: obj.clone().unbind(),
+        })
+    }
+}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hash.hash(state);
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Key) -> bool {
+        // An __eq__ that raises counts as not equal.
+        Python::with_gil(|py| self.obj.bind(py).eq(other.obj.bind(py)).unwrap_or(false))
+    }
+}
+
+impl Eq for Key {}
+
+impl Clone for Key {
+    fn clone(&self) -> Key {
+        Python::with_gil(|py| Key {
+            hash: self.hash,
+            obj: self.obj.clone_ref(py),
+        })
+    }
+}
+
+// Python's index rules: -1 is the last element, and anything out of range is an IndexError.
+fn index(i: isize, len: usize) -> PyResult<usize> {
+    let i = if i < 0 { i + len as isize } else { i };
+    if (0..len as isize).contains(&i) {
+        Ok(i as usize)
+    } else {
+        Err(PyIndexError::new_err("index out of range"))
+    }
+}
+
+fn snapshot<'a>(py: Python<'_>, elems: impl Iterator<Item = &'a PyObject>) -> Snapshot {
+    Snapshot(
+        elems
+            .map(|x| x.clone_ref(py))
+            .collect::<Vec<_>>()
+            .into_iter(),
+    )
+}
+
+// "[1, 'a']", with each element's repr.
+fn repr<'a>(py: Python<'_>, elems: impl Iterator<Item = &'a PyObject>) -> PyResult<String> {
+    let elems = elems
+        .map(|x| Ok(x.bind(py).repr()?.to_string()))
+        .collect::<PyResult<Vec<_>>>()?;
+    Ok(format!("[{}]", elems.join(", ")))
+}
+
+#[pymethods]
+impl PyDeque {
+    #[new]
+    #[pyo3(signature = (iterable = None))]
+    fn new(iterable: Option<&Bound<'_, PyAny>>) -> PyResult<Self> {
+        let mut list = unsafe_doubly_linked_deque::List::new();
+        if let Some(iterable) = iterable {
+            for elem in iterable.try_iter()? {
+                list.push_back(elem?.unbind());
+            }
+        }
+        Ok(PyDeque(list))
+    }
+
+    fn append(&mut self, elem: PyObject) {
+        self.0.push_back(elem);
+    }
+
+    fn appendleft(&mut self, elem: PyObject) {
+        self.0.push_front(elem);
+    }
+
+    fn pop(&mut self) -> PyResult<PyObject> {
+        self.0
+            .pop_back()
+            .ok_or_else(|| PyIndexError::new_err("pop from an empty deque"))
+    }
+
+    fn popleft(&mut self) -> PyResult<PyObject> {
+        self.0
+            .pop_front()
+            .ok_or_else(|| PyIndexError::new_err("pop from an empty deque"))
+    }
+
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.len()
+    }
+
+    fn __getitem__(&self, py: Python<'_>, i: isize) -> PyResult<PyObject> {
+        let len = self.0.len();
+        let i = index(i, len)?;
+        let elem = if i < len / 2 {
+            self.0.iter().nth(i)
+        } else {
+            self.0.iter().nth_back(len - 1 - i)
+        };
+        Ok(elem.unwrap().clone_ref(py))
+    }
+
+    fn __iter__(&self, py: Python<'_>) -> Snapshot {
+        snapshot(py, self.0.iter())
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!("Deque({})", repr(py, self.0.iter())?))
+    }
+}
+
+#[pymethods]
+impl PyLruCache {
+    #[new]
+    fn new(capacity: usize) -> PyResult<Self> {
+        if capacity == 0 {
+            return Err(PyValueError::new_err(
+                "an LRU cache needs room for at least one entry",
+            ));
+        }
+        Ok(PyLruCache(LruCache::new(capacity)))
+    }
+
+    #[getter]
+    fn capacity(&self) -> usize {
+        self.0.capacity()
+    }
+
+    // Like dict.get: None (or `default`) if it's not there. Counts as a use.
+    #[pyo3(signature = (key, default = None))]
+    fn get(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        default: Option<PyObject>,
+    ) -> PyResult<Option<PyObject>> {
+        let value = self.0.get(&Key::new(key)?).map(|v| v.clone_ref(py));
+        Ok(value.or(default))
+    }
+
+    // Returns the (key, value) that made room, if any: see LruCache::put.
+    fn put(
+        &mut self,
+        key: &Bound<'_, PyAny>,
+        value: PyObject,
+    ) -> PyResult<Option<(PyObject, PyObject)>> {
+        let evicted = self.0.put(Key::new(key)?, value);
+        Ok(evicted.map(|(k, v)| (k.obj, v)))
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.len()
+    }
+
+    fn __contains__(&self, key: &Bound<'_, PyAny>) -> PyResult<bool> {
+        Ok(self.0.contains_key(&Key::new(key)?))
+    }
+
+    fn __getitem__(&mut self, py: Python<'_>, key: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        match self.0.get(&Key::new(key)?) {
+            Some(value) => Ok(value.clone_ref(py)),
+            None => Err(PyKeyError::new_err(key.clone().unbind())),
+        }
+    }
+
+    fn __setitem__(&mut self, key: &Bound<'_, PyAny>, value: PyObject) -> PyResult<()> {
+        self.0.put(Key::new(key)?, value);
+        Ok(())
+    }
+
+    fn __delitem__(&mut self, key: &Bound<'_, PyAny>) -> PyResult<()> {
+        match self.0.pop(&Key::new(key)?) {
+            Some(_) => Ok(()),
+            None => Err(PyKeyError::new_err(key.clone().unbind())),
+        }
+    }
+
+    // The keys, most recently used first.
+    fn __iter__(&self, py: Python<'_>) -> Snapshot {
+        snapshot(py, self.0.iter().map(|(k, _)| &k.obj))
+    }
+}
+
+#[pymethods]
+impl PyPersistentList {
+    // PersistentList([1, 2, 3]) has 1 at the head.
+    #[new]
+    #[pyo3(signature = (iterable = None))]
+    fn new(iterable: Option<&Bound<'_, PyAny>>) -> PyResult<Self> {
+        let mut elems = Vec::new();
+        if let Some(iterable) = iterable {
+            for elem in iterable.try_iter()? {
+                elems.push(elem?.unbind());
+            }
+        }
+        Ok(PyPersistentList(
+            persistent_stack::List::new().cons_all(elems),
+        ))
+    }
+
+    fn prepend(&self, elem: PyObject) -> Self {
+        PyPersistentList(self.0.prepend(elem))
+    }
+
+    // The empty list's tail is the empty list, as in Rust.
+    fn tail(&self) -> Self {
+        PyPersistentList(self.0.tail())
+    }
+
+    #[getter]
+    fn head(&self, py: Python<'_>) -> Option<PyObject> {
+        self.0.head().map(|x| x.clone_ref(py))
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.len()
+    }
+
+    fn __getitem__(&self, py: Python<'_>, i: isize) -> PyResult<PyObject> {
+        let i = index(i, self.0.len())?;
+        Ok(self.0.iter().nth(i).unwrap().clone_ref(py))
+    }
+
+    fn __iter__(&self, py: Python<'_>) -> Snapshot {
+        snapshot(py, self.0.iter())
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!("PersistentList({})", repr(py, self.0.iter())?))
+    }
+}
+
+#[pymethods]
+impl Snapshot {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> Option<PyObject> {
+        self.0.next()
+    }
+}
+
+#[pymodule]
+fn linked_list_rc(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyDeque>()?;
+    m.add_class::<PyLruCache>()?;
+    m.add_class::<PyPersistentList>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PyDeque, PyLruCache, PyPersistentList};
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+    use std::ffi::CString;
+
+    // Run `code` with the three classes in scope, as after `from linked_list_rc import *`.
+    fn run(code: &str) {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let globals = PyDict::new(py);
+            globals.set_item("Deque", py.get_type::<PyDeque>()).unwrap();
+            globals
+                .set_item("LruCache", py.get_type::<PyLruCache>())
+                .unwrap();
+            globals
+                .set_item("PersistentList", py.get_type::<PyPersistentList>())
+                .unwrap();
+            py.run(&CString::new(code).unwrap(), Some(&globals), None)
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn deque() {
+        run(r#"
+d = Deque([1, 2, 3])
+d.appendleft(0)
+assert d.pop() == 3
+assert len(d) == 3
+assert (d[0], d[1], d[-1]) == (0, 1, 2)
+assert list(d) == [0, 1, 2]
+assert repr(d) == "Deque([0, 1, 2])"
+try:
+    d[3]
+    assert False
+except IndexError:
+    pass
+# Changing the deque while iterating over it is fine: the loop sees the snapshot.
+for x in d:
+    d.append(x)
+assert list(d) == [0, 1, 2, 0, 1, 2]
+"#);
+    }
+
+    #[test]
+    fn lru_cache() {
+        run(r#"
+cache = LruCache(2)
+cache["a"] = 1
+cache[("b", 2)] = 2
+assert cache["a"] == 1
+assert cache.put("c", 3) == (("b", 2), 2)
+assert list(cache) == ["c", "a"]
+assert "b" not in cache and "a" in cache
+assert cache.get("b", 0) == 0
+del cache["a"]
+assert len(cache) == 1
+try:
+    cache[[]] = 0
+    assert False
+except TypeError:
+    pass
+"#);
+    }
+
+    #[test]
+    fn persistent_list() {
+        run(r#"
+xs = PersistentList([2, 3])
+ys = xs.prepend(1)
+assert list(ys) == [1, 2, 3] and list(xs) == [2, 3]
+assert ys.head == 1 and list(ys.tail()) == [2, 3]
+assert ys[-1] == 3 and len(ys) == 3
+assert PersistentList().head is None
+"#);
+    }
+}
@@ -0,0 +1,369 @@
+/*
+ Recording and replaying operations
+
+A list that ends up in a bad state got there through a sequence of pushes and pops, and that
+sequence is the bug report: "this trace corrupts the deque" can be run again, by anyone, on any
+version of the code. A Recorder wraps a list, implements the traits in traits.rs like the list
+does, and writes down every change made through it, along with what each pop returned:
+
+    let mut list = Recorder::new(unsafe_doubly_linked_deque::List::new());
+    list.push_back(1);
+    list.push_front(0);
+    list.pop_back();
+    let trace = list.trace();   // [PushBack(1), PushFront(0), PopBack(Some(1))]
+
+replay() does the trace again on another list, which can be a different implementation, and
+checks each pop gets what the recording got. Recorded on one deque and replayed on another is a
+differential test; recorded before a change and replayed after is a regression test. Either way
+the list ends up with the same contents as the one the trace was recorded on, and the first pop
+that comes out differently is an error that says which op it was.
+
+Ops are kept with the trait they came through: Stack::push is Push, Queue::push is Enqueue, and
+a deque's push_back is PushBack. A deque's Stack::push can push at either end (the trait doesn't
+say which), so mapping one onto another would give a trace that only replays on the list it was
+recorded on. replay_stack and replay_queue take a list that only implements that one trait, and
+stop at an op it has no method for; replay needs all three.
+
+Only changes are recorded. Peeks and iteration don't change anything, so replaying without them
+reaches the same state. Recording a push clones the element into the trace, so T: Clone.
+
+With the `serde` feature, an Op is Serialize and Deserialize, so a trace is a Vec<Op<T>> in
+whatever format serde writes, attached to an issue or checked into a test.
+*/
+
+use crate::traits::{Collection, Deque, Queue, Stack};
+use std::fmt;
+use std::ops::Deref;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Op<T> {
+    // Stack.
+    Push(T),
+    Pop(Option<T>),
+    // Queue.
+    Enqueue(T),
+    Dequeue(Option<T>),
+    // Deque.
+    PushFront(T),
+    PushBack(T),
+    PopFront(Option<T>),
+    PopBack(Option<T>),
+}
+
+pub struct Recorder<L, T> {
+    list: L,
+    trace: Vec<Op<T>>,
+}
+
+impl<L, T> Recorder<L, T> {
+    pub fn new(list: L) -> Self {
+        Recorder {
+            list,
+            trace: Vec::new(),
+        }
+    }
+
+    pub fn get(&self) -> &L {
+        &self.list
+    }
+
+    pub fn trace(&self) -> &[Op<T>] {
+        &self.trace
+    }
+
+    pub fn into_parts(self) -> (L, Vec<Op<T>>) {
+        (self.list, self.trace)
+    }
+}
+
+impl<L: Default, T> Default for Recorder<L, T> {
+    fn default() -> Self {
+        Self::new(L::default())
+    }
+}
+
+impl<L: Collection, T> Collection for Recorder<L, T> {
+    fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+}
+
+impl<T: Clone, L: Stack<T>> Stack<T> for Recorder<L, T> {
+    fn push(&mut self, elem: T) {
+        self.trace.push(Op::Push(elem.clone()));
+        Stack::push(&mut self.list, elem);
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        let elem = Stack::pop(&mut self.list);
+        self.trace.push(Op::Pop(elem.clone()));
+        elem
+    }
+
+    fn peek(&self) -> Option<impl Deref<Target = T> + '_> {
+        Stack::peek(&self.list)
+    }
+}
+
+impl<T: Clone, L: Queue<T>> Queue<T> for Recorder<L, T> {
+    fn push(&mut self, elem: T) {
+        self.trace.push(Op::Enqueue(elem.clone()));
+        Queue::push(&mut self.list, elem);
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        let elem = Queue::pop(&mut self.list);
+        self.trace.push(Op::Dequeue(elem.clone()));
+        elem
+    }
+
+    fn peek(&self) -> Option<impl Deref<Target = T> + '_> {
+        Queue::peek(&self.list)
+    }
+}
+
+impl<T: Clone, L: Deque<T>> Deque<T> for Recorder<L, T> {
+    fn push_front(&mut self, elem: T) {
+        self.trace.push(Op::PushFront(elem.clone()));
+        self.list.push_front(elem);
+    }
+
+    fn push_back(&mut self, elem: T) {
+        self.trace.push(Op::PushBack(elem.clone()));
+        self.list.push_back(elem);
+    }
+
+    fn pop_front(&mut self) -> Option<T> {
+        let elem = self.list.pop_front();
+        self.trace.push(Op::PopFront(elem.clone()));
+        elem
+    }
+
+    fn pop_back(&mut self) -> Option<T> {
+        let elem = self.list.pop_back();
+        self.trace.push(Op::PopBack(elem.clone()));
+        elem
+    }
+
+    fn peek_front(&self) -> Option<impl Deref<Target = T> + '_> {
+        self.list.peek_front()
+    }
+
+    fn peek_back(&self) -> Option<impl Deref<Target = T> + '_> {
+        self.list.peek_back()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReplayError<T> {
+    // trace[at] is a pop that gave `got`, where the recording got `expected`.
+    Diverged {
+        at: usize,
+        expected: Option<T>,
+        got: Option<T>,
+    },
+    // trace[at] came through a trait this list doesn't implement.
+    Unsupported {
+        at: usize,
+    },
+}
+
+impl<T> fmt::Display for ReplayError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Diverged { at, .. } => {
+                write!(f, "op {at} popped something other than what was recorded")
+            }
+            ReplayError::Unsupported { at } => write!(f, "op {at} can't be done on this list"),
+        }
+    }
+}
+
+// The stack ops, on anything that's a stack. Anything else in the trace is Unsupported.
+pub fn replay_stack<T, L>(trace: &[Op<T>], list: &mut L) -> Result<(), ReplayError<T>>
+where
+    T: Clone + PartialEq,
+    L: Stack<T>,
+{
+    run(trace, |op| match op {
+        Op::Push(elem) => {
+            Stack::push(list, elem.clone());
+            Some(None)
+        }
+        Op::Pop(_) => Some(Stack::pop(list)),
+        _ => None,
+    })
+}
+
+pub fn replay_queue<T, L>(trace: &[Op<T>], list: &mut L) -> Result<(), ReplayError<T>>
+where
+    T: Clone + PartialEq,
+    L: Queue<T>,
+{
+    run(trace, |op| match op {
+        Op::Enqueue(elem) => {
+            Queue::push(list, elem.clone());
+            Some(None)
+        }
+        Op::Dequeue(_) => Some(Queue::pop(list)),
+        _ => None,
+    })
+}
+
+// Every op, on a list that's all three.
+pub fn replay<T, L>(trace: &[Op<T>], list: &mut L) -> Result<(), ReplayError<T>>
+where
+    T: Clone + PartialEq,
+    L: Stack<T> + Queue<T> + Deque<T>,
+{
+    run(trace, |op| {
+        match op {
+            Op::Push(elem) => Stack::push(list, elem.clone()),
+            Op::Enqueue(elem) => Queue::push(list, elem.clone()),
+            Op::PushFront(elem) => list.push_front(elem.clone()),
+            Op::PushBack(elem) => list.push_back(elem.clone()),
+            Op::Pop(_) => return Some(Stack::pop(list)),
+            Op::Dequeue(_) => return Some(Queue::pop(list)),
+            Op::PopFront(_) => return Some(list.pop_front()),
+            Op::PopBack(_) => return Some(list.pop_back()),
+        }
+        Some(None)
+    })
+}
+
+impl<T> Op<T> {
+    // What a pop got when it was recorded. None for a push.
+    fn popped(&self) -> Option<&Option<T>> {
+        match self {
+            Op::Pop(elem) | Op::Dequeue(elem) | Op::PopFront(elem) | Op::PopBack(elem) => {
+                Some(elem)
+            }
+            Op::Push(_) | Op::Enqueue(_) | Op::PushFront(_) | Op::PushBack(_) => None,
+        }
+    }
+}
+
+// `apply` does one op, and returns what it popped (None for a push), or None if it can't do it.
+fn run<T: Clone + PartialEq>(
+    trace: &[Op<T>],
+    mut apply: impl FnMut(&Op<T>) -> Option<Option<T>>,
+) -> Result<(), ReplayError<T>> {
+    for (at, op) in trace.iter().enumerate() {
+        let got = apply(op).ok_or(ReplayError::Unsupported { at })?;
+        match op.popped() {
+            Some(expected) if *expected != got => {
+                return Err(ReplayError::Diverged {
+                    at,
+                    expected: expected.clone(),
+                    got,
+                })
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{replay, replay_queue, replay_stack, Op, Recorder, ReplayError};
+    use crate::traits::{Deque, Queue, Stack};
+    use crate::{
+        doubly_linked_deque, generic_and_iterators, unsafe_doubly_linked_deque,
+        unsafe_single_linked_queue,
+    };
+
+    #[test]
+    fn record() {
+        let mut list = Recorder::new(unsafe_doubly_linked_deque::List::new());
+        list.push_back(1);
+        list.push_front(0);
+        assert_eq!(list.peek_back().as_deref(), Some(&1));
+        assert_eq!(list.pop_back(), Some(1));
+        Queue::push(&mut list, 2);
+        Stack::pop(&mut list);
+        assert_eq!(
+            list.trace(),
+            [
+                Op::PushBack(1),
+                Op::PushFront(0),
+                Op::PopBack(Some(1)),
+                Op::Enqueue(2),
+                Op::Pop(Some(0)),
+            ]
+        );
+    }
+
+    // Recorded on one deque, replayed on the other.
+    #[test]
+    fn differential() {
+        let mut list = Recorder::new(unsafe_doubly_linked_deque::List::new());
+        for x in 0..50 {
+            match x % 7 {
+                0 | 3 => list.push_front(x),
+                1 | 4 => Stack::push(&mut list, x),
+                2 => {
+                    list.pop_back();
+                }
+                5 => Queue::push(&mut list, x),
+                _ => {
+                    Queue::pop(&mut list);
+                }
+            }
+        }
+        let (recorded, trace) = list.into_parts();
+
+        let mut other = doubly_linked_deque::List::new();
+        assert_eq!(replay(&trace, &mut other), Ok(()));
+        let other: Vec<_> = other.into_iter().collect();
+        assert_eq!(other, recorded.into_iter().collect::<Vec<_>>());
+
+        // A stack trace replays on any stack, and a queue's on any queue.
+        let mut stack = Recorder::new(doubly_linked_deque::List::new());
+        (0..10).for_each(|x| Stack::push(&mut stack, x));
+        Stack::pop(&mut stack);
+        let mut boxed = generic_and_iterators::List::new();
+        assert_eq!(replay_stack(stack.trace(), &mut boxed), Ok(()));
+        assert_eq!(boxed.peek(), Some(&8));
+
+        let mut queue = unsafe_single_linked_queue::List::new();
+        let trace = [Op::Enqueue(1), Op::Enqueue(2), Op::Dequeue(Some(1))];
+        assert_eq!(replay_queue(&trace, &mut queue), Ok(()));
+        assert_eq!(queue.peek(), Some(&2));
+    }
+
+    #[test]
+    fn replay_errors() {
+        let trace = [Op::PushBack(1), Op::PushBack(2), Op::PopFront(Some(2))];
+        let mut list = unsafe_doubly_linked_deque::List::new();
+        assert_eq!(
+            replay(&trace, &mut list),
+            Err(ReplayError::Diverged {
+                at: 2,
+                expected: Some(2),
+                got: Some(1),
+            })
+        );
+
+        let mut stack = generic_and_iterators::List::new();
+        assert_eq!(
+            replay_stack(&trace, &mut stack),
+            Err(ReplayError::Unsupported { at: 0 })
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let trace = vec![Op::PushFront(1), Op::PopBack(Some(1)), Op::Dequeue(None)];
+        let json = serde_json::to_string(&trace).unwrap();
+        assert_eq!(json, r#"[{"PushFront":1},{"PopBack":1},{"Dequeue":null}]"#);
+        let back: Vec<Op<i32>> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, trace);
+    }
+}
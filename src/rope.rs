@@ -0,0 +1,307 @@
+/*
+ A rope
+
+A piece table (piece_table.rs) never copies text, but every edit adds pieces. A rope takes the
+other approach: the text itself is cut into chunks of at most CHUNK bytes, each owned by a node
+of a singly linked list.
+
+head -> ["It was the best "] -> ["of times, it "] -> ["was the worst of times"] -> None
+
+- insert(pos, text) walks to the chunk pos falls in and inserts into that chunk's String. That
+  shifts at most one chunk's worth of bytes, not the rest of the text. If the chunk ends up over
+  CHUNK, it's split in half (repeatedly, for a big insert) and the new halves are linked in
+  after it.
+- delete(range) drains the range out of the chunks it covers and unlinks any left empty. Then the
+  chunks either side of the cut are merged if they fit in one, so deleting doesn't leave a trail
+  of tiny chunks behind.
+- chunks() iterates the text as &str slices, one per chunk, which is what you'd hand to a writer
+  or a renderer. byte_chunks() is the same as &[u8].
+
+Finding the chunk is a walk from the head, so an edit is O(chunks + CHUNK): the list is what a
+real rope replaces with a balanced tree of chunks to get O(log n). With 1 KiB chunks, a 1 MB text
+is 1000 nodes to walk, which is still plenty fast for the edits a person makes.
+
+Positions are byte offsets and have to be on char boundaries, as in String. A chunk never ends in
+the middle of a char either, so every chunk is a valid &str.
+*/
+
+use std::fmt;
+use std::ops::Range;
+
+const CHUNK: usize = 1024;
+
+pub struct Rope {
+    head: Link,
+    len: usize,
+    chunk_size: usize,
+}
+
+type Link = Option<Box<Chunk>>;
+
+struct Chunk {
+    text: String,
+    next: Link,
+}
+
+impl Rope {
+    pub fn new() -> Self {
+        Self::with_chunk_size(CHUNK)
+    }
+
+    // Small chunks are mostly useful for testing.
+    pub fn with_chunk_size(chunk_size: usize) -> Self {
+        assert!(chunk_size >= 8, "chunks must fit a few chars");
+        Rope {
+            head: None,
+            len: 0,
+            chunk_size,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks().count()
+    }
+
+    // The link holding the chunk `pos` is in, and where that chunk starts. A position between
+    // two chunks counts as the end of the first one.
+    fn seek(&mut self, pos: usize) -> (&mut Link, usize) {
+        let mut at = 0;
+        let mut link = &mut self.head;
+        while link
+            .as_ref()
+            .is_some_and(|chunk| at + chunk.text.len() < pos)
+        {
+            let chunk = link.as_mut().unwrap();
+            at += chunk.text.len();
+            link = &mut chunk.next;
+        }
+        (link, at)
+    }
+
+    fn check_boundary(&mut self, pos: usize) {
+        assert!(pos <= self.len, "position {pos} is past the end");
+        let (link, at) = self.seek(pos);
+        if let Some(chunk) = link {
+            assert!(
+                chunk.text.is_char_boundary(pos - at),
+                "position {pos} isn't on a char boundary"
+            );
+        }
+    }
+
+    pub fn insert(&mut self, pos: usize, text: &str) {
+        self.check_boundary(pos);
+        if text.is_empty() {
+            return;
+        }
+        let chunk_size = self.chunk_size;
+        self.len += text.len();
+        let (link, at) = self.seek(pos);
+        let chunk = link.get_or_insert_with(|| {
+            Box::new(Chunk {
+                text: String::new(),
+                next: None,
+            })
+        });
+        chunk.text.insert_str(pos - at, text);
+
+        // Split in half until every piece fits.
+        let mut cur = chunk;
+        while cur.text.len() > chunk_size {
+            let mut cut = chunk_size.min(cur.text.len() / 2);
+            while !cur.text.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            let rest = Box::new(Chunk {
+                text: cur.text.split_off(cut),
+                next: cur.next.take(),
+            });
+            cur = cur.next.insert(rest);
+        }
+    }
+
+    pub fn delete(&mut self, range: Range<usize>) {
+        assert!(range.start <= range.end, "range starts after it ends");
+        self.check_boundary(range.start);
+        self.check_boundary(range.end);
+        if range.is_empty() {
+            return;
+        }
+        self.len -= range.len();
+
+        let (mut link, mut at) = self.seek(range.start);
+        let mut end = range.end;
+        while at < end {
+            let chunk = link.as_mut().unwrap();
+            let len = chunk.text.len();
+            if at + len > range.start {
+                let lo = range.start.max(at) - at;
+                let hi = end.min(at + len) - at;
+                chunk.text.drain(lo..hi);
+                // Everything after has moved back by what we just removed.
+                end -= hi - lo;
+            }
+            if chunk.text.is_empty() {
+                *link = chunk.next.take();
+            } else {
+                at += chunk.text.len();
+                link = &mut link.as_mut().unwrap().next;
+            }
+        }
+
+        // Merge across the cut if the two sides fit in one chunk.
+        let chunk_size = self.chunk_size;
+        let (link, _) = self.seek(range.start);
+        if let Some(chunk) = link {
+            if let Some(next) = chunk
+                .next
+                .take_if(|next| chunk.text.len() + next.text.len() <= chunk_size)
+            {
+                chunk.text.push_str(&next.text);
+                chunk.next = next.next;
+            }
+        }
+    }
+
+    pub fn chunks(&self) -> Chunks<'_> {
+        Chunks {
+            next: self.head.as_deref(),
+        }
+    }
+
+    pub fn byte_chunks(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        self.chunks().map(str::as_bytes)
+    }
+
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.chunks().flat_map(str::chars)
+    }
+}
+
+impl Default for Rope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<&str> for Rope {
+    fn from(text: &str) -> Self {
+        let mut rope = Rope::new();
+        rope.insert(0, text);
+        rope
+    }
+}
+
+impl Drop for Rope {
+    fn drop(&mut self) {
+        let mut cur = self.head.take();
+        while let Some(mut chunk) = cur {
+            cur = chunk.next.take();
+        }
+    }
+}
+
+impl fmt::Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chunks().try_for_each(|chunk| f.write_str(chunk))
+    }
+}
+
+impl fmt::Debug for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.chunks()).finish()
+    }
+}
+
+pub struct Chunks<'a> {
+    next: Option<&'a Chunk>,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.next.map(|chunk| {
+            self.next = chunk.next.as_deref();
+            chunk.text.as_str()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Rope;
+
+    #[test]
+    fn edits() {
+        let mut rope = Rope::with_chunk_size(8);
+        rope.insert(0, "");
+        assert_eq!(rope.chunk_count(), 0);
+        rope.insert(0, "hello world");
+        assert_eq!(rope.to_string(), "hello world");
+        assert!(rope.chunks().all(|chunk| chunk.len() <= 8));
+
+        rope.insert(5, ",");
+        rope.insert(rope.len(), "!");
+        rope.insert(0, "> ");
+        assert_eq!(rope.to_string(), "> hello, world!");
+        assert_eq!(rope.len(), 15);
+
+        rope.delete(2..9);
+        assert_eq!(rope.to_string(), "> world!");
+        rope.delete(0..rope.len());
+        assert!(rope.is_empty());
+        assert_eq!(rope.chunk_count(), 0);
+        assert_eq!(format!("{:?}", rope), "[]");
+    }
+
+    #[test]
+    fn against_a_string() {
+        let mut rope = Rope::with_chunk_size(16);
+        let mut string = String::new();
+        for i in 0..200usize {
+            let pos = (i * 7919) % (string.len() + 1);
+            // Only ASCII here, so every position is a char boundary.
+            let text = ["ab", "cdefghijklmnopqrstuvwxyz", "", "0123"][i % 4];
+            rope.insert(pos, text);
+            string.insert_str(pos, text);
+            if i % 3 == 0 {
+                let start = (i * 31) % string.len();
+                let end = (start + i % 40).min(string.len());
+                rope.delete(start..end);
+                string.replace_range(start..end, "");
+            }
+            assert!(rope
+                .chunks()
+                .all(|chunk| !chunk.is_empty() && chunk.len() <= 16));
+        }
+        assert_eq!(rope.to_string(), string);
+        assert_eq!(rope.len(), string.len());
+        assert_eq!(rope.byte_chunks().flatten().count(), string.len());
+    }
+
+    #[test]
+    fn unicode() {
+        let mut rope = Rope::with_chunk_size(8);
+        rope.insert(0, "ŕöpé ŵïţĥ ćĥàŕś");
+        assert!(rope.chunks().all(|chunk| chunk.len() <= 8));
+        assert!(rope.chars().eq("ŕöpé ŵïţĥ ćĥàŕś".chars()));
+        rope.delete(0..8);
+        assert_eq!(rope.to_string(), "ŵïţĥ ćĥàŕś");
+    }
+
+    #[test]
+    #[should_panic(expected = "char boundary")]
+    fn splitting_a_char() {
+        let mut rope = Rope::from("né");
+        rope.delete(0..2);
+    }
+}
@@ -0,0 +1,290 @@
+/*
+ A segmented lock-free queue
+
+The MS queue allocates (and later frees through the epoch collector) one node per element. Here
+the list is made of fixed-size segments instead, so one allocation covers SEGMENT elements:
+
+head                                     tail
+ |                                        |
+[ x | x | x | 4 | 5 | 6 | 7 | 8 ] -> [ 9 | 10 | _ | _ | _ | _ | _ | _ ] -> null
+              ^ pop_idx                         ^ push_idx
+
+Each segment has its own two counters:
+
+- push claims slot `push_idx` with a fetch_add, writes the value, and flags the slot as ready.
+  Once the counter runs off the end, the segment is full: whoever notices links a fresh segment
+  after it and moves `tail` on.
+- pop claims slot `pop_idx` with a CAS, but only if a push has claimed it too. It then waits for
+  that slot's ready flag, which is the one place a thread can wait on another: a push that has
+  claimed a slot is always a few instructions away from filling it.
+- Once every slot of the head segment has been claimed by a pop, `head` moves on to the next
+  segment and the old one goes to the epoch collector, just like a node of the MS queue.
+*/
+
+use crate::epoch;
+use crate::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use crate::sync::hint;
+use crate::trace;
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr;
+
+const SEGMENT: usize = 32;
+
+pub struct SegQueue<T> {
+    head: AtomicPtr<Segment<T>>,
+    tail: AtomicPtr<Segment<T>>,
+    _boo: PhantomData<T>,
+}
+
+struct Slot<T> {
+    value: UnsafeCell<MaybeUninit<T>>,
+    ready: AtomicBool,
+}
+
+struct Segment<T> {
+    slots: [Slot<T>; SEGMENT],
+    // Both can run past SEGMENT: that just means "this segment is used up".
+    push_idx: AtomicUsize,
+    pop_idx: AtomicUsize,
+    next: AtomicPtr<Segment<T>>,
+}
+
+impl<T> Segment<T> {
+    fn new() -> *mut Segment<T> {
+        Box::into_raw(Box::new(Segment {
+            slots: std::array::from_fn(|_| Slot {
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+                ready: AtomicBool::new(false),
+            }),
+            push_idx: AtomicUsize::new(0),
+            pop_idx: AtomicUsize::new(0),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+unsafe impl<T: Send> Send for SegQueue<T> {}
+unsafe impl<T: Send> Sync for SegQueue<T> {}
+
+impl<T> SegQueue<T> {
+    pub fn new() -> Self {
+        let segment = Segment::new();
+        SegQueue {
+            head: AtomicPtr::new(segment),
+            tail: AtomicPtr::new(segment),
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn push(&self, value: T) {
+        let op = trace::span!("push");
+        let _guard = epoch::pin();
+        loop {
+            let tail = self.tail.load(Ordering::Acquire);
+            let segment = unsafe { &*tail };
+            let i = segment.push_idx.fetch_add(1, Ordering::AcqRel);
+            if i < SEGMENT {
+                let slot = &segment.slots[i];
+                // The fetch_add made slot `i` ours alone.
+                unsafe { (*slot.value.get()).write(value) };
+                slot.ready.store(true, Ordering::Release);
+                op.done(None, 1);
+                return;
+            }
+
+            // Full: make sure there's a next segment and that `tail` points at it.
+            let mut next = segment.next.load(Ordering::Acquire);
+            if next.is_null() {
+                let fresh = Segment::new();
+                match segment.next.compare_exchange(
+                    ptr::null_mut(),
+                    fresh,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                ) {
+                    Ok(_) => next = fresh,
+                    Err(current) => {
+                        // Someone beat us to it. Ours was never shared, so just free it.
+                        drop(unsafe { Box::from_raw(fresh) });
+                        next = current;
+                    }
+                }
+            }
+            let _ = self
+                .tail
+                .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let op = trace::span!("pop");
+        let guard = epoch::pin();
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            let segment = unsafe { &*head };
+            let i = segment.pop_idx.load(Ordering::Acquire);
+
+            if i >= SEGMENT {
+                // Used up: move on to the next segment, if there is one.
+                let next = segment.next.load(Ordering::Acquire);
+                if next.is_null() {
+                    op.done(None, 0);
+                    return None;
+                }
+                // Like the MS queue: `tail` must not be left behind on a segment we free.
+                let _ =
+                    self.tail
+                        .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed);
+                if self
+                    .head
+                    .compare_exchange(head, next, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    unsafe { guard.defer_destroy(head) };
+                }
+                continue;
+            }
+
+            // Nothing claimed past `i` yet, and pushes only go to a later segment once this one
+            // is full, so the queue is empty.
+            if i >= segment.push_idx.load(Ordering::Acquire).min(SEGMENT) {
+                op.done(None, 0);
+                return None;
+            }
+            if segment
+                .pop_idx
+                .compare_exchange(i, i + 1, Ordering::AcqRel, Ordering::Relaxed)
+                .is_err()
+            {
+                continue;
+            }
+
+            let slot = &segment.slots[i];
+            while !slot.ready.load(Ordering::Acquire) {
+                hint::spin_loop();
+            }
+            // Slot `i` is ours, and its value is fully written.
+            op.done(None, 1);
+            return Some(unsafe { (*slot.value.get()).assume_init_read() });
+        }
+    }
+
+    // Just a snapshot: other threads may push or pop right after.
+    pub fn is_empty(&self) -> bool {
+        let _guard = epoch::pin();
+        let segment = unsafe { &*self.head.load(Ordering::Acquire) };
+        let i = segment.pop_idx.load(Ordering::Acquire);
+        if i >= SEGMENT {
+            segment.next.load(Ordering::Acquire).is_null()
+        } else {
+            i >= segment.push_idx.load(Ordering::Acquire).min(SEGMENT)
+        }
+    }
+}
+
+impl<T> Default for SegQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for SegQueue<T> {
+    fn drop(&mut self) {
+        // No one else is left, so every claimed slot between the two counters holds a value.
+        let mut cur = self.head.load(Ordering::Relaxed);
+        while !cur.is_null() {
+            let mut segment = unsafe { Box::from_raw(cur) };
+            let start = segment.pop_idx.load(Ordering::Relaxed).min(SEGMENT);
+            let end = segment.push_idx.load(Ordering::Relaxed).min(SEGMENT);
+            for slot in &mut segment.slots[start..end] {
+                unsafe { slot.value.get_mut().assume_init_drop() };
+            }
+            cur = segment.next.load(Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SegQueue, SEGMENT};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn basics() {
+        let queue = SegQueue::new();
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+
+        // Enough to cross a few segment boundaries.
+        for i in 0..SEGMENT * 3 + 5 {
+            queue.push(i);
+        }
+        assert!(!queue.is_empty());
+        for i in 0..SEGMENT * 3 + 5 {
+            assert_eq!(queue.pop(), Some(i));
+        }
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+
+        queue.push(100);
+        assert_eq!(queue.pop(), Some(100));
+    }
+
+    #[test]
+    fn drop_remaining() {
+        let queue = SegQueue::new();
+        for i in 0..SEGMENT * 2 {
+            queue.push(i.to_string());
+        }
+        for _ in 0..SEGMENT + 3 {
+            queue.pop();
+        }
+        drop(queue);
+    }
+
+    #[test]
+    fn threads() {
+        let queue = Arc::new(SegQueue::new());
+        let producers: Vec<_> = (0..2)
+            .map(|t| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        queue.push(t * 1000 + i);
+                    }
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..2)
+            .map(|_| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    let mut got = Vec::new();
+                    while got.len() < 1000 {
+                        if let Some(v) = queue.pop() {
+                            got.push(v);
+                        }
+                    }
+                    got
+                })
+            })
+            .collect();
+        for p in producers {
+            p.join().unwrap();
+        }
+
+        let got: Vec<Vec<i32>> = consumers.into_iter().map(|c| c.join().unwrap()).collect();
+        for mine in &got {
+            for t in 0..2 {
+                let from_t: Vec<_> = mine.iter().filter(|&&v| v / 1000 == t).collect();
+                assert!(from_t.windows(2).all(|w| w[0] < w[1]));
+            }
+        }
+        let mut all: Vec<i32> = got.into_iter().flatten().collect();
+        all.sort();
+        assert_eq!(all, (0..2000).collect::<Vec<_>>());
+    }
+}
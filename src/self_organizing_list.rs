@@ -0,0 +1,272 @@
+/*
+ A self-organizing list
+
+Searching a linked list is a linear scan from the front, so the cost of a lookup is the position
+of what you're looking for. If some elements are looked up far more often than others (and in
+practice they usually are), it pays to keep those near the front. A self-organizing list does
+that by itself: every successful find() rearranges the list a little, using one of three classic
+heuristics:
+
+- MoveToFront: the element found goes straight to the front. Adapts fastest, and is provably
+  within a factor of 2 of the best possible static order, but one lookup of a rare element is
+  enough to push everything else back a place.
+- Transpose: the element found swaps places with the one before it. Slow to adapt, but a single
+  odd lookup barely disturbs anything.
+- Count: every element counts its hits, and the list is kept sorted by count. Converges on the
+  best static order for a fixed access pattern, at the price of a counter per element and
+  being slow to forget once the pattern changes.
+
+The list underneath is arena_list::ArenaList, which can unlink an element and relink it anywhere
+in O(1) given its handle. Each variant costs O(1) after the scan, except Count, which has to walk
+back past elements with fewer hits.
+
+stats() counts lookups and how far they had to scan, so the strategies can be compared on a real
+access pattern. That's what the tests do.
+*/
+
+use crate::arena_list::{ArenaList, Handle};
+use crate::mem_usage::MemUsage;
+use crate::validate::{self, Invalid};
+use std::cmp::Reverse;
+use std::fmt::{self, Debug};
+use std::mem::size_of;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Strategy {
+    MoveToFront,
+    Transpose,
+    Count,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Stats {
+    pub lookups: u64,
+    pub hits: u64,
+    // Elements looked at, across all lookups (a hit at the front costs 1).
+    pub comparisons: u64,
+}
+
+impl Stats {
+    // The average number of elements a lookup had to look at.
+    pub fn mean_cost(&self) -> f64 {
+        self.comparisons as f64 / self.lookups.max(1) as f64
+    }
+}
+
+pub struct SelfOrganizingList<T> {
+    list: ArenaList<Entry<T>>,
+    strategy: Strategy,
+    stats: Stats,
+}
+
+struct Entry<T> {
+    elem: T,
+    // Only used by Strategy::Count.
+    hits: u64,
+}
+
+impl<T> SelfOrganizingList<T> {
+    pub fn new(strategy: Strategy) -> Self {
+        SelfOrganizingList {
+            list: ArenaList::new(),
+            strategy,
+            stats: Stats::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    // The arena's, with the hit counter each entry carries counted as overhead. See mem_usage.rs.
+    pub fn mem_usage(&self) -> MemUsage {
+        let usage = self.list.mem_usage();
+        MemUsage {
+            node_overhead: usage.node_overhead + size_of::<Entry<T>>() - size_of::<T>(),
+            ..usage
+        }
+    }
+
+    // The arena's own checks, and for Count, the hits going down from the front. See validate.rs.
+    pub fn validate(&self) -> Result<(), Invalid> {
+        self.list.validate()?;
+        if self.strategy == Strategy::Count {
+            let hits: Vec<_> = self.list.iter().map(|entry| Reverse(entry.hits)).collect();
+            validate::sorted(&hits, false)?;
+        }
+        Ok(())
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+    }
+
+    // New elements go at the back: they have to earn their way forward.
+    pub fn push(&mut self, elem: T) {
+        self.list.push_back(Entry { elem, hits: 0 });
+        validate::check!(self);
+    }
+
+    // Scan from the front for the first element matching `pred`, counting the cost.
+    fn scan(&mut self, mut pred: impl FnMut(&T) -> bool) -> Option<Handle> {
+        self.stats.lookups += 1;
+        let mut cur = self.list.front_handle();
+        while let Some(h) = cur {
+            self.stats.comparisons += 1;
+            if pred(&self.list.get(h).unwrap().elem) {
+                self.stats.hits += 1;
+                return Some(h);
+            }
+            cur = self.list.next(h);
+        }
+        None
+    }
+
+    // Find the first element matching `pred`, and move it forward according to the strategy.
+    pub fn find(&mut self, pred: impl FnMut(&T) -> bool) -> Option<&mut T> {
+        let found = self.scan(pred)?;
+        let h = self.reorganize(found);
+        validate::check!(self);
+        self.list.get_mut(h).map(|entry| &mut entry.elem)
+    }
+
+    pub fn contains(&mut self, elem: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.find(|x| x == elem).is_some()
+    }
+
+    // Removing isn't an access: nothing else moves.
+    pub fn remove(&mut self, pred: impl FnMut(&T) -> bool) -> Option<T> {
+        let found = self.scan(pred)?;
+        let entry = self.list.remove(found);
+        validate::check!(self);
+        entry.map(|entry| entry.elem)
+    }
+
+    // Move the element just found, returning its (possibly new) handle.
+    fn reorganize(&mut self, h: Handle) -> Handle {
+        match self.strategy {
+            Strategy::MoveToFront => {
+                self.list.move_to_front(h);
+                h
+            }
+            Strategy::Transpose => match self.list.prev(h) {
+                Some(prev) => self.move_before(h, prev),
+                None => h,
+            },
+            Strategy::Count => {
+                let entry = self.list.get_mut(h).unwrap();
+                entry.hits += 1;
+                let hits = entry.hits;
+                // Walk back past everything with fewer hits; ties keep their order.
+                let mut before = None;
+                let mut cur = self.list.prev(h);
+                while let Some(p) = cur.filter(|&p| self.list.get(p).unwrap().hits < hits) {
+                    before = Some(p);
+                    cur = self.list.prev(p);
+                }
+                match before {
+                    Some(target) => self.move_before(h, target),
+                    None => h,
+                }
+            }
+        }
+    }
+
+    fn move_before(&mut self, h: Handle, target: Handle) -> Handle {
+        let entry = self.list.remove(h).unwrap();
+        self.list.insert_before(target, entry).ok().unwrap()
+    }
+
+    // In the current order, front first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> + ExactSizeIterator + '_ {
+        self.list.iter().map(|entry| &entry.elem)
+    }
+}
+
+impl<T: Debug> Debug for SelfOrganizingList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SelfOrganizingList, Strategy};
+
+    fn list(strategy: Strategy) -> SelfOrganizingList<char> {
+        let mut list = SelfOrganizingList::new(strategy);
+        for c in "abcde".chars() {
+            list.push(c);
+        }
+        list
+    }
+
+    fn order(list: &SelfOrganizingList<char>) -> String {
+        list.iter().collect()
+    }
+
+    #[test]
+    fn strategies() {
+        let mut mtf = list(Strategy::MoveToFront);
+        assert!(mtf.contains(&'d'));
+        assert_eq!(order(&mtf), "dabce");
+        mtf.find(|&c| c == 'e');
+        assert_eq!(order(&mtf), "edabc");
+
+        let mut transpose = list(Strategy::Transpose);
+        assert!(transpose.contains(&'d'));
+        assert_eq!(order(&transpose), "abdce");
+        assert!(transpose.contains(&'a'));
+        assert_eq!(order(&transpose), "abdce");
+
+        let mut count = list(Strategy::Count);
+        for c in "ddeeecd".chars() {
+            count.contains(&c);
+        }
+        // e: 3 hits, d: 3 (reached 3 later, so stays behind e), c: 1.
+        assert_eq!(order(&count), "edcab");
+
+        assert!(!count.contains(&'z'));
+        *count.find(|&c| c == 'a').unwrap() = 'A';
+        assert_eq!(count.remove(|&c| c == 'b'), Some('b'));
+        assert_eq!(format!("{:?}", count), "['e', 'd', 'c', 'A']");
+    }
+
+    #[test]
+    fn skewed_lookups_get_cheaper() {
+        // 'a' is pushed last but looked up most: 9 of every 12 lookups.
+        let pattern = "aaabaaacaaad".repeat(50);
+        let mut costs = Vec::new();
+        for strategy in [Strategy::MoveToFront, Strategy::Transpose, Strategy::Count] {
+            let mut list = SelfOrganizingList::new(strategy);
+            for c in "dcbefghija".chars() {
+                list.push(c);
+            }
+            for c in pattern.chars() {
+                assert!(list.contains(&c));
+            }
+            let stats = list.stats();
+            assert_eq!((stats.lookups, stats.hits), (600, 600));
+            costs.push(stats.mean_cost());
+        }
+        // Left alone, 'a' would cost 10 comparisons every time.
+        for cost in costs {
+            assert!(cost < 2.5, "mean cost {cost}");
+        }
+    }
+}
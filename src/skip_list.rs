@@ -0,0 +1,624 @@
+/*
+ A skip list (Pugh, 1990)
+
+A sorted linked list is easy to keep sorted, but finding anything in it means walking it from
+the front. A skip list stacks "express lanes" on top of the sorted list: every node is on level
+0, about half of them are also on level 1, a quarter on level 2, and so on:
+
+level 2: head ----------------------------> 7 ---------------------> None
+level 1: head ----------> 3 --------------> 7 ---------> 11 -------> None
+level 0: head --> 1 ----> 3 ----> 5 ------> 7 --> 9 ---> 11 --> 12 -> None
+
+A search starts at the top of `head`, runs along each level for as long as the next key is still
+smaller than the one it's looking for, then drops down a level. Each level roughly halves what's
+left, so that's O(log n) steps on average, with no rebalancing ever: a node's height is decided
+by coin flips when it's inserted and never changes.
+
+- Each node keeps its forward links in a Vec, one per level it's on. `head` is the same thing
+  without a key: MAX_LEVEL links.
+- insert and remove first collect, for every level, the link that points at (or would point at)
+  the node in question. Splicing a node in or out is then just rewriting those links, exactly
+  like a singly linked list, once per level.
+- Level 0 is a plain sorted singly linked list, so iteration, range() and Drop only ever use that.
+
+SkipMap holds key/value pairs; SkipSet is a SkipMap with () values.
+*/
+
+use crate::mem_usage::MemUsage;
+use crate::panic_safety;
+use crate::validate::{self, Invalid};
+use std::borrow::Borrow;
+use std::fmt::{self, Debug};
+use std::iter;
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::ops::{Bound, RangeBounds};
+use std::ptr::{self, NonNull};
+
+// Plenty for 2^16 elements at p = 1/2, and still fine (just slower) well beyond that.
+const MAX_LEVEL: usize = 16;
+
+pub struct SkipMap<K, V> {
+    head: [Link<K, V>; MAX_LEVEL],
+    len: usize,
+    // xorshift state for picking node heights.
+    rng: u64,
+    _boo: PhantomData<Box<Node<K, V>>>,
+}
+
+type Link<K, V> = Option<NonNull<Node<K, V>>>;
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    // One link per level the node is on; never empty.
+    next: Vec<Link<K, V>>,
+}
+
+impl<K, V> SkipMap<K, V> {
+    pub fn new() -> Self {
+        SkipMap {
+            head: [None; MAX_LEVEL],
+            len: 0,
+            rng: 0x2545_f491_4f6c_dd1d,
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // A node for each element, plus each node's tower of links, which is a Vec of its own and as
+    // tall as the node's level. The head's links are in the map itself. See mem_usage.rs.
+    pub fn mem_usage(&self) -> MemUsage {
+        let mut towers = 0;
+        let mut cur = self.head[0];
+        while let Some(node) = cur {
+            let node = unsafe { node.as_ref() };
+            towers += node.next.capacity() * size_of::<Link<K, V>>();
+            cur = node.next[0];
+        }
+        MemUsage::nodes::<Node<K, V>, (K, V)>(self.len, 1).plus(towers)
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            next: self.head[0],
+            end: None,
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.iter().next()
+    }
+
+    // Coin flips: level 1 with probability 1/2, level 2 with 1/4, ...
+    fn random_level(&mut self) -> usize {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng = x;
+        (x.trailing_zeros() as usize + 1).min(MAX_LEVEL)
+    }
+
+    // The first node whose key is not `before` the target, i.e. where a search for it ends up.
+    // `before` has to be true for some prefix of the keys and false for the rest.
+    fn seek(&self, before: impl Fn(&K) -> bool) -> Link<K, V> {
+        let mut links: &[Link<K, V>] = &self.head;
+        for level in (0..MAX_LEVEL).rev() {
+            while let Some(node) = links[level] {
+                let node = unsafe { &*node.as_ptr() };
+                if !before(&node.key) {
+                    break;
+                }
+                links = &node.next;
+            }
+        }
+        links[0]
+    }
+}
+
+impl<K: Ord, V> SkipMap<K, V> {
+    // For each level, a pointer to the link that a node with `key` would be spliced in after.
+    //
+    // These are raw pointers into `head` and into the nodes' Vecs, all live at the same time, so
+    // they're computed without ever creating a &mut to a whole array or Vec buffer (which would
+    // invalidate the others).
+    fn predecessors<Q>(&mut self, key: &Q) -> [*mut Link<K, V>; MAX_LEVEL]
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut preds = [ptr::null_mut(); MAX_LEVEL];
+        let mut links: *mut Link<K, V> = self.head.as_mut_ptr();
+        for level in (0..MAX_LEVEL).rev() {
+            unsafe {
+                while let Some(node) = *links.add(level) {
+                    let node = node.as_ptr();
+                    if (*node).key.borrow() >= key {
+                        break;
+                    }
+                    // A node reached on `level` is at least `level + 1` high.
+                    links = (*node).next.as_mut_ptr();
+                }
+                preds[level] = links.add(level);
+            }
+        }
+        preds
+    }
+
+    // Level 0 is `len` nodes with their keys strictly in order, and each level above it links
+    // exactly the nodes that are on it, in the same order: walking level 0 and keeping, for each
+    // level, where that level's last link pointed, each node is where every one of its levels
+    // expected it. A node missing from a level, or one a level skips over, is a Link at that node.
+    // See validate.rs.
+    pub fn validate(&self) -> Result<(), Invalid> {
+        validate::walk(self.head[0], self.len, |node| unsafe {
+            node.as_ref().next[0]
+        })?;
+        validate::sorted(self.keys(), true)?;
+        let mut expected = self.head;
+        for (at, node) in
+            iter::successors(self.head[0], |node| unsafe { node.as_ref().next[0] }).enumerate()
+        {
+            let next = unsafe { &node.as_ref().next };
+            if next.is_empty() || next.len() > MAX_LEVEL {
+                return Err(Invalid::Node { at });
+            }
+            for (level, link) in next.iter().enumerate() {
+                if expected[level] != Some(node) {
+                    return Err(Invalid::Link { at });
+                }
+                expected[level] = *link;
+            }
+        }
+        match expected.iter().any(Option::is_some) {
+            true => Err(Invalid::Link { at: self.len }),
+            false => Ok(()),
+        }
+    }
+
+    // Returns the old value if `key` was already there (the key itself is kept).
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        // Picked up front: touching `self` after predecessors() would invalidate its pointers.
+        let height = self.random_level();
+        let preds = self.predecessors(&key);
+        unsafe {
+            if let Some(node) = *preds[0] {
+                let node = &mut *node.as_ptr();
+                if node.key == key {
+                    return Some(std::mem::replace(&mut node.value, value));
+                }
+            }
+
+            let node = Box::into_raw(Box::new(Node {
+                key,
+                value,
+                next: vec![None; height],
+            }));
+            let next = (*node).next.as_mut_ptr();
+            for (level, pred) in preds.iter().enumerate().take(height) {
+                *next.add(level) = **pred;
+                **pred = Some(NonNull::new_unchecked(node));
+            }
+        }
+        self.len += 1;
+        validate::check!(self);
+        None
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let preds = self.predecessors(key);
+        unsafe {
+            let node = (*preds[0])?;
+            if (*node.as_ptr()).key.borrow() != key {
+                return None;
+            }
+            let node = Box::from_raw(node.as_ptr());
+            // Every level the node is on, its predecessor there points right at it.
+            for (level, next) in node.next.iter().enumerate() {
+                *preds[level] = *next;
+            }
+            self.len -= 1;
+            validate::check!(self);
+            Some(node.value)
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let node = self.seek(|k| k.borrow() < key)?;
+        let node = unsafe { &*node.as_ptr() };
+        (node.key.borrow() == key).then_some(&node.value)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let node = self.seek(|k| k.borrow() < key)?;
+        let node = unsafe { &mut *node.as_ptr() };
+        (node.key.borrow() == key).then_some(&mut node.value)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    // Everything with a key in `range`, in order. Finding both ends is O(log n); after that each
+    // step is one link.
+    pub fn range<Q, R>(&self, range: R) -> Iter<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(s) => self.seek(|k| k.borrow() < s),
+            Bound::Excluded(s) => self.seek(|k| k.borrow() <= s),
+            Bound::Unbounded => self.head[0],
+        };
+        let end = match range.end_bound() {
+            Bound::Included(e) => self.seek(|k| k.borrow() <= e),
+            Bound::Excluded(e) => self.seek(|k| k.borrow() < e),
+            Bound::Unbounded => None,
+        };
+        // A backwards range (say 5..3) is just empty.
+        let backwards = match (start, end) {
+            (Some(s), Some(e)) => unsafe { (*s.as_ptr()).key > (*e.as_ptr()).key },
+            _ => false,
+        };
+        Iter {
+            next: if backwards { end } else { start },
+            end,
+            _boo: PhantomData,
+        }
+    }
+}
+
+impl<K, V> Drop for SkipMap<K, V> {
+    fn drop(&mut self) {
+        // Level 0 has every node exactly once, and it's the only one used from here on. It moves
+        // past each node before the node is freed, so that a key or value whose Drop panics
+        // doesn't stop the rest (see panic_safety.rs).
+        panic_safety::pop_all(self, |map| match map.head[0] {
+            Some(node) => {
+                let node = unsafe { Box::from_raw(node.as_ptr()) };
+                map.head[0] = node.next[0];
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+impl<K, V> Default for SkipMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for SkipMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = SkipMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: Ord, V> Extend<(K, V)> for SkipMap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for SkipMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+// Nodes are owned exclusively by the map, like a Box would be.
+unsafe impl<K: Send, V: Send> Send for SkipMap<K, V> {}
+unsafe impl<K: Sync, V: Sync> Sync for SkipMap<K, V> {}
+
+// Walks level 0 from `next` up to (not including) `end`.
+pub struct Iter<'a, K, V> {
+    next: Link<K, V>,
+    end: Link<K, V>,
+    _boo: PhantomData<&'a Node<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == self.end {
+            return None;
+        }
+        self.next.map(|node| {
+            let node = unsafe { &*node.as_ptr() };
+            self.next = node.next[0];
+            (&node.key, &node.value)
+        })
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a SkipMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct IntoIter<K, V>(SkipMap<K, V>);
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Always the first node, so only the head's links can point at it.
+        let node = self.0.head[0]?;
+        let node = unsafe { Box::from_raw(node.as_ptr()) };
+        for (level, next) in node.next.iter().enumerate() {
+            self.0.head[level] = *next;
+        }
+        self.0.len -= 1;
+        Some((node.key, node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<K, V> IntoIterator for SkipMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+/* -------------------------------- SkipSet -------------------------------- */
+
+pub struct SkipSet<T> {
+    map: SkipMap<T, ()>,
+}
+
+impl<T> SkipSet<T> {
+    pub fn new() -> Self {
+        SkipSet {
+            map: SkipMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn mem_usage(&self) -> MemUsage {
+        self.map.mem_usage()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.map.keys()
+    }
+}
+
+impl<T: Ord> SkipSet<T> {
+    // False if it was already there.
+    pub fn insert(&mut self, value: T) -> bool {
+        self.map.insert(value, ()).is_none()
+    }
+
+    pub fn remove<Q>(&mut self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.remove(value).is_some()
+    }
+
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.contains_key(value)
+    }
+
+    // See SkipMap::validate.
+    pub fn validate(&self) -> Result<(), Invalid> {
+        self.map.validate()
+    }
+
+    pub fn range<Q, R>(&self, range: R) -> impl Iterator<Item = &T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        self.map.range(range).map(|(k, _)| k)
+    }
+}
+
+impl<T> Default for SkipSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for SkipSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        SkipSet {
+            map: iter.into_iter().map(|x| (x, ())).collect(),
+        }
+    }
+}
+
+impl<T: Debug> Debug for SkipSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SkipMap, SkipSet};
+    use crate::validate::Invalid;
+    use std::mem::size_of;
+
+    #[test]
+    fn map_basics() {
+        let mut map = SkipMap::new();
+        assert_eq!(map.get(&1), None);
+
+        for k in [5, 1, 9, 3, 7] {
+            assert_eq!(map.insert(k, k * 10), None);
+        }
+        assert_eq!(map.insert(3, 33), Some(30));
+        assert_eq!(map.len(), 5);
+        assert_eq!(map.get(&3), Some(&33));
+        *map.get_mut(&9).unwrap() += 1;
+        assert_eq!(
+            map.iter().collect::<Vec<_>>(),
+            vec![(&1, &10), (&3, &33), (&5, &50), (&7, &70), (&9, &91)]
+        );
+
+        assert_eq!(map.remove(&5), Some(50));
+        assert_eq!(map.remove(&5), None);
+        assert!(!map.contains_key(&5));
+        assert_eq!(map.keys().copied().collect::<Vec<_>>(), vec![1, 3, 7, 9]);
+        assert_eq!(
+            map.into_iter().map(|(_, v)| v).collect::<Vec<_>>(),
+            vec![10, 33, 70, 91]
+        );
+    }
+
+    #[test]
+    fn borrowed_keys() {
+        let mut map = SkipMap::new();
+        map.insert(String::from("b"), 2);
+        map.insert(String::from("a"), 1);
+        assert_eq!(map.get("a"), Some(&1));
+        assert_eq!(map.remove("b"), Some(2));
+        assert_eq!(format!("{map:?}"), r#"{"a": 1}"#);
+    }
+
+    #[test]
+    fn ranges() {
+        use std::ops::Bound::Excluded;
+
+        let set: SkipSet<i32> = (0..20).map(|x| x * 2).collect();
+        let range = |r| set.range(r).copied().collect::<Vec<_>>();
+        assert_eq!(range(3..9), vec![4, 6, 8]);
+        assert_eq!(range(35..100), vec![36, 38]);
+        assert_eq!(set.range(4..=8).count(), 3);
+        assert_eq!(set.range(..3).count(), 2);
+        assert_eq!(set.range((Excluded(4), Excluded(8))).count(), 1);
+
+        let (hi, lo) = (9, 5);
+        assert_eq!(range(hi..lo), Vec::<i32>::new());
+        assert_eq!(range(100..200), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn many() {
+        // Enough elements to use plenty of levels, in a scrambled order.
+        let mut set = SkipSet::new();
+        for i in 0..2000u32 {
+            assert!(set.insert(i.wrapping_mul(7919) % 2000));
+        }
+        assert!(!set.insert(42));
+        assert_eq!(set.len(), 2000);
+        assert!(set.iter().copied().eq(0..2000));
+
+        for i in (0..2000).step_by(2) {
+            assert!(set.remove(&i));
+        }
+        assert!(set.iter().copied().eq((1..2000).step_by(2)));
+        assert!(set.contains(&1999) && !set.contains(&1998));
+    }
+
+    #[test]
+    fn mem_usage() {
+        const WORD: usize = size_of::<usize>();
+        let set: SkipSet<u64> = (0..1000).collect();
+        let usage = set.mem_usage();
+        assert_eq!(usage, set.map.mem_usage());
+        assert_eq!(usage.nodes, 1000);
+        // The tower's Vec is the overhead in the node; its links are on top.
+        assert_eq!(usage.node_overhead, 3 * WORD);
+        let towers = usage.heap_bytes - 1000 * (8 + 3 * WORD);
+        // Every node is on level 0, and at p = 1/2 about as many links again are above it.
+        assert!(towers >= 1000 * WORD);
+        assert!(towers < 3000 * WORD);
+    }
+
+    #[test]
+    fn validate() {
+        let mut map: SkipMap<_, _> = (0..100).map(|i| (i, ())).collect();
+        assert_eq!(map.validate(), Ok(()));
+
+        // Skip the first node on level 0.
+        let first = map.head[0].unwrap();
+        map.head[0] = unsafe { first.as_ref().next[0] };
+        assert_eq!(
+            map.validate(),
+            Err(Invalid::Len {
+                stored: 100,
+                counted: 99
+            })
+        );
+        map.head[0] = Some(first);
+
+        // Skip the first node on level 1, but not on level 0.
+        let tall = map.head[1].unwrap();
+        map.head[1] = unsafe { tall.as_ref().next[1] };
+        let at = unsafe { tall.as_ref().key };
+        assert_eq!(map.validate(), Err(Invalid::Link { at }));
+        map.head[1] = Some(tall);
+
+        unsafe { (*first.as_ptr()).key = 50 };
+        assert_eq!(map.validate(), Err(Invalid::Order { at: 1 }));
+        unsafe { (*first.as_ptr()).key = 0 };
+        assert_eq!(map.validate(), Ok(()));
+    }
+}
@@ -0,0 +1,240 @@
+/*
+ A slab
+
+arena_list.rs keeps its nodes in a Vec and threads a free list through the vacant slots, so that
+popping and pushing reuses memory instead of allocating. Take the list part away and what's left
+is useful on its own: a pool of values, each with a key (its index) that stays valid until it's
+removed, no matter what else is inserted or removed around it.
+
+entries:  [ "a" | free | "c" | free | "e" ]
+free: 3 -> 1 -> NIL
+
+- insert takes the slot at the head of the free list, or pushes a new one if it's empty: O(1).
+- remove swaps the value out for a vacant entry and pushes the slot onto the free list: O(1).
+- get is an index, O(1), and never has to search.
+
+The most recently freed slot is reused first, which keeps the slab compact and the slot that's
+reused likely still in cache.
+
+Unlike arena_list::Handle, keys aren't generational: once a key's value is removed, the key will
+name whatever's inserted into that slot next. That's the usual trade for a slab (keys are plain
+usizes, and there's no counter per slot), so it's the owner's job not to hold on to keys past
+their removal.
+*/
+
+use crate::mem_usage::MemUsage;
+use std::fmt::{self, Debug};
+use std::mem::size_of;
+use std::ops::{Index, IndexMut};
+
+const NIL: usize = usize::MAX;
+
+pub struct Slab<T> {
+    entries: Vec<Entry<T>>,
+    // Head of the free list of vacant slots.
+    free: usize,
+    len: usize,
+}
+
+enum Entry<T> {
+    Occupied(T),
+    Vacant { next_free: usize },
+}
+
+impl<T> Slab<T> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Slab {
+            entries: Vec::with_capacity(capacity),
+            free: NIL,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // How many values fit before the slab has to grow.
+    pub fn capacity(&self) -> usize {
+        self.entries.capacity() - self.len
+    }
+
+    // An entry per value, plus the vacant ones and spare capacity; see mem_usage.rs.
+    pub fn mem_usage(&self) -> MemUsage {
+        MemUsage {
+            heap_bytes: self.entries.capacity() * size_of::<Entry<T>>(),
+            ..MemUsage::nodes::<Entry<T>, T>(self.len, 1)
+        }
+    }
+
+    // The key the next insert will return.
+    pub fn vacant_key(&self) -> usize {
+        match self.free {
+            NIL => self.entries.len(),
+            i => i,
+        }
+    }
+
+    pub fn insert(&mut self, value: T) -> usize {
+        let key = self.vacant_key();
+        if key == self.entries.len() {
+            self.entries.push(Entry::Occupied(value));
+        } else {
+            match std::mem::replace(&mut self.entries[key], Entry::Occupied(value)) {
+                Entry::Vacant { next_free } => self.free = next_free,
+                Entry::Occupied(_) => unreachable!("occupied slot on the free list"),
+            }
+        }
+        self.len += 1;
+        key
+    }
+
+    pub fn contains(&self, key: usize) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn get(&self, key: usize) -> Option<&T> {
+        match self.entries.get(key)? {
+            Entry::Occupied(value) => Some(value),
+            Entry::Vacant { .. } => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, key: usize) -> Option<&mut T> {
+        match self.entries.get_mut(key)? {
+            Entry::Occupied(value) => Some(value),
+            Entry::Vacant { .. } => None,
+        }
+    }
+
+    // None if `key` is vacant (or was never handed out).
+    pub fn remove(&mut self, key: usize) -> Option<T> {
+        if !self.contains(key) {
+            return None;
+        }
+        let vacant = Entry::Vacant {
+            next_free: self.free,
+        };
+        match std::mem::replace(&mut self.entries[key], vacant) {
+            Entry::Occupied(value) => {
+                self.free = key;
+                self.len -= 1;
+                Some(value)
+            }
+            Entry::Vacant { .. } => unreachable!(),
+        }
+    }
+
+    // Keeps the memory, like Vec::clear.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.free = NIL;
+        self.len = 0;
+    }
+
+    // (key, value) pairs, in key order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (usize, &T)> + '_ {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(key, entry)| match entry {
+                Entry::Occupied(value) => Some((key, value)),
+                Entry::Vacant { .. } => None,
+            })
+    }
+
+    // Unlike arena_list, there's no order to follow here, so this is just the Vec's iter_mut.
+    pub fn iter_mut(&mut self) -> impl DoubleEndedIterator<Item = (usize, &mut T)> + '_ {
+        self.entries
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(key, entry)| match entry {
+                Entry::Occupied(value) => Some((key, value)),
+                Entry::Vacant { .. } => None,
+            })
+    }
+}
+
+impl<T> Default for Slab<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Index<usize> for Slab<T> {
+    type Output = T;
+
+    fn index(&self, key: usize) -> &T {
+        self.get(key).expect("no value for that key")
+    }
+}
+
+impl<T> IndexMut<usize> for Slab<T> {
+    fn index_mut(&mut self, key: usize) -> &mut T {
+        self.get_mut(key).expect("no value for that key")
+    }
+}
+
+impl<T: Debug> Debug for Slab<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Slab;
+
+    #[test]
+    fn basics() {
+        let mut slab = Slab::new();
+        let a = slab.insert("a");
+        let b = slab.insert("b");
+        let c = slab.insert("c");
+        assert_eq!((a, b, c), (0, 1, 2));
+        assert_eq!(slab.len(), 3);
+        assert_eq!(slab[b], "b");
+
+        assert_eq!(slab.remove(b), Some("b"));
+        assert_eq!(slab.remove(b), None);
+        assert_eq!(slab.get(b), None);
+        assert_eq!(slab.remove(7), None);
+        // Removing b didn't disturb the keys of the others.
+        assert_eq!((slab[a], slab[c]), ("a", "c"));
+
+        slab[c] = "C";
+        *slab.get_mut(a).unwrap() = "A";
+        assert_eq!(format!("{:?}", slab), r#"{0: "A", 2: "C"}"#);
+    }
+
+    #[test]
+    fn reuses_slots() {
+        let mut slab = Slab::with_capacity(4);
+        let keys: Vec<usize> = (0..4).map(|i| slab.insert(i)).collect();
+        slab.remove(keys[1]);
+        slab.remove(keys[3]);
+        // Most recently freed first.
+        assert_eq!(slab.vacant_key(), 3);
+        assert_eq!(slab.insert(30), 3);
+        assert_eq!(slab.insert(10), 1);
+        assert_eq!(slab.insert(4), 4);
+
+        for (key, value) in slab.iter_mut() {
+            *value += key as i32 * 100;
+        }
+        let all: Vec<_> = slab.iter().map(|(k, &v)| (k, v)).collect();
+        assert_eq!(all, [(0, 0), (1, 110), (2, 202), (3, 330), (4, 404)]);
+
+        slab.clear();
+        assert!(slab.is_empty());
+        assert_eq!(slab.insert(0), 0);
+    }
+}
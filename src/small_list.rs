@@ -0,0 +1,294 @@
+/*
+ A small-list optimization
+
+Most lists in real programs are short: the children of a tree node, the edges out of one vertex,
+the arguments of a call. For those, generic_and_iterators::List spends an allocation per element
+on lists that would fit in a few words. SmallList<T, N> keeps its first N elements inline, in the
+struct itself, and only starts allocating nodes when there are more:
+
+ inline: [a b c d]  spill: (empty)                       len <= N: no allocation at all
+ inline: [a b c d]  spill: g -> f -> e                   after three more pushes
+
+It's a stack with the same API as List (push, pop, peek, iter, ...). The inline part holds the
+bottom of the stack, and anything pushed while the inline part is full goes on the spill list,
+which is an ordinary List. So the order is always "spill, top first, then inline, top first",
+and that's the order iter() yields. Once the spill list is popped empty, pushes go back inline.
+
+remove_first can take an element out of the inline part while the spill list isn't empty,
+leaving room inline under elements that are on the list. That's fine: nothing needs the inline
+part to be full, only that everything in it is below everything on the list, and new pushes go on
+the list while it's in use.
+
+The inline part is `[MaybeUninit<T>; N]` with a `len`, as in unrolled_list.rs: slots `..len`
+are initialized. benches/small_lists.rs compares building and draining many short lists against
+List and Vec.
+*/
+
+use crate::generic_and_iterators::{self, List};
+use crate::mem_usage::MemUsage;
+use crate::validate::{self, Invalid};
+use std::fmt::{self, Debug};
+use std::iter::{Chain, Rev};
+use std::mem::MaybeUninit;
+use std::{ptr, slice};
+
+pub struct SmallList<T, const N: usize> {
+    inline: [MaybeUninit<T>; N],
+    inline_len: usize,
+    spill: List<T>,
+    spilled: usize,
+}
+
+impl<T, const N: usize> SmallList<T, N> {
+    pub fn new() -> Self {
+        SmallList {
+            inline: [const { MaybeUninit::uninit() }; N],
+            inline_len: 0,
+            spill: List::new(),
+            spilled: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inline_len + self.spilled
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Only the spilled elements are on the heap; the inline ones are part of size_of::<Self>().
+    // See mem_usage.rs.
+    pub fn mem_usage(&self) -> MemUsage {
+        self.spill.mem_usage()
+    }
+
+    // `spilled` is how long the spill list is, and the inline part fits in its N slots. The spill
+    // list is a chain of Boxes, with nothing to check but its length; a bad inline_len is a Node at
+    // `spilled`, where the inline part starts. See validate.rs.
+    pub fn validate(&self) -> Result<(), Invalid> {
+        let counted = self.spill.iter().take(self.spilled + 1).count();
+        if counted != self.spilled {
+            return Err(Invalid::Len {
+                stored: self.spilled,
+                counted,
+            });
+        }
+        if self.inline_len > N {
+            return Err(Invalid::Node { at: self.spilled });
+        }
+        Ok(())
+    }
+
+    // Whether any elements are in allocated nodes.
+    pub fn is_spilled(&self) -> bool {
+        self.spilled > 0
+    }
+
+    fn inline(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.inline.as_ptr().cast(), self.inline_len) }
+    }
+
+    fn inline_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.inline.as_mut_ptr().cast(), self.inline_len) }
+    }
+
+    pub fn push(&mut self, elem: T) {
+        if self.spilled > 0 || self.inline_len == N {
+            self.spill.push(elem);
+            self.spilled += 1;
+        } else {
+            self.inline[self.inline_len].write(elem);
+            self.inline_len += 1;
+        }
+        validate::check!(self);
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let elem = if self.spilled > 0 {
+            self.spilled -= 1;
+            self.spill.pop()
+        } else if self.inline_len == 0 {
+            None
+        } else {
+            self.inline_len -= 1;
+            // Safety: the slot was initialized, and is now past `inline_len`, so it won't be read
+            // or dropped again.
+            Some(unsafe { self.inline[self.inline_len].assume_init_read() })
+        };
+        validate::check!(self);
+        elem
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.spill.peek().or_else(|| self.inline().last())
+    }
+
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        if self.spilled > 0 {
+            return self.spill.peek_mut();
+        }
+        self.inline_mut().last_mut()
+    }
+
+    // Unlink and return the first element (from the top) `pred` is true of.
+    pub fn remove_first(&mut self, mut pred: impl FnMut(&T) -> bool) -> Option<T> {
+        if let Some(elem) = self.spill.remove_first(&mut pred) {
+            self.spilled -= 1;
+            validate::check!(self);
+            return Some(elem);
+        }
+        let at = self.inline().iter().rposition(pred)?;
+        // Safety: `at` is initialized; read it out and shift the ones above it down over it.
+        let elem = unsafe {
+            let p = self.inline.as_mut_ptr().add(at);
+            let elem = p.read().assume_init();
+            ptr::copy(p.add(1), p, self.inline_len - at - 1);
+            self.inline_len -= 1;
+            elem
+        };
+        validate::check!(self);
+        Some(elem)
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter(self.spill.iter().chain(self.inline().iter().rev()))
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        let inline =
+            unsafe { slice::from_raw_parts_mut(self.inline.as_mut_ptr().cast(), self.inline_len) };
+        IterMut(self.spill.iter_mut().chain(inline.iter_mut().rev()))
+    }
+}
+
+impl<T, const N: usize> Default for SmallList<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for SmallList<T, N> {
+    fn drop(&mut self) {
+        // The spill list drops itself.
+        unsafe { ptr::drop_in_place(self.inline_mut()) }
+    }
+}
+
+impl<T: Debug, const N: usize> Debug for SmallList<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+pub struct IntoIter<T, const N: usize>(SmallList<T, N>);
+
+impl<T, const N: usize> IntoIterator for SmallList<T, N> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N>;
+
+    fn into_iter(self) -> IntoIter<T, N> {
+        IntoIter(self)
+    }
+}
+
+impl<T, const N: usize> Iterator for IntoIter<T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.pop()
+    }
+}
+
+pub struct Iter<'a, T>(Chain<generic_and_iterators::Iter<'a, T>, Rev<slice::Iter<'a, T>>>);
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.0.next()
+    }
+}
+
+pub struct IterMut<'a, T>(Chain<generic_and_iterators::IterMut<'a, T>, Rev<slice::IterMut<'a, T>>>);
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<&'a mut T> {
+        self.0.next()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SmallList;
+    use std::rc::Rc;
+
+    #[test]
+    fn basics() {
+        let mut list: SmallList<i32, 2> = SmallList::new();
+        assert_eq!(list.pop(), None);
+        assert_eq!(list.peek(), None);
+
+        list.push(1);
+        list.push(2);
+        assert!(!list.is_spilled());
+        list.push(3);
+        list.push(4);
+        assert!(list.is_spilled());
+        assert_eq!(list.len(), 4);
+        assert_eq!(list.peek(), Some(&4));
+        *list.peek_mut().unwrap() = 40;
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), [40, 3, 2, 1]);
+        list.iter_mut().for_each(|x| *x += 1);
+        assert_eq!(format!("{:?}", list), "[41, 4, 3, 2]");
+
+        assert_eq!(list.pop(), Some(41));
+        assert_eq!(list.pop(), Some(4));
+        assert!(!list.is_spilled());
+        assert_eq!(list.pop(), Some(3));
+        list.push(5);
+        assert_eq!(list.into_iter().collect::<Vec<_>>(), [5, 2]);
+    }
+
+    #[test]
+    fn remove_first() {
+        let mut list: SmallList<i32, 3> = SmallList::new();
+        for x in 1..=5 {
+            list.push(x);
+        }
+        // From the inline part, under elements on the spill list.
+        assert_eq!(list.remove_first(|&x| x == 2), Some(2));
+        assert_eq!(list.remove_first(|&x| x == 5), Some(5));
+        assert_eq!(list.remove_first(|&x| x == 9), None);
+        list.push(6);
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), [6, 4, 3, 1]);
+        assert_eq!(list.len(), 4);
+        let mut popped = Vec::new();
+        while let Some(x) = list.pop() {
+            popped.push(x);
+        }
+        assert_eq!(popped, [6, 4, 3, 1]);
+    }
+
+    #[test]
+    fn drops() {
+        let rc = Rc::new(());
+        {
+            let mut list: SmallList<_, 4> = SmallList::new();
+            for _ in 0..10 {
+                list.push(rc.clone());
+            }
+            list.pop();
+            assert_eq!(Rc::strong_count(&rc), 10);
+        }
+        assert_eq!(Rc::strong_count(&rc), 1);
+
+        // N = 0 is just a List.
+        let mut list: SmallList<i32, 0> = SmallList::new();
+        list.push(1);
+        assert!(list.is_spilled());
+        assert_eq!(list.pop(), Some(1));
+    }
+}
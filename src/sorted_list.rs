@@ -0,0 +1,350 @@
+/*
+ A sorted linked list
+
+generic_and_iterators::List with one invariant on top: the elements are always in ascending
+order. insert finds the right place instead of pushing at the head, and that order buys a few
+things back:
+
+- contains and remove can stop as soon as they pass where the element would be, instead of
+  scanning the whole list on a miss.
+- merge of two sorted lists is one pass that relinks the existing nodes, O(n + m) with no
+  allocation: the merge step of merge sort, which is what linked lists are best at.
+- dedup only has to compare neighbours.
+
+Everything works on the List's links directly, through a `&mut Link<T>` "cursor" that points
+at the link to change:
+
+let mut link = &mut self.list.head;
+while link.as_ref().is_some_and(|node| node.elem < elem) {
+    link = &mut link.as_mut().unwrap().next;
+}
+// *link is the first node >= elem (or None); splicing a node in or out is just *link = ...
+
+It looks roundabout (why not `while let Some(node) = link`?), but that version keeps `link`
+borrowed by the loop pattern and the borrow checker won't let us assign to *link afterwards.
+
+insert places equal elements after the ones already there, so the list is stable: equal
+elements keep their insertion order, and merge keeps self's before other's.
+*/
+
+use crate::generic_and_iterators::{self, Link, List, Node};
+use crate::mem_usage::MemUsage;
+use crate::validate::{self, Invalid};
+use std::fmt::{self, Debug};
+
+pub struct SortedList<T: Ord> {
+    list: List<T>,
+    len: usize,
+}
+
+impl<T: Ord> SortedList<T> {
+    pub fn new() -> Self {
+        SortedList {
+            list: List::new(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // See mem_usage.rs.
+    pub fn mem_usage(&self) -> MemUsage {
+        MemUsage::nodes::<Node<T>, T>(self.len, 1)
+    }
+
+    // `len` elements, in order. See validate.rs.
+    pub fn validate(&self) -> Result<(), Invalid> {
+        let counted = self.iter().take(self.len + 1).count();
+        if counted != self.len {
+            return Err(Invalid::Len {
+                stored: self.len,
+                counted,
+            });
+        }
+        validate::sorted(self.iter(), false)
+    }
+
+    // The smallest element.
+    pub fn first(&self) -> Option<&T> {
+        self.list.peek()
+    }
+
+    pub fn pop_first(&mut self) -> Option<T> {
+        let elem = self.list.pop()?;
+        self.len -= 1;
+        validate::check!(self);
+        Some(elem)
+    }
+
+    // The link holding the first element that `past` isn't true of.
+    fn seek(&mut self, mut past: impl FnMut(&T) -> bool) -> &mut Link<T> {
+        let mut link = &mut self.list.head;
+        while link.as_ref().is_some_and(|node| past(&node.elem)) {
+            link = &mut link.as_mut().unwrap().next;
+        }
+        link
+    }
+
+    pub fn insert(&mut self, elem: T) {
+        let kind = self.list.kind;
+        let link = self.seek(|x| *x <= elem);
+        *link = Some(kind.boxed(Node {
+            elem,
+            next: link.take(),
+        }));
+        self.len += 1;
+        validate::check!(self);
+    }
+
+    // Remove one element equal to `elem`.
+    pub fn remove(&mut self, elem: &T) -> Option<T> {
+        let kind = self.list.kind;
+        let link = self.seek(|x| x < elem);
+        if link.as_ref().is_none_or(|node| node.elem != *elem) {
+            return None;
+        }
+        let node = kind.unboxed(link.take().unwrap());
+        *link = node.next;
+        self.len -= 1;
+        validate::check!(self);
+        Some(node.elem)
+    }
+
+    pub fn contains(&self, elem: &T) -> bool {
+        self.iter().find(|x| *x >= elem) == Some(elem)
+    }
+
+    // Merge `other` in, relinking its nodes rather than copying them.
+    pub fn merge(&mut self, mut other: SortedList<T>) {
+        let mut a = self.list.head.take();
+        let mut b = other.list.head.take();
+        let mut tail = &mut self.list.head;
+        loop {
+            let take_a = match (&a, &b) {
+                (Some(x), Some(y)) => x.elem <= y.elem,
+                // One side's run out: the rest of the other is already in order.
+                _ => {
+                    *tail = a.or(b);
+                    break;
+                }
+            };
+            let from = if take_a { &mut a } else { &mut b };
+            let mut node = from.take().unwrap();
+            *from = node.next.take();
+            tail = &mut tail.insert(node).next;
+        }
+        self.len += std::mem::take(&mut other.len);
+        validate::check!(self);
+    }
+
+    // Drop all but the first of each run of equal elements.
+    pub fn dedup(&mut self) {
+        self.dedup_by(|next, kept| next == kept);
+    }
+
+    // Like Vec::dedup_by: `same(next, kept)` is called on each element and the last one kept
+    // before it, and the element is dropped if it returns true. `kept` can be changed (to fold
+    // `next` into it, say), but not in a way that changes its place in the order.
+    pub fn dedup_by(&mut self, mut same: impl FnMut(&mut T, &mut T) -> bool) {
+        let mut cur = self.list.head.as_deref_mut();
+        while let Some(node) = cur {
+            while let Some(next) = node
+                .next
+                .take_if(|next| same(&mut next.elem, &mut node.elem))
+            {
+                node.next = self.list.kind.unboxed(next).next;
+                self.len -= 1;
+            }
+            cur = node.next.as_deref_mut();
+        }
+        validate::check!(self);
+    }
+
+    // Keep only the elements `keep` is true of.
+    pub fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) {
+        let mut link = &mut self.list.head;
+        while link.is_some() {
+            if keep(&link.as_ref().unwrap().elem) {
+                link = &mut link.as_mut().unwrap().next;
+            } else {
+                let node = self.list.kind.unboxed(link.take().unwrap());
+                *link = node.next;
+                self.len -= 1;
+            }
+        }
+        validate::check!(self);
+    }
+
+    pub fn iter(&self) -> generic_and_iterators::Iter<'_, T> {
+        self.list.iter()
+    }
+
+    pub fn into_list(self) -> List<T> {
+        self.list
+    }
+}
+
+impl<T: Ord + Clone> Clone for SortedList<T> {
+    fn clone(&self) -> Self {
+        // Already in order, so no need to sort: push them back to front.
+        let elems: Vec<&T> = self.iter().collect();
+        let mut list = List::new();
+        for elem in elems.into_iter().rev() {
+            list.push(elem.clone());
+        }
+        SortedList {
+            list,
+            len: self.len,
+        }
+    }
+}
+
+impl<T: Ord> Default for SortedList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> Extend<T> for SortedList<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.merge(iter.into_iter().collect());
+    }
+}
+
+// Sorts first, then builds the list back to front with push: O(n log n), where inserting one at
+// a time would be O(n^2).
+impl<T: Ord> FromIterator<T> for SortedList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut elems: Vec<T> = iter.into_iter().collect();
+        elems.sort();
+        let len = elems.len();
+        let mut list = List::new();
+        for elem in elems.into_iter().rev() {
+            list.push(elem);
+        }
+        let list = SortedList { list, len };
+        validate::check!(&list);
+        list
+    }
+}
+
+impl<T: Ord> IntoIterator for SortedList<T> {
+    type Item = T;
+    type IntoIter = generic_and_iterators::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.list.into_iter()
+    }
+}
+
+impl<'a, T: Ord> IntoIterator for &'a SortedList<T> {
+    type Item = &'a T;
+    type IntoIter = generic_and_iterators::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T: Ord + Debug> Debug for SortedList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SortedList;
+
+    fn elems(list: &SortedList<i32>) -> Vec<i32> {
+        list.iter().copied().collect()
+    }
+
+    #[test]
+    fn basics() {
+        let mut list = SortedList::new();
+        for x in [5, 1, 4, 1, 3] {
+            list.insert(x);
+        }
+        assert_eq!(elems(&list), [1, 1, 3, 4, 5]);
+        assert_eq!(list.len(), 5);
+        assert_eq!(list.first(), Some(&1));
+
+        assert!(list.contains(&4));
+        assert!(!list.contains(&2));
+        assert!(!list.contains(&9));
+        assert_eq!(list.remove(&1), Some(1));
+        assert_eq!(list.remove(&2), None);
+        assert_eq!(list.remove(&5), Some(5));
+        assert_eq!(elems(&list), [1, 3, 4]);
+
+        assert_eq!(list.pop_first(), Some(1));
+        assert_eq!(list.len(), 2);
+        assert_eq!(format!("{:?}", list), "[3, 4]");
+    }
+
+    #[test]
+    fn merge_and_dedup() {
+        let mut a: SortedList<i32> = [7, 1, 5, 3].into_iter().collect();
+        let b: SortedList<i32> = [6, 2, 3, 8, 9].into_iter().collect();
+        a.merge(b);
+        assert_eq!(elems(&a), [1, 2, 3, 3, 5, 6, 7, 8, 9]);
+        assert_eq!(a.len(), 9);
+
+        a.extend([3, 1, 10]);
+        a.dedup();
+        assert_eq!(elems(&a), [1, 2, 3, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(a.len(), 9);
+
+        a.retain(|&x| x % 3 != 0);
+        assert_eq!(elems(&a), [1, 2, 5, 7, 8, 10]);
+        assert_eq!(elems(&a.clone()), elems(&a));
+        a.dedup_by(|next, kept| *next - *kept == 1);
+        assert_eq!(elems(&a), [1, 5, 7, 10]);
+        assert_eq!(a.len(), 4);
+
+        a.merge(SortedList::new());
+        let mut empty = SortedList::new();
+        empty.merge(a);
+        assert_eq!(empty.into_iter().count(), 4);
+    }
+
+    #[test]
+    fn stable() {
+        // Ordered by the first field only, so equal keys can be told apart.
+        struct Key(i32, char);
+
+        impl PartialEq for Key {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+        }
+        impl Eq for Key {}
+        impl PartialOrd for Key {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Key {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.0.cmp(&other.0)
+            }
+        }
+
+        let mut list = SortedList::new();
+        list.insert(Key(1, 'a'));
+        list.insert(Key(0, 'b'));
+        list.insert(Key(1, 'c'));
+        let mut other = SortedList::new();
+        other.insert(Key(1, 'd'));
+        list.merge(other);
+        let order: String = list.iter().map(|k| k.1).collect();
+        assert_eq!(order, "bacd");
+    }
+}
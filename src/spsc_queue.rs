@@ -0,0 +1,299 @@
+/*
+ A single-producer, single-consumer linked queue
+
+With exactly one thread on each end, neither side ever has to retry anything: push and pop are
+wait-free. The list runs from the oldest node to the newest, and the consumer's `tail` is a stub
+whose value has already been taken, just like the MPSC queue:
+
+first -> ... -> tail_prev -> tail -> A -> B -> head
+(producer's cache)         (consumer)          (producer)
+
+- push fills a node, links it after `head` with a Release store, and moves `head` on.
+- pop follows `tail.next` (Acquire), takes the value, and that node becomes the new stub.
+- Nodes the consumer has moved past stay linked at the front. The producer reuses them from
+  `first` instead of allocating, as long as it doesn't catch up with `tail_prev`, the last node
+  the consumer has handed back. `tail_copy` is the producer's cached view of `tail_prev`, so it
+  only touches the consumer's cache line when its own view runs out.
+- The cache is bounded: at most `cache_bound` nodes are ever marked as reusable. Any other node
+  the consumer passes is unlinked and freed right away, so a burst doesn't pin its memory forever.
+
+The producer-owned and consumer-owned fields sit on separate cache lines, so the two threads
+don't keep invalidating each other's cache on every operation ("false sharing").
+*/
+
+use crate::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use crate::sync::Arc;
+use crate::trace;
+use std::cell::{Cell, UnsafeCell};
+use std::marker::PhantomData;
+use std::ptr;
+
+struct Node<T> {
+    value: Option<T>,
+    // Counted against cache_bound, so the consumer hands it back instead of freeing it.
+    cached: bool,
+    next: AtomicPtr<Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn new() -> *mut Node<T> {
+        Box::into_raw(Box::new(Node {
+            value: None,
+            cached: false,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+#[repr(align(64))]
+struct CachePadded<T>(T);
+
+struct ProducerSide<T> {
+    head: Cell<*mut Node<T>>,
+    first: Cell<*mut Node<T>>,
+    tail_copy: Cell<*mut Node<T>>,
+}
+
+struct ConsumerSide<T> {
+    tail: Cell<*mut Node<T>>,
+    // Shared with the producer: everything before this node may be reused.
+    tail_prev: AtomicPtr<Node<T>>,
+    cache_bound: usize,
+    cached_nodes: AtomicUsize,
+}
+
+struct Queue<T> {
+    producer: CachePadded<ProducerSide<T>>,
+    consumer: CachePadded<ConsumerSide<T>>,
+    // Values are moved in and out through raw pointers.
+    _boo: PhantomData<UnsafeCell<T>>,
+}
+
+// Each side's Cells are only touched by the one Producer or Consumer handle that owns that side.
+unsafe impl<T: Send> Send for Queue<T> {}
+unsafe impl<T: Send> Sync for Queue<T> {}
+
+impl<T> Queue<T> {
+    // Safety: producer side only.
+    unsafe fn push(&self, value: T) {
+        let p = &self.producer.0;
+        let node = self.alloc();
+        (*node).value = Some(value);
+        (*node).next.store(ptr::null_mut(), Ordering::Relaxed);
+        (*p.head.get()).next.store(node, Ordering::Release);
+        p.head.set(node);
+    }
+
+    // Safety: producer side only.
+    unsafe fn alloc(&self) -> *mut Node<T> {
+        let p = &self.producer.0;
+        if p.first.get() != p.tail_copy.get() {
+            return self.take_first();
+        }
+        p.tail_copy
+            .set(self.consumer.0.tail_prev.load(Ordering::Acquire));
+        if p.first.get() != p.tail_copy.get() {
+            return self.take_first();
+        }
+        Node::new()
+    }
+
+    unsafe fn take_first(&self) -> *mut Node<T> {
+        let p = &self.producer.0;
+        let node = p.first.get();
+        p.first.set((*node).next.load(Ordering::Relaxed));
+        node
+    }
+
+    // Safety: consumer side only.
+    unsafe fn pop(&self) -> Option<T> {
+        let c = &self.consumer.0;
+        let tail = c.tail.get();
+        let next = (*tail).next.load(Ordering::Acquire);
+        if next.is_null() {
+            return None;
+        }
+        let value = (*next).value.take();
+        c.tail.set(next);
+
+        // Decide what happens to the old stub: hand it back to the producer, or free it.
+        if c.cache_bound == 0 {
+            c.tail_prev.store(tail, Ordering::Release);
+        } else {
+            let cached = c.cached_nodes.load(Ordering::Relaxed);
+            if cached < c.cache_bound && !(*tail).cached {
+                c.cached_nodes.store(cached + 1, Ordering::Relaxed);
+                (*tail).cached = true;
+            }
+            if (*tail).cached {
+                c.tail_prev.store(tail, Ordering::Release);
+            } else {
+                // Unlink it. The producer never reads tail_prev.next until we publish a newer
+                // tail_prev, and that Release store will carry this write with it.
+                (*c.tail_prev.load(Ordering::Relaxed))
+                    .next
+                    .store(next, Ordering::Relaxed);
+                drop(Box::from_raw(tail));
+            }
+        }
+        value
+    }
+
+    // Safety: consumer side only. The node stays put until the consumer pops past it.
+    unsafe fn front(&self) -> *mut Node<T> {
+        (*self.consumer.0.tail.get()).next.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        // Both handles are gone. Every node, cached or live, is reachable from `first`.
+        let mut cur = self.producer.0.first.get();
+        while !cur.is_null() {
+            let node = unsafe { Box::from_raw(cur) };
+            cur = node.next.load(Ordering::Relaxed);
+        }
+    }
+}
+
+pub struct Producer<T> {
+    queue: Arc<Queue<T>>,
+}
+
+pub struct Consumer<T> {
+    queue: Arc<Queue<T>>,
+}
+
+// Keep up to `cache_bound` nodes around for reuse (0 means no limit: every node is reused).
+pub fn queue<T>(cache_bound: usize) -> (Producer<T>, Consumer<T>) {
+    let stub = Node::new();
+    let queue = Arc::new(Queue {
+        producer: CachePadded(ProducerSide {
+            head: Cell::new(stub),
+            first: Cell::new(stub),
+            tail_copy: Cell::new(stub),
+        }),
+        consumer: CachePadded(ConsumerSide {
+            tail: Cell::new(stub),
+            tail_prev: AtomicPtr::new(stub),
+            cache_bound,
+            cached_nodes: AtomicUsize::new(0),
+        }),
+        _boo: PhantomData,
+    });
+    (
+        Producer {
+            queue: queue.clone(),
+        },
+        Consumer { queue },
+    )
+}
+
+// Taking &mut self is what guarantees a single producer and a single consumer: the handles
+// aren't Clone, and they can't be used from two threads at once.
+impl<T> Producer<T> {
+    pub fn push(&mut self, value: T) {
+        let op = trace::span!("push");
+        unsafe { self.queue.push(value) };
+        op.done(None, 1);
+    }
+}
+
+impl<T> Consumer<T> {
+    pub fn pop(&mut self) -> Option<T> {
+        let op = trace::span!("pop");
+        let value = unsafe { self.queue.pop() };
+        op.done(None, value.is_some() as usize);
+        value
+    }
+
+    pub fn peek(&mut self) -> Option<&mut T> {
+        unsafe { self.queue.front().as_mut()?.value.as_mut() }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::queue;
+    use std::thread;
+
+    #[test]
+    fn basics() {
+        for bound in [0, 1, 2] {
+            let (mut tx, mut rx) = queue(bound);
+            assert_eq!(rx.pop(), None);
+            tx.push(1);
+            tx.push(2);
+            assert_eq!(rx.peek(), Some(&mut 1));
+            assert_eq!(rx.pop(), Some(1));
+            assert_eq!(rx.pop(), Some(2));
+            assert_eq!(rx.pop(), None);
+
+            // Reuse cached nodes (or allocate fresh ones past the bound).
+            for i in 0..10 {
+                tx.push(i);
+            }
+            for i in 0..10 {
+                if let Some(v) = rx.peek() {
+                    *v += 100;
+                }
+                assert_eq!(rx.pop(), Some(i + 100));
+            }
+            assert_eq!(rx.peek(), None);
+        }
+    }
+
+    #[test]
+    fn node_cache_is_bounded() {
+        let (mut tx, mut rx) = queue(2);
+        for i in 0..100 {
+            tx.push(i);
+        }
+        while rx.pop().is_some() {}
+
+        // Count what's left in the list: the cached nodes plus the current stub.
+        let queue = &rx.queue;
+        let mut nodes = 0;
+        let mut cur = queue.producer.0.first.get();
+        while !cur.is_null() {
+            nodes += 1;
+            cur = unsafe { (*cur).next.load(std::sync::atomic::Ordering::Relaxed) };
+        }
+        assert!(nodes <= 3, "{nodes} nodes kept");
+    }
+
+    #[test]
+    fn drop_with_values() {
+        let rc = std::rc::Rc::new(());
+        {
+            let (mut tx, mut rx) = queue(4);
+            for _ in 0..10 {
+                tx.push(rc.clone());
+            }
+            rx.pop();
+        }
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn threads() {
+        let (mut tx, mut rx) = queue(16);
+        let producer = thread::spawn(move || {
+            for i in 0..100_000 {
+                tx.push(i);
+            }
+        });
+
+        let mut expected = 0;
+        while expected < 100_000 {
+            if let Some(v) = rx.pop() {
+                assert_eq!(v, expected);
+                expected += 1;
+            } else {
+                std::hint::spin_loop();
+            }
+        }
+        producer.join().unwrap();
+        assert_eq!(rx.pop(), None);
+    }
+}
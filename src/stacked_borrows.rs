@@ -1,4 +1,4 @@
-pub fn basic_borrow() -> () {
+pub fn basic_borrow() {
     unsafe {
         let mut data = 10;
         let ref1 = &mut data;
@@ -12,7 +12,7 @@ pub fn basic_borrow() -> () {
     }
 }
 
-pub fn complex_borrow() -> () {
+pub fn complex_borrow() {
     unsafe {
         let mut data = 10;
         let ref1 = &mut data;
@@ -33,7 +33,7 @@ pub fn complex_borrow() -> () {
     }
 }
 
-pub fn borrow_arrays() -> () {
+pub fn borrow_arrays() {
     unsafe {
         let mut data = [0; 10];
 
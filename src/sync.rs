@@ -0,0 +1,13 @@
+// The concurrent modules take their atomics, locks and threads from here rather than straight from
+// std. Building with `RUSTFLAGS="--cfg loom"` swaps in loom's versions, so that tests/loom.rs can
+// explore every interleaving of the lock-free code instead of whichever one the OS happens to pick.
+
+#[cfg(not(loom))]
+pub(crate) use std::sync::{atomic, Arc, Mutex};
+#[cfg(not(loom))]
+pub(crate) use std::{hint, thread, thread_local};
+
+#[cfg(loom)]
+pub(crate) use loom::sync::{atomic, Arc, Mutex};
+#[cfg(loom)]
+pub(crate) use loom::{hint, thread, thread_local};
@@ -0,0 +1,216 @@
+/*
+ Tagged pointers
+
+A pointer to a T is always a multiple of align_of::<T>(), so its low bits are always zero: one
+bit for a u16, three for a u64 or anything else 8-aligned. Lock-free code puts flags there, so
+that a flag and a pointer can be read, and compare-and-swapped, together as one word. The Harris
+list's "this node is removed" mark is the classic example: it has to change atomically with the
+`next` pointer it sits in, and a separate flag field couldn't. Single-threaded code gets a flag
+for free the same way: the unsafe deque keeps each node's mark in its `front` link, where a bool
+field would have cost a word per node.
+
+Doing that with bare `addr | 1` is easy to get wrong (forget to mask a pointer before following
+it and you read from one byte past the node), so TaggedPtr keeps the pointer and the tag apart:
+
+- new(ptr, tag) checks that the pointer really is aligned and that the tag fits in the free
+  bits, and panics if not. TAG_BITS says how many there are for a given T.
+- ptr() always comes back with the tag masked off, and tag() with the pointer masked off.
+- It's just a word: Copy, comparable, and what AtomicTaggedPtr loads, stores and CASes.
+- Tagging uses map_addr, so the pointer keeps its provenance and Miri can still follow it.
+
+None of it needs unsafe: a TaggedPtr hands out raw pointers, and dereferencing them is as unsafe
+as it always was.
+*/
+
+use crate::sync::atomic::{AtomicPtr, Ordering};
+use std::fmt;
+use std::mem;
+use std::ptr;
+
+pub struct TaggedPtr<T> {
+    // The pointer with the tag in its low bits. *const rather than *mut so that TaggedPtr<T> is
+    // covariant in T, like NonNull: a node holding one doesn't make its list invariant.
+    raw: *const T,
+}
+
+impl<T> TaggedPtr<T> {
+    // How many low bits are free for the tag.
+    pub const TAG_BITS: u32 = mem::align_of::<T>().trailing_zeros();
+    pub const TAG_MASK: usize = (1 << Self::TAG_BITS) - 1;
+
+    pub fn new(ptr: *mut T, tag: usize) -> Self {
+        assert!(
+            ptr.addr() & Self::TAG_MASK == 0,
+            "pointer {ptr:p} isn't aligned"
+        );
+        assert!(
+            tag & !Self::TAG_MASK == 0,
+            "tag {tag} doesn't fit in {} bits",
+            Self::TAG_BITS
+        );
+        TaggedPtr {
+            raw: ptr.map_addr(|addr| addr | tag),
+        }
+    }
+
+    pub fn null() -> Self {
+        TaggedPtr { raw: ptr::null() }
+    }
+
+    pub fn ptr(self) -> *mut T {
+        self.raw.cast_mut().map_addr(|addr| addr & !Self::TAG_MASK)
+    }
+
+    pub fn tag(self) -> usize {
+        self.raw.addr() & Self::TAG_MASK
+    }
+
+    // The same pointer, with `tag` instead.
+    pub fn with_tag(self, tag: usize) -> Self {
+        Self::new(self.ptr(), tag)
+    }
+
+    // Null, ignoring the tag.
+    pub fn is_null(self) -> bool {
+        self.ptr().is_null()
+    }
+}
+
+// Untagged.
+impl<T> From<*mut T> for TaggedPtr<T> {
+    fn from(ptr: *mut T) -> Self {
+        Self::new(ptr, 0)
+    }
+}
+
+// Not derived: the derives would want T: Clone and so on, and a pointer doesn't need that.
+impl<T> Clone for TaggedPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TaggedPtr<T> {}
+
+impl<T> PartialEq for TaggedPtr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.raw == other.raw
+    }
+}
+
+impl<T> Eq for TaggedPtr<T> {}
+
+impl<T> fmt::Debug for TaggedPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:p} tag {}", self.ptr(), self.tag())
+    }
+}
+
+// An AtomicPtr that loads and stores TaggedPtrs, so the tag and the pointer always change
+// together.
+pub struct AtomicTaggedPtr<T> {
+    raw: AtomicPtr<T>,
+}
+
+impl<T> AtomicTaggedPtr<T> {
+    pub fn new(ptr: TaggedPtr<T>) -> Self {
+        AtomicTaggedPtr {
+            raw: AtomicPtr::new(ptr.raw.cast_mut()),
+        }
+    }
+
+    pub fn null() -> Self {
+        Self::new(TaggedPtr::null())
+    }
+
+    pub fn load(&self, order: Ordering) -> TaggedPtr<T> {
+        TaggedPtr {
+            raw: self.raw.load(order),
+        }
+    }
+
+    pub fn store(&self, ptr: TaggedPtr<T>, order: Ordering) {
+        self.raw.store(ptr.raw.cast_mut(), order)
+    }
+
+    pub fn swap(&self, ptr: TaggedPtr<T>, order: Ordering) -> TaggedPtr<T> {
+        TaggedPtr {
+            raw: self.raw.swap(ptr.raw.cast_mut(), order),
+        }
+    }
+
+    // Succeeds only if both the pointer and the tag match `current`.
+    pub fn compare_exchange(
+        &self,
+        current: TaggedPtr<T>,
+        new: TaggedPtr<T>,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<TaggedPtr<T>, TaggedPtr<T>> {
+        self.raw
+            .compare_exchange(current.raw.cast_mut(), new.raw.cast_mut(), success, failure)
+            .map(|raw| TaggedPtr { raw })
+            .map_err(|raw| TaggedPtr { raw })
+    }
+}
+
+impl<T> fmt::Debug for AtomicTaggedPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.load(Ordering::Relaxed).fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AtomicTaggedPtr, TaggedPtr};
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn tags() {
+        assert_eq!(TaggedPtr::<u8>::TAG_BITS, 0);
+        assert_eq!(TaggedPtr::<u32>::TAG_BITS, 2);
+        assert_eq!(TaggedPtr::<u64>::TAG_MASK, 0b111);
+
+        let x: *mut u64 = &mut 5;
+        let p = TaggedPtr::new(x, 0b101);
+        assert_eq!(p.ptr(), x);
+        assert_eq!(p.tag(), 0b101);
+        assert_eq!(unsafe { *p.ptr() }, 5);
+        let q = p.with_tag(0);
+        assert_ne!(p, q);
+        assert_eq!(q, TaggedPtr::from(x));
+
+        let null = TaggedPtr::<u64>::null().with_tag(1);
+        assert!(null.is_null());
+        assert_eq!(null.tag(), 1);
+    }
+
+    #[test]
+    fn atomic() {
+        let mut a = 1u32;
+        let mut b = 2u32;
+        let a = TaggedPtr::from(&mut a as *mut u32);
+        let b = TaggedPtr::from(&mut b as *mut u32);
+        let atomic = AtomicTaggedPtr::new(a);
+
+        // Marking the pointer makes a CAS that expects it unmarked fail.
+        let marked = a.with_tag(1);
+        assert_eq!(
+            atomic.compare_exchange(a, marked, Ordering::AcqRel, Ordering::Acquire),
+            Ok(a)
+        );
+        assert_eq!(
+            atomic.compare_exchange(a, b, Ordering::AcqRel, Ordering::Acquire),
+            Err(marked)
+        );
+        assert_eq!(atomic.swap(b, Ordering::AcqRel), marked);
+        assert_eq!(atomic.load(Ordering::Acquire).ptr(), b.ptr());
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't fit in 1 bits")]
+    fn tag_too_big() {
+        let mut x = 0u16;
+        TaggedPtr::new(&mut x, 2);
+    }
+}
@@ -0,0 +1,410 @@
+/*
+ A hierarchical timing wheel
+
+Network stacks and runtimes keep huge numbers of timers (a timeout per connection, a retry per
+request), and almost all of them are cancelled before they fire. So the operations that have to
+be cheap are schedule and cancel, and a heap's O(log n) for both is more than they'd like. A
+timing wheel makes both O(1) with linked lists.
+
+Time moves in ticks. A single wheel is an array of 64 slots, one per tick, each a list of the
+timers due at that tick; advancing the clock by a tick fires everything in the next slot. That
+only covers 64 ticks, so there are 4 wheels (levels) of increasing coarseness, like the digits of
+a base-64 number:
+
+level 0: 64 slots of 1 tick                  deadlines within 64 ticks of now
+level 1: 64 slots of 64 ticks
+level 2: 64 slots of 4096 ticks
+level 3: 64 slots of 262144 ticks            up to 2^24 ticks away
+
+A timer goes in the level of the highest base-64 digit where its deadline differs from now, at
+the slot given by the deadline's digit there. When the clock reaches a time whose lower digits
+are all 0, the slot for the new digit at each such level is emptied and its timers are placed
+again, now relative to the new time. Each cascade moves a timer down at least one level, so it
+moves at most 4 times in its whole life, and most are cancelled long before any of that.
+Deadlines further out than level 3 reaches wait on an overflow list, looked at again whenever
+the top three digits roll over.
+
+The lists are intrusive: the prev/next links live in the timer entries themselves (like
+intrusive_list.rs, but indices into one arena instead of pinned pointers), and each slot is just
+a head and tail. That's what makes cancel O(1). A TimerHandle names the entry, and unlinking it
+from whichever slot it's in touches only its neighbours. Handles are generational, as in
+arena_list.rs, so cancelling a timer that's already fired or been cancelled does nothing.
+
+advance(to) steps through the ticks one at a time while level 0 has timers in it. When the
+lowest L levels are all empty, nothing can fire or cascade before the next multiple of 64^L, so
+it jumps straight there. With every level empty it goes further: an empty wheel jumps to `to`,
+and one with only overflow timers to the multiple of 64^4 where the earliest of them cascades
+in. A clock that's mostly idle, or whose timers are all far off, costs little to advance, even
+across the whole u64 range.
+*/
+
+const LEVELS: usize = 4;
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+// Index of the overflow list in `lists`, after the LEVELS * SLOTS slot lists.
+const OVERFLOW: usize = LEVELS * SLOTS;
+const NIL: u32 = u32::MAX;
+
+pub struct TimerWheel<T> {
+    now: u64,
+    entries: Vec<Entry<T>>,
+    // Head of the free list of vacant entries, linked through `next`.
+    free: u32,
+    lists: Box<[ListHead]>,
+    // How many timers are on each level, with the overflow list as level LEVELS.
+    per_level: [usize; LEVELS + 1],
+    len: usize,
+}
+
+#[derive(Clone, Copy)]
+struct ListHead {
+    head: u32,
+    tail: u32,
+}
+
+struct Entry<T> {
+    // Bumped every time the entry is vacated, so handles to it go stale.
+    generation: u32,
+    // None while vacant.
+    value: Option<T>,
+    deadline: u64,
+    // Which list it's on, and its neighbours there.
+    list: usize,
+    prev: u32,
+    next: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TimerHandle {
+    index: u32,
+    generation: u32,
+}
+
+impl<T> TimerWheel<T> {
+    pub fn new(now: u64) -> Self {
+        TimerWheel {
+            now,
+            entries: Vec::new(),
+            free: NIL,
+            lists: vec![
+                ListHead {
+                    head: NIL,
+                    tail: NIL
+                };
+                OVERFLOW + 1
+            ]
+            .into_boxed_slice(),
+            per_level: [0; LEVELS + 1],
+            len: 0,
+        }
+    }
+
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /* ---- the intrusive lists ---- */
+
+    fn push_back(&mut self, list: usize, i: u32) {
+        let tail = self.lists[list].tail;
+        self.per_level[list / SLOTS] += 1;
+        let entry = &mut self.entries[i as usize];
+        entry.list = list;
+        entry.prev = tail;
+        entry.next = NIL;
+        match tail {
+            NIL => self.lists[list].head = i,
+            _ => self.entries[tail as usize].next = i,
+        }
+        self.lists[list].tail = i;
+    }
+
+    fn unlink(&mut self, i: u32) {
+        let Entry {
+            list, prev, next, ..
+        } = self.entries[i as usize];
+        self.per_level[list / SLOTS] -= 1;
+        match prev {
+            NIL => self.lists[list].head = next,
+            _ => self.entries[prev as usize].next = next,
+        }
+        match next {
+            NIL => self.lists[list].tail = prev,
+            _ => self.entries[next as usize].prev = prev,
+        }
+    }
+
+    // Empty `list`, returning its old head. The entries keep their next links, so the caller
+    // can still walk it.
+    fn take_list(&mut self, list: usize) -> u32 {
+        let head = self.lists[list].head;
+        let mut i = head;
+        while i != NIL {
+            self.per_level[list / SLOTS] -= 1;
+            i = self.entries[i as usize].next;
+        }
+        self.lists[list] = ListHead {
+            head: NIL,
+            tail: NIL,
+        };
+        head
+    }
+
+    /* ---- the wheel ---- */
+
+    // The list entry `i` belongs on, given its deadline and the current time.
+    fn place(&mut self, i: u32) {
+        let deadline = self.entries[i as usize].deadline;
+        let diff = deadline ^ self.now;
+        let level = match diff {
+            0 => 0,
+            _ => ((63 - diff.leading_zeros()) / SLOT_BITS) as usize,
+        };
+        let list = if level < LEVELS {
+            let slot = (deadline >> (level as u32 * SLOT_BITS)) as usize & (SLOTS - 1);
+            level * SLOTS + slot
+        } else {
+            OVERFLOW
+        };
+        self.push_back(list, i);
+    }
+
+    // Run `value` at tick `deadline`. A deadline that's already passed fires on the next advance.
+    // A clock at u64::MAX has no next tick, so a timer scheduled then never fires (it can still
+    // be cancelled).
+    pub fn schedule(&mut self, deadline: u64, value: T) -> TimerHandle {
+        let deadline = deadline.max(self.now.saturating_add(1));
+        let index = match self.free {
+            NIL => {
+                let i = u32::try_from(self.entries.len())
+                    .ok()
+                    .filter(|&i| i != NIL)
+                    .expect("timer wheel is full");
+                self.entries.push(Entry {
+                    generation: 0,
+                    value: None,
+                    deadline: 0,
+                    list: 0,
+                    prev: NIL,
+                    next: NIL,
+                });
+                i
+            }
+            i => {
+                self.free = self.entries[i as usize].next;
+                i
+            }
+        };
+        let entry = &mut self.entries[index as usize];
+        entry.value = Some(value);
+        entry.deadline = deadline;
+        self.place(index);
+        self.len += 1;
+        TimerHandle {
+            index,
+            generation: self.entries[index as usize].generation,
+        }
+    }
+
+    fn is_live(&self, handle: TimerHandle) -> bool {
+        self.entries
+            .get(handle.index as usize)
+            .is_some_and(|e| e.generation == handle.generation && e.value.is_some())
+    }
+
+    pub fn deadline(&self, handle: TimerHandle) -> Option<u64> {
+        self.is_live(handle)
+            .then(|| self.entries[handle.index as usize].deadline)
+    }
+
+    // Take entry `i` out of the wheel (it must be unlinked already) and vacate it.
+    fn release(&mut self, i: u32) -> T {
+        let entry = &mut self.entries[i as usize];
+        entry.generation = entry.generation.wrapping_add(1);
+        entry.next = self.free;
+        self.free = i;
+        self.len -= 1;
+        entry.value.take().unwrap()
+    }
+
+    // None if the timer has already fired or been cancelled.
+    pub fn cancel(&mut self, handle: TimerHandle) -> Option<T> {
+        if !self.is_live(handle) {
+            return None;
+        }
+        self.unlink(handle.index);
+        Some(self.release(handle.index))
+    }
+
+    // Move the clock forward to `to`, returning the values of every timer that came due, in
+    // deadline order (and schedule order for equal deadlines).
+    pub fn advance(&mut self, to: u64) -> Vec<T> {
+        let mut fired = Vec::new();
+        while self.now < to {
+            if self.len == 0 {
+                self.now = to;
+                break;
+            }
+            // Nothing happens until the next multiple of 64^(number of empty levels at the
+            // bottom), so go straight there.
+            let empty = self.per_level.iter().take_while(|&&n| n == 0).count();
+            let span = 1u64 << (empty.min(LEVELS) as u32 * SLOT_BITS);
+            let mut next = (self.now / span).saturating_add(1).saturating_mul(span);
+            if empty == LEVELS {
+                // Only overflow timers, and none of them moves before the multiple of 64^4 at
+                // or below its deadline.
+                next = next.max(self.earliest_overflow() & !(span - 1));
+            }
+            self.now = next.min(to);
+            self.cascade();
+            let mut i = self.take_list(self.now as usize & (SLOTS - 1));
+            while i != NIL {
+                let next = self.entries[i as usize].next;
+                fired.push(self.release(i));
+                i = next;
+            }
+        }
+        fired
+    }
+
+    fn earliest_overflow(&self) -> u64 {
+        let mut earliest = u64::MAX;
+        let mut i = self.lists[OVERFLOW].head;
+        while i != NIL {
+            earliest = earliest.min(self.entries[i as usize].deadline);
+            i = self.entries[i as usize].next;
+        }
+        earliest
+    }
+
+    // Re-place the timers in every slot whose time has just come, top level first.
+    fn cascade(&mut self) {
+        let mut lists = Vec::new();
+        if self.now.trailing_zeros() >= LEVELS as u32 * SLOT_BITS {
+            lists.push(OVERFLOW);
+        }
+        for level in (1..LEVELS).rev() {
+            let shift = level as u32 * SLOT_BITS;
+            if self.now & ((1 << shift) - 1) == 0 {
+                let slot = (self.now >> shift) as usize & (SLOTS - 1);
+                lists.push(level * SLOTS + slot);
+            }
+        }
+        for list in lists {
+            let mut i = self.take_list(list);
+            while i != NIL {
+                let next = self.entries[i as usize].next;
+                self.place(i);
+                i = next;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TimerWheel;
+
+    #[test]
+    fn fires_in_order() {
+        let mut wheel = TimerWheel::new(0);
+        // Spread over all four levels and the overflow list.
+        let deadlines = [3, 64, 65, 100, 4095, 4096, 5000, 300_000, 17_000_000, 3];
+        for (i, &deadline) in deadlines.iter().enumerate() {
+            wheel.schedule(deadline, i);
+        }
+        assert_eq!(wheel.len(), 10);
+
+        assert_eq!(wheel.advance(2), [0usize; 0]);
+        assert_eq!(wheel.advance(3), [0, 9]);
+        assert_eq!(wheel.advance(64), [1]);
+        assert_eq!(wheel.advance(5000), [2, 3, 4, 5, 6]);
+        assert_eq!(wheel.advance(299_999), [0usize; 0]);
+        assert_eq!(wheel.advance(300_000), [7]);
+        assert_eq!(wheel.advance(16_999_999), [0usize; 0]);
+        assert_eq!(wheel.advance(17_000_000), [8]);
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn cancel() {
+        let mut wheel = TimerWheel::new(1000);
+        let a = wheel.schedule(1010, "a");
+        let b = wheel.schedule(1010, "b");
+        let c = wheel.schedule(9000, "c");
+        assert_eq!(wheel.deadline(c), Some(9000));
+
+        assert_eq!(wheel.cancel(b), Some("b"));
+        assert_eq!(wheel.cancel(b), None);
+        assert_eq!(wheel.deadline(b), None);
+        // b's entry is reused, but the old handle stays stale.
+        let d = wheel.schedule(500, "d");
+        assert_eq!(wheel.deadline(d), Some(1001));
+        assert_eq!(wheel.cancel(b), None);
+
+        assert_eq!(wheel.advance(1010), ["d", "a"]);
+        assert_eq!(wheel.cancel(a), None);
+        assert_eq!(wheel.cancel(c), Some("c"));
+        assert_eq!(wheel.advance(10_000), Vec::<&str>::new());
+        assert_eq!(wheel.now(), 10_000);
+    }
+
+    #[test]
+    fn against_a_sort() {
+        let start = 123_456;
+        let mut wheel = TimerWheel::new(start);
+        let mut expected = Vec::new();
+        for i in 0..2000u64 {
+            let deadline = start + 1 + (i * 2654435761) % 200_000;
+            let handle = wheel.schedule(deadline, i);
+            if i % 5 == 0 {
+                wheel.cancel(handle);
+            } else {
+                expected.push((deadline, i));
+            }
+        }
+        expected.sort();
+        let fired = wheel.advance(start + 200_000);
+        assert_eq!(fired, expected.iter().map(|&(_, i)| i).collect::<Vec<_>>());
+    }
+    // Each of these would take billions of steps one span at a time.
+    #[test]
+    fn far_jumps() {
+        let mut wheel = TimerWheel::<usize>::new(0);
+        assert_eq!(wheel.advance(u64::MAX), [0usize; 0]);
+        assert_eq!(wheel.now(), u64::MAX);
+
+        let mut wheel = TimerWheel::new(0);
+        wheel.schedule(u64::MAX, 2);
+        wheel.schedule(1 << 40, 0);
+        wheel.schedule(1 << 50, 1);
+        assert_eq!(wheel.advance(1 << 45), [0]);
+        assert_eq!(wheel.now(), 1 << 45);
+        assert_eq!(wheel.advance(u64::MAX), [1, 2]);
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn end_of_time() {
+        let mut wheel = TimerWheel::new(u64::MAX - 1);
+        let a = wheel.schedule(u64::MAX, "a");
+        let b = wheel.schedule(0, "b");
+        assert_eq!(wheel.deadline(b), Some(u64::MAX));
+        assert_eq!(wheel.advance(u64::MAX), ["a", "b"]);
+        assert_eq!(wheel.deadline(a), None);
+
+        // Nothing comes after u64::MAX, so this one never fires.
+        let c = wheel.schedule(5, "c");
+        assert_eq!(wheel.deadline(c), Some(u64::MAX));
+        assert_eq!(wheel.advance(u64::MAX), [""; 0]);
+        assert_eq!(wheel.cancel(c), Some("c"));
+    }
+}
@@ -0,0 +1,174 @@
+/*
+ Tracing
+
+A queue that's backing up, or a cache that's evicting more than it should, shows up in production
+as latency somewhere else. With the `tracing` feature, the concurrent queues and the caches say
+what they're doing through the tracing crate, so the subscriber an application already has can
+line their depth up against everything else it records, without anyone wrapping the types.
+
+Each operation is a span at TRACE level, named after the method, with the module as its target:
+
+    push{len=3 moved=1}                  linked_list_rc::blocking_queue
+    get{len=100 moved=0 hit=false}       linked_list_rc::lru_cache
+
+- `len` is the length straight after the operation, where it's known for free: behind the lock, or
+  in the cache's map. The lock-free queues don't keep a count, so theirs stays empty.
+- `moved` is how many elements went in or came out: 1 for a push, 1 or 0 for a pop.
+- A few operations have a flag of their own: `hit` for a cache lookup, `evicted` for a put that
+  had to make room, `waited` for a blocking call that had to sleep.
+
+The span is entered for as long as the operation runs, so its time is the operation's latency,
+including any time spent blocked. A blocking pop that waits shows up as a long span with
+`waited=true`. An operation made of others nests their spans inside its own: a cache put that
+evicts has a pop_lru span in it, and a channel's recv a try_recv for each time it looked.
+
+async_channel's futures can't hold a span across an await, so there each poll is a span of its
+own (poll_send, poll_recv), and one that came back Pending has moved=0.
+
+Without the feature, span! is an empty struct and done() does nothing, so none of this is compiled
+in. With it but no subscriber listening, each call costs a check of a cached flag.
+*/
+
+// One operation's span, entered until this is dropped.
+#[must_use]
+pub(crate) struct Op(#[cfg(feature = "tracing")] pub(crate) tracing::span::EnteredSpan);
+
+// span!("push") enters a span for the operation, with `len` and `moved` to be filled in by done().
+// Any other names after it are extra fields for flag().
+#[cfg(feature = "tracing")]
+macro_rules! span {
+    ($name:literal $(, $flag:ident)*) => {
+        $crate::trace::Op(
+            tracing::trace_span!(
+                $name,
+                len = tracing::field::Empty,
+                moved = tracing::field::Empty
+                $(, $flag = tracing::field::Empty)*
+            )
+            .entered(),
+        )
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! span {
+    ($name:literal $(, $flag:ident)*) => {
+        $crate::trace::Op()
+    };
+}
+
+pub(crate) use span;
+
+impl Op {
+    // `len` is None where the structure doesn't know it without counting.
+    #[inline]
+    pub(crate) fn done(&self, len: Option<usize>, moved: usize) {
+        #[cfg(feature = "tracing")]
+        {
+            if let Some(len) = len {
+                self.0.record("len", len);
+            }
+            self.0.record("moved", moved);
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = (len, moved);
+    }
+
+    // `field` has to be one of the names given to span!.
+    #[inline]
+    pub(crate) fn flag(&self, field: &str, value: bool) {
+        #[cfg(feature = "tracing")]
+        self.0.record(field, value);
+        #[cfg(not(feature = "tracing"))]
+        let _ = (field, value);
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod test {
+    use crate::blocking_queue::BlockingQueue;
+    use crate::lru_cache::LruCache;
+    use crate::ms_queue::MsQueue;
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    // Keeps every span it's given, as its name and the fields recorded on it, in order.
+    #[derive(Clone, Default)]
+    struct Spans(Arc<Mutex<Vec<String>>>);
+
+    struct Line<'a>(&'a mut String);
+
+    impl Visit for Line<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.push_str(&format!(" {}={value:?}", field.name()));
+        }
+    }
+
+    impl Subscriber for Spans {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut spans = self.0.lock().unwrap();
+            spans.push(span.metadata().name().to_string());
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let mut spans = self.0.lock().unwrap();
+            values.record(&mut Line(&mut spans[span.into_u64() as usize - 1]));
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event<'_>) {}
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn spans() {
+        let spans = Spans::default();
+        tracing::subscriber::with_default(spans.clone(), || {
+            let queue = BlockingQueue::with_capacity(2);
+            queue.push(1);
+            queue.try_push(2).unwrap();
+            assert_eq!(queue.try_push(3), Err(3));
+            queue.pop();
+
+            let lock_free = MsQueue::new();
+            lock_free.push(1);
+            lock_free.pop();
+            lock_free.pop();
+
+            let mut cache = LruCache::new(1);
+            cache.put("a", 1);
+            cache.get("a");
+            cache.put("b", 2);
+            cache.get("a");
+        });
+        assert_eq!(
+            *spans.0.lock().unwrap(),
+            [
+                "push len=1 moved=1 waited=false",
+                "try_push len=2 moved=1",
+                "try_push len=2 moved=0",
+                "pop len=1 moved=1 waited=false",
+                "push moved=1",
+                "pop moved=1",
+                "pop moved=0",
+                "put len=1 moved=1 evicted=false",
+                "get len=1 moved=0 hit=true",
+                "put len=1 moved=1 evicted=true",
+                "pop_lru len=0 moved=1",
+                "get len=1 moved=0 hit=false",
+            ]
+        );
+    }
+}
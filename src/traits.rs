@@ -0,0 +1,170 @@
+/*
+ One interface for every stack, queue and deque
+
+Each list module has its own API, grown along with the tutorial: basic_impl's push(i32),
+generic_and_iterators' push(T), the deques' push_front and push_back, the persistent stack's
+prepend(&self), which returns a new list instead of changing this one. They all do much the same
+thing, but there's no way to write one function, test or benchmark that runs on any of them.
+
+These traits are that way. They're re-exported at the crate root:
+
+- Collection: len and is_empty, which every one of them has. It's a supertrait of the other three
+  rather than part of each, so that a deque, which is all three, has one len and not three.
+- Stack<T>: push and pop at the same end, peek at it.
+- Queue<T>: push at the back, pop and peek at the front.
+- Deque<T>: both ends, both ways.
+
+peek returns `impl Deref<Target = T>` rather than &T, because doubly_linked_deque can only lend
+out its elements through a RefCell's Ref; for everything else it's just &T.
+
+Not every list keeps a count: len() walks the list where it has to (basic_impl, doubly_linked_deque
+and pointer_list's, which is generic_and_iterators and both persistent stacks), and is_empty()
+stays O(1) everywhere. The persistent stacks are adapters: push puts a new node in
+front and pop moves the list on to its tail, never touching a node other lists may share. So
+popping clones the element out of a shared node, and it needs T: Clone.
+
+The inherent methods are still there, and win when both are in scope, so implementing the traits
+changes nothing for existing code.
+*/
+
+use std::ops::Deref;
+
+pub trait Collection {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+pub trait Stack<T>: Collection {
+    fn push(&mut self, elem: T);
+
+    fn pop(&mut self) -> Option<T>;
+
+    fn peek(&self) -> Option<impl Deref<Target = T> + '_>;
+}
+
+pub trait Queue<T>: Collection {
+    // At the back.
+    fn push(&mut self, elem: T);
+
+    // From the front.
+    fn pop(&mut self) -> Option<T>;
+
+    fn peek(&self) -> Option<impl Deref<Target = T> + '_>;
+}
+
+pub trait Deque<T>: Collection {
+    fn push_front(&mut self, elem: T);
+
+    fn push_back(&mut self, elem: T);
+
+    fn pop_front(&mut self) -> Option<T>;
+
+    fn pop_back(&mut self) -> Option<T>;
+
+    fn peek_front(&self) -> Option<impl Deref<Target = T> + '_>;
+
+    fn peek_back(&self) -> Option<impl Deref<Target = T> + '_>;
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Deque, Queue, Stack};
+    use crate::allocator::Bump;
+    use crate::{
+        arc_persistent_stack, basic_impl, doubly_linked_deque, generic_and_iterators,
+        persistent_stack, pointer_list, unsafe_doubly_linked_deque, unsafe_single_linked_queue,
+    };
+
+    // The same code against every implementation.
+    fn stack<S: Stack<i32> + Default>() {
+        empty_stack(S::default());
+    }
+
+    // For the lists that need something to be made with, like an allocator.
+    fn empty_stack<S: Stack<i32>>(mut stack: S) {
+        assert!(stack.is_empty());
+        assert!(stack.peek().is_none());
+        for x in 1..=3 {
+            stack.push(x);
+        }
+        assert_eq!(stack.len(), 3);
+        assert_eq!(stack.peek().as_deref(), Some(&3));
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(stack.pop(), Some(2));
+        assert_eq!(stack.len(), 1);
+        assert!(!stack.is_empty());
+    }
+
+    fn queue<Q: Queue<i32> + Default>() {
+        let mut queue = Q::default();
+        assert!(queue.is_empty());
+        for x in 1..=3 {
+            queue.push(x);
+        }
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.peek().as_deref(), Some(&1));
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.len(), 1);
+    }
+
+    fn deque<D: Deque<i32> + Default>() {
+        let mut deque = D::default();
+        deque.push_back(2);
+        deque.push_front(1);
+        deque.push_back(3);
+        assert_eq!(deque.len(), 3);
+        assert_eq!(deque.peek_front().as_deref(), Some(&1));
+        assert_eq!(deque.peek_back().as_deref(), Some(&3));
+        assert_eq!(deque.pop_back(), Some(3));
+        assert_eq!(deque.pop_front(), Some(1));
+        assert_eq!(deque.pop_front(), Some(2));
+        assert!(deque.is_empty());
+        assert_eq!(deque.pop_back(), None);
+    }
+
+    #[test]
+    fn stacks() {
+        stack::<basic_impl::List>();
+        stack::<generic_and_iterators::List<i32>>();
+        stack::<persistent_stack::List<i32>>();
+        stack::<arc_persistent_stack::List<i32>>();
+        stack::<pointer_list::BoxList<i32>>();
+        stack::<pointer_list::RcList<i32>>();
+        stack::<pointer_list::ArcList<i32>>();
+        stack::<doubly_linked_deque::List<i32>>();
+        stack::<unsafe_doubly_linked_deque::List<i32>>();
+
+        let bump = Bump::with_capacity(1024);
+        empty_stack(generic_and_iterators::List::new_in(&bump));
+    }
+
+    #[test]
+    fn queues() {
+        queue::<unsafe_single_linked_queue::List<i32>>();
+        queue::<doubly_linked_deque::List<i32>>();
+        queue::<unsafe_doubly_linked_deque::List<i32>>();
+    }
+
+    #[test]
+    fn deques() {
+        deque::<doubly_linked_deque::List<i32>>();
+        deque::<unsafe_doubly_linked_deque::List<i32>>();
+    }
+
+    #[test]
+    fn persistent_adapter() {
+        let shared: persistent_stack::List<_> = (1..=3).fold(Default::default(), |l, x| {
+            persistent_stack::List::prepend(&l, x)
+        });
+        let mut stack = shared.clone();
+        Stack::push(&mut stack, 4);
+        assert_eq!(Stack::pop(&mut stack), Some(4));
+        assert_eq!(Stack::pop(&mut stack), Some(3));
+        // Popping a copy leaves the shared nodes alone.
+        assert_eq!(shared.iter().copied().collect::<Vec<_>>(), [3, 2, 1]);
+    }
+}
@@ -0,0 +1,196 @@
+/*
+ A lock-free stack (Treiber, 1986)
+
+The same singly linked stack as everywhere else in this crate, except `head` is an AtomicPtr and
+any number of threads can push and pop through a shared reference:
+
+- push links a new node in front of the current head and CASes `head` over to it, retrying if
+  another thread got there first.
+- pop reads the head and its `next`, and CASes `head` over to `next`.
+
+The hard part is pop. Between loading `head` and reading `head.next`, another thread may pop that
+very node and free it. Worse, the allocation could be reused for a new node that gets pushed
+back, so our CAS would succeed against a stale `next` (the ABA problem). Both go away if nodes
+are never freed while a thread might still be looking at them, which is exactly what epoch
+reclamation guarantees: pop pins the thread and defers freeing the node it unlinked.
+*/
+
+use crate::epoch::{self, Guard};
+use crate::sync::atomic::{AtomicPtr, Ordering};
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ptr;
+
+pub struct TreiberStack<T> {
+    head: AtomicPtr<Node<T>>,
+    _boo: PhantomData<T>,
+}
+
+pub(crate) struct Node<T> {
+    // Moved out by whichever pop unlinks the node; the deferred free must not drop it again.
+    pub(crate) elem: ManuallyDrop<T>,
+    next: *mut Node<T>,
+}
+
+impl<T> Node<T> {
+    pub(crate) fn new(elem: T) -> *mut Node<T> {
+        Box::into_raw(Box::new(Node {
+            elem: ManuallyDrop::new(elem),
+            next: ptr::null_mut(),
+        }))
+    }
+}
+
+// Elements move between threads through push/pop, and &TreiberStack only ever hands them out by
+// value, so T: Send is all it takes.
+unsafe impl<T: Send> Send for TreiberStack<T> {}
+unsafe impl<T: Send> Sync for TreiberStack<T> {}
+
+impl<T> TreiberStack<T> {
+    pub fn new() -> Self {
+        TreiberStack {
+            head: AtomicPtr::new(ptr::null_mut()),
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn push(&self, elem: T) {
+        let node = Node::new(elem);
+        while !self.try_push(node) {}
+    }
+
+    // A single attempt at linking `node` in. False if another thread moved `head` first, in
+    // which case `node` is still ours.
+    pub(crate) fn try_push(&self, node: *mut Node<T>) -> bool {
+        let head = self.head.load(Ordering::Relaxed);
+        // Nobody else can see `node` until the CAS succeeds.
+        unsafe { (*node).next = head };
+        self.head
+            .compare_exchange(head, node, Ordering::Release, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = epoch::pin();
+        loop {
+            if let Ok(elem) = self.try_pop(&guard) {
+                return elem;
+            }
+        }
+    }
+
+    // A single attempt at popping. Err if another thread moved `head` first.
+    pub(crate) fn try_pop(&self, guard: &Guard) -> Result<Option<T>, ()> {
+        let head = self.head.load(Ordering::Acquire);
+        if head.is_null() {
+            return Ok(None);
+        }
+        // We're pinned, so `head` can't be freed under us even if it's popped meanwhile.
+        let next = unsafe { (*head).next };
+        match self
+            .head
+            .compare_exchange(head, next, Ordering::Acquire, Ordering::Relaxed)
+        {
+            Ok(_) => unsafe {
+                // We unlinked it, so the element is ours to take.
+                let elem = ManuallyDrop::into_inner(ptr::read(&(*head).elem));
+                guard.defer_destroy(head);
+                Ok(Some(elem))
+            },
+            Err(_) => Err(()),
+        }
+    }
+
+    // Just a snapshot: other threads may push or pop right after.
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+}
+
+impl<T> Default for TreiberStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for TreiberStack<T> {
+    fn drop(&mut self) {
+        // &mut self: no other thread can be in push or pop, so free the nodes directly.
+        let mut cur = self.head.load(Ordering::Relaxed);
+        while !cur.is_null() {
+            let mut node = unsafe { Box::from_raw(cur) };
+            unsafe { ManuallyDrop::drop(&mut node.elem) };
+            cur = node.next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TreiberStack;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn basics() {
+        let stack = TreiberStack::new();
+        assert_eq!(stack.pop(), None);
+        assert!(stack.is_empty());
+
+        stack.push(1);
+        stack.push(2);
+        stack.push(3);
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(stack.pop(), Some(2));
+
+        stack.push(4);
+        assert_eq!(stack.pop(), Some(4));
+        assert_eq!(stack.pop(), Some(1));
+        assert_eq!(stack.pop(), None);
+    }
+
+    #[test]
+    fn drop_remaining() {
+        let rc = std::rc::Rc::new(());
+        {
+            // Rc isn't Send, but a stack on a single thread is fine.
+            let stack = TreiberStack::new();
+            for _ in 0..10 {
+                stack.push(rc.clone());
+            }
+            drop(stack.pop());
+            assert_eq!(std::rc::Rc::strong_count(&rc), 10);
+        }
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn threads() {
+        let stack = Arc::new(TreiberStack::new());
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let stack = stack.clone();
+                thread::spawn(move || {
+                    let mut popped = Vec::new();
+                    for i in 0..1000 {
+                        stack.push(t * 1000 + i);
+                        if i % 2 == 0 {
+                            popped.extend(stack.pop());
+                        }
+                    }
+                    popped
+                })
+            })
+            .collect();
+
+        let mut all: Vec<i32> = handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect();
+        while let Some(v) = stack.pop() {
+            all.push(v);
+        }
+        all.sort();
+        assert_eq!(all, (0..4000).collect::<Vec<_>>());
+    }
+}
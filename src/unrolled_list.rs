@@ -0,0 +1,685 @@
+/*
+ An unrolled linked list
+
+Every list in this crate so far pays one allocation, and at least one pointer, per element, and
+walking it means a cache miss per element too: nodes end up wherever the allocator put them.
+An unrolled list stores up to N elements per node instead, in a small inline array:
+
+front                                                  back
+ |                                                      |
+[a b c d _ _ _ _] <-> [e f g h i j _ _] <-> [k l m _ _ _ _ _]
+
+Within a node the elements are contiguous, so iterating is mostly a slice walk, and there's one
+allocation (and two link pointers) per N elements. It's still a linked list where it counts:
+inserting or removing in the middle only shifts elements within one node, never the whole list.
+
+- Each node holds `[MaybeUninit<T>; N]` and a `len`; slots `..len` are initialized, the rest
+  aren't. No node is ever left empty.
+- insert into a full node splits it: the back half moves into a fresh node right after it.
+- remove that leaves a node less than half full merges the next node into it, if they fit in
+  one. That keeps nodes reasonably full, so the list doesn't degrade into one element per node.
+- Indexing walks from whichever end is closer, a node at a time.
+
+N is a const generic, so the node size is fixed at compile time and picked per use:
+UnrolledList<u8, 64> and UnrolledList<String, 4> are both reasonable. benches/lists.rs compares
+iteration against the node-per-element deque.
+*/
+
+use crate::mem_usage::MemUsage;
+use crate::panic_safety;
+use crate::validate::{self, Invalid};
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr::{self, NonNull};
+
+pub struct UnrolledList<T, const N: usize> {
+    front: Link<T, N>,
+    back: Link<T, N>,
+    len: usize,
+    _boo: PhantomData<T>,
+}
+
+type Link<T, const N: usize> = Option<NonNull<Node<T, N>>>;
+
+struct Node<T, const N: usize> {
+    elems: [MaybeUninit<T>; N],
+    len: usize,
+    prev: Link<T, N>,
+    next: Link<T, N>,
+}
+
+impl<T, const N: usize> Node<T, N> {
+    fn new() -> NonNull<Node<T, N>> {
+        const {
+            assert!(
+                N >= 2,
+                "an unrolled list needs room for at least 2 elements per node"
+            )
+        };
+        let node = Box::new(Node {
+            elems: [const { MaybeUninit::uninit() }; N],
+            len: 0,
+            prev: None,
+            next: None,
+        });
+        NonNull::from(Box::leak(node))
+    }
+
+    // Shift `at..len` one slot right and put `elem` at `at`. Needs room.
+    fn insert(&mut self, at: usize, elem: T) {
+        debug_assert!(self.len < N && at <= self.len);
+        unsafe {
+            let p = self.elems.as_mut_ptr().add(at);
+            ptr::copy(p, p.add(1), self.len - at);
+            p.write(MaybeUninit::new(elem));
+        }
+        self.len += 1;
+    }
+
+    // Take the element at `at` and shift the rest left over it.
+    fn remove(&mut self, at: usize) -> T {
+        debug_assert!(at < self.len);
+        unsafe {
+            let p = self.elems.as_mut_ptr().add(at);
+            let elem = p.read().assume_init();
+            ptr::copy(p.add(1), p, self.len - at - 1);
+            self.len -= 1;
+            elem
+        }
+    }
+
+    // Move `self.elems[from..len]` onto the end of `other`.
+    fn move_tail_to(&mut self, from: usize, other: &mut Node<T, N>) {
+        let count = self.len - from;
+        debug_assert!(other.len + count <= N);
+        unsafe {
+            ptr::copy_nonoverlapping(
+                self.elems.as_ptr().add(from),
+                other.elems.as_mut_ptr().add(other.len),
+                count,
+            );
+        }
+        self.len = from;
+        other.len += count;
+    }
+
+    fn as_slice(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.elems.as_ptr().cast(), self.len) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { std::slice::from_raw_parts_mut(self.elems.as_mut_ptr().cast(), self.len) }
+    }
+}
+
+impl<T, const N: usize> UnrolledList<T, N> {
+    pub fn new() -> Self {
+        UnrolledList {
+            front: None,
+            back: None,
+            len: 0,
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // A node is a chunk of N slots, full or not, so the less full the chunks are, the more of
+    // heap_bytes is empty slots. See mem_usage.rs.
+    pub fn mem_usage(&self) -> MemUsage {
+        let mut nodes = 0;
+        let mut cur = self.front;
+        while let Some(node) = cur {
+            nodes += 1;
+            cur = unsafe { (*node.as_ptr()).next };
+        }
+        MemUsage::nodes::<Node<T, N>, T>(nodes, N)
+    }
+
+    // The links as in any doubly linked list, no node empty or over N, and the nodes' lens adding
+    // up to `len`. So here Len counts elements, and `at` in the rest is a node, not an element.
+    // No node is empty, so a cycle runs the count past `len`, and the walk stops there. See
+    // validate.rs.
+    pub fn validate(&self) -> Result<(), Invalid> {
+        let (mut prev, mut cur, mut at, mut counted) = (None, self.front, 0, 0);
+        while let Some(node) = cur {
+            let node_ref = unsafe { node.as_ref() };
+            if node_ref.prev != prev {
+                return Err(Invalid::Link { at });
+            }
+            if node_ref.len == 0 || node_ref.len > N {
+                return Err(Invalid::Node { at });
+            }
+            counted += node_ref.len;
+            if counted > self.len {
+                break;
+            }
+            (prev, cur, at) = (Some(node), node_ref.next, at + 1);
+        }
+        if counted != self.len {
+            return Err(Invalid::Len {
+                stored: self.len,
+                counted,
+            });
+        }
+        if prev != self.back {
+            return Err(Invalid::Ends);
+        }
+        Ok(())
+    }
+
+    // A new, empty node after `after` (or at the front, if None).
+    fn link_after(&mut self, after: Link<T, N>) -> NonNull<Node<T, N>> {
+        let new = Node::new();
+        unsafe {
+            let next = match after {
+                Some(a) => (*a.as_ptr()).next.replace(new),
+                None => self.front.replace(new),
+            };
+            (*new.as_ptr()).prev = after;
+            (*new.as_ptr()).next = next;
+            match next {
+                Some(n) => (*n.as_ptr()).prev = Some(new),
+                None => self.back = Some(new),
+            }
+        }
+        new
+    }
+
+    // Unlink and free `node`. Its elements must already be gone (len == 0).
+    fn unlink(&mut self, node: NonNull<Node<T, N>>) {
+        unsafe {
+            let boxed = Box::from_raw(node.as_ptr());
+            debug_assert_eq!(boxed.len, 0);
+            match boxed.prev {
+                Some(p) => (*p.as_ptr()).next = boxed.next,
+                None => self.front = boxed.next,
+            }
+            match boxed.next {
+                Some(n) => (*n.as_ptr()).prev = boxed.prev,
+                None => self.back = boxed.prev,
+            }
+        }
+    }
+
+    // The node holding element `index`, and where in it. Walks from the nearer end.
+    fn locate(&self, index: usize) -> (NonNull<Node<T, N>>, usize) {
+        assert!(
+            index < self.len,
+            "index {index} out of bounds (len {})",
+            self.len
+        );
+        unsafe {
+            if index < self.len / 2 {
+                let mut node = self.front.unwrap();
+                let mut i = index;
+                while i >= (*node.as_ptr()).len {
+                    i -= (*node.as_ptr()).len;
+                    node = (*node.as_ptr()).next.unwrap();
+                }
+                (node, i)
+            } else {
+                let mut node = self.back.unwrap();
+                // How many elements sit after `index`.
+                let mut after = self.len - 1 - index;
+                while after >= (*node.as_ptr()).len {
+                    after -= (*node.as_ptr()).len;
+                    node = (*node.as_ptr()).prev.unwrap();
+                }
+                (node, (*node.as_ptr()).len - 1 - after)
+            }
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            return None;
+        }
+        let (node, at) = self.locate(index);
+        Some(unsafe { (*node.as_ptr()).elems[at].assume_init_ref() })
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len {
+            return None;
+        }
+        let (node, at) = self.locate(index);
+        Some(unsafe { (*node.as_ptr()).elems[at].assume_init_mut() })
+    }
+
+    pub fn push_back(&mut self, elem: T) {
+        let node = match self.back {
+            Some(back) if unsafe { (*back.as_ptr()).len } < N => back,
+            back => self.link_after(back),
+        };
+        unsafe {
+            let node = &mut *node.as_ptr();
+            node.insert(node.len, elem);
+        }
+        self.len += 1;
+        validate::check!(self);
+    }
+
+    pub fn push_front(&mut self, elem: T) {
+        let node = match self.front {
+            Some(front) if unsafe { (*front.as_ptr()).len } < N => front,
+            _ => self.link_after(None),
+        };
+        unsafe { (*node.as_ptr()).insert(0, elem) };
+        self.len += 1;
+        validate::check!(self);
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        (!self.is_empty()).then(|| self.remove(0))
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        (!self.is_empty()).then(|| self.remove(self.len - 1))
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.front
+            .map(|node| unsafe { (*node.as_ptr()).elems[0].assume_init_ref() })
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        self.back.map(|node| unsafe {
+            let node = &*node.as_ptr();
+            node.elems[node.len - 1].assume_init_ref()
+        })
+    }
+
+    // Insert so that `elem` ends up at `index`; everything from there on moves back one.
+    pub fn insert(&mut self, index: usize, elem: T) {
+        assert!(
+            index <= self.len,
+            "index {index} out of bounds (len {})",
+            self.len
+        );
+        if index == self.len {
+            return self.push_back(elem);
+        }
+        let (node, mut at) = self.locate(index);
+        let mut node = node;
+        unsafe {
+            if (*node.as_ptr()).len == N {
+                // Full: split off the back half into a new node.
+                let new = self.link_after(Some(node));
+                (*node.as_ptr()).move_tail_to(N / 2, &mut *new.as_ptr());
+                if at > N / 2 {
+                    node = new;
+                    at -= N / 2;
+                }
+            }
+            (*node.as_ptr()).insert(at, elem);
+        }
+        self.len += 1;
+        validate::check!(self);
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        let (node, at) = self.locate(index);
+        self.len -= 1;
+        let elem = unsafe {
+            let elem = (*node.as_ptr()).remove(at);
+            let len = (*node.as_ptr()).len;
+            if len == 0 {
+                self.unlink(node);
+            } else if len < N / 2 {
+                // Running low: pull the next node in, if the two fit in one.
+                if let Some(next) = (*node.as_ptr()).next {
+                    if len + (*next.as_ptr()).len <= N {
+                        (*next.as_ptr()).move_tail_to(0, &mut *node.as_ptr());
+                        self.unlink(next);
+                    }
+                }
+            }
+            elem
+        };
+        validate::check!(self);
+        elem
+    }
+
+    pub fn clear(&mut self) {
+        *self = UnrolledList::new();
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, N> {
+        Iter {
+            node: self.front,
+            slice: [].iter(),
+            len: self.len,
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, N> {
+        IterMut {
+            node: self.front,
+            slice: [].iter_mut(),
+            len: self.len,
+            _boo: PhantomData,
+        }
+    }
+
+    // How many nodes the elements are spread over.
+    pub fn node_count(&self) -> usize {
+        let mut count = 0;
+        let mut cur = self.front;
+        while let Some(node) = cur {
+            count += 1;
+            cur = unsafe { (*node.as_ptr()).next };
+        }
+        count
+    }
+}
+
+// A node at a time, moving front on first. If an element's Drop panics, drop_in_place still drops
+// the rest of the slice, the node is freed as the panic unwinds, and pop_all carries on with the
+// next one (see panic_safety.rs).
+impl<T, const N: usize> Drop for UnrolledList<T, N> {
+    fn drop(&mut self) {
+        panic_safety::pop_all(self, |list| match list.front {
+            Some(node) => {
+                let mut node = unsafe { Box::from_raw(node.as_ptr()) };
+                list.front = node.next;
+                unsafe { ptr::drop_in_place(node.as_mut_slice()) };
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+impl<T, const N: usize> Default for UnrolledList<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Extend<T> for UnrolledList<T, N> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push_back(elem);
+        }
+    }
+}
+
+impl<T, const N: usize> FromIterator<T> for UnrolledList<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = UnrolledList::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for UnrolledList<T, N> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: Debug, const N: usize> Debug for UnrolledList<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+impl<T: PartialEq, const N: usize> PartialEq for UnrolledList<T, N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other)
+    }
+}
+
+impl<T: Eq, const N: usize> Eq for UnrolledList<T, N> {}
+
+unsafe impl<T: Send, const N: usize> Send for UnrolledList<T, N> {}
+unsafe impl<T: Sync, const N: usize> Sync for UnrolledList<T, N> {}
+
+/* ------------------------------- iterators ------------------------------- */
+
+// Hands out one node's slice at a time, then moves on to the next node.
+pub struct Iter<'a, T, const N: usize> {
+    node: Link<T, N>,
+    slice: std::slice::Iter<'a, T>,
+    len: usize,
+    _boo: PhantomData<&'a T>,
+}
+
+impl<'a, T, const N: usize> Iterator for Iter<'a, T, N> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            if let Some(elem) = self.slice.next() {
+                self.len -= 1;
+                return Some(elem);
+            }
+            let node = unsafe { &*self.node?.as_ptr() };
+            self.slice = node.as_slice().iter();
+            self.node = node.next;
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T, const N: usize> ExactSizeIterator for Iter<'_, T, N> {}
+
+pub struct IterMut<'a, T, const N: usize> {
+    node: Link<T, N>,
+    slice: std::slice::IterMut<'a, T>,
+    len: usize,
+    _boo: PhantomData<&'a mut T>,
+}
+
+impl<'a, T, const N: usize> Iterator for IterMut<'a, T, N> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<&'a mut T> {
+        loop {
+            if let Some(elem) = self.slice.next() {
+                self.len -= 1;
+                return Some(elem);
+            }
+            let node = unsafe { &mut *self.node?.as_ptr() };
+            self.node = node.next;
+            self.slice = node.as_mut_slice().iter_mut();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T, const N: usize> ExactSizeIterator for IterMut<'_, T, N> {}
+
+// Reads elements straight out of the front node and frees each node once it's been emptied, so
+// nothing gets shifted along the way.
+pub struct IntoIter<T, const N: usize> {
+    list: UnrolledList<T, N>,
+    // Slots of the front node before this one have already been moved out.
+    taken: usize,
+}
+
+impl<T, const N: usize> Iterator for IntoIter<T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        loop {
+            let node = self.list.front?;
+            let node_ref = unsafe { &mut *node.as_ptr() };
+            if self.taken < node_ref.len {
+                let elem = unsafe { node_ref.elems[self.taken].assume_init_read() };
+                self.taken += 1;
+                self.list.len -= 1;
+                return Some(elem);
+            }
+            // All moved out already: forget them, then free the node.
+            node_ref.len = 0;
+            self.taken = 0;
+            self.list.unlink(node);
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.list.len, Some(self.list.len))
+    }
+}
+
+impl<T, const N: usize> ExactSizeIterator for IntoIter<T, N> {}
+
+impl<T, const N: usize> Drop for IntoIter<T, N> {
+    fn drop(&mut self) {
+        // The list's own Drop would drop the front node's moved-out slots a second time, so this
+        // has to empty it, even if an element's Drop panics part way (see panic_safety.rs).
+        panic_safety::pop_all(self, |iter| iter.next().is_some());
+    }
+}
+
+impl<T, const N: usize> IntoIterator for UnrolledList<T, N> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            list: self,
+            taken: 0,
+        }
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a UnrolledList<T, N> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a mut UnrolledList<T, N> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::UnrolledList;
+    use crate::validate::Invalid;
+
+    #[test]
+    fn basics() {
+        let mut list: UnrolledList<i32, 4> = UnrolledList::new();
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.pop_back(), None);
+
+        for i in 0..10 {
+            list.push_back(i);
+        }
+        list.push_front(-1);
+        assert_eq!(list.len(), 11);
+        assert_eq!(list.front(), Some(&-1));
+        assert_eq!(list.back(), Some(&9));
+        assert_eq!(list.get(5), Some(&4));
+        assert_eq!(list.get(11), None);
+        *list.get_mut(0).unwrap() = 100;
+
+        assert_eq!(list.pop_front(), Some(100));
+        assert_eq!(list.pop_back(), Some(9));
+        assert_eq!(
+            list.iter().copied().collect::<Vec<_>>(),
+            (0..9).collect::<Vec<_>>()
+        );
+        for x in &mut list {
+            *x *= 2;
+        }
+        assert_eq!(
+            list.into_iter().collect::<Vec<_>>(),
+            (0..9).map(|x| x * 2).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn insert_and_remove_keep_nodes_full() {
+        // Check everything against a Vec doing the same thing.
+        let mut list: UnrolledList<u32, 8> = UnrolledList::new();
+        let mut model = Vec::new();
+        for i in 0..500u32 {
+            let at = (i.wrapping_mul(2654435761) as usize) % (model.len() + 1);
+            list.insert(at, i);
+            model.insert(at, i);
+        }
+        assert!(list.iter().eq(model.iter()));
+        // Splitting never leaves a node less than half full.
+        assert!(list.node_count() <= 2 * list.len().div_ceil(8));
+
+        for i in 0..400u32 {
+            let at = (i.wrapping_mul(40503) as usize) % model.len();
+            assert_eq!(list.remove(at), model.remove(at));
+        }
+        assert!(list.iter().eq(model.iter()));
+        assert_eq!(list.len(), 100);
+        for (i, x) in model.iter().enumerate() {
+            assert_eq!(list.get(i), Some(x));
+        }
+    }
+
+    #[test]
+    fn validate() {
+        let list: UnrolledList<i32, 4> = (0..10).collect();
+        assert_eq!(list.validate(), Ok(()));
+        let back = list.back.unwrap();
+
+        unsafe { (*back.as_ptr()).len = 5 };
+        assert_eq!(list.validate(), Err(Invalid::Node { at: 2 }));
+        unsafe { (*back.as_ptr()).len = 1 };
+        assert_eq!(
+            list.validate(),
+            Err(Invalid::Len {
+                stored: 10,
+                counted: 9
+            })
+        );
+        unsafe { (*back.as_ptr()).len = 2 };
+
+        let prev = unsafe { (*back.as_ptr()).prev.take() };
+        assert_eq!(list.validate(), Err(Invalid::Link { at: 2 }));
+        unsafe { (*back.as_ptr()).prev = prev };
+        assert_eq!(list.validate(), Ok(()));
+    }
+
+    #[test]
+    fn drops() {
+        let mut list: UnrolledList<String, 3> = (0..10).map(|i| i.to_string()).collect();
+        list.remove(4);
+        list.insert(2, String::from("x"));
+        let cloned = list.clone();
+        assert_eq!(list, cloned);
+        assert_eq!(
+            format!("{:?}", cloned.iter().take(3).collect::<Vec<_>>()),
+            r#"["0", "1", "x"]"#
+        );
+
+        // A partly consumed IntoIter drops the rest exactly once.
+        let mut iter = list.into_iter();
+        assert_eq!(iter.next().as_deref(), Some("0"));
+        assert_eq!(iter.next().as_deref(), Some("1"));
+        drop(iter);
+    }
+}
@@ -0,0 +1,1897 @@
+/*
+ An unsafe doubly linked deque
+
+doubly_linked_deque.rs is the teaching version: Rc<RefCell<Node>> everywhere, runtime borrow
+checks, and peek has to return Ref/RefMut guards. This one is what you'd actually ship, with the
+same API shape as std::collections::LinkedList:
+
+None <- front <-> B <-> C <-> back -> None
+
+- Links are Option<NonNull<Node<T>>>: covariant, pointer-sized, and never null when Some (same
+  reasoning as unsafe_single_linked_queue.rs). A node's `front` link is a TaggedPtr instead, with
+  the node's mark in its low bit (see "marks" further down).
+- PhantomData<T> tells the drop checker we own Ts.
+- Each node is a Box allocation. We turn it into a raw pointer when linking it in, and back into a
+  Box exactly once when unlinking it.
+- Node links are named by direction too: `node.front` points towards the front of the list.
+- The invariants every method maintains: `front` is None iff `back` is None iff `len == 0`, the
+  first node's `front` and the last node's `back` are None, and `a.back == b` iff `b.front == a`.
+
+CursorMut is the reason to pick a linked list over a VecDeque at all: it sits on a node (or on the
+"ghost" element between back and front) and can insert, remove, split and splice there in O(1).
+*/
+
+use crate::allocator::Allocator;
+use crate::diff::{self, Edit};
+use crate::format::{self, ParseListError};
+use crate::mem_usage::MemUsage;
+use crate::panic_safety;
+use crate::tagged_ptr::TaggedPtr;
+use crate::traits::{Collection, Deque, Queue, Stack};
+use crate::validate::{self, Invalid};
+use crate::{
+    doubly_linked_deque, generic_and_iterators, persistent_stack, unsafe_single_linked_queue,
+};
+use std::cmp::Ordering;
+use std::collections::{LinkedList, VecDeque};
+use std::fmt::{self, Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::ptr::{self, NonNull};
+use std::str::FromStr;
+
+pub struct List<T> {
+    front: Link<T>,
+    back: Link<T>,
+    len: usize,
+    _boo: PhantomData<T>,
+}
+
+type Link<T> = Option<NonNull<Node<T>>>;
+
+struct Node<T> {
+    // Tagged MARKED: the node's mark (see "marks" below). Read it with link() and change it with
+    // set_front(), which keep the mark out of the way.
+    front: TaggedPtr<Node<T>>,
+    back: Link<T>,
+    elem: T,
+}
+
+const MARKED: usize = 1;
+
+fn link<T>(front: TaggedPtr<Node<T>>) -> Link<T> {
+    NonNull::new(front.ptr())
+}
+
+fn tagged<T>(link: Link<T>, tag: usize) -> TaggedPtr<Node<T>> {
+    TaggedPtr::new(link.map_or(ptr::null_mut(), NonNull::as_ptr), tag)
+}
+
+// Point `node`'s front link elsewhere, keeping its mark. Only the field is borrowed, never the
+// whole node, so an &mut to its elem handed out earlier stays valid.
+unsafe fn set_front<T>(node: NonNull<Node<T>>, to: Link<T>) {
+    let front = &mut (*node.as_ptr()).front;
+    *front = tagged(to, front.tag());
+}
+
+impl<T> List<T> {
+    pub fn new() -> Self {
+        List {
+            front: None,
+            back: None,
+            len: 0,
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn push_front(&mut self, elem: T) {
+        unsafe {
+            let new = NonNull::new_unchecked(Box::into_raw(Box::new(Node {
+                front: tagged(None, 0),
+                back: None,
+                elem,
+            })));
+            match self.front {
+                Some(old) => {
+                    set_front(old, Some(new));
+                    (*new.as_ptr()).back = Some(old);
+                }
+                None => self.back = Some(new),
+            }
+            self.front = Some(new);
+            self.len += 1;
+        }
+        validate::check!(self);
+    }
+
+    pub fn push_back(&mut self, elem: T) {
+        unsafe {
+            let new = NonNull::new_unchecked(Box::into_raw(Box::new(Node {
+                front: tagged(None, 0),
+                back: None,
+                elem,
+            })));
+            match self.back {
+                Some(old) => {
+                    (*old.as_ptr()).back = Some(new);
+                    set_front(new, Some(old));
+                }
+                None => self.front = Some(new),
+            }
+            self.back = Some(new);
+            self.len += 1;
+        }
+        validate::check!(self);
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        let elem = self.front.map(|node| unsafe {
+            let boxed = Box::from_raw(node.as_ptr());
+            self.front = boxed.back;
+            match self.front {
+                Some(new) => set_front(new, None),
+                None => self.back = None,
+            }
+            self.len -= 1;
+            boxed.elem
+        });
+        validate::check!(self);
+        elem
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        let elem = self.back.map(|node| unsafe {
+            let boxed = Box::from_raw(node.as_ptr());
+            self.back = link(boxed.front);
+            match self.back {
+                Some(new) => (*new.as_ptr()).back = None,
+                None => self.front = None,
+            }
+            self.len -= 1;
+            boxed.elem
+        });
+        validate::check!(self);
+        elem
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.front.map(|node| unsafe { &(*node.as_ptr()).elem })
+    }
+
+    pub fn front_mut(&mut self) -> Option<&mut T> {
+        self.front.map(|node| unsafe { &mut (*node.as_ptr()).elem })
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        self.back.map(|node| unsafe { &(*node.as_ptr()).elem })
+    }
+
+    pub fn back_mut(&mut self) -> Option<&mut T> {
+        self.back.map(|node| unsafe { &mut (*node.as_ptr()).elem })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // See mem_usage.rs.
+    pub fn mem_usage(&self) -> MemUsage {
+        MemUsage::nodes::<Node<T>, T>(self.len, 1)
+    }
+
+    // The invariants at the top of this file, checked by walking the list. See validate.rs.
+    pub fn validate(&self) -> Result<(), Invalid> {
+        validate::walk_doubly(self.front, self.back, self.len, |node| unsafe {
+            let node = node.as_ref();
+            (link(node.front), node.back)
+        })
+    }
+
+    pub fn clear(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+
+    pub fn contains(&self, elem: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.iter().any(|x| x == elem)
+    }
+
+    // The edits that turn this list into `other`, front to back. See diff.rs.
+    pub fn diff<'a>(&'a self, other: &'a List<T>) -> Vec<Edit<'a, T>>
+    where
+        T: PartialEq,
+    {
+        diff::diff(self.iter(), other.iter())
+    }
+
+    // O(1): relink other's front after our back.
+    pub fn append(&mut self, other: &mut List<T>) {
+        let (Some(other_front), Some(other_back)) = (other.front.take(), other.back.take()) else {
+            return;
+        };
+        unsafe {
+            match self.back {
+                Some(back) => {
+                    (*back.as_ptr()).back = Some(other_front);
+                    set_front(other_front, Some(back));
+                }
+                None => self.front = Some(other_front),
+            }
+        }
+        self.back = Some(other_back);
+        self.len += std::mem::take(&mut other.len);
+        validate::check!(self);
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            front: self.front,
+            back: self.back,
+            len: self.len,
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            front: self.front,
+            back: self.back,
+            len: self.len,
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, T> {
+        CursorMut {
+            list: self,
+            cur: None,
+            index: None,
+        }
+    }
+}
+
+// Carrying on past an element whose Drop panics: see panic_safety.rs.
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        panic_safety::pop_all(self, |list| list.pop_front().is_some());
+    }
+}
+
+/* ----------------------------- iterators ----------------------------- */
+
+// Iter and IterMut walk in from both ends at once and use `len` to know when they meet, so they
+// never hand out the same element twice.
+pub struct Iter<'a, T> {
+    front: Link<T>,
+    back: Link<T>,
+    len: usize,
+    _boo: PhantomData<&'a T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.front.map(|node| unsafe {
+            self.len -= 1;
+            self.front = (*node.as_ptr()).back;
+            &(*node.as_ptr()).elem
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.back.map(|node| unsafe {
+            self.len -= 1;
+            self.back = link((*node.as_ptr()).front);
+            &(*node.as_ptr()).elem
+        })
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<T> Clone for Iter<'_, T> {
+    fn clone(&self) -> Self {
+        Iter { ..*self }
+    }
+}
+
+pub struct IterMut<'a, T> {
+    front: Link<T>,
+    back: Link<T>,
+    len: usize,
+    _boo: PhantomData<&'a mut T>,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.front.map(|node| unsafe {
+            self.len -= 1;
+            self.front = (*node.as_ptr()).back;
+            &mut (*node.as_ptr()).elem
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for IterMut<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.back.map(|node| unsafe {
+            self.len -= 1;
+            self.back = link((*node.as_ptr()).front);
+            &mut (*node.as_ptr()).elem
+        })
+    }
+}
+
+impl<T> ExactSizeIterator for IterMut<'_, T> {}
+
+pub struct IntoIter<T> {
+    list: List<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.list.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.list.len, Some(self.list.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.list.pop_back()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { list: self }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut List<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+/* ------------------- the crate's own traits (traits.rs) ------------------- */
+
+impl<T> Collection for List<T> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+// A stack at the front, and a queue from back to front.
+impl<T> Stack<T> for List<T> {
+    fn push(&mut self, elem: T) {
+        self.push_front(elem)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        self.pop_front()
+    }
+
+    fn peek(&self) -> Option<impl Deref<Target = T> + '_> {
+        self.front()
+    }
+}
+
+impl<T> Queue<T> for List<T> {
+    fn push(&mut self, elem: T) {
+        self.push_back(elem)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        self.pop_front()
+    }
+
+    fn peek(&self) -> Option<impl Deref<Target = T> + '_> {
+        self.front()
+    }
+}
+
+impl<T> Deque<T> for List<T> {
+    fn push_front(&mut self, elem: T) {
+        List::push_front(self, elem)
+    }
+
+    fn push_back(&mut self, elem: T) {
+        List::push_back(self, elem)
+    }
+
+    fn pop_front(&mut self) -> Option<T> {
+        List::pop_front(self)
+    }
+
+    fn pop_back(&mut self) -> Option<T> {
+        List::pop_back(self)
+    }
+
+    fn peek_front(&self) -> Option<impl Deref<Target = T> + '_> {
+        self.front()
+    }
+
+    fn peek_back(&self) -> Option<impl Deref<Target = T> + '_> {
+        self.back()
+    }
+}
+
+/* ------------------------- the usual std traits ------------------------- */
+
+// "[1, 2, 3]", front first. See format.rs.
+impl<T: Display> Display for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format::write_list(f, self.iter())
+    }
+}
+
+impl<T: FromStr> FromStr for List<T> {
+    type Err = ParseListError<T::Err>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        format::parse_list(s).map(List::from_iter)
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// From the other lists, front first: the top of a stack, the head of a persistent list, the
+// front of a queue.
+impl<T> From<generic_and_iterators::List<T>> for List<T> {
+    fn from(list: generic_and_iterators::List<T>) -> Self {
+        list.into_iter().collect()
+    }
+}
+
+impl<T, A: Allocator> From<unsafe_single_linked_queue::List<T, A>> for List<T> {
+    fn from(queue: unsafe_single_linked_queue::List<T, A>) -> Self {
+        queue.into_iter().collect()
+    }
+}
+
+impl<T> From<doubly_linked_deque::List<T>> for List<T> {
+    fn from(deque: doubly_linked_deque::List<T>) -> Self {
+        deque.into_iter().collect()
+    }
+}
+
+// The nodes may be shared, so the elements are cloned.
+impl<T: Clone> From<persistent_stack::List<T>> for List<T> {
+    fn from(list: persistent_stack::List<T>) -> Self {
+        list.iter().cloned().collect()
+    }
+}
+
+// And to and from std's, so code already using LinkedList or VecDeque can switch one piece at a
+// time. Extend takes them too, as it takes any IntoIterator.
+impl<T> From<LinkedList<T>> for List<T> {
+    fn from(list: LinkedList<T>) -> Self {
+        list.into_iter().collect()
+    }
+}
+
+impl<T> From<List<T>> for LinkedList<T> {
+    fn from(list: List<T>) -> Self {
+        list.into_iter().collect()
+    }
+}
+
+impl<T> From<VecDeque<T>> for List<T> {
+    fn from(deque: VecDeque<T>) -> Self {
+        deque.into_iter().collect()
+    }
+}
+
+impl<T> From<List<T>> for VecDeque<T> {
+    fn from(list: List<T>) -> Self {
+        // IntoIter is exact-size, so this allocates once.
+        list.into_iter().collect()
+    }
+}
+
+impl<T: Clone> Clone for List<T> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T> Extend<T> for List<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push_back(elem);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = List::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T: Debug> Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other)
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+impl<T: PartialOrd> PartialOrd for List<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other)
+    }
+}
+
+impl<T: Ord> Ord for List<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other)
+    }
+}
+
+impl<T: Hash> Hash for List<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        for item in self {
+            item.hash(state);
+        }
+    }
+}
+
+// Same reasoning as the unsafe queue: we own our nodes, so we're as thread-safe as T.
+unsafe impl<T: Send> Send for List<T> {}
+unsafe impl<T: Sync> Sync for List<T> {}
+
+unsafe impl<T: Sync> Send for Iter<'_, T> {}
+unsafe impl<T: Sync> Sync for Iter<'_, T> {}
+
+unsafe impl<T: Send> Send for IterMut<'_, T> {}
+unsafe impl<T: Sync> Sync for IterMut<'_, T> {}
+
+// And as Unpin as the queue: the elements don't move when the List does.
+impl<T> Unpin for List<T> {}
+
+/* ------------------------------- CursorMut ------------------------------- */
+
+/*
+A cursor points either at an element or at the "ghost" position between back and front:
+
+ghost -> front <-> ... <-> back -> ghost
+
+Moving next from the ghost goes to the front, moving next from the back goes to the ghost, and
+likewise backwards. `index` is None exactly when `cur` is the ghost.
+*/
+pub struct CursorMut<'a, T> {
+    list: &'a mut List<T>,
+    cur: Link<T>,
+    index: Option<usize>,
+}
+
+impl<T> CursorMut<'_, T> {
+    pub fn index(&self) -> Option<usize> {
+        self.index
+    }
+
+    pub fn move_next(&mut self) {
+        if let Some(cur) = self.cur {
+            unsafe {
+                self.cur = (*cur.as_ptr()).back;
+                if self.cur.is_some() {
+                    *self.index.as_mut().unwrap() += 1;
+                } else {
+                    self.index = None;
+                }
+            }
+        } else if !self.list.is_empty() {
+            self.cur = self.list.front;
+            self.index = Some(0);
+        }
+    }
+
+    pub fn move_prev(&mut self) {
+        if let Some(cur) = self.cur {
+            unsafe {
+                self.cur = link((*cur.as_ptr()).front);
+                if self.cur.is_some() {
+                    *self.index.as_mut().unwrap() -= 1;
+                } else {
+                    self.index = None;
+                }
+            }
+        } else if !self.list.is_empty() {
+            self.cur = self.list.back;
+            self.index = Some(self.list.len - 1);
+        }
+    }
+
+    pub fn current(&mut self) -> Option<&mut T> {
+        self.cur.map(|node| unsafe { &mut (*node.as_ptr()).elem })
+    }
+
+    pub fn peek_next(&mut self) -> Option<&mut T> {
+        unsafe {
+            let next = match self.cur {
+                Some(cur) => (*cur.as_ptr()).back,
+                None => self.list.front,
+            };
+            next.map(|node| &mut (*node.as_ptr()).elem)
+        }
+    }
+
+    pub fn peek_prev(&mut self) -> Option<&mut T> {
+        unsafe {
+            let prev = match self.cur {
+                Some(cur) => link((*cur.as_ptr()).front),
+                None => self.list.back,
+            };
+            prev.map(|node| &mut (*node.as_ptr()).elem)
+        }
+    }
+
+    // Insert right after the cursor (at the front if the cursor is on the ghost).
+    pub fn insert_after(&mut self, elem: T) {
+        let Some(cur) = self.cur else {
+            self.list.push_front(elem);
+            return;
+        };
+        unsafe {
+            let next = (*cur.as_ptr()).back;
+            let new = NonNull::new_unchecked(Box::into_raw(Box::new(Node {
+                front: tagged(Some(cur), 0),
+                back: next,
+                elem,
+            })));
+            (*cur.as_ptr()).back = Some(new);
+            match next {
+                Some(next) => set_front(next, Some(new)),
+                None => self.list.back = Some(new),
+            }
+        }
+        self.list.len += 1;
+        validate::check!(self.list);
+    }
+
+    // Insert right before the cursor (at the back if the cursor is on the ghost).
+    pub fn insert_before(&mut self, elem: T) {
+        let Some(cur) = self.cur else {
+            self.list.push_back(elem);
+            return;
+        };
+        unsafe {
+            let prev = link((*cur.as_ptr()).front);
+            let new = NonNull::new_unchecked(Box::into_raw(Box::new(Node {
+                front: tagged(prev, 0),
+                back: Some(cur),
+                elem,
+            })));
+            set_front(cur, Some(new));
+            match prev {
+                Some(prev) => (*prev.as_ptr()).back = Some(new),
+                None => self.list.front = Some(new),
+            }
+        }
+        self.list.len += 1;
+        *self.index.as_mut().unwrap() += 1;
+        validate::check!(self.list);
+    }
+
+    // Remove the current element and move to the next one (or the ghost).
+    pub fn remove_current(&mut self) -> Option<T> {
+        let cur = self.cur?;
+        let elem = unsafe {
+            let boxed = Box::from_raw(cur.as_ptr());
+            match link(boxed.front) {
+                Some(prev) => (*prev.as_ptr()).back = boxed.back,
+                None => self.list.front = boxed.back,
+            }
+            match boxed.back {
+                Some(next) => set_front(next, link(boxed.front)),
+                None => self.list.back = link(boxed.front),
+            }
+            self.list.len -= 1;
+            self.cur = boxed.back;
+            if self.cur.is_none() {
+                self.index = None;
+            }
+            boxed.elem
+        };
+        validate::check!(self.list);
+        Some(elem)
+    }
+
+    // Everything before the cursor becomes a new list; the cursor keeps the rest. On the ghost,
+    // that's the whole list.
+    pub fn split_before(&mut self) -> List<T> {
+        let Some(cur) = self.cur else {
+            return std::mem::take(self.list);
+        };
+        unsafe {
+            let index = self.index.unwrap();
+            let old_len = self.list.len;
+            let prev = link((*cur.as_ptr()).front);
+            set_front(cur, None);
+            if let Some(prev) = prev {
+                (*prev.as_ptr()).back = None;
+            }
+
+            let before = List {
+                front: prev.and(self.list.front),
+                back: prev,
+                len: index,
+                _boo: PhantomData,
+            };
+            self.list.front = Some(cur);
+            self.list.len = old_len - index;
+            self.index = Some(0);
+            validate::check!(self.list);
+            validate::check!(&before);
+            before
+        }
+    }
+
+    // Everything after the cursor becomes a new list; the cursor keeps the rest. On the ghost,
+    // that's the whole list.
+    pub fn split_after(&mut self) -> List<T> {
+        let Some(cur) = self.cur else {
+            return std::mem::take(self.list);
+        };
+        unsafe {
+            let index = self.index.unwrap();
+            let old_len = self.list.len;
+            let next = (*cur.as_ptr()).back.take();
+            if let Some(next) = next {
+                set_front(next, None);
+            }
+
+            let after = List {
+                front: next,
+                back: next.and(self.list.back),
+                len: old_len - index - 1,
+                _boo: PhantomData,
+            };
+            self.list.back = Some(cur);
+            self.list.len = index + 1;
+            validate::check!(self.list);
+            validate::check!(&after);
+            after
+        }
+    }
+
+    // Move all of `input` in before the cursor (at the back if on the ghost). O(1).
+    pub fn splice_before(&mut self, mut input: List<T>) {
+        let (Some(in_front), Some(in_back)) = (input.front.take(), input.back.take()) else {
+            return;
+        };
+        let in_len = std::mem::take(&mut input.len);
+        unsafe {
+            match self.cur {
+                Some(cur) => {
+                    match link((*cur.as_ptr()).front) {
+                        Some(prev) => {
+                            (*prev.as_ptr()).back = Some(in_front);
+                            set_front(in_front, Some(prev));
+                        }
+                        None => self.list.front = Some(in_front),
+                    }
+                    set_front(cur, Some(in_back));
+                    (*in_back.as_ptr()).back = Some(cur);
+                    *self.index.as_mut().unwrap() += in_len;
+                }
+                None => match self.list.back {
+                    Some(back) => {
+                        (*back.as_ptr()).back = Some(in_front);
+                        set_front(in_front, Some(back));
+                        self.list.back = Some(in_back);
+                    }
+                    None => {
+                        self.list.front = Some(in_front);
+                        self.list.back = Some(in_back);
+                    }
+                },
+            }
+        }
+        self.list.len += in_len;
+        validate::check!(self.list);
+    }
+
+    // Move all of `input` in after the cursor (at the front if on the ghost). O(1).
+    pub fn splice_after(&mut self, mut input: List<T>) {
+        let (Some(in_front), Some(in_back)) = (input.front.take(), input.back.take()) else {
+            return;
+        };
+        let in_len = std::mem::take(&mut input.len);
+        unsafe {
+            match self.cur {
+                Some(cur) => {
+                    match (*cur.as_ptr()).back {
+                        Some(next) => {
+                            set_front(next, Some(in_back));
+                            (*in_back.as_ptr()).back = Some(next);
+                        }
+                        None => self.list.back = Some(in_back),
+                    }
+                    (*cur.as_ptr()).back = Some(in_front);
+                    set_front(in_front, Some(cur));
+                }
+                None => match self.list.front {
+                    Some(front) => {
+                        set_front(front, Some(in_back));
+                        (*in_back.as_ptr()).back = Some(front);
+                        self.list.front = Some(in_front);
+                    }
+                    None => {
+                        self.list.front = Some(in_front);
+                        self.list.back = Some(in_back);
+                    }
+                },
+            }
+        }
+        self.list.len += in_len;
+        validate::check!(self.list);
+    }
+}
+
+unsafe impl<T: Send> Send for CursorMut<'_, T> {}
+unsafe impl<T: Sync> Sync for CursorMut<'_, T> {}
+
+/* --------------------------------- marks --------------------------------- */
+
+/*
+Every node carries a one-bit mark, for algorithms that make a pass over the list flagging elements
+and then deal with the flagged ones afterwards: mark-and-sweep, or "remove these, but not until
+I've looked at all of them". A cursor reads and sets the mark of the element it's on, and
+remove_marked() sweeps the marked elements out into a list of their own, relinking their nodes
+rather than reallocating them.
+
+The mark doesn't take a field. A node holds pointers, so it's pointer-aligned and the low bits of
+any pointer to one are always zero. The mark lives in the lowest bit of the node's `front` link, a
+tagged_ptr::TaggedPtr. A bool next to the links would cost a whole word per node once padded:
+
+Node { front, back, elem: u64 }           3 words
+Node { front, back, elem: u64, marked }   4 words
+
+The mark belongs to the node, so it stays with its element through cursor moves, splits and
+splices. New elements start out unmarked.
+*/
+
+impl<T> CursorMut<'_, T> {
+    // False on the ghost.
+    pub fn is_marked(&self) -> bool {
+        self.cur
+            .is_some_and(|cur| unsafe { (*cur.as_ptr()).front.tag() == MARKED })
+    }
+
+    // Does nothing on the ghost.
+    pub fn set_marked(&mut self, marked: bool) {
+        if let Some(cur) = self.cur {
+            unsafe {
+                let front = &mut (*cur.as_ptr()).front;
+                *front = front.with_tag(if marked { MARKED } else { 0 });
+            }
+        }
+    }
+}
+
+impl<T> List<T> {
+    // Move the marked elements, in order, into a new list, and unmark them. O(len), and no
+    // allocation: it's the same nodes, relinked.
+    pub fn remove_marked(&mut self) -> List<T> {
+        let mut marked = List::new();
+        let mut next = self.front;
+        while let Some(node) = next {
+            unsafe {
+                next = (*node.as_ptr()).back;
+                let front = (*node.as_ptr()).front;
+                if front.tag() != MARKED {
+                    continue;
+                }
+
+                let prev = link(front);
+                match prev {
+                    Some(prev) => (*prev.as_ptr()).back = next,
+                    None => self.front = next,
+                }
+                match next {
+                    Some(next) => set_front(next, prev),
+                    None => self.back = prev,
+                }
+                self.len -= 1;
+
+                (*node.as_ptr()).front = tagged(marked.back, 0);
+                (*node.as_ptr()).back = None;
+                match marked.back {
+                    Some(back) => (*back.as_ptr()).back = Some(node),
+                    None => marked.front = Some(node),
+                }
+                marked.back = Some(node);
+                marked.len += 1;
+            }
+        }
+        validate::check!(self);
+        validate::check!(&marked);
+        marked
+    }
+}
+
+/* -------------------------- versioned snapshots -------------------------- */
+
+/*
+Versioned wraps a deque and lets you save its contents as numbered versions and roll back to any
+of them later, e.g. to try something speculatively and undo it if it doesn't pan out. In between
+it's just the deque (it derefs to List), so every operation, cursors included, works as usual.
+
+Snapshots are stored as persistent lists (persistent_stack.rs), front first. Copying the whole
+deque every time would cost O(len) memory per version; instead each new snapshot reuses the
+longest run at the back that hasn't changed since the previous one, and only copies the front
+part in front of it:
+
+v0:          [c] -> [d] -> [e]
+v1: [a] -> [b] --^                  after two push_front's, v1 copies only a and b
+
+So a deque that's mostly used from the front (a stack, or a queue with pushes at the front)
+pays for what changed, not for its length. Taking a snapshot still walks the deque once to
+find that shared run.
+
+A Version is only an index into the Versioned that handed it out, so it also records which one
+that was. Given another's, restore() and version() return ForeignVersion rather than whatever
+happens to be at that index here.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    owner: usize,
+    index: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForeignVersion;
+
+impl Display for ForeignVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("version was taken by a different Versioned")
+    }
+}
+
+impl std::error::Error for ForeignVersion {}
+
+pub struct Versioned<T> {
+    list: List<T>,
+    versions: Vec<crate::persistent_stack::List<T>>,
+    // Unique to this Versioned, stamped on every Version it hands out.
+    id: usize,
+}
+
+impl<T: Clone + PartialEq> Versioned<T> {
+    pub fn new(list: List<T>) -> Self {
+        static NEXT_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        Versioned {
+            list,
+            versions: Vec::new(),
+            id: NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+        }
+    }
+
+    pub fn snapshot(&mut self) -> Version {
+        let empty = crate::persistent_stack::List::new();
+        let previous = self.versions.last().unwrap_or(&empty);
+        let previous_elems: Vec<&T> = previous.iter().collect();
+        let unchanged = self
+            .list
+            .iter()
+            .rev()
+            .zip(previous_elems.iter().rev())
+            .take_while(|(now, before)| now == *before)
+            .count();
+
+        let shared = previous.nth_tail(previous_elems.len() - unchanged);
+        let changed = self.list.iter().take(self.list.len - unchanged).cloned();
+        self.versions.push(shared.cons_all(changed));
+        Version {
+            owner: self.id,
+            index: self.versions.len() - 1,
+        }
+    }
+
+    // Put the deque back the way it was at `version`. Versions taken after it are kept, so you
+    // can roll forward again too.
+    pub fn restore(&mut self, version: Version) -> Result<(), ForeignVersion> {
+        self.list = self.get(version)?.iter().cloned().collect();
+        Ok(())
+    }
+
+    // The contents at `version`, front first, without touching the deque.
+    pub fn version(&self, version: Version) -> Result<impl Iterator<Item = &T>, ForeignVersion> {
+        Ok(self.get(version)?.iter())
+    }
+
+    fn get(&self, version: Version) -> Result<&crate::persistent_stack::List<T>, ForeignVersion> {
+        if version.owner != self.id {
+            return Err(ForeignVersion);
+        }
+        Ok(&self.versions[version.index])
+    }
+
+    pub fn versions(&self) -> usize {
+        self.versions.len()
+    }
+
+    pub fn into_inner(self) -> List<T> {
+        self.list
+    }
+}
+
+impl<T> std::ops::Deref for Versioned<T> {
+    type Target = List<T>;
+
+    fn deref(&self) -> &List<T> {
+        &self.list
+    }
+}
+
+impl<T> std::ops::DerefMut for Versioned<T> {
+    fn deref_mut(&mut self) -> &mut List<T> {
+        &mut self.list
+    }
+}
+
+// With the `serde` feature: a sequence, front first, the same as a Vec or VecDeque. Deserializing
+// is push_back in a loop.
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::List;
+    use serde::de::{SeqAccess, Visitor};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::fmt;
+    use std::marker::PhantomData;
+
+    impl<T: Serialize> Serialize for List<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(self)
+        }
+    }
+
+    struct ListVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for ListVisitor<T> {
+        type Value = List<T>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a sequence")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<List<T>, A::Error> {
+            let mut list = List::new();
+            while let Some(elem) = seq.next_element()? {
+                list.push_back(elem);
+            }
+            Ok(list)
+        }
+    }
+
+    impl<'de, T: Deserialize<'de>> Deserialize<'de> for List<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_seq(ListVisitor(PhantomData))
+        }
+    }
+}
+
+// With the `rkyv` feature: archived as an ArchivedVec of the elements, front first, readable in
+// place. Deserializing pushes them onto the back of a new deque.
+#[cfg(feature = "rkyv")]
+mod rkyv_impl {
+    use super::List;
+    use rkyv::rancor::Fallible;
+    use rkyv::ser::{Allocator, Writer};
+    use rkyv::vec::{ArchivedVec, VecResolver};
+    use rkyv::{Archive, Deserialize, Place, Serialize};
+
+    impl<T: Archive> Archive for List<T> {
+        type Archived = ArchivedVec<T::Archived>;
+        type Resolver = VecResolver;
+
+        fn resolve(&self, resolver: VecResolver, out: Place<Self::Archived>) {
+            ArchivedVec::resolve_from_len(self.len(), resolver, out);
+        }
+    }
+
+    impl<T, S> Serialize<S> for List<T>
+    where
+        T: Serialize<S>,
+        S: Fallible + Allocator + Writer + ?Sized,
+    {
+        fn serialize(&self, serializer: &mut S) -> Result<VecResolver, S::Error> {
+            ArchivedVec::serialize_from_iter::<T, _, _>(self.iter(), serializer)
+        }
+    }
+
+    impl<T, D> Deserialize<List<T>, D> for ArchivedVec<T::Archived>
+    where
+        T: Archive,
+        T::Archived: Deserialize<T, D>,
+        D: Fallible + ?Sized,
+    {
+        fn deserialize(&self, deserializer: &mut D) -> Result<List<T>, D::Error> {
+            let mut list = List::new();
+            for elem in self.iter() {
+                list.push_back(elem.deserialize(deserializer)?);
+            }
+            Ok(list)
+        }
+    }
+}
+
+// With the `arbitrary` feature: a deque built by a run of operations the fuzzer's bytes choose
+// (pushes and pops at both ends, inserts and removals through a cursor, and rotations that split
+// the deque and splice it back together), so the nodes have been relinked every which way, not
+// just pushed in order. Each step starts with a bool, false once the bytes run out.
+#[cfg(feature = "arbitrary")]
+mod arbitrary_impl {
+    use super::List;
+    use arbitrary::{Arbitrary, Result, Unstructured};
+
+    impl<'a, T: Arbitrary<'a>> Arbitrary<'a> for List<T> {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            let mut list = List::new();
+            while u.arbitrary()? {
+                let op = u.int_in_range(0..=6u8)?;
+                match op {
+                    0 => list.push_front(T::arbitrary(u)?),
+                    1 => list.push_back(T::arbitrary(u)?),
+                    2 => drop(list.pop_front()),
+                    3 => drop(list.pop_back()),
+                    _ if list.is_empty() => {}
+                    _ => {
+                        let at = u.choose_index(list.len())?;
+                        let mut cursor = list.cursor_mut();
+                        for _ in 0..=at {
+                            cursor.move_next();
+                        }
+                        match op {
+                            4 => cursor.insert_before(T::arbitrary(u)?),
+                            5 => drop(cursor.remove_current()),
+                            _ => {
+                                // Rotate: the front part goes round to the back.
+                                let mut front = cursor.split_before();
+                                list.append(&mut front);
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(list)
+        }
+    }
+}
+
+// Proofs for Kani, a model checker: `cargo kani --features verification`. Each one covers every
+// sequence of operations up to MAX long, which Kani checks for undefined behaviour (dangling or
+// freed pointers, double frees, leaks) as well as for the asserts. See the unsafe queue's for
+// why a small bound is enough.
+//
+// The deque always holds a run of consecutive numbers, pushed on at either end, so two counters
+// are a complete model of it. Every check reads it from both ends, so a `front` link that's wrong
+// is caught as surely as a `back` one.
+#[cfg(all(kani, feature = "verification"))]
+mod verification {
+    use super::List;
+
+    const MAX: i8 = 4;
+
+    // `list` holds exactly lo..hi.
+    fn holds(list: &List<i8>, lo: i8, hi: i8) {
+        assert_eq!(list.len(), (hi - lo) as usize);
+        assert_eq!(list.is_empty(), lo == hi);
+        assert_eq!(list.front(), (lo < hi).then_some(&lo));
+        assert_eq!(list.back(), (lo < hi).then_some(&(hi - 1)));
+        assert!(list.iter().copied().eq(lo..hi));
+        assert!(list.iter().rev().copied().eq((lo..hi).rev()));
+    }
+
+    // Every interleaving of up to MAX pushes and pops, at either end.
+    #[kani::proof]
+    #[kani::unwind(6)]
+    fn push_pop() {
+        let mut list = List::new();
+        let (mut lo, mut hi) = (0i8, 0i8);
+        for _ in 0..MAX {
+            match kani::any::<u8>() % 4 {
+                0 => {
+                    lo -= 1;
+                    list.push_front(lo);
+                }
+                1 => {
+                    list.push_back(hi);
+                    hi += 1;
+                }
+                2 => {
+                    let expected = (lo < hi).then_some(lo);
+                    assert_eq!(list.pop_front(), expected);
+                    lo += expected.is_some() as i8;
+                }
+                _ => {
+                    let expected = (lo < hi).then_some(hi - 1);
+                    assert_eq!(list.pop_back(), expected);
+                    hi -= expected.is_some() as i8;
+                }
+            }
+            holds(&list, lo, hi);
+        }
+    }
+
+    // Whatever the value, it comes back out unchanged, from either end.
+    #[kani::proof]
+    #[kani::unwind(3)]
+    fn any_value() {
+        let (x, y): (u64, u64) = (kani::any(), kani::any());
+        let mut list = List::new();
+        list.push_back(x);
+        list.push_front(y);
+        assert_eq!(list.pop_back(), Some(x));
+        assert_eq!(list.pop_back(), Some(y));
+        assert!(list.is_empty());
+    }
+
+    // Two deques of up to MAX / 2 elements each, either of them possibly empty.
+    #[kani::proof]
+    #[kani::unwind(6)]
+    fn append() {
+        let a_len: i8 = kani::any_where(|&n| (0..=MAX / 2).contains(&n));
+        let b_len: i8 = kani::any_where(|&n| (0..=MAX / 2).contains(&n));
+        let mut a = List::new();
+        let mut b = List::new();
+        for x in 0..a_len {
+            a.push_back(x);
+        }
+        for x in a_len..a_len + b_len {
+            b.push_back(x);
+        }
+
+        a.append(&mut b);
+        holds(&a, 0, a_len + b_len);
+        holds(&b, 0, 0);
+        b.push_front(100);
+        assert_eq!(b.pop_back(), Some(100));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{set_front, ForeignVersion, IntoIter, Iter, IterMut, List, Node, Versioned};
+    use crate::counting_alloc::Counts;
+    use crate::validate::Invalid;
+
+    fn list_from<T: Clone>(v: &[T]) -> List<T> {
+        v.iter().cloned().collect()
+    }
+
+    // Walk the links in both directions and check them against each other and `len`.
+    fn check_links<T: Eq + std::fmt::Debug>(list: &List<T>) {
+        let from_front: Vec<_> = list.iter().collect();
+        let mut from_back: Vec<_> = list.iter().rev().collect();
+        from_back.reverse();
+        assert_eq!(from_front, from_back);
+        assert_eq!(from_front.len(), list.len());
+        assert_eq!(list.validate(), Ok(()));
+    }
+
+    #[test]
+    fn basics_front() {
+        let mut list = List::new();
+
+        // Try to break an empty list
+        assert_eq!(list.len(), 0);
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.len(), 0);
+
+        // Try to break a one item list
+        list.push_front(10);
+        assert_eq!(list.len(), 1);
+        assert_eq!(list.pop_front(), Some(10));
+        assert_eq!(list.len(), 0);
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.len(), 0);
+
+        // Mess around
+        list.push_front(10);
+        assert_eq!(list.len(), 1);
+        list.push_front(20);
+        assert_eq!(list.len(), 2);
+        list.push_front(30);
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.pop_front(), Some(30));
+        assert_eq!(list.len(), 2);
+        list.push_front(40);
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.pop_front(), Some(40));
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.pop_front(), Some(20));
+        assert_eq!(list.len(), 1);
+        assert_eq!(list.pop_front(), Some(10));
+        assert_eq!(list.len(), 0);
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.len(), 0);
+    }
+
+    #[test]
+    fn basics_back() {
+        let mut list = List::new();
+        assert_eq!(list.pop_back(), None);
+
+        list.push_back(1);
+        list.push_back(2);
+        list.push_front(0);
+        check_links(&list);
+        assert_eq!(list.front(), Some(&0));
+        assert_eq!(list.back(), Some(&2));
+
+        *list.front_mut().unwrap() = 10;
+        *list.back_mut().unwrap() = 20;
+        assert_eq!(list.pop_back(), Some(20));
+        assert_eq!(list.pop_back(), Some(1));
+        assert_eq!(list.pop_back(), Some(10));
+        assert_eq!(list.pop_back(), None);
+        assert_eq!(list.front(), None);
+        assert_eq!(list.back(), None);
+    }
+
+    #[test]
+    fn iterators() {
+        let mut list = list_from(&[0, 1, 2, 3, 4, 5, 6]);
+
+        let mut iter = list.iter();
+        assert_eq!(iter.len(), 7);
+        assert_eq!(iter.next(), Some(&0));
+        assert_eq!(iter.next_back(), Some(&6));
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.clone().count(), 5);
+        assert_eq!(iter.rev().collect::<Vec<_>>(), vec![&5, &4, &3, &2, &1]);
+
+        for x in list.iter_mut().rev() {
+            *x *= 10;
+        }
+        let mut iter = list.iter_mut();
+        assert_eq!(iter.next(), Some(&mut 0));
+        assert_eq!(iter.next_back(), Some(&mut 60));
+
+        let mut iter = list.into_iter();
+        assert_eq!(iter.len(), 7);
+        assert_eq!(iter.next_back(), Some(60));
+        assert_eq!(iter.next(), Some(0));
+        assert_eq!(iter.collect::<Vec<_>>(), vec![10, 20, 30, 40, 50]);
+
+        let empty: List<i32> = List::new();
+        assert_eq!(empty.iter().next(), None);
+        assert_eq!(empty.iter().next_back(), None);
+    }
+
+    #[test]
+    fn traits() {
+        let list = list_from(&[1, 2, 3]);
+        let copy = list.clone();
+        assert_eq!(list, copy);
+        assert_ne!(list, list_from(&[1, 2]));
+        assert!(list < list_from(&[1, 2, 4]));
+        assert!(list > list_from(&[1, 2]));
+        assert_eq!(format!("{:?}", list), "[1, 2, 3]");
+
+        let mut extended = List::new();
+        extended.extend(vec![1, 2]);
+        extended.extend(Some(3));
+        assert_eq!(extended, list);
+        assert!(list.contains(&2));
+        assert!(!list.contains(&5));
+
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+        let hash = |l: &List<i32>| {
+            let mut h = DefaultHasher::new();
+            l.hash(&mut h);
+            h.finish()
+        };
+        assert_eq!(hash(&list), hash(&copy));
+
+        let mut cleared = copy;
+        cleared.clear();
+        assert!(cleared.is_empty());
+        check_links(&cleared);
+    }
+
+    #[test]
+    fn append() {
+        let mut a = list_from(&[1, 2]);
+        let mut b = list_from(&[3, 4]);
+        a.append(&mut b);
+        check_links(&a);
+        check_links(&b);
+        assert_eq!(a, list_from(&[1, 2, 3, 4]));
+        assert!(b.is_empty());
+
+        b.append(&mut a);
+        check_links(&b);
+        assert_eq!(b.len(), 4);
+        b.append(&mut a);
+        assert_eq!(b.len(), 4);
+    }
+
+    // Break each invariant by hand, and put it back before the list is dropped.
+    #[test]
+    fn validate() {
+        let mut list = list_from(&[1, 2, 3]);
+        list.len = 2;
+        assert_eq!(
+            list.validate(),
+            Err(Invalid::Len {
+                stored: 2,
+                counted: 3
+            })
+        );
+        list.len = 3;
+
+        let (front, back) = (list.front.unwrap(), list.back.unwrap());
+        list.back = list.front;
+        assert_eq!(list.validate(), Err(Invalid::Ends));
+        list.back = Some(back);
+
+        unsafe {
+            set_front(back, Some(front));
+            assert_eq!(list.validate(), Err(Invalid::Link { at: 2 }));
+            set_front(back, (*front.as_ptr()).back);
+        }
+        assert_eq!(list.validate(), Ok(()));
+    }
+
+    #[test]
+    fn cursor_move_peek() {
+        let mut m = list_from(&[1, 2, 3, 4, 5, 6]);
+        let mut cursor = m.cursor_mut();
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&mut 1));
+        assert_eq!(cursor.peek_next(), Some(&mut 2));
+        assert_eq!(cursor.peek_prev(), None);
+        assert_eq!(cursor.index(), Some(0));
+        cursor.move_prev();
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.peek_next(), Some(&mut 1));
+        assert_eq!(cursor.peek_prev(), Some(&mut 6));
+        assert_eq!(cursor.index(), None);
+        cursor.move_next();
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&mut 2));
+        assert_eq!(cursor.peek_next(), Some(&mut 3));
+        assert_eq!(cursor.peek_prev(), Some(&mut 1));
+        assert_eq!(cursor.index(), Some(1));
+
+        let mut cursor = m.cursor_mut();
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&mut 6));
+        assert_eq!(cursor.peek_next(), None);
+        assert_eq!(cursor.peek_prev(), Some(&mut 5));
+        assert_eq!(cursor.index(), Some(5));
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.peek_next(), Some(&mut 1));
+        assert_eq!(cursor.peek_prev(), Some(&mut 6));
+        assert_eq!(cursor.index(), None);
+        cursor.move_prev();
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&mut 5));
+        assert_eq!(cursor.peek_next(), Some(&mut 6));
+        assert_eq!(cursor.peek_prev(), Some(&mut 4));
+        assert_eq!(cursor.index(), Some(4));
+    }
+
+    #[test]
+    fn cursor_mut_insert_remove() {
+        let mut m = list_from(&[1, 2, 3, 4, 5, 6]);
+        let mut cursor = m.cursor_mut();
+        cursor.move_next();
+        cursor.splice_before(Some(7).into_iter().collect());
+        cursor.splice_after(Some(8).into_iter().collect());
+        check_links(&m);
+        assert_eq!(
+            m.iter().cloned().collect::<Vec<_>>(),
+            &[7, 1, 8, 2, 3, 4, 5, 6]
+        );
+
+        let mut cursor = m.cursor_mut();
+        cursor.move_next();
+        cursor.move_prev();
+        cursor.splice_before(Some(9).into_iter().collect());
+        cursor.splice_after(Some(10).into_iter().collect());
+        check_links(&m);
+        assert_eq!(
+            m.iter().cloned().collect::<Vec<_>>(),
+            &[10, 7, 1, 8, 2, 3, 4, 5, 6, 9]
+        );
+
+        let mut cursor = m.cursor_mut();
+        cursor.move_next();
+        cursor.move_prev();
+        assert_eq!(cursor.remove_current(), None);
+        cursor.move_next();
+        cursor.move_next();
+        assert_eq!(cursor.remove_current(), Some(7));
+        cursor.move_prev();
+        cursor.move_prev();
+        cursor.move_prev();
+        assert_eq!(cursor.remove_current(), Some(9));
+        cursor.move_next();
+        assert_eq!(cursor.remove_current(), Some(10));
+        check_links(&m);
+        assert_eq!(
+            m.iter().cloned().collect::<Vec<_>>(),
+            &[1, 8, 2, 3, 4, 5, 6]
+        );
+
+        let mut cursor = m.cursor_mut();
+        cursor.move_next();
+        cursor.insert_before(0);
+        assert_eq!(cursor.index(), Some(1));
+        cursor.insert_after(11);
+        cursor.move_prev();
+        cursor.move_prev();
+        cursor.insert_after(-1);
+        cursor.insert_before(99);
+        check_links(&m);
+        assert_eq!(
+            m.iter().cloned().collect::<Vec<_>>(),
+            &[-1, 0, 1, 11, 8, 2, 3, 4, 5, 6, 99]
+        );
+
+        let mut m = list_from(&[1, 8, 2, 3, 4, 5, 6]);
+        let mut cursor = m.cursor_mut();
+        cursor.move_next();
+        let mut p: List<u32> = List::new();
+        p.extend([100, 101, 102, 103]);
+        let mut q: List<u32> = List::new();
+        q.extend([200, 201, 202, 203]);
+        cursor.splice_after(p);
+        cursor.splice_before(q);
+        check_links(&m);
+        assert_eq!(
+            m.iter().cloned().collect::<Vec<_>>(),
+            &[200, 201, 202, 203, 1, 100, 101, 102, 103, 8, 2, 3, 4, 5, 6]
+        );
+
+        let mut cursor = m.cursor_mut();
+        cursor.move_next();
+        cursor.move_prev();
+        let tmp = cursor.split_before();
+        assert_eq!(m.into_iter().collect::<Vec<u32>>(), &[] as &[u32]);
+        let mut m = tmp;
+        let mut cursor = m.cursor_mut();
+        for _ in 0..7 {
+            cursor.move_next();
+        }
+        assert_eq!(cursor.current(), Some(&mut 101));
+        let tmp = cursor.split_after();
+        assert_eq!(
+            tmp.into_iter().collect::<Vec<_>>(),
+            &[102, 103, 8, 2, 3, 4, 5, 6]
+        );
+        check_links(&m);
+        assert_eq!(
+            m.iter().cloned().collect::<Vec<_>>(),
+            &[200, 201, 202, 203, 1, 100, 101]
+        );
+
+        let mut cursor = m.cursor_mut();
+        cursor.move_next();
+        cursor.move_next();
+        let before = cursor.split_before();
+        assert_eq!(cursor.index(), Some(0));
+        check_links(&before);
+        check_links(&m);
+        assert_eq!(before.into_iter().collect::<Vec<_>>(), &[200]);
+        assert_eq!(
+            m.into_iter().collect::<Vec<_>>(),
+            &[201, 202, 203, 1, 100, 101]
+        );
+    }
+
+    #[test]
+    fn allocations() {
+        let counts = Counts::start();
+        let mut list = list_from(&[1, 2, 3]);
+        let mut other = list_from(&[4]);
+        assert_eq!((counts.allocs(), counts.deallocs()), (4, 0));
+
+        // Relinking: no node allocated or freed, and the iterators don't allocate either.
+        let counts = Counts::start();
+        list.append(&mut other);
+        let mut cursor = list.cursor_mut();
+        cursor.move_next();
+        cursor.move_next();
+        let front = cursor.split_before();
+        cursor.splice_after(front);
+        assert!(list.iter().eq(&[2, 1, 3, 4]));
+        assert_eq!((counts.allocs(), counts.deallocs()), (0, 0));
+
+        let counts = Counts::start();
+        assert_eq!(list.pop_front(), Some(2));
+        list.push_back(5);
+        drop(list);
+        assert_eq!((counts.allocs(), counts.deallocs()), (1, 5));
+    }
+
+    #[test]
+    fn send_sync() {
+        let list = list_from(&[1, 2, 3]);
+        let list = std::thread::spawn(move || {
+            let mut list = list;
+            list.push_back(4);
+            list
+        })
+        .join()
+        .unwrap();
+        std::thread::scope(|s| {
+            s.spawn(|| assert_eq!(list.iter().sum::<i32>(), 10));
+            s.spawn(|| assert_eq!(list.len(), 4));
+        });
+    }
+
+    #[test]
+    fn std_interop() {
+        use std::collections::{LinkedList, VecDeque};
+
+        let mut list = List::from(VecDeque::from([2, 3]));
+        list.push_front(1);
+        list.extend(LinkedList::from([4, 5]));
+        check_links(&list);
+        let std_list = LinkedList::from(list);
+        assert_eq!(std_list.back(), Some(&5));
+        let list = List::from(std_list);
+        assert_eq!(VecDeque::from(list), [1, 2, 3, 4, 5]);
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn arbitrary() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let bytes: Vec<u8> = (0..4096u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let mut u = Unstructured::new(&bytes);
+        while !u.is_empty() {
+            let list = List::<u16>::arbitrary(&mut u).unwrap();
+            // The links agree both ways and with the count.
+            assert_eq!(list.iter().count(), list.len());
+            assert!(list
+                .iter()
+                .rev()
+                .eq(list.iter().collect::<Vec<_>>().into_iter().rev()));
+            assert_eq!(list.front(), list.iter().next());
+            assert_eq!(list.back(), list.iter().next_back());
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let list = list_from(&["a", "b", "c"]);
+        let json = serde_json::to_string(&list).unwrap();
+        assert_eq!(json, serde_json::to_string(&vec!["a", "b", "c"]).unwrap());
+        let back: List<String> = serde_json::from_str(&json).unwrap();
+        check_links(&back);
+        assert_eq!(back, list_from(&["a".to_string(), "b".into(), "c".into()]));
+        assert!(serde_json::from_str::<List<i32>>("[1, \"two\"]").is_err());
+    }
+
+    #[allow(dead_code)]
+    fn assert_properties() {
+        fn is_send<T: Send>() {}
+        fn is_sync<T: Sync>() {}
+
+        is_send::<List<i32>>();
+        is_sync::<List<i32>>();
+        is_send::<IntoIter<i32>>();
+        is_sync::<IntoIter<i32>>();
+        is_send::<Iter<i32>>();
+        is_sync::<Iter<i32>>();
+        is_send::<IterMut<i32>>();
+        is_sync::<IterMut<i32>>();
+
+        fn linked_list_covariant<'a, T>(x: List<&'static T>) -> List<&'a T> {
+            x
+        }
+        fn iter_covariant<'i, 'a, T>(x: Iter<'i, &'static T>) -> Iter<'i, &'a T> {
+            x
+        }
+        fn into_iter_covariant<'a, T>(x: IntoIter<&'static T>) -> IntoIter<&'a T> {
+            x
+        }
+    }
+
+    #[test]
+    fn versioned() {
+        let mut deque = Versioned::new(List::from_iter([3, 4, 5]));
+        let v0 = deque.snapshot();
+
+        deque.push_front(2);
+        deque.push_front(1);
+        let v1 = deque.snapshot();
+
+        // Speculate, then change our mind.
+        deque.pop_back();
+        deque.cursor_mut().insert_after(0);
+        deque.restore(v1).unwrap();
+        assert_eq!(
+            deque.iter().copied().collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 5]
+        );
+
+        deque.restore(v0).unwrap();
+        assert_eq!(deque.iter().copied().collect::<Vec<_>>(), vec![3, 4, 5]);
+        // v1 is still there to go back to.
+        assert_eq!(deque.version(v1).unwrap().count(), 5);
+
+        // v1 was built on top of v0's nodes rather than copying them.
+        assert!(deque.versions[v0.index].is_suffix_of(&deque.versions[v1.index]));
+
+        deque.push_back(6);
+        let v2 = deque.snapshot();
+        assert_eq!(deque.versions(), 3);
+        assert_eq!(
+            deque.version(v2).unwrap().copied().collect::<Vec<_>>(),
+            vec![3, 4, 5, 6]
+        );
+        assert_eq!(deque.into_inner().len(), 4);
+    }
+
+    #[test]
+    fn marks() {
+        // The mark is in the front link's spare bit, not a field of its own.
+        assert_eq!(
+            std::mem::size_of::<Node<u64>>(),
+            3 * std::mem::size_of::<usize>()
+        );
+
+        let mut m = list_from(&[1, 2, 3, 4, 5, 6]);
+        let mut cursor = m.cursor_mut();
+        assert!(!cursor.is_marked());
+        cursor.set_marked(true);
+        assert!(!cursor.is_marked());
+        // Mark the even ones.
+        for _ in 0..6 {
+            cursor.move_next();
+            let even = *cursor.current().unwrap() % 2 == 0;
+            cursor.set_marked(even);
+        }
+        // Relinking the neighbours leaves a node's mark alone.
+        cursor.move_prev();
+        cursor.insert_before(10);
+        cursor.insert_after(11);
+        assert_eq!(cursor.current(), Some(&mut 5));
+        assert!(!cursor.is_marked());
+        cursor.move_next();
+        assert!(!cursor.is_marked());
+        cursor.move_next();
+        assert!(cursor.is_marked());
+        check_links(&m);
+
+        // Marks go with their nodes through a split and a splice.
+        let mut cursor = m.cursor_mut();
+        cursor.move_next();
+        cursor.move_next();
+        let front = cursor.split_before();
+        cursor.move_prev();
+        cursor.splice_after(front);
+        assert_eq!(
+            m.iter().copied().collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 10, 5, 11, 6]
+        );
+        check_links(&m);
+
+        let marked = m.remove_marked();
+        assert_eq!(marked.iter().copied().collect::<Vec<_>>(), vec![2, 4, 6]);
+        assert_eq!(m.iter().copied().collect::<Vec<_>>(), vec![1, 3, 10, 5, 11]);
+        check_links(&m);
+        check_links(&marked);
+        assert_eq!(m.validate(), Ok(()));
+        assert_eq!(marked.validate(), Ok(()));
+
+        // Swept elements come out unmarked, so a second sweep finds nothing.
+        let mut marked = marked;
+        assert!(marked.remove_marked().is_empty());
+        assert_eq!(marked.len(), 3);
+
+        // Marked at both ends, and every element.
+        let mut m = list_from(&[1, 2, 3]);
+        let mut cursor = m.cursor_mut();
+        for _ in 0..3 {
+            cursor.move_next();
+            cursor.set_marked(true);
+        }
+        assert_eq!(m.remove_marked().len(), 3);
+        assert!(m.is_empty());
+        assert_eq!(m.validate(), Ok(()));
+    }
+
+    #[test]
+    fn foreign_version() {
+        let mut one = Versioned::new(List::from_iter([1, 2]));
+        let mut other = Versioned::new(List::from_iter([7, 8, 9]));
+        let theirs = other.snapshot();
+        other.push_back(10);
+        other.snapshot();
+
+        // `one` has no versions at all: an unchecked index would panic.
+        assert_eq!(one.restore(theirs), Err(ForeignVersion));
+        assert!(one.version(theirs).is_err());
+
+        // Same index as `theirs`, so an unchecked lookup would silently hand back `mine`.
+        let mine = one.snapshot();
+        assert_ne!(mine, theirs);
+        one.push_back(3);
+        assert_eq!(one.restore(theirs), Err(ForeignVersion));
+        assert_eq!(one.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        other.restore(theirs).unwrap();
+        assert_eq!(other.iter().copied().collect::<Vec<_>>(), vec![7, 8, 9]);
+    }
+}
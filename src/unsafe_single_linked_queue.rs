@@ -1,23 +1,1894 @@
-use std::mem;
+/*
+ An unsafe singly linked queue
 
-pub struct List<T> {
+The safe version of this doesn't work: `tail` would need to point into a node that `head` (or the
+previous node's `next`) already owns through a Box, and Box is unique ownership. So we give up and
+manage the nodes ourselves with raw pointers:
+
+head -> A -> B -> C <- tail
+
+- push goes to the tail, pop comes off the head.
+- Nodes are allocated with Box::new and turned into raw pointers with Box::into_raw, then turned
+  back into a Box with Box::from_raw exactly once when they are popped.
+- Never mix raw pointers and references to the same node carelessly (see stacked_borrows.rs):
+  once a node is behind a raw pointer, we only ever go through that raw pointer.
+
+Why NonNull<Node<T>> rather than *mut Node<T>?
+
+- *mut T is invariant over T. That makes List<&'static str> unusable where a List<&'a str> is
+  expected, unlike every std collection. NonNull<T> is covariant, like Box<T>.
+- NonNull is never null, so Option<NonNull<_>> is the same size as a raw pointer (the "null
+  pointer niche" optimization).
+- Raw pointers don't tell the compiler that we own a T. PhantomData<T> does, so the drop checker
+  knows dropping a List<T> may drop Ts.
+
+Nodes come from an allocator::Allocator, Global unless the queue was made with new_in(alloc) (see
+allocator.rs). The operations that pass nodes between queues without copying them (split_off,
+pop_all, steal_half) give the new queue a clone of the allocator, which the Allocator contract
+says can free the same memory. append, leak and the raw parts are for Global queues only: two
+allocators of the same type aren't necessarily interchangeable, and a leaked node mustn't outlive
+an allocator the queue only borrowed.
+*/
+
+use crate::allocator::{Allocator, Global};
+use crate::diff::{self, Edit};
+use crate::format::{self, ParseListError};
+use crate::mem_usage::MemUsage;
+use crate::panic_safety;
+use crate::pointer_list::{BoxKind, Ptr};
+use crate::traits::{Collection, Queue};
+use crate::validate::{self, Invalid};
+use crate::{generic_and_iterators, persistent_stack, unsafe_doubly_linked_deque};
+use std::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use std::collections::{LinkedList, VecDeque};
+use std::fmt::{self, Display};
+use std::io;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::ptr::{self, NonNull};
+use std::slice;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+pub struct List<T, A: Allocator = Global> {
     head: Link<T>,
-    // Won't work. FIXME.
     tail: Link<T>,
+    len: usize,
+    // Upper bound on `len` enforced by try_push; None for an unbounded queue.
+    cap: Option<usize>,
+    // Where the nodes come from: None for one allocation per node from `alloc`, or a block pool
+    // shared with every queue split off from this one.
+    pool: Option<Arc<BlockPool<T>>>,
+    alloc: A,
+    _boo: PhantomData<T>,
 }
 
-type Link<T> = Option<Box<Node<T>>>;
+type Link<T> = Option<NonNull<Node<T>>>;
 
-struct Node<T> {
-    elem: T,
+// Public only so raw node pointers can appear in into_raw_parts/from_raw_parts; the fields stay
+// private.
+//
+// Laid out exactly like generic_and_iterators::Node (repr(C), `next` first, and its links are
+// Option<NonNull> too), so a chain of either is a chain of the other. See the
+// From impls below.
+#[repr(C)]
+pub struct Node<T> {
     next: Link<T>,
+    elem: T,
 }
 
 impl<T> List<T> {
     pub fn new() -> List<T> {
+        List::new_in(Global)
+    }
+
+    // A bounded queue: try_push refuses to grow it past `cap` elements, and push (and extend)
+    // panic. append doesn't check the bound, so producers that want backpressure should stick to
+    // try_push.
+    pub fn with_capacity(cap: usize) -> List<T> {
+        List::empty(Some(cap), Global)
+    }
+
+    // Nodes come from blocks of BLOCK_NODES and are recycled through a free list, so steady
+    // push/pop traffic rarely reaches the global allocator. See BlockPool below.
+    pub fn with_block_allocation() -> List<T> {
+        List {
+            pool: Some(Arc::new(BlockPool::new())),
+            ..List::empty(None, Global)
+        }
+    }
+}
+
+impl<T, A: Allocator> List<T, A> {
+    // An unbounded queue whose nodes come from `alloc`.
+    pub fn new_in(alloc: A) -> List<T, A> {
+        List::empty(None, alloc)
+    }
+
+    fn empty(cap: Option<usize>, alloc: A) -> List<T, A> {
         List {
             head: None,
             tail: None,
+            len: 0,
+            cap,
+            pool: None,
+            alloc,
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    // Allocate a node for `elem`, from the pool if this queue has one. Err(elem) if the allocator
+    // fails.
+    //
+    // Box::new aborts the process when the allocator fails (and Box::try_new is still unstable),
+    // so allocate the node by hand. With Global, memory from Layout::new::<Node<T>>() is exactly
+    // what Box would have allocated, so it can still be freed with Box::from_raw.
+    fn alloc_node(&self, elem: T) -> Result<NonNull<Node<T>>, T> {
+        let node = match &self.pool {
+            Some(pool) => pool.alloc(),
+            None => self
+                .alloc
+                .allocate(Layout::new::<Node<T>>())
+                .ok()
+                .map(NonNull::cast),
+        };
+        match node {
+            Some(node) => {
+                unsafe { node.as_ptr().write(Node { elem, next: None }) };
+                Ok(node)
+            }
+            None => Err(elem),
+        }
+    }
+
+    // Safety: `node` must be an unlinked node allocated by this queue's allocator.
+    unsafe fn free_node(&self, node: NonNull<Node<T>>) -> T {
+        match &self.pool {
+            Some(pool) => {
+                let elem = ptr::read(&(*node.as_ptr()).elem);
+                pool.release(node);
+                elem
+            }
+            None => {
+                let elem = ptr::read(&(*node.as_ptr()).elem);
+                self.alloc.deallocate(node.cast(), Layout::new::<Node<T>>());
+                elem
+            }
+        }
+    }
+
+    // Push unless the queue is full or the node can't be allocated; either way the element is
+    // handed back instead of panicking or aborting.
+    pub fn try_push(&mut self, elem: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(elem);
+        }
+        let node = self.alloc_node(elem)?;
+        unsafe { self.link_back(node) };
+        Ok(())
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.cap.is_some_and(|cap| self.len >= cap)
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.cap
+    }
+
+    // Panics if the queue is bounded and full; try_push hands the element back instead.
+    pub fn push(&mut self, elem: T) {
+        assert!(!self.is_full(), "queue is full");
+        self.push_past_cap(elem);
+    }
+
+    // push without the bound, for append.
+    fn push_past_cap(&mut self, elem: T) {
+        match self.alloc_node(elem) {
+            Ok(new_tail) => unsafe { self.link_back(new_tail) },
+            // Same as what Box::new does on failure.
+            Err(_) => handle_alloc_error(Layout::new::<Node<T>>()),
+        }
+    }
+
+    // Safety: `new_tail` must be a live node from this queue's allocator with `next == None` that
+    // no other list owns.
+    unsafe fn link_back(&mut self, new_tail: NonNull<Node<T>>) {
+        match self.tail {
+            Some(old_tail) => (*old_tail.as_ptr()).next = Some(new_tail),
+            None => self.head = Some(new_tail),
+        }
+        self.tail = Some(new_tail);
+        self.len += 1;
+        validate::check!(self);
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let elem = self.head.map(|head| unsafe {
+            self.head = (*head.as_ptr()).next;
+            self.len -= 1;
+
+            if self.head.is_none() {
+                // The queue is empty, so tail must not keep pointing at the freed node.
+                self.tail = None;
+            }
+            // Take ownership of the element back and free the node.
+            self.free_node(head)
+        });
+        validate::check!(self);
+        elem
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.head.map(|node| unsafe { &(*node.as_ptr()).elem })
+    }
+
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        self.head.map(|node| unsafe { &mut (*node.as_ptr()).elem })
+    }
+
+    // Update the front element in place (e.g. decrement a retry counter) instead of popping and
+    // re-pushing it. Returns whatever `f` returns, or None if the queue is empty.
+    pub fn map_front<R>(&mut self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        self.peek_mut().map(f)
+    }
+
+    // The most recently pushed element. `tail` is only Some while the queue is non-empty, and
+    // then it always points at a live node.
+    pub fn peek_back(&self) -> Option<&T> {
+        self.tail.map(|node| unsafe { &(*node.as_ptr()).elem })
+    }
+
+    pub fn peek_back_mut(&mut self) -> Option<&mut T> {
+        self.tail.map(|node| unsafe { &mut (*node.as_ptr()).elem })
+    }
+
+    // Front of the queue becomes index 0.
+    pub fn into_vec(mut self) -> Vec<T> {
+        let mut vec = Vec::with_capacity(self.len);
+        while let Some(elem) = self.pop() {
+            vec.push(elem);
+        }
+        vec
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // What the queue has allocated (see mem_usage.rs). A pooled queue counts its whole pool, free
+    // nodes included, even when other queues share it.
+    pub fn mem_usage(&self) -> MemUsage {
+        let usage = MemUsage::nodes::<Node<T>, T>(self.len, 1);
+        match &self.pool {
+            Some(pool) => MemUsage {
+                heap_bytes: pool.bytes(),
+                ..usage
+            },
+            None => usage,
+        }
+    }
+
+    // `len` nodes from head, the last of them tail. The bound isn't checked: push and append are
+    // allowed past it. See validate.rs.
+    pub fn validate(&self) -> Result<(), Invalid> {
+        let last = validate::walk(self.head, self.len, |node| unsafe { node.as_ref().next })?;
+        if last != self.tail {
+            return Err(Invalid::Ends);
+        }
+        Ok(())
+    }
+}
+
+impl<T, A: Allocator + Clone> List<T, A> {
+    // An empty queue with the same bound, node pool and allocator.
+    fn empty_like(&self) -> List<T, A> {
+        let mut list = List::empty(self.cap, self.alloc.clone());
+        list.pool = self.pool.clone();
+        list
+    }
+
+    // Keep the first `at` elements and return the rest as a new queue. Like Vec::split_off, this
+    // panics if `at > len`.
+    pub fn split_off(&mut self, at: usize) -> List<T, A> {
+        assert!(
+            at <= self.len,
+            "split_off index (is {at}) should be <= len (is {})",
+            self.len
+        );
+
+        if at == 0 {
+            return self.pop_all();
+        }
+        if at == self.len {
+            return self.empty_like();
+        }
+
+        unsafe {
+            // Walk to the last node we keep. 0 < at < len, so it exists and has a successor.
+            let mut last = self.head.unwrap();
+            for _ in 1..at {
+                last = (*last.as_ptr()).next.unwrap();
+            }
+
+            let mut rest = self.empty_like();
+            rest.head = (*last.as_ptr()).next.take();
+            rest.tail = self.tail;
+            rest.len = self.len - at;
+            self.tail = Some(last);
+            self.len = at;
+            validate::check!(self);
+            validate::check!(&rest);
+            rest
+        }
+    }
+
+    // Take the whole chain in O(1) (head, tail and len), leaving an empty queue with the same
+    // bound behind, so a consumer can grab everything at once and process it elsewhere.
+    pub fn pop_all(&mut self) -> List<T, A> {
+        let empty = self.empty_like();
+        std::mem::replace(self, empty)
+    }
+
+    // Work-stealing primitive: detach the back half (len / 2 elements, rounded down) into a new
+    // queue. The owner keeps the front, which it would have popped next anyway. No allocation;
+    // it walks to the middle and cuts the chain there.
+    pub fn steal_half(&mut self) -> List<T, A> {
+        self.split_off(self.len - self.len / 2)
+    }
+}
+
+impl<T> List<T> {
+    // Move all of `other` onto the end of `self` in O(1). This is what the tail pointer buys us:
+    // no walking either chain, just one relink. `other` is left empty (and still usable).
+    //
+    // Nodes have to go back to the allocator they came from, so if the two queues allocate
+    // differently (one uses with_block_allocation, or they have different pools) and `self` isn't
+    // empty, the elements are moved over one by one instead.
+    pub fn append(&mut self, other: &mut List<T>) {
+        if other.is_empty() {
+            return;
+        }
+        if !self.same_allocator(other) {
+            if !self.is_empty() {
+                while let Some(elem) = other.pop() {
+                    self.push_past_cap(elem);
+                }
+                return;
+            }
+            self.pool = other.pool.clone();
+        }
+
+        let Some(other_head) = other.head.take() else {
+            return;
+        };
+        match self.tail {
+            Some(tail) => unsafe { (*tail.as_ptr()).next = Some(other_head) },
+            None => self.head = Some(other_head),
+        }
+        self.tail = other.tail.take();
+        self.len += std::mem::take(&mut other.len);
+        validate::check!(self);
+    }
+
+    fn same_allocator(&self, other: &List<T>) -> bool {
+        match (&self.pool, &other.pool) {
+            (None, None) => true,
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    // Build the chain back to front: each new node just points at the previous head, so there is
+    // no tail to chase, and the very first node we create is the tail.
+    pub fn from_vec(vec: Vec<T>) -> List<T> {
+        let mut list = List::new();
+        for elem in vec.into_iter().rev() {
+            unsafe {
+                let node = NonNull::new_unchecked(Box::into_raw(Box::new(Node {
+                    elem,
+                    next: list.head,
+                })));
+                if list.tail.is_none() {
+                    list.tail = Some(node);
+                }
+                list.head = Some(node);
+                list.len += 1;
+            }
+        }
+        list
+    }
+
+    // Give up ownership of the chain without freeing it: (head, tail, len), with null pointers for
+    // an empty queue. Nodes are Box allocations linked through `next`, and the last one's `next`
+    // is None. Any bound set by with_capacity is not part of the raw parts.
+    //
+    // Pooled nodes can't be handed out as Box allocations, so a with_block_allocation queue is
+    // first copied into plain nodes (O(n)).
+    pub fn into_raw_parts(self) -> (*mut Node<T>, *mut Node<T>, usize) {
+        if self.pool.is_some() {
+            return List::from_vec(self.into_vec()).into_raw_parts();
+        }
+        let me = ManuallyDrop::new(self);
+        let raw = |link: Link<T>| link.map_or(ptr::null_mut(), NonNull::as_ptr);
+        (raw(me.head), raw(me.tail), me.len)
+    }
+
+    /// Take back a chain from into_raw_parts.
+    ///
+    /// # Safety
+    ///
+    /// The parts must come from `into_raw_parts` on a `List<T>` (same T), must not have been used
+    /// to build another List since, and the nodes must not have been modified or freed in between.
+    pub unsafe fn from_raw_parts(head: *mut Node<T>, tail: *mut Node<T>, len: usize) -> List<T> {
+        let mut list = List::new();
+        list.head = NonNull::new(head);
+        list.tail = NonNull::new(tail);
+        list.len = len;
+        validate::check!(&list);
+        list
+    }
+
+    // Like Vec::leak: forget the queue and hand out its elements for as long as you like. The
+    // nodes are never freed, which is the point for "build once, keep forever" data. (With block
+    // allocation the pool is leaked along with them.)
+    pub fn leak<'a>(self) -> IterMut<'a, T>
+    where
+        T: 'a,
+    {
+        let me = ManuallyDrop::new(self);
+        IterMut {
+            next: me.head,
+            len: me.len,
+            _boo: PhantomData,
+        }
+    }
+}
+
+// "[1, 2, 3]", front first. See format.rs.
+impl<T: Display, A: Allocator> Display for List<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format::write_list(f, self.iter())
+    }
+}
+
+impl<T: FromStr> FromStr for List<T> {
+    type Err = ParseListError<T::Err>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut list = List::new();
+        list.extend(format::parse_list(s)?);
+        Ok(list)
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/*
+ Conversions
+
+A generic_and_iterators::List is a chain of boxed nodes from the top down, and a Global queue
+without a pool is a chain of nodes from the front back, allocated just like a Box. The two Node
+types have the same layout, so converting between them reuses every node: a stack becomes a
+queue (top at the front) by walking to the end for the tail and the length, and a queue becomes
+a stack by handing over its head. Neither allocates or moves an element.
+
+Everything else (a pooled queue, a deque, a persistent list) moves or clones elements into new
+nodes, front first.
+*/
+
+impl<T> From<generic_and_iterators::List<T>> for List<T> {
+    fn from(mut list: generic_and_iterators::List<T>) -> Self {
+        let Some(head) = list.head.take() else {
+            return List::new();
+        };
+        // Safety: same layout (see Node), and a Box's memory is what a Global queue's nodes are
+        // made of. The `next` links are read as NonNull, never as Box, from here on.
+        unsafe {
+            let head = head.into_raw().as_ptr().cast::<Node<T>>();
+            let (mut tail, mut len) = (head, 1);
+            while let Some(next) = (*tail).next {
+                tail = next.as_ptr();
+                len += 1;
+            }
+            List::from_raw_parts(head, tail, len)
+        }
+    }
+}
+
+impl<T> From<List<T>> for generic_and_iterators::List<T> {
+    fn from(queue: List<T>) -> Self {
+        // into_raw_parts copies pooled nodes into plain ones first.
+        let (head, _, _) = queue.into_raw_parts();
+        generic_and_iterators::List {
+            // Safety: as above, the other way round. Only the head needs to become a Ptr; the
+            // links after it already are, bit for bit.
+            head: NonNull::new(head).map(|head| unsafe { Ptr::from_raw(head.cast()) }),
+            kind: BoxKind(Global),
+        }
+    }
+}
+
+impl<T> From<unsafe_doubly_linked_deque::List<T>> for List<T> {
+    fn from(deque: unsafe_doubly_linked_deque::List<T>) -> Self {
+        let mut queue = List::new();
+        queue.extend(deque);
+        queue
+    }
+}
+
+// The nodes may be shared, so the elements are cloned.
+impl<T: Clone> From<persistent_stack::List<T>> for List<T> {
+    fn from(list: persistent_stack::List<T>) -> Self {
+        let mut queue = List::new();
+        queue.extend(list.iter().cloned());
+        queue
+    }
+}
+
+// std's LinkedList and VecDeque, front to front, so code using them can switch one piece at a
+// time.
+impl<T> From<LinkedList<T>> for List<T> {
+    fn from(list: LinkedList<T>) -> Self {
+        let mut queue = List::new();
+        queue.extend(list);
+        queue
+    }
+}
+
+impl<T, A: Allocator> From<List<T, A>> for LinkedList<T> {
+    fn from(queue: List<T, A>) -> Self {
+        queue.into_iter().collect()
+    }
+}
+
+impl<T> From<VecDeque<T>> for List<T> {
+    fn from(deque: VecDeque<T>) -> Self {
+        // Free when the VecDeque's buffer doesn't wrap around, and one copy when it does.
+        List::from_vec(deque.into())
+    }
+}
+
+impl<T, A: Allocator> From<List<T, A>> for VecDeque<T> {
+    fn from(queue: List<T, A>) -> Self {
+        // IntoIter is exact-size, so this allocates once.
+        queue.into_iter().collect()
+    }
+}
+
+// Pushes onto the back like push, so it panics if a bounded queue fills up.
+impl<T, A: Allocator> Extend<T> for List<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+// The crate-wide traits (see traits.rs).
+impl<T, A: Allocator> Collection for List<T, A> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<T, A: Allocator> Queue<T> for List<T, A> {
+    fn push(&mut self, elem: T) {
+        List::push(self, elem)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        List::pop(self)
+    }
+
+    fn peek(&self) -> Option<impl Deref<Target = T> + '_> {
+        List::peek(self)
+    }
+}
+
+// Nothing is ever pinned through a List: the elements live in their own heap nodes, and moving
+// the List moves only the pointers to them. (PhantomData<T> alone would make it !Unpin for any
+// !Unpin T.)
+impl<T, A: Allocator + Unpin> Unpin for List<T, A> {}
+
+// With the `async` feature a queue can feed an async pipeline directly. There's never anything to
+// wait for, so the stream just hands out what's queued and ends once the queue is empty.
+#[cfg(feature = "async")]
+impl<T, A: Allocator + Unpin> futures_core::Stream for List<T, A> {
+    type Item = T;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<T>> {
+        std::task::Poll::Ready(self.get_mut().pop())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+/*
+ Byte pipes
+
+A queue of u8 is a pipe: write at the back, read from the front. Implementing std::io's Read and
+Write for it (as std does for VecDeque<u8>) lets it stand in wherever a stream is expected, an
+in-memory pipe for testing code that takes a reader or writer:
+
+    let mut pipe = List::new();
+    write!(pipe, "hello\n")?;
+    let mut line = String::new();
+    pipe.read_line(&mut line)?;
+
+- read pops up to buf.len() bytes and returns how many. An empty queue reads as end of file, 0.
+- write pushes the bytes and returns how many. A bounded queue (with_capacity) takes as many as
+  fit, like writing into a fixed-size slice: once it's full a write takes nothing, so write_all
+  fails with WriteZero rather than blocking. flush has nothing to do.
+- BufRead's buffer is the front node's byte, a slice of one, which is enough for read_line,
+  lines() and read_until to work a byte at a time.
+
+It's a node per byte, which is the price of a linked list, not a fast pipe: each byte costs an
+allocation and 16 bytes of node. VecDeque<u8> is the pipe to use when it only has to work.
+*/
+
+impl<A: Allocator> io::Read for List<u8, A> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+        for slot in buf {
+            let Some(byte) = self.pop() else { break };
+            *slot = byte;
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+impl<A: Allocator> io::BufRead for List<u8, A> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.peek().map_or(&[], slice::from_ref))
+    }
+
+    fn consume(&mut self, amt: usize) {
+        for _ in 0..amt {
+            self.pop();
+        }
+    }
+}
+
+impl<A: Allocator> io::Write for List<u8, A> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut n = 0;
+        for &byte in buf {
+            if self.try_push(byte).is_err() {
+                break;
+            }
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/*
+ Drop
+
+Free the nodes in a plain loop: pop each one, which unlinks it and gives the node back to its
+allocator, then drop its element. Nothing recurses, so arbitrarily long queues are fine. If an
+element's Drop panics, the loop carries on while the panic unwinds (panic_safety.rs), so the
+elements after it are still dropped and their nodes freed.
+
+std collections can also hold borrows that die in the same scope as the collection itself:
+
+    let mut list = List::new();
+    let x = 5;
+    list.push(&x); // `x` is dropped before `list`
+
+That's only allowed if the compiler knows our Drop won't *use* the T values (it just drops them).
+The way to promise that is `#[may_dangle]` (the "dropck eyepatch"), which is still nightly-only,
+so it sits behind the `nightly` feature. PhantomData<T> is what tells the compiler we still drop
+Ts, which the eyepatch needs to be sound.
+*/
+
+impl<T, A: Allocator> List<T, A> {
+    fn free_nodes(&mut self) {
+        panic_safety::pop_all(self, |list| list.pop().is_some());
+    }
+}
+
+#[cfg(feature = "nightly")]
+unsafe impl<#[may_dangle] T, A: Allocator> Drop for List<T, A> {
+    fn drop(&mut self) {
+        self.free_nodes();
+    }
+}
+
+#[cfg(not(feature = "nightly"))]
+impl<T, A: Allocator> Drop for List<T, A> {
+    fn drop(&mut self) {
+        self.free_nodes();
+    }
+}
+
+/*
+ Block allocation
+
+One malloc/free per push/pop adds up under sustained traffic. With block allocation, nodes are
+carved out of blocks of BLOCK_NODES nodes. Free nodes are threaded through their own `next`
+field into a free list (their `elem` is uninitialized while they sit there):
+
+free -> [ ] -> [ ] -> [ ] -> None
+
+- alloc pops a node off the free list, allocating a whole new block only when it is empty.
+- release pushes the node back. Blocks are never returned to the global allocator until the
+  pool itself goes away.
+
+Queues created by split_off/pop_all/steal_half keep using the same pool, so nodes can move
+between them freely. That's why the pool is behind an Arc (and a Mutex, since the queue is Send).
+The pool never touches a T, so it stores only type-erased pointers plus PhantomData<T>, which
+keeps List<T> covariant.
+*/
+
+const BLOCK_NODES: usize = 32;
+
+struct BlockPool<T> {
+    inner: Mutex<PoolInner>,
+    _boo: PhantomData<T>,
+}
+
+struct PoolInner {
+    free: Option<NonNull<u8>>,
+    blocks: Vec<NonNull<u8>>,
+    block_layout: Layout,
+}
+
+// The pointers are only ever touched with the mutex held.
+unsafe impl Send for PoolInner {}
+
+impl<T> BlockPool<T> {
+    fn new() -> Self {
+        BlockPool {
+            inner: Mutex::new(PoolInner {
+                free: None,
+                blocks: Vec::new(),
+                block_layout: Layout::array::<Node<T>>(BLOCK_NODES).unwrap(),
+            }),
+            _boo: PhantomData,
+        }
+    }
+
+    // An uninitialized node, or None if a new block was needed and couldn't be allocated.
+    fn alloc(&self) -> Option<NonNull<Node<T>>> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.free.is_none() {
+            let block = NonNull::new(unsafe { alloc(inner.block_layout) })?;
+            inner.blocks.push(block);
+            for i in 0..BLOCK_NODES {
+                unsafe { Self::push_free(&mut inner, block.cast::<Node<T>>().add(i)) };
+            }
+        }
+
+        let node = inner.free?.cast::<Node<T>>();
+        // Only `next` is initialized on a free node, so read just that field.
+        inner.free = unsafe { ptr::addr_of!((*node.as_ptr()).next).read() }.map(NonNull::cast);
+        Some(node)
+    }
+
+    // Safety: `node` came from this pool's alloc and its element has already been moved out.
+    unsafe fn release(&self, node: NonNull<Node<T>>) {
+        Self::push_free(&mut self.inner.lock().unwrap(), node);
+    }
+
+    unsafe fn push_free(inner: &mut PoolInner, node: NonNull<Node<T>>) {
+        ptr::addr_of_mut!((*node.as_ptr()).next).write(inner.free.map(NonNull::cast));
+        inner.free = Some(node.cast());
+    }
+
+    fn bytes(&self) -> usize {
+        let inner = self.inner.lock().unwrap();
+        inner.blocks.len() * inner.block_layout.size()
+    }
+
+    #[cfg(test)]
+    fn blocks(&self) -> usize {
+        self.inner.lock().unwrap().blocks.len()
+    }
+}
+
+impl Drop for PoolInner {
+    fn drop(&mut self) {
+        for block in self.blocks.drain(..) {
+            unsafe { dealloc(block.as_ptr(), self.block_layout) };
+        }
+    }
+}
+
+/*
+ Raw pointers are neither Send nor Sync, so the compiler assumes the worst about List<T>. But the
+queue uniquely owns its nodes, just like a Box would, so it's exactly as thread-safe as T is:
+
+- List<T> and IterMut hand out T / &mut T, so they are Send when T: Send (like Vec<T>, &mut T).
+- Iter hands out &T, so it is Send when T: Sync (like &T).
+- Sharing any of them only gives out &T, so they are Sync when T: Sync.
+*/
+unsafe impl<T: Send, A: Allocator + Send> Send for List<T, A> {}
+unsafe impl<T: Sync, A: Allocator + Sync> Sync for List<T, A> {}
+
+unsafe impl<T: Sync> Send for Iter<'_, T> {}
+unsafe impl<T: Sync> Sync for Iter<'_, T> {}
+
+unsafe impl<T: Send> Send for IterMut<'_, T> {}
+unsafe impl<T: Sync> Sync for IterMut<'_, T> {}
+
+pub struct IntoIter<T, A: Allocator = Global>(List<T, A>);
+
+// The borrowing iterators hold raw pointers too, so they also need PhantomData for the lifetime
+// (and to get the same variance as &'a T / &'a mut T).
+pub struct Iter<'a, T> {
+    next: Link<T>,
+    len: usize,
+    _boo: PhantomData<&'a T>,
+}
+
+pub struct IterMut<'a, T> {
+    next: Link<T>,
+    len: usize,
+    _boo: PhantomData<&'a mut T>,
+}
+
+impl<T> Clone for Iter<'_, T> {
+    fn clone(&self) -> Self {
+        Iter { ..*self }
+    }
+}
+
+impl<T, A: Allocator> List<T, A> {
+    // The edits that turn this list into `other`, front to back. See diff.rs.
+    pub fn diff<'a>(&'a self, other: &'a List<T, A>) -> Vec<Edit<'a, T>>
+    where
+        T: PartialEq,
+    {
+        diff::diff(self.iter(), other.iter())
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            next: self.head,
+            len: self.len,
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            next: self.head,
+            len: self.len,
+            _boo: PhantomData,
+        }
+    }
+}
+
+impl<T, A: Allocator> IntoIterator for List<T, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T, A>;
+
+    fn into_iter(self) -> IntoIter<T, A> {
+        IntoIter(self)
+    }
+}
+
+// So `for x in &list` and `for x in &mut list` work, and generic code bounded on IntoIterator
+// accepts borrowed queues.
+impl<'a, T, A: Allocator> IntoIterator for &'a List<T, A> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T, A: Allocator> IntoIterator for &'a mut List<T, A> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+impl<T, A: Allocator> Iterator for IntoIter<T, A> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<T, A: Allocator> ExactSizeIterator for IntoIter<T, A> {}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next.map(|node| unsafe {
+            self.len -= 1;
+            self.next = (*node.as_ptr()).next;
+            &(*node.as_ptr()).elem
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next.map(|node| unsafe {
+            self.len -= 1;
+            self.next = (*node.as_ptr()).next;
+            &mut (*node.as_ptr()).elem
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for IterMut<'_, T> {}
+
+// With the `serde` feature: a sequence, front first, the same as a Vec. A deserialized queue is
+// unbounded and allocates from Global, and it's built by pushing, one node at a time.
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::List;
+    use crate::allocator::Allocator;
+    use serde::de::{SeqAccess, Visitor};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::fmt;
+    use std::marker::PhantomData;
+
+    impl<T: Serialize, A: Allocator> Serialize for List<T, A> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(self)
+        }
+    }
+
+    struct ListVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for ListVisitor<T> {
+        type Value = List<T>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a sequence")
+        }
+
+        fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<List<T>, S::Error> {
+            let mut list = List::new();
+            while let Some(elem) = seq.next_element()? {
+                list.push(elem);
+            }
+            Ok(list)
+        }
+    }
+
+    impl<'de, T: Deserialize<'de>> Deserialize<'de> for List<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_seq(ListVisitor(PhantomData))
+        }
+    }
+}
+
+// With the `rkyv` feature: archived as an ArchivedVec of the elements, front first, readable in
+// place. Deserializing pushes them onto a new (unbounded, Global) queue.
+#[cfg(feature = "rkyv")]
+mod rkyv_impl {
+    use super::List;
+    use crate::allocator;
+    use rkyv::rancor::Fallible;
+    use rkyv::ser::{Allocator, Writer};
+    use rkyv::vec::{ArchivedVec, VecResolver};
+    use rkyv::{Archive, Deserialize, Place, Serialize};
+
+    impl<T: Archive, A: allocator::Allocator> Archive for List<T, A> {
+        type Archived = ArchivedVec<T::Archived>;
+        type Resolver = VecResolver;
+
+        fn resolve(&self, resolver: VecResolver, out: Place<Self::Archived>) {
+            ArchivedVec::resolve_from_len(self.len(), resolver, out);
+        }
+    }
+
+    impl<T, A, S> Serialize<S> for List<T, A>
+    where
+        T: Serialize<S>,
+        A: allocator::Allocator,
+        S: Fallible + Allocator + Writer + ?Sized,
+    {
+        fn serialize(&self, serializer: &mut S) -> Result<VecResolver, S::Error> {
+            ArchivedVec::serialize_from_iter::<T, _, _>(self.iter(), serializer)
+        }
+    }
+
+    impl<T, D> Deserialize<List<T>, D> for ArchivedVec<T::Archived>
+    where
+        T: Archive,
+        T::Archived: Deserialize<T, D>,
+        D: Fallible + ?Sized,
+    {
+        fn deserialize(&self, deserializer: &mut D) -> Result<List<T>, D::Error> {
+            let mut list = List::new();
+            for elem in self.iter() {
+                list.push(elem.deserialize(deserializer)?);
+            }
+            Ok(list)
+        }
+    }
+}
+
+// With the `arbitrary` feature: a queue built by a run of operations the fuzzer's bytes choose:
+// pushes, pops, and rotations that split_off the queue and append the front part back on, on
+// either plain or block-allocated nodes. Each step starts with a bool, false once the bytes run
+// out. Always the unbounded, Global queue.
+#[cfg(feature = "arbitrary")]
+mod arbitrary_impl {
+    use super::List;
+    use arbitrary::{Arbitrary, Result, Unstructured};
+
+    impl<'a, T: Arbitrary<'a>> Arbitrary<'a> for List<T> {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            let mut list = if u.arbitrary()? {
+                List::with_block_allocation()
+            } else {
+                List::new()
+            };
+            while u.arbitrary()? {
+                match u.int_in_range(0..=3u8)? {
+                    0 | 1 => list.push(T::arbitrary(u)?),
+                    2 => drop(list.pop()),
+                    _ if list.is_empty() => {}
+                    _ => {
+                        let at = u.choose_index(list.len())?;
+                        let mut back = list.split_off(at);
+                        back.append(&mut list);
+                        list = back;
+                    }
+                }
+            }
+            Ok(list)
+        }
+    }
+}
+
+// Proofs for Kani, a model checker: `cargo kani --features verification`. Where a test runs one
+// sequence of operations, each proof here covers every sequence up to MAX operations long, and
+// Kani checks all of them for undefined behaviour (a dangling, freed or misaligned pointer, a
+// node freed twice, a leak) as well as for the asserts. The bound is what keeps that finite:
+// nothing about the queue changes between four elements and four thousand except how long the
+// walks are.
+//
+// The queue always holds a run of consecutive numbers, so two counters are a complete model of it.
+#[cfg(all(kani, feature = "verification"))]
+mod verification {
+    use super::List;
+
+    const MAX: u8 = 4;
+
+    // Every interleaving of up to MAX pushes and pops.
+    #[kani::proof]
+    #[kani::unwind(6)]
+    fn push_pop() {
+        let mut list = List::new();
+        // The queue holds popped..pushed.
+        let (mut pushed, mut popped) = (0u8, 0u8);
+        for _ in 0..MAX {
+            if kani::any() {
+                list.push(pushed);
+                pushed += 1;
+            } else {
+                let expected = (popped < pushed).then_some(popped);
+                assert_eq!(list.pop(), expected);
+                popped += expected.is_some() as u8;
+            }
+            assert_eq!(list.len(), (pushed - popped) as usize);
+            assert_eq!(list.peek(), (popped < pushed).then_some(&popped));
+            assert_eq!(
+                list.peek_back(),
+                pushed.checked_sub(1).filter(|&x| x >= popped).as_ref()
+            );
+        }
+    }
+
+    // Whatever the value, it comes back out unchanged.
+    #[kani::proof]
+    #[kani::unwind(3)]
+    fn any_value() {
+        let (x, y): (u64, u64) = (kani::any(), kani::any());
+        let mut list = List::new();
+        list.push(x);
+        list.push(y);
+        assert_eq!(list.pop(), Some(x));
+        assert_eq!(list.pop(), Some(y));
+        assert!(list.is_empty());
+    }
+
+    // Two queues of up to MAX / 2 elements each, either of them possibly empty.
+    #[kani::proof]
+    #[kani::unwind(6)]
+    fn append() {
+        let a_len: u8 = kani::any_where(|&n| n <= MAX / 2);
+        let b_len: u8 = kani::any_where(|&n| n <= MAX / 2);
+        let mut a = List::new();
+        let mut b = List::new();
+        for x in 0..a_len {
+            a.push(x);
+        }
+        for x in a_len..a_len + b_len {
+            b.push(x);
+        }
+
+        a.append(&mut b);
+        assert!(b.is_empty());
+        assert_eq!(b.pop(), None);
+        assert_eq!(a.len(), (a_len + b_len) as usize);
+        assert_eq!(a.peek_back(), (a_len + b_len).checked_sub(1).as_ref());
+        // b still works, and shares nothing with a.
+        b.push(100);
+        for x in 0..a_len + b_len {
+            assert_eq!(a.pop(), Some(x));
+        }
+        assert_eq!(a.pop(), None);
+        assert_eq!(b.pop(), Some(100));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{IntoIter, Iter, IterMut, List};
+    use crate::counting_alloc::Counts;
+
+    #[test]
+    fn basics() {
+        let mut list = List::new();
+
+        // Check empty list behaves right
+        assert_eq!(list.pop(), None);
+
+        // Populate list
+        list.push(1);
+        list.push(2);
+        list.push(3);
+
+        // Check normal removal
+        assert_eq!(list.pop(), Some(1));
+        assert_eq!(list.pop(), Some(2));
+
+        // Push some more just to make sure nothing's corrupted
+        list.push(4);
+        list.push(5);
+
+        // Check normal removal
+        assert_eq!(list.pop(), Some(3));
+        assert_eq!(list.pop(), Some(4));
+
+        // Check exhaustion
+        assert_eq!(list.pop(), Some(5));
+        assert_eq!(list.pop(), None);
+
+        // Check the exhaustion case fixed the pointer right
+        list.push(6);
+        list.push(7);
+
+        // Check normal removal
+        assert_eq!(list.pop(), Some(6));
+        assert_eq!(list.pop(), Some(7));
+        assert_eq!(list.pop(), None);
+    }
+
+    #[test]
+    fn peek_and_iters() {
+        let mut list = List::new();
+        assert_eq!(list.peek(), None);
+        list.push(1);
+        list.push(2);
+        list.push(3);
+        assert_eq!(list.peek(), Some(&1));
+
+        for elem in list.iter_mut() {
+            *elem *= 10;
+        }
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![&10, &20, &30]);
+        assert_eq!(list.into_iter().collect::<Vec<_>>(), vec![10, 20, 30]);
+    }
+
+    #[test]
+    fn peek_mut() {
+        let mut list = List::new();
+        assert_eq!(list.peek_mut(), None);
+        assert_eq!(list.map_front(|x: &mut i32| *x += 1), None);
+
+        list.push(3);
+        list.push(10);
+        if let Some(front) = list.peek_mut() {
+            *front = 2;
+        }
+        let left = list.map_front(|retries| {
+            *retries -= 1;
+            *retries
+        });
+        assert_eq!(left, Some(1));
+        assert_eq!(list.into_vec(), vec![1, 10]);
+    }
+
+    #[test]
+    fn peek_back() {
+        let mut list = List::new();
+        assert_eq!(list.peek_back(), None);
+        assert_eq!(list.peek_back_mut(), None);
+
+        list.push(1);
+        assert_eq!(list.peek_back(), Some(&1));
+        list.push(2);
+        assert_eq!(list.peek_back(), Some(&2));
+
+        if let Some(last) = list.peek_back_mut() {
+            *last = 20;
+        }
+        assert_eq!(list.pop(), Some(1));
+        assert_eq!(list.peek_back(), Some(&20));
+        assert_eq!(list.pop(), Some(20));
+        assert_eq!(list.peek_back(), None);
+    }
+
+    #[test]
+    fn bounded() {
+        let mut list = List::with_capacity(2);
+        assert_eq!(list.capacity(), Some(2));
+        assert!(!list.is_full());
+        assert_eq!(list.try_push(1), Ok(()));
+        assert_eq!(list.try_push(2), Ok(()));
+        assert!(list.is_full());
+        assert_eq!(list.try_push(3), Err(3));
+        assert_eq!(list.len(), 2);
+
+        assert_eq!(list.pop(), Some(1));
+        assert_eq!(list.try_push(3), Ok(()));
+        assert_eq!(list.into_iter().collect::<Vec<_>>(), vec![2, 3]);
+
+        let mut unbounded = List::new();
+        assert_eq!(unbounded.capacity(), None);
+        for i in 0..100 {
+            assert_eq!(unbounded.try_push(i), Ok(()));
+        }
+        assert!(!unbounded.is_full());
+
+        // append doesn't check the bound.
+        let mut list = List::with_capacity(2);
+        list.push(1);
+        list.append(&mut List::from_vec(vec![2, 3]));
+        assert_eq!(list.into_vec(), vec![1, 2, 3]);
+
+        // split_off keeps the bound on both halves.
+        let mut list = List::with_capacity(1);
+        list.push(1);
+        let mut rest = list.split_off(0);
+        assert_eq!(list.capacity(), Some(1));
+        assert_eq!(rest.try_push(2), Err(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "queue is full")]
+    fn push_when_full() {
+        let mut list = List::with_capacity(1);
+        list.push(1);
+        list.push(2);
+    }
+
+    #[test]
+    #[should_panic(expected = "queue is full")]
+    fn extend_past_capacity() {
+        let mut list = List::with_capacity(2);
+        list.extend([1, 2, 3]);
+    }
+
+    #[test]
+    fn byte_pipe() {
+        use std::io::{self, BufRead, Read, Write};
+
+        let mut pipe = List::new();
+        write!(pipe, "hello\nworld").unwrap();
+        let mut line = String::new();
+        pipe.read_line(&mut line).unwrap();
+        assert_eq!(line, "hello\n");
+        let mut buf = [0; 3];
+        assert_eq!(pipe.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf, b"wor");
+
+        // Through io::copy into another queue, then drained: empty reads as end of file.
+        let mut other = List::new();
+        assert_eq!(io::copy(&mut pipe, &mut other).unwrap(), 2);
+        let mut rest = Vec::new();
+        other.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"ld");
+        assert_eq!(other.read(&mut buf).unwrap(), 0);
+
+        // A bounded pipe takes what fits.
+        let mut bounded = List::with_capacity(4);
+        assert_eq!(bounded.write(b"abcdef").unwrap(), 4);
+        assert_eq!(bounded.write(b"ef").unwrap(), 0);
+        let err = bounded.write_all(b"ef").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+        bounded.consume(2);
+        bounded.write_all(b"ef").unwrap();
+        assert!(bounded.lines().map(Result::unwrap).eq(["cdef"]));
+    }
+
+    #[test]
+    fn vec_conversions() {
+        let mut list = List::from_vec(vec![1, 2, 3]);
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.peek(), Some(&1));
+        assert_eq!(list.peek_back(), Some(&3));
+        list.push(4);
+        assert_eq!(list.pop(), Some(1));
+        assert_eq!(list.into_vec(), vec![2, 3, 4]);
+
+        let mut empty = List::from_vec(Vec::new());
+        assert!(empty.is_empty());
+        empty.push(1);
+        assert_eq!(empty.into_vec(), vec![1]);
+    }
+
+    #[test]
+    fn raw_parts() {
+        let list = List::from_vec(vec![1, 2, 3]);
+        let (head, tail, len) = list.into_raw_parts();
+        assert_eq!(len, 3);
+        assert!(!head.is_null() && !tail.is_null());
+
+        let mut list = unsafe { List::from_raw_parts(head, tail, len) };
+        list.push(4);
+        assert_eq!(list.into_vec(), vec![1, 2, 3, 4]);
+
+        let (head, tail, len) = List::<i32>::new().into_raw_parts();
+        assert!(head.is_null() && tail.is_null());
+        let mut list = unsafe { List::from_raw_parts(head, tail, len) };
+        assert_eq!(list.pop(), None);
+        list.push(1);
+        assert_eq!(list.len(), 1);
+    }
+
+    #[test]
+    fn leak() {
+        let mut names: Vec<&'static mut String> =
+            List::from_vec(vec!["a".to_string(), "b".to_string()])
+                .leak()
+                .collect();
+        names[0].push('!');
+        assert_eq!(*names[0], "a!");
+        assert_eq!(*names[1], "b");
+    }
+
+    #[test]
+    fn steal_half() {
+        let mut list = List::from_vec((1..=5).collect());
+        let mut stolen = list.steal_half();
+        assert_eq!(stolen.len(), 2);
+        list.push(6);
+        stolen.push(7);
+        assert_eq!(list.into_vec(), vec![1, 2, 3, 6]);
+        assert_eq!(stolen.into_vec(), vec![4, 5, 7]);
+
+        // Nothing to steal from a single job.
+        let mut one = List::from_vec(vec![1]);
+        assert!(one.steal_half().is_empty());
+        assert_eq!(one.len(), 1);
+        assert!(List::<i32>::new().steal_half().is_empty());
+    }
+
+    #[test]
+    fn pop_all() {
+        let mut list = List::from_vec(vec![1, 2, 3]);
+        let batch = list.pop_all();
+        assert!(list.is_empty());
+        assert_eq!(list.pop(), None);
+        list.push(4);
+        assert_eq!(batch.into_vec(), vec![1, 2, 3]);
+        assert_eq!(list.into_vec(), vec![4]);
+
+        let mut bounded = List::<i32>::with_capacity(3);
+        assert!(bounded.pop_all().is_empty());
+        assert_eq!(bounded.capacity(), Some(3));
+    }
+
+    #[test]
+    fn block_allocation() {
+        let mut list = List::with_block_allocation();
+        for i in 0..100 {
+            list.push(i);
+        }
+        let pool = list.pool.clone().unwrap();
+        assert_eq!(pool.blocks(), 4);
+
+        // Popped nodes are recycled instead of freed.
+        for i in 0..100 {
+            assert_eq!(list.pop(), Some(i));
+        }
+        // And still count as the queue's memory.
+        let usage = list.mem_usage();
+        assert_eq!(usage.nodes, 0);
+        assert_eq!(
+            usage.heap_bytes,
+            4 * 32 * std::mem::size_of::<super::Node<i32>>()
+        );
+        for i in 0..100 {
+            assert_eq!(list.try_push(i), Ok(()));
+        }
+        assert_eq!(pool.blocks(), 4);
+
+        // Split-off halves share the pool, so relinking between them stays O(1).
+        let mut rest = list.split_off(50);
+        assert!(rest.same_allocator(&list));
+        rest.push(100);
+        list.append(&mut rest);
+        assert_eq!(list.len(), 101);
+        assert_eq!(list.peek_back(), Some(&100));
+
+        // Mixing with a plain queue moves elements instead of nodes.
+        let mut plain = List::from_vec(vec![-1, -2]);
+        list.append(&mut plain);
+        assert!(plain.is_empty());
+        assert_eq!(list.len(), 103);
+        plain.append(&mut list);
+        assert!(list.is_empty());
+        assert!(plain.same_allocator(&list));
+
+        let (head, tail, len) = plain.into_raw_parts();
+        let plain = unsafe { List::from_raw_parts(head, tail, len) };
+        assert_eq!(plain.into_vec().len(), 103);
+
+        // Elements still get dropped, the pool outlives every queue using it.
+        let rc = std::rc::Rc::new(());
+        let mut list = List::with_block_allocation();
+        for _ in 0..40 {
+            list.push(rc.clone());
+        }
+        let rest = list.split_off(10);
+        drop(list);
+        assert_eq!(std::rc::Rc::strong_count(&rc), 31);
+        drop(rest);
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn into_iter_refs() {
+        fn total<'a>(items: impl IntoIterator<Item = &'a i32>) -> i32 {
+            items.into_iter().sum()
+        }
+
+        let mut list = List::from_vec(vec![1, 2, 3]);
+        for x in &mut list {
+            *x += 1;
+        }
+        let mut seen = Vec::new();
+        for x in &list {
+            seen.push(*x);
+        }
+        assert_eq!(seen, vec![2, 3, 4]);
+        assert_eq!(total(&list), 9);
+    }
+
+    #[test]
+    fn len() {
+        let mut list = List::new();
+        assert!(list.is_empty());
+        list.push(1);
+        list.push(2);
+        list.push(3);
+        assert_eq!(list.len(), 3);
+        assert!(!list.is_empty());
+        list.pop();
+        assert_eq!(list.len(), 2);
+
+        let mut iter = list.iter();
+        assert_eq!(iter.len(), 2);
+        iter.next();
+        assert_eq!(iter.len(), 1);
+        assert_eq!(list.iter_mut().len(), 2);
+
+        let mut into_iter = list.into_iter();
+        into_iter.next();
+        assert_eq!(into_iter.len(), 1);
+    }
+
+    #[test]
+    fn append() {
+        let mut a = List::new();
+        let mut b = List::new();
+        a.push(1);
+        a.push(2);
+        b.push(3);
+        b.push(4);
+
+        a.append(&mut b);
+        assert_eq!(a.len(), 4);
+        assert!(b.is_empty());
+        assert_eq!(b.pop(), None);
+
+        // Both tails must be right afterwards.
+        a.push(5);
+        b.push(6);
+        assert_eq!(a.iter().collect::<Vec<_>>(), vec![&1, &2, &3, &4, &5]);
+        assert_eq!(b.iter().collect::<Vec<_>>(), vec![&6]);
+
+        // Appending onto an empty queue, and appending an empty queue.
+        let mut empty = List::new();
+        empty.append(&mut a);
+        assert_eq!(empty.len(), 5);
+        empty.append(&mut a);
+        assert_eq!(empty.len(), 5);
+        empty.push(7);
+        assert_eq!(
+            empty.into_iter().collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 5, 7]
+        );
+    }
+
+    #[test]
+    fn allocations() {
+        let mut list = List::new();
+        let mut other = List::new();
+        let counts = Counts::start();
+        list.push(1);
+        list.push(2);
+        other.push(3);
+        assert_eq!((counts.allocs(), counts.deallocs()), (3, 0));
+
+        // Relinking: no node allocated or freed.
+        let counts = Counts::start();
+        list.append(&mut other);
+        let mut rest = list.split_off(1);
+        list.append(&mut rest);
+        let all = list.pop_all();
+        assert_eq!((counts.allocs(), counts.deallocs()), (0, 0));
+
+        let counts = Counts::start();
+        drop(all);
+        assert_eq!((counts.allocs(), counts.deallocs()), (0, 3));
+    }
+
+    #[test]
+    fn send_sync() {
+        fn is_send<T: Send>() {}
+        fn is_sync<T: Sync>() {}
+        is_send::<List<i32>>();
+        is_sync::<List<i32>>();
+        is_send::<IntoIter<i32>>();
+        is_send::<Iter<i32>>();
+        is_sync::<Iter<i32>>();
+        is_send::<IterMut<i32>>();
+        is_sync::<IterMut<i32>>();
+
+        let mut list = List::new();
+        list.push(1);
+        list.push(2);
+
+        // Move it to another thread and back.
+        let mut list = std::thread::spawn(move || {
+            list.push(3);
+            list
+        })
+        .join()
+        .unwrap();
+
+        // Iterate from several threads at once.
+        std::thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| assert_eq!(list.iter().sum::<i32>(), 6));
+            }
+        });
+
+        let iter_mut = list.iter_mut();
+        std::thread::scope(|s| {
+            s.spawn(move || iter_mut.for_each(|x| *x *= 2));
+        });
+        assert_eq!(list.into_iter().collect::<Vec<_>>(), vec![2, 4, 6]);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "debug-validate", ignore = "quadratic under debug-validate")]
+    fn long_drop() {
+        let mut list = List::new();
+        for i in 0..1_000_000 {
+            list.push(i);
+        }
+        drop(list);
+
+        // Elements are dropped too.
+        let rc = std::rc::Rc::new(());
+        let mut list = List::new();
+        for _ in 0..10 {
+            list.push(rc.clone());
+        }
+        drop(list);
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+    }
+
+    #[cfg(feature = "nightly")]
+    #[test]
+    fn may_dangle() {
+        let mut list = List::new();
+        let x = 5;
+        list.push(&x);
+        assert_eq!(list.peek(), Some(&&5));
+    }
+
+    #[test]
+    fn split_off() {
+        let mut list = List::new();
+        for i in 1..=5 {
+            list.push(i);
+        }
+
+        let mut rest = list.split_off(2);
+        assert_eq!(list.len(), 2);
+        assert_eq!(rest.len(), 3);
+        // Both tails have to be right for push to work.
+        list.push(10);
+        rest.push(20);
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![&1, &2, &10]);
+        assert_eq!(rest.iter().collect::<Vec<_>>(), vec![&3, &4, &5, &20]);
+
+        // Splitting at the end gives an empty queue and leaves `list` alone.
+        let mut empty = list.split_off(3);
+        assert!(empty.is_empty());
+        empty.push(1);
+        list.push(11);
+        assert_eq!(list.len(), 4);
+
+        // Splitting at 0 moves everything.
+        let mut all = list.split_off(0);
+        assert!(list.is_empty());
+        assert_eq!(list.pop(), None);
+        list.push(0);
+        all.push(12);
+        assert_eq!(all.into_iter().collect::<Vec<_>>(), vec![1, 2, 10, 11, 12]);
+        assert_eq!(list.into_iter().collect::<Vec<_>>(), vec![0]);
+
+        let mut empty: List<i32> = List::new();
+        assert!(empty.split_off(0).is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn split_off_out_of_bounds() {
+        let mut list = List::new();
+        list.push(1);
+        list.split_off(2);
+    }
+
+    #[test]
+    fn allocator() {
+        use crate::allocator::Bump;
+        use std::mem::size_of;
+
+        let bump = Bump::with_capacity(1024);
+        let mut list = List::new_in(&bump);
+        for i in 0..8u64 {
+            list.push(i);
+        }
+        assert_eq!(bump.used(), 8 * size_of::<[u64; 2]>());
+
+        // The split-off queue shares the allocator, so both can keep pushing and dropping.
+        let mut rest = list.steal_half();
+        rest.push(8);
+        assert_eq!(list.pop_all().into_vec(), [0, 1, 2, 3]);
+        assert_eq!(rest.iter().copied().collect::<Vec<_>>(), [4, 5, 6, 7, 8]);
+
+        // A full bump shows up as try_push failing, not as an abort.
+        let small = Bump::with_capacity(2 * size_of::<[u64; 2]>());
+        let mut list = List::new_in(&small);
+        assert_eq!(list.try_push(1u64), Ok(()));
+        assert_eq!(list.try_push(2), Ok(()));
+        assert_eq!(list.try_push(3), Err(3));
+        assert_eq!(list.into_iter().collect::<Vec<_>>(), [1, 2]);
+    }
+
+    #[test]
+    fn conversions() {
+        use crate::{generic_and_iterators, persistent_stack, unsafe_doubly_linked_deque};
+
+        let mut stack = generic_and_iterators::List::new();
+        for x in [3, 2, 1] {
+            stack.push(x);
+        }
+        let top: *const i32 = stack.peek().unwrap();
+        let mut queue = List::from(stack);
+        assert_eq!(queue.len(), 3);
+        // Same node, so the same element, now at the front.
+        assert!(std::ptr::eq(queue.peek().unwrap(), top));
+        queue.push(4);
+
+        let mut stack = generic_and_iterators::List::from(queue);
+        assert!(std::ptr::eq(stack.peek().unwrap(), top));
+        assert_eq!(stack.iter().copied().collect::<Vec<_>>(), [1, 2, 3, 4]);
+        assert_eq!(stack.pop(), Some(1));
+
+        // Pooled nodes are copied, and end up the same.
+        let mut pooled = List::with_block_allocation();
+        pooled.push("a".to_string());
+        pooled.push("b".to_string());
+        let stack = generic_and_iterators::List::from(pooled);
+        assert_eq!(stack.iter().collect::<Vec<_>>(), ["a", "b"]);
+
+        // Round the rest, in order.
+        let deque = unsafe_doubly_linked_deque::List::from(List::from(stack));
+        let persistent = persistent_stack::List::from(deque);
+        let queue = List::from(persistent.clone());
+        let deque = unsafe_doubly_linked_deque::List::from(queue);
+        let refcell = crate::doubly_linked_deque::List::from(deque);
+        let deque = unsafe_doubly_linked_deque::List::from(refcell);
+        let stack = generic_and_iterators::List::from(deque);
+        assert_eq!(stack.iter().collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(persistent.iter().collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(
+            generic_and_iterators::List::from(persistent)
+                .into_iter()
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn std_interop() {
+        use std::collections::{LinkedList, VecDeque};
+
+        let mut ring = VecDeque::with_capacity(4);
+        ring.extend([0, 1, 2, 3]);
+        ring.pop_front();
+        ring.push_back(4);
+        // Wrapped around in its buffer, and still comes out in order.
+        let mut queue = List::from(ring);
+        assert_eq!(queue.pop(), Some(1));
+        queue.extend(LinkedList::from([5, 6]));
+        queue.extend(VecDeque::from([7]));
+
+        let std_list = LinkedList::from(queue);
+        assert_eq!(
+            std_list.iter().copied().collect::<Vec<_>>(),
+            [2, 3, 4, 5, 6, 7]
+        );
+        let queue = List::from(std_list);
+        assert_eq!(VecDeque::from(queue), [2, 3, 4, 5, 6, 7]);
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn arbitrary() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let bytes: Vec<u8> = (0..4096u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let mut u = Unstructured::new(&bytes);
+        while !u.is_empty() {
+            let mut list = List::<u16>::arbitrary(&mut u).unwrap();
+            assert_eq!(list.iter().count(), list.len());
+            assert_eq!(list.peek_back(), list.iter().last());
+            // Still a working queue at the back.
+            list.push(7);
+            assert_eq!(list.peek_back(), Some(&7));
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    #[cfg_attr(feature = "debug-validate", ignore = "quadratic under debug-validate")]
+    fn serde() {
+        let mut list = List::new();
+        for i in 0..100_000 {
+            list.push(i);
+        }
+        let json = serde_json::to_string(&list).unwrap();
+        assert_eq!(
+            json,
+            serde_json::to_string(&list.iter().collect::<Vec<_>>()).unwrap()
+        );
+        // Long enough to overflow the stack if anything recursed.
+        let mut back: List<i32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.len(), 100_000);
+        assert_eq!(back.peek_back(), Some(&99_999));
+        back.push(-1);
+        assert_eq!(back.pop(), Some(0));
+    }
+
+    #[cfg(feature = "rkyv")]
+    #[test]
+    fn rkyv() {
+        use crate::unsafe_doubly_linked_deque;
+        use rkyv::rancor::Error;
+        use rkyv::vec::ArchivedVec;
+        use rkyv::Archived;
+
+        let mut list = List::new();
+        for i in 0..1000u32 {
+            list.push(i);
+        }
+        let bytes = rkyv::to_bytes::<Error>(&list).unwrap();
+        let archived = rkyv::access::<ArchivedVec<Archived<u32>>, Error>(&bytes).unwrap();
+        assert_eq!(archived.iter().map(|x| x.to_native()).sum::<u32>(), 499_500);
+        let mut back: List<u32> = rkyv::deserialize::<_, Error>(archived).unwrap();
+        assert_eq!(back.peek_back(), Some(&999));
+        assert_eq!(back.pop(), Some(0));
+
+        // Same format as the deque, so one can be read back as the other.
+        let deque: unsafe_doubly_linked_deque::List<u32> =
+            rkyv::deserialize::<_, Error>(archived).unwrap();
+        assert_eq!(deque.len(), 1000);
+    }
+
+    #[test]
+    fn niche() {
+        use std::mem::size_of;
+        // Option<NonNull<_>> uses null for None, so a link is just one pointer.
+        assert_eq!(size_of::<super::Link<u8>>(), size_of::<usize>());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn stream() {
+        use futures::{Stream, StreamExt};
+
+        let mut list = List::new();
+        list.push(1);
+        list.push(2);
+        list.push(3);
+        assert_eq!(list.size_hint(), (3, Some(3)));
+        let doubled: Vec<i32> = futures::executor::block_on(list.map(|x| x * 2).collect());
+        assert_eq!(doubled, vec![2, 4, 6]);
+    }
+
+    // These only need to compile: each one fails to type-check if the type is not covariant.
+    #[allow(dead_code)]
+    fn covariance() {
+        fn list<'a, T>(x: List<&'static T>) -> List<&'a T> {
+            x
+        }
+        fn into_iter<'a, T>(x: IntoIter<&'static T>) -> IntoIter<&'a T> {
+            x
+        }
+        fn iter<'i, 'a, T>(x: Iter<'i, &'static T>) -> Iter<'i, &'a T> {
+            x
+        }
+        fn iter_lifetime<'a, T>(x: Iter<'static, T>) -> Iter<'a, T> {
+            x
+        }
+        fn iter_mut_lifetime<'a, T>(x: IterMut<'static, T>) -> IterMut<'a, T> {
+            x
         }
     }
 }
@@ -0,0 +1,224 @@
+/*
+ Checking a list's invariants
+
+Every list that keeps more than a chain of owning links keeps some of it twice: a length next to
+the nodes it counts, a tail pointer next to the last node's null, a back link next to the forward
+one. The methods keep the copies in step, and a bug in one of them shows up much later, as a pop
+that returns the wrong element, or a use-after-free when the list is dropped. By then the code that
+broke it is long gone from the stack.
+
+validate() on a list walks all of it and checks that everything it stores twice agrees:
+
+- Length: the stored length is the number of nodes actually reachable. The walk stops one node
+  past the stored length, so a cycle shows up as a list one longer than it should be, rather than
+  a walk that never ends.
+- Ends: head and tail (or front and back) are the first and last nodes, and both are empty
+  together.
+- Links: in a doubly linked list, each node's link back points at the node before it.
+- Sharing: each node has exactly the owners the list accounts for. doubly_linked_deque can check
+  its Rc counts; the raw-pointer lists can't see who else holds a pointer, but one of their nodes
+  linked in twice makes a cycle, which the length catches.
+- Order, for the lists that keep their elements sorted.
+- Anything else the list keeps: an unrolled list's per-node counts, an arena's free list, an
+  indexable skip list's link widths.
+
+It returns the first thing it finds wrong as an Invalid, which says what and (where it can) at
+which node, counting from the front.
+
+That's enough for a test to call validate() after whatever it does. To find the operation that
+breaks a list, rather than just the test, build with the `debug-validate` feature: each list's
+mutating methods then run validate() on the way out, and panic at the first one that leaves the
+list invalid. That makes every operation O(n), so it's only on in debug builds, and only when
+asked for. In a release build, or without the feature, check! is nothing at all. The crate's own
+tests that build lists of 100,000 elements or more would go quadratic under it, so they're ignored
+when it's on, and the rest run in CI with it:
+
+    cargo test --features debug-validate
+
+The lists that are nothing but a chain of Boxes or Rcs (generic_and_iterators, persistent_stack,
+arc_persistent_stack, pointer_list, basic_impl) have no validate(): they store nothing twice, and
+any shape the types allow is a valid list. vlist shares its blocks by design, so it checks the
+blocks it uses rather than who owns them: that the slots it claims are filled, and each block's
+count of the elements after it.
+*/
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Invalid {
+    // `counted` nodes are reachable, where the list says it has `stored`. Counting stops at
+    // stored + 1.
+    Len { stored: usize, counted: usize },
+    // The front or back pointer isn't the first or last node.
+    Ends,
+    // Node `at`'s link back doesn't point at node `at - 1` (or, for `at` 0, isn't empty).
+    Link { at: usize },
+    // Node `at` has more owners than the list accounts for.
+    Shared { at: usize },
+    // Element `at` is out of order with the one before it.
+    Order { at: usize },
+    // Node `at` is wrong in some way of its own: empty or over-full, say, or a width that doesn't
+    // match what it spans.
+    Node { at: usize },
+}
+
+impl fmt::Display for Invalid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Invalid::Len { stored, counted } => {
+                write!(f, "length is {stored}, but {counted} nodes are linked")
+            }
+            Invalid::Ends => f.write_str("front or back isn't the end of the list"),
+            Invalid::Link { at } => write!(f, "node {at} doesn't link back to the node before it"),
+            Invalid::Shared { at } => write!(f, "node {at} is shared"),
+            Invalid::Order { at } => write!(f, "element {at} is out of order"),
+            Invalid::Node { at } => write!(f, "node {at} is malformed"),
+        }
+    }
+}
+
+impl Error for Invalid {}
+
+// Walk a singly linked chain from `first`, which should have `len` nodes, and return the last one.
+pub(crate) fn walk<P: Copy>(
+    first: Option<P>,
+    len: usize,
+    mut next: impl FnMut(P) -> Option<P>,
+) -> Result<Option<P>, Invalid> {
+    let (mut cur, mut last, mut counted) = (first, None, 0);
+    while let Some(node) = cur {
+        if counted == len {
+            return Err(Invalid::Len {
+                stored: len,
+                counted: len + 1,
+            });
+        }
+        last = cur;
+        cur = next(node);
+        counted += 1;
+    }
+    if counted != len {
+        return Err(Invalid::Len {
+            stored: len,
+            counted,
+        });
+    }
+    Ok(last)
+}
+
+// The same for a doubly linked one, from `front`. `links` gives a node's link back and its link
+// forward, and each link back has to be the node walked from.
+pub(crate) fn walk_doubly<P: Copy + PartialEq>(
+    front: Option<P>,
+    back: Option<P>,
+    len: usize,
+    mut links: impl FnMut(P) -> (Option<P>, Option<P>),
+) -> Result<(), Invalid> {
+    let (mut prev, mut at) = (None, 0);
+    let mut link_err = None;
+    let last = walk(front, len, |node| {
+        let (before, after) = links(node);
+        if before != prev && link_err.is_none() {
+            link_err = Some(Invalid::Link { at });
+        }
+        prev = Some(node);
+        at += 1;
+        after
+    })?;
+    if let Some(err) = link_err {
+        return Err(err);
+    }
+    if last != back {
+        return Err(Invalid::Ends);
+    }
+    Ok(())
+}
+
+// Each element no less than the one before it, or, if `strict`, greater.
+pub(crate) fn sorted<'a, T: Ord + 'a>(
+    elems: impl IntoIterator<Item = &'a T>,
+    strict: bool,
+) -> Result<(), Invalid> {
+    let mut elems = elems.into_iter();
+    let Some(mut prev) = elems.next() else {
+        return Ok(());
+    };
+    for (at, elem) in elems.enumerate() {
+        if elem < prev || (strict && elem == prev) {
+            return Err(Invalid::Order { at: at + 1 });
+        }
+        prev = elem;
+    }
+    Ok(())
+}
+
+// check!(self) at the end of a mutating method: with `debug-validate` in a debug build, panic if
+// it left the list invalid. Anything after the list is passed on to validate() (ghost_list's
+// token).
+macro_rules! check {
+    ($list:expr $(, $arg:expr)*) => {
+        #[cfg(all(feature = "debug-validate", debug_assertions))]
+        {
+            if let Err(invalid) = $list.validate($($arg),*) {
+                panic!(
+                    "{} is invalid: {invalid}",
+                    std::any::type_name_of_val(&*$list)
+                );
+            }
+        }
+    };
+}
+
+pub(crate) use check;
+
+#[cfg(test)]
+mod test {
+    use super::{sorted, walk, walk_doubly, Invalid};
+
+    // Nodes are indexes into a table of links, None for a null one.
+    #[test]
+    fn walks() {
+        let next = [Some(1), Some(2), None];
+        assert_eq!(walk(Some(0), 3, |i| next[i]), Ok(Some(2)));
+        assert_eq!(
+            walk(Some(0), 2, |i| next[i]),
+            Err(Invalid::Len {
+                stored: 2,
+                counted: 3
+            })
+        );
+        let cycle = [Some(1), Some(0)];
+        assert_eq!(
+            walk(Some(0), 5, |i| cycle[i]),
+            Err(Invalid::Len {
+                stored: 5,
+                counted: 6
+            })
+        );
+
+        let mut links = [(None, Some(1)), (Some(0), Some(2)), (Some(1), None)];
+        assert_eq!(walk_doubly(Some(0), Some(2), 3, |i| links[i]), Ok(()));
+        assert_eq!(
+            walk_doubly(Some(0), Some(1), 3, |i| links[i]),
+            Err(Invalid::Ends)
+        );
+        links[2].0 = Some(0);
+        assert_eq!(
+            walk_doubly(Some(0), Some(2), 3, |i| links[i]),
+            Err(Invalid::Link { at: 2 })
+        );
+        assert_eq!(
+            walk_doubly(None, Some(0), 0, |i| links[i]),
+            Err(Invalid::Ends)
+        );
+    }
+
+    #[test]
+    fn order() {
+        assert_eq!(sorted(&[1, 2, 2, 3], false), Ok(()));
+        assert_eq!(sorted(&[1, 2, 2, 3], true), Err(Invalid::Order { at: 2 }));
+        assert_eq!(sorted(&[2, 1], false), Err(Invalid::Order { at: 1 }));
+        assert_eq!(sorted::<u8>(&[], true), Ok(()));
+    }
+}
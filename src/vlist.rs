@@ -0,0 +1,335 @@
+/*
+ VList (Bagwell, 2002)
+
+A persistent sequence like persistent_stack::List, but instead of one Rc node per element the
+elements live in blocks whose capacity doubles as the list grows:
+
+list -> [ 8 | . | . | . | . | . | . | . ] -> [ 4 | 5 | 6 | 7 ] -> [ 2 | 3 ] -> [ 1 ]
+          ^
+          newest element (slot 0 of each block is its oldest element)
+
+- prepend is O(1) amortized: it writes into the next free slot of the current block, and only
+  allocates a new (twice as large) block when the current one is full.
+- get(i) is O(log n): there are only O(log n) blocks to skip over.
+- One Rc header per block instead of per element, so far better memory density.
+
+Sharing works like the cons list. A VList is just (block, how many slots of it we use). Two lists
+can point at the same block with different lengths. Prepending to the shorter one can't write
+into a slot the longer one already claimed, so it starts a new block pointing back at the shared
+part instead. Each slot is a OnceCell: it's written at most once and never changes after that,
+so handing out &T is fine.
+*/
+
+use crate::mem_usage::{self, MemUsage};
+use crate::validate::{self, Invalid};
+use std::cell::OnceCell;
+use std::mem::size_of;
+use std::rc::Rc;
+
+pub struct VList<T> {
+    block: Option<Rc<Block<T>>>,
+    // Number of slots of `block` (from the start) that belong to this list.
+    len_in_block: usize,
+}
+
+struct Block<T> {
+    // Slot 0 is the oldest element of the block.
+    elems: Vec<OnceCell<T>>,
+    // The older block and how many of its slots belong to us.
+    next: Option<Rc<Block<T>>>,
+    next_len: usize,
+    // Total number of elements in `next` and everything after it.
+    base: usize,
+}
+
+impl<T> Block<T> {
+    fn new(capacity: usize, next: Option<Rc<Block<T>>>, next_len: usize) -> Self {
+        let base = next.as_ref().map_or(0, |block| block.base) + next_len;
+        Block {
+            elems: (0..capacity).map(|_| OnceCell::new()).collect(),
+            next,
+            next_len,
+            base,
+        }
+    }
+}
+
+impl<T> VList<T> {
+    pub fn new() -> Self {
+        VList {
+            block: None,
+            len_in_block: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.block
+            .as_ref()
+            .map_or(0, |block| block.base + self.len_in_block)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.block.is_none()
+    }
+
+    // A node is a block: the Rc'd header, and the Vec of slots it points to. Blocks are shared
+    // with the lists this one was prepended from (and any prepended from it), and every slot is
+    // counted, including ones that belong only to those. See mem_usage.rs.
+    pub fn mem_usage(&self) -> MemUsage {
+        let mut usage = MemUsage {
+            nodes: 0,
+            node_overhead: mem_usage::rc_size::<Block<T>>(),
+            heap_bytes: 0,
+        };
+        let mut cur = self.block.as_deref();
+        while let Some(block) = cur {
+            usage.nodes += 1;
+            usage.heap_bytes +=
+                usage.node_overhead + block.elems.capacity() * size_of::<OnceCell<T>>();
+            cur = block.next.as_deref();
+        }
+        usage
+    }
+
+    pub fn prepend(&self, elem: T) -> VList<T> {
+        let elem = match &self.block {
+            Some(block) if self.len_in_block < block.elems.len() => {
+                // Try to claim the next slot. If another version already took it, fall through
+                // and start a fresh block.
+                match block.elems[self.len_in_block].set(elem) {
+                    Ok(()) => {
+                        let list = VList {
+                            block: Some(block.clone()),
+                            len_in_block: self.len_in_block + 1,
+                        };
+                        validate::check!(&list);
+                        return list;
+                    }
+                    Err(elem) => elem,
+                }
+            }
+            _ => elem,
+        };
+
+        let capacity = (self.len_in_block * 2).max(1);
+        let block = Block::new(capacity, self.block.clone(), self.len_in_block);
+        // Fresh block: the first slot is always free.
+        let _ = block.elems[0].set(elem);
+        let list = VList {
+            block: Some(Rc::new(block)),
+            len_in_block: 1,
+        };
+        validate::check!(&list);
+        list
+    }
+
+    pub fn tail(&self) -> VList<T> {
+        let list = match &self.block {
+            Some(block) if self.len_in_block > 1 => VList {
+                block: Some(block.clone()),
+                len_in_block: self.len_in_block - 1,
+            },
+            Some(block) => VList {
+                block: block.next.clone(),
+                len_in_block: block.next_len,
+            },
+            None => VList::new(),
+        };
+        validate::check!(&list);
+        list
+    }
+
+    // Blocks are shared, so there's no one owner to count. What's checked is each block this
+    // list uses, newest first: the slots it claims exist and are filled, and its `base` is what's
+    // in the blocks after it. A block that's wrong is a Node at that block. See validate.rs.
+    pub fn validate(&self) -> Result<(), Invalid> {
+        let (mut block, mut len, mut at) = (self.block.as_deref(), self.len_in_block, 0);
+        while let Some(b) = block {
+            let claimed = b.elems.get(..len).filter(|_| len > 0);
+            if claimed.is_none_or(|slots| slots.iter().any(|slot| slot.get().is_none())) {
+                return Err(Invalid::Node { at });
+            }
+            if b.base != b.next.as_ref().map_or(0, |next| next.base) + b.next_len {
+                return Err(Invalid::Node { at });
+            }
+            (block, len, at) = (b.next.as_deref(), b.next_len, at + 1);
+        }
+        match len {
+            0 => Ok(()),
+            len => Err(Invalid::Len {
+                stored: len,
+                counted: 0,
+            }),
+        }
+    }
+
+    pub fn head(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    // Index 0 is the head (most recently prepended element).
+    pub fn get(&self, mut index: usize) -> Option<&T> {
+        let mut block = self.block.as_deref()?;
+        let mut len = self.len_in_block;
+        while index >= len {
+            index -= len;
+            len = block.next_len;
+            block = block.next.as_deref()?;
+        }
+        block.elems[len - 1 - index].get()
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            block: self.block.as_deref(),
+            len: self.len_in_block,
+        }
+    }
+}
+
+impl<T> Default for VList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for VList<T> {
+    fn clone(&self) -> Self {
+        VList {
+            block: self.block.clone(),
+            len_in_block: self.len_in_block,
+        }
+    }
+}
+
+pub struct Iter<'a, T> {
+    block: Option<&'a Block<T>>,
+    len: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let block = self.block?;
+        self.len -= 1;
+        let elem = block.elems[self.len].get();
+        if self.len == 0 {
+            self.block = block.next.as_deref();
+            self.len = block.next_len;
+        }
+        elem
+    }
+}
+
+// Same iterative unwinding as the persistent list, just per block.
+impl<T> Drop for Block<T> {
+    fn drop(&mut self) {
+        let mut next = self.next.take();
+        while let Some(block) = next {
+            match Rc::into_inner(block) {
+                Some(mut block) => next = block.next.take(),
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::VList;
+    use std::rc::Rc;
+
+    #[test]
+    fn basics() {
+        let list = VList::new();
+        assert_eq!(list.head(), None);
+        assert!(list.is_empty());
+
+        let list = list.prepend(1).prepend(2).prepend(3);
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.head(), Some(&3));
+
+        let list = list.tail();
+        assert_eq!(list.head(), Some(&2));
+
+        let list = list.tail();
+        assert_eq!(list.head(), Some(&1));
+
+        let list = list.tail();
+        assert_eq!(list.head(), None);
+        assert_eq!(list.len(), 0);
+
+        let list = list.tail();
+        assert_eq!(list.head(), None);
+    }
+
+    #[test]
+    fn get_and_iter() {
+        let mut list = VList::new();
+        for i in 0..100 {
+            list = list.prepend(i);
+        }
+        assert_eq!(list.len(), 100);
+        for i in 0..100 {
+            assert_eq!(list.get(i), Some(&(99 - i)));
+        }
+        assert_eq!(list.get(100), None);
+        assert_eq!(
+            list.iter().copied().collect::<Vec<_>>(),
+            (0..100).rev().collect::<Vec<_>>()
+        );
+
+        // Blocks double, so 100 elements need only a handful of them.
+        let mut blocks = 0;
+        let mut block = list.block.as_deref();
+        while let Some(b) = block {
+            blocks += 1;
+            block = b.next.as_deref();
+        }
+        assert_eq!(blocks, 7);
+    }
+
+    #[test]
+    fn sharing() {
+        // Blocks of 1 and 2: [2, _] -> [1], so there is one free slot.
+        let base = VList::new().prepend(1).prepend(2);
+
+        // `base` owns the next free slot and extends in place.
+        let longer = base.prepend(3);
+        assert!(Rc::ptr_eq(
+            longer.block.as_ref().unwrap(),
+            base.block.as_ref().unwrap()
+        ));
+
+        // ...so a second prepend onto `base` has to branch off into a new block.
+        let other = base.prepend(30);
+        assert!(!Rc::ptr_eq(
+            other.block.as_ref().unwrap(),
+            base.block.as_ref().unwrap()
+        ));
+        assert_eq!(longer.iter().collect::<Vec<_>>(), vec![&3, &2, &1]);
+        assert_eq!(other.iter().collect::<Vec<_>>(), vec![&30, &2, &1]);
+        assert_eq!(base.iter().collect::<Vec<_>>(), vec![&2, &1]);
+        assert_eq!(other.len(), 3);
+
+        let forked = longer.tail().tail().prepend(20);
+        assert_eq!(forked.iter().collect::<Vec<_>>(), vec![&20, &1]);
+        assert_eq!(forked.get(1), Some(&1));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "debug-validate", ignore = "quadratic under debug-validate")]
+    fn long_drop() {
+        let mut list = VList::new();
+        let mut forks = Vec::new();
+        for i in 0..100_000 {
+            list = list.prepend(i);
+            if i % 1000 == 0 {
+                forks.push(list.tail().prepend(-i));
+            }
+        }
+        drop(list);
+        drop(forks);
+    }
+}
@@ -0,0 +1,332 @@
+/*
+ A wait list of pinned, self-linking waiters
+
+async_channel.rs keeps its waiting senders in an intrusive list inside the channel. This is the
+same idea pulled out on its own, the way async runtimes build their Notify and event types: a
+WaitList that tasks can wait on, and notify_one/notify_all to wake them.
+
+    let waiter = list.wait();    // a Waiter, not linked anywhere yet
+    waiter.await;                // first poll links it in; a notify unlinks it and wakes it
+
+Each Waiter carries its own list node, so waiting never allocates, and a waiter that gives up
+(its task is cancelled and the future dropped) takes itself out of the list in O(1). The list
+only holds raw pointers to those nodes, which live inside futures that other code owns. What
+keeps that sound is the API, not the caller's care:
+
+- A Waiter is !Unpin, and the only way to link it is poll, which takes Pin<&mut Self>. Once
+  it's pinned it can't move again, so the pointer the list has to it stays good.
+- Pin also promises that a pinned value's memory isn't reused until its Drop has run. Waiter's
+  Drop unlinks, so the list never points at a dead node. (mem::forget on a pinned Box just leaks
+  it, and leaked memory is still valid to point at.)
+- A Waiter borrows its WaitList, so the list can't be dropped or moved while one exists.
+- Nodes are only read or written with the list's lock held, whether by their own Waiter or by
+  whoever is walking the list. That's what makes Waiter Send and Sync despite the raw pointers.
+
+Waiters are woken in the order they first waited. notify_one is a baton: if the waiter it picks
+is dropped before it's polled again, it passes the notification on to the next one rather than
+losing it.
+*/
+
+use std::cell::UnsafeCell;
+use std::future::Future;
+use std::marker::PhantomPinned;
+use std::pin::Pin;
+use std::ptr;
+use std::sync::{Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+pub struct WaitList {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    head: *mut Node,
+    tail: *mut Node,
+    len: usize,
+}
+
+// The pointers are only followed with the lock held.
+unsafe impl Send for Inner {}
+
+struct Node {
+    waker: Option<Waker>,
+    prev: *mut Node,
+    next: *mut Node,
+    state: State,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    // Not polled yet, so not in the list.
+    Idle,
+    Waiting,
+    // Taken off the list by a notify, and not polled since. `one` is set for notify_one.
+    Notified { one: bool },
+    // Polled after being notified: the future has completed.
+    Done,
+}
+
+impl Inner {
+    // Safety (for both): `node` points at a live, pinned Node, and the caller holds the lock.
+    unsafe fn push_back(&mut self, node: *mut Node) {
+        (*node).prev = self.tail;
+        (*node).next = ptr::null_mut();
+        match self.tail.as_mut() {
+            Some(tail) => tail.next = node,
+            None => self.head = node,
+        }
+        self.tail = node;
+        self.len += 1;
+    }
+
+    unsafe fn remove(&mut self, node: *mut Node) {
+        let Node { prev, next, .. } = *node;
+        match prev.as_mut() {
+            Some(prev) => prev.next = next,
+            None => self.head = next,
+        }
+        match next.as_mut() {
+            Some(next) => next.prev = prev,
+            None => self.tail = prev,
+        }
+        self.len -= 1;
+    }
+
+    // Take the first waiter off the list and mark it notified. Returns its waker, to call once
+    // the lock is released.
+    fn notify_front(&mut self, one: bool) -> Option<Option<Waker>> {
+        let node = self.head;
+        if node.is_null() {
+            return None;
+        }
+        // Safety: everything in the list is live and pinned, and we hold the lock.
+        unsafe {
+            self.remove(node);
+            (*node).state = State::Notified { one };
+            Some((*node).waker.take())
+        }
+    }
+}
+
+impl WaitList {
+    pub const fn new() -> Self {
+        WaitList {
+            inner: Mutex::new(Inner {
+                head: ptr::null_mut(),
+                tail: ptr::null_mut(),
+                len: 0,
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        // Nothing panics while the list is half-updated, so a poisoned lock is still fine to use.
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // A future that completes once it's been notified. It only joins the queue when first
+    // polled.
+    pub fn wait(&self) -> Waiter<'_> {
+        Waiter {
+            list: self,
+            node: UnsafeCell::new(Node {
+                waker: None,
+                prev: ptr::null_mut(),
+                next: ptr::null_mut(),
+                state: State::Idle,
+            }),
+            _pin: PhantomPinned,
+        }
+    }
+
+    // Wake the waiter that's been waiting longest. False if nobody was waiting; the notification
+    // isn't saved for later.
+    pub fn notify_one(&self) -> bool {
+        let Some(waker) = self.lock().notify_front(true) else {
+            return false;
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        true
+    }
+
+    // Wake every waiter, returning how many there were.
+    pub fn notify_all(&self) -> usize {
+        let mut wakers = Vec::new();
+        let mut inner = self.lock();
+        while let Some(waker) = inner.notify_front(false) {
+            wakers.push(waker);
+        }
+        drop(inner);
+        let count = wakers.len();
+        wakers.into_iter().flatten().for_each(Waker::wake);
+        count
+    }
+
+    // How many waiters are in the list.
+    pub fn waiting(&self) -> usize {
+        self.lock().len
+    }
+}
+
+impl Default for WaitList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Waiter<'a> {
+    list: &'a WaitList,
+    // Only touched with the list's lock held.
+    node: UnsafeCell<Node>,
+    _pin: PhantomPinned,
+}
+
+// All access to the node goes through the list's lock.
+unsafe impl Send for Waiter<'_> {}
+unsafe impl Sync for Waiter<'_> {}
+
+impl Future for Waiter<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut inner = self.list.lock();
+        let node = self.node.get();
+        // Safety: we hold the lock, and `self` is pinned, so the node can be linked and will stay
+        // put until Drop unlinks it.
+        unsafe {
+            match (*node).state {
+                State::Idle => {
+                    (*node).waker = Some(cx.waker().clone());
+                    (*node).state = State::Waiting;
+                    inner.push_back(node);
+                    Poll::Pending
+                }
+                // Polled again, maybe from another task.
+                State::Waiting => {
+                    (*node).waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+                State::Notified { .. } | State::Done => {
+                    (*node).state = State::Done;
+                    Poll::Ready(())
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        let mut inner = self.list.lock();
+        let node = self.node.get();
+        // Safety: we hold the lock, and a waiting node is in the list.
+        let state = unsafe { (*node).state };
+        match state {
+            State::Waiting => unsafe { inner.remove(node) },
+            // Woken but never told: give the turn to the next waiter.
+            State::Notified { one: true } => {
+                let waker = inner.notify_front(true);
+                drop(inner);
+                if let Some(Some(waker)) = waker {
+                    waker.wake();
+                }
+            }
+            State::Idle | State::Notified { one: false } | State::Done => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::WaitList;
+    use futures::executor::block_on;
+    use futures::task::noop_waker_ref;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Context;
+    use std::thread;
+
+    #[test]
+    fn wakes_in_order() {
+        let list = WaitList::new();
+        let cx = &mut Context::from_waker(noop_waker_ref());
+        let mut a = pin!(list.wait());
+        let mut b = pin!(list.wait());
+        let mut c = pin!(list.wait());
+        assert!(!list.notify_one());
+        assert_eq!(list.waiting(), 0);
+
+        for waiter in [a.as_mut(), b.as_mut(), c.as_mut()] {
+            assert!(waiter.poll(cx).is_pending());
+        }
+        assert_eq!(list.waiting(), 3);
+
+        assert!(list.notify_one());
+        assert!(a.as_mut().poll(cx).is_ready());
+        assert!(b.as_mut().poll(cx).is_pending());
+        assert_eq!(list.notify_all(), 2);
+        assert!(b.as_mut().poll(cx).is_ready());
+        assert!(c.as_mut().poll(cx).is_ready());
+        assert_eq!(list.waiting(), 0);
+    }
+
+    #[test]
+    fn dropped_waiters_unlink() {
+        let list = WaitList::new();
+        let cx = &mut Context::from_waker(noop_waker_ref());
+        let mut last = pin!(list.wait());
+        {
+            let mut gone = pin!(list.wait());
+            assert!(gone.as_mut().poll(cx).is_pending());
+            assert!(last.as_mut().poll(cx).is_pending());
+            assert_eq!(list.waiting(), 2);
+        }
+        assert_eq!(list.waiting(), 1);
+        assert!(list.notify_one());
+        assert!(last.as_mut().poll(cx).is_ready());
+
+        // Never polled, so never linked.
+        drop(list.wait());
+        assert_eq!(list.waiting(), 0);
+    }
+
+    #[test]
+    fn passes_notification_on() {
+        let list = WaitList::new();
+        let cx = &mut Context::from_waker(noop_waker_ref());
+        let mut second = pin!(list.wait());
+        {
+            let mut first = pin!(list.wait());
+            let _ = first.as_mut().poll(cx);
+            let _ = second.as_mut().poll(cx);
+            assert!(list.notify_one());
+            // `first` is dropped here without having seen it...
+        }
+        // ...so `second` gets it instead.
+        assert_eq!(list.waiting(), 0);
+        assert!(second.as_mut().poll(cx).is_ready());
+    }
+
+    #[test]
+    fn across_threads() {
+        let list = WaitList::new();
+        let woken = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    block_on(list.wait());
+                    woken.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+            // A notify before a thread has started waiting is lost, so keep at it.
+            while woken.load(Ordering::SeqCst) < 4 {
+                list.notify_all();
+                thread::yield_now();
+            }
+        });
+        assert_eq!(list.waiting(), 0);
+    }
+}
@@ -0,0 +1,185 @@
+/*
+ The lists from JavaScript
+
+With the `wasm` feature, and built for wasm32-unknown-unknown with wasm-bindgen, this module
+exports two of the crate's lists as JavaScript classes. The elements are JsValues, so anything a
+page has (numbers, strings, objects, DOM nodes) can go in, and a visualization can drive the real
+data structure instead of a JavaScript imitation of it:
+
+    import { Deque, PersistentList } from "./pkg/linked_list_rc.js";
+
+    const deque = new Deque();
+    deque.pushBack({ id: 1 });
+    deque.pushFront("zero");
+    deque.length;            // 2
+    deque.popBack();         // { id: 1 }
+    deque.popFront();        // "zero"
+    deque.popFront();        // undefined
+
+    const xs = new PersistentList().prepend(3).prepend(2);
+    const ys = xs.prepend(1);
+    ys.toArray();            // [1, 2, 3]
+    xs.toArray();            // [2, 3], unchanged
+    ys.sharesTailWith(xs);   // xs itself: every node of xs is shared
+
+- Deque is unsafe_doubly_linked_deque::List<JsValue>: pushFront, pushBack, popFront, popBack,
+  peekFront, peekBack, length, clear.
+- PersistentList is persistent_stack::List<JsValue>: prepend and tail return new lists, head
+  peeks, and sharesTailWith(other) returns the longest tail the two lists share node for node
+  (see persistent_stack.rs), which is what a picture of structural sharing needs.
+
+Empty is `undefined`, like Array.prototype.pop, rather than an exception.
+
+Iterating is toArray(): a JavaScript array of the elements, front first, for `for...of` or to hand
+to a rendering library. It's a snapshot, so the list can change while the page walks it.
+
+Build with `wasm-pack build --target web -- --features wasm` (or cargo build for wasm32 and run
+wasm-bindgen on the output). The tests need wasm-bindgen-test-runner:
+`cargo test --target wasm32-unknown-unknown --features wasm --lib wasm`, which .cargo/config.toml
+sets up.
+*/
+
+use crate::{persistent_stack, unsafe_doubly_linked_deque};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(js_name = Deque)]
+pub struct JsDeque(unsafe_doubly_linked_deque::List<JsValue>);
+
+#[wasm_bindgen(js_class = Deque)]
+impl JsDeque {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> JsDeque {
+        JsDeque(unsafe_doubly_linked_deque::List::new())
+    }
+
+    #[wasm_bindgen(js_name = pushFront)]
+    pub fn push_front(&mut self, elem: JsValue) {
+        self.0.push_front(elem);
+    }
+
+    #[wasm_bindgen(js_name = pushBack)]
+    pub fn push_back(&mut self, elem: JsValue) {
+        self.0.push_back(elem);
+    }
+
+    #[wasm_bindgen(js_name = popFront)]
+    pub fn pop_front(&mut self) -> Option<JsValue> {
+        self.0.pop_front()
+    }
+
+    #[wasm_bindgen(js_name = popBack)]
+    pub fn pop_back(&mut self) -> Option<JsValue> {
+        self.0.pop_back()
+    }
+
+    // A new reference to the same JavaScript value, not a copy of it.
+    #[wasm_bindgen(js_name = peekFront)]
+    pub fn peek_front(&self) -> Option<JsValue> {
+        self.0.front().cloned()
+    }
+
+    #[wasm_bindgen(js_name = peekBack)]
+    pub fn peek_back(&self) -> Option<JsValue> {
+        self.0.back().cloned()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    #[wasm_bindgen(js_name = toArray)]
+    pub fn to_array(&self) -> Vec<JsValue> {
+        self.0.iter().cloned().collect()
+    }
+}
+
+impl Default for JsDeque {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen(js_name = PersistentList)]
+pub struct JsPersistentList(persistent_stack::List<JsValue>);
+
+#[wasm_bindgen(js_class = PersistentList)]
+impl JsPersistentList {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> JsPersistentList {
+        JsPersistentList(persistent_stack::List::new())
+    }
+
+    pub fn prepend(&self, elem: JsValue) -> JsPersistentList {
+        JsPersistentList(self.0.prepend(elem))
+    }
+
+    pub fn tail(&self) -> JsPersistentList {
+        JsPersistentList(self.0.tail())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn head(&self) -> Option<JsValue> {
+        self.0.head().cloned()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.0.len()
+    }
+
+    #[wasm_bindgen(js_name = sharesTailWith)]
+    pub fn shares_tail_with(&self, other: &JsPersistentList) -> Option<JsPersistentList> {
+        self.0.shares_tail_with(&other.0).map(JsPersistentList)
+    }
+
+    #[wasm_bindgen(js_name = toArray)]
+    pub fn to_array(&self) -> Vec<JsValue> {
+        self.0.iter().cloned().collect()
+    }
+}
+
+impl Default for JsPersistentList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// JsValues only exist inside a JavaScript engine, so these only run on wasm32.
+#[cfg(all(test, target_arch = "wasm32"))]
+mod test {
+    use super::{JsDeque, JsPersistentList};
+    use wasm_bindgen::JsValue;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn deque() {
+        let mut deque = JsDeque::new();
+        deque.push_back(JsValue::from(2));
+        deque.push_front(JsValue::from("one"));
+        assert_eq!(deque.length(), 2);
+        assert_eq!(deque.peek_back(), Some(JsValue::from(2)));
+        assert_eq!(deque.to_array(), [JsValue::from("one"), JsValue::from(2)]);
+        assert_eq!(deque.pop_front(), Some(JsValue::from("one")));
+        assert_eq!(deque.pop_back(), Some(JsValue::from(2)));
+        assert_eq!(deque.pop_back(), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn persistent_list() {
+        let xs = JsPersistentList::new()
+            .prepend(JsValue::from(3))
+            .prepend(JsValue::from(2));
+        let ys = xs.prepend(JsValue::from(1));
+        assert_eq!(ys.length(), 3);
+        assert_eq!(ys.head(), Some(JsValue::from(1)));
+        assert_eq!(xs.to_array(), [JsValue::from(2), JsValue::from(3)]);
+        let shared = ys.shares_tail_with(&xs).unwrap();
+        assert_eq!(shared.length(), 2);
+        assert_eq!(JsPersistentList::new().tail().head(), None);
+    }
+}
@@ -0,0 +1,547 @@
+/*
+ An XOR linked list
+
+A doubly linked list needs two pointers per node. An XOR list gets away with one: each node
+stores `prev ^ next`, the XOR of its neighbours' addresses (with 0 standing in for "none"):
+
+addr:      100          200          300
+        [A: 0^200] <-> [B: 100^300] <-> [C: 200^0]
+
+Walking it needs two addresses at a time. Coming from A (100) into B, B's link is 100 ^ 300, and
+XORing it with where we came from leaves where we're going: 300. Walking backwards works the same
+way, which is why both ends, a cursor, and a double-ended iterator are all possible. What's not
+possible is starting from a node on its own: without one of its neighbours there's no way to
+untangle its link.
+
+The catch in Rust is provenance. A pointer isn't just an address; it also carries permission to
+access one particular allocation, and an address that went through XOR has lost it. With every
+node in its own Box, the only way back from an address to a usable pointer is the "exposed
+provenance" API (with_exposed_provenance), which the std docs point to for exactly this case.
+
+This list stays within strict provenance instead. Nodes aren't allocated one by one; they're
+carved out of a few large chunks, each a single allocation:
+
+chunks: [ n n n n ] [ n n n n n n n n ] [ n n n n n n n n n n n n n n n n ] ...
+
+Turning an address back into a pointer means finding the chunk it falls in and calling
+`chunk.with_addr(addr)`, which borrows that chunk's provenance. Chunks double in size, so there
+are only O(log n) of them to look through, and freed nodes go on a free list (threaded through
+the same link field) to be reused before any new chunk is needed.
+
+Run the tests under `MIRIFLAGS=-Zmiri-strict-provenance cargo +nightly miri test xor_list` to
+check that claim.
+*/
+
+use crate::mem_usage::MemUsage;
+use crate::panic_safety;
+use crate::validate::{self, Invalid};
+use std::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use std::marker::PhantomData;
+use std::mem::{self, size_of, MaybeUninit};
+use std::ptr::NonNull;
+
+pub struct XorList<T> {
+    // Addresses, not pointers: 0 means "none".
+    front: usize,
+    back: usize,
+    len: usize,
+    pool: Pool<T>,
+}
+
+struct Node<T> {
+    // Initialized exactly while the node is in the list.
+    elem: MaybeUninit<T>,
+    // prev ^ next while in the list; the next free node while on the free list.
+    link: usize,
+}
+
+struct Chunk<T> {
+    ptr: NonNull<Node<T>>,
+    cap: usize,
+}
+
+struct Pool<T> {
+    chunks: Vec<Chunk<T>>,
+    // How many nodes of the last chunk have been handed out so far.
+    used: usize,
+    free: usize,
+    _boo: PhantomData<T>,
+}
+
+const FIRST_CHUNK: usize = 4;
+
+impl<T> Pool<T> {
+    // Give `addr` back the provenance of the chunk it lives in.
+    fn node(&self, addr: usize) -> *mut Node<T> {
+        self.find(addr)
+            .unwrap_or_else(|| unreachable!("address {addr:#x} is not in any chunk"))
+    }
+
+    fn find(&self, addr: usize) -> Option<*mut Node<T>> {
+        let size = mem::size_of::<Node<T>>();
+        // Newest (biggest) chunk first.
+        self.chunks.iter().rev().find_map(|chunk| {
+            let start = chunk.ptr.as_ptr().addr();
+            let inside = (start..start + chunk.cap * size).contains(&addr)
+                && (addr - start).is_multiple_of(size);
+            inside.then(|| chunk.ptr.as_ptr().with_addr(addr))
+        })
+    }
+
+    // Nodes handed out so far, in the list or on the free list.
+    fn handed_out(&self) -> usize {
+        let full: usize = self.chunks.iter().map(|chunk| chunk.cap).sum();
+        full - self.chunks.last().map_or(0, |last| last.cap - self.used)
+    }
+
+    fn alloc(&mut self, elem: T) -> *mut Node<T> {
+        let node = if self.free != 0 {
+            let node = self.node(self.free);
+            self.free = unsafe { (*node).link };
+            node
+        } else {
+            if self.chunks.last().is_none_or(|last| self.used == last.cap) {
+                let cap = self.chunks.last().map_or(FIRST_CHUNK, |last| last.cap * 2);
+                let layout = Layout::array::<Node<T>>(cap).unwrap();
+                let Some(ptr) = NonNull::new(unsafe { alloc(layout) }.cast()) else {
+                    handle_alloc_error(layout);
+                };
+                self.chunks.push(Chunk { ptr, cap });
+                self.used = 0;
+            }
+            let node = unsafe { self.chunks.last().unwrap().ptr.as_ptr().add(self.used) };
+            self.used += 1;
+            node
+        };
+        unsafe {
+            node.write(Node {
+                elem: MaybeUninit::new(elem),
+                link: 0,
+            })
+        };
+        node
+    }
+
+    // Safety: `node` came from alloc, and its element has already been moved out or dropped.
+    unsafe fn release(&mut self, node: *mut Node<T>) {
+        (*node).link = self.free;
+        self.free = node.addr();
+    }
+}
+
+impl<T> Drop for Pool<T> {
+    fn drop(&mut self) {
+        // Elements are the list's business; by now they're all gone.
+        for chunk in &self.chunks {
+            let layout = Layout::array::<Node<T>>(chunk.cap).unwrap();
+            unsafe { dealloc(chunk.ptr.as_ptr().cast(), layout) };
+        }
+    }
+}
+
+impl<T> XorList<T> {
+    pub fn new() -> Self {
+        XorList {
+            front: 0,
+            back: 0,
+            len: 0,
+            pool: Pool {
+                chunks: Vec::new(),
+                used: 0,
+                free: 0,
+                _boo: PhantomData,
+            },
+        }
+    }
+
+    fn link(&self, addr: usize) -> usize {
+        unsafe { (*self.pool.node(addr)).link }
+    }
+
+    // `len` nodes from front, each at the address of a node in the pool, the last of them back.
+    // Untangling each link with the node before it is the only way to find the next one, so the
+    // walk also checks each link's back half. The rest of the nodes the pool has handed out are on
+    // its free list. See validate.rs.
+    pub fn validate(&self) -> Result<(), Invalid> {
+        let (mut prev, mut at, mut stray) = (0, 0, None);
+        let walked = validate::walk((self.front != 0).then_some(self.front), self.len, |addr| {
+            let Some(node) = self.pool.find(addr) else {
+                stray.get_or_insert(at);
+                return None;
+            };
+            let next = unsafe { (*node).link } ^ prev;
+            (prev, at) = (addr, at + 1);
+            (next != 0).then_some(next)
+        });
+        if let Some(at) = stray {
+            return Err(Invalid::Node { at });
+        }
+        if walked?.unwrap_or(0) != self.back {
+            return Err(Invalid::Ends);
+        }
+
+        let free = self.pool.handed_out() - self.len;
+        let (mut at, mut stray) = (0, None);
+        let walked = validate::walk(
+            (self.pool.free != 0).then_some(self.pool.free),
+            free,
+            |addr| {
+                let Some(node) = self.pool.find(addr) else {
+                    stray.get_or_insert(at);
+                    return None;
+                };
+                at += 1;
+                let next = unsafe { (*node).link };
+                (next != 0).then_some(next)
+            },
+        );
+        if let Some(at) = stray {
+            return Err(Invalid::Node { at });
+        }
+        walked.map(drop)
+    }
+
+    pub fn push_front(&mut self, elem: T) {
+        let node = self.pool.alloc(elem);
+        unsafe {
+            (*node).link = self.front;
+            if self.front != 0 {
+                // Its link was 0 ^ next; now it's node ^ next.
+                (*self.pool.node(self.front)).link ^= node.addr();
+            } else {
+                self.back = node.addr();
+            }
+        }
+        self.front = node.addr();
+        self.len += 1;
+        validate::check!(self);
+    }
+
+    pub fn push_back(&mut self, elem: T) {
+        let node = self.pool.alloc(elem);
+        unsafe {
+            (*node).link = self.back;
+            if self.back != 0 {
+                (*self.pool.node(self.back)).link ^= node.addr();
+            } else {
+                self.front = node.addr();
+            }
+        }
+        self.back = node.addr();
+        self.len += 1;
+        validate::check!(self);
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.front == 0 {
+            return None;
+        }
+        unsafe {
+            let node = self.pool.node(self.front);
+            // The front has no prev, so its link is just next.
+            let next = (*node).link;
+            if next != 0 {
+                (*self.pool.node(next)).link ^= self.front;
+            } else {
+                self.back = 0;
+            }
+            self.front = next;
+            self.len -= 1;
+            let elem = (*node).elem.assume_init_read();
+            self.pool.release(node);
+            validate::check!(self);
+            Some(elem)
+        }
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        if self.back == 0 {
+            return None;
+        }
+        unsafe {
+            let node = self.pool.node(self.back);
+            let prev = (*node).link;
+            if prev != 0 {
+                (*self.pool.node(prev)).link ^= self.back;
+            } else {
+                self.front = 0;
+            }
+            self.back = prev;
+            self.len -= 1;
+            let elem = (*node).elem.assume_init_read();
+            self.pool.release(node);
+            validate::check!(self);
+            Some(elem)
+        }
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.elem(self.front)
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        self.elem(self.back)
+    }
+
+    fn elem(&self, addr: usize) -> Option<&T> {
+        (addr != 0).then(|| unsafe { (*self.pool.node(addr)).elem.assume_init_ref() })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Nodes come from the pool's chunks, so the chunks (free nodes and all) are the heap. See
+    // mem_usage.rs.
+    pub fn mem_usage(&self) -> MemUsage {
+        let chunks = &self.pool.chunks;
+        let nodes: usize = chunks.iter().map(|chunk| chunk.cap).sum();
+        MemUsage {
+            heap_bytes: nodes * size_of::<Node<T>>() + chunks.capacity() * size_of::<Chunk<T>>(),
+            ..MemUsage::nodes::<Node<T>, T>(self.len, 1)
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            list: self,
+            front: (0, self.front),
+            back: (0, self.back),
+            len: self.len,
+        }
+    }
+
+    // A cursor starting on the "ghost" position between back and front, like the deque's.
+    pub fn cursor(&self) -> Cursor<'_, T> {
+        Cursor {
+            list: self,
+            prev: 0,
+            cur: 0,
+            index: None,
+        }
+    }
+}
+
+// Carrying on past an element whose Drop panics: see panic_safety.rs.
+impl<T> Drop for XorList<T> {
+    fn drop(&mut self) {
+        panic_safety::pop_all(self, |list| list.pop_front().is_some());
+    }
+}
+
+impl<T> Default for XorList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> FromIterator<T> for XorList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = XorList::new();
+        for elem in iter {
+            list.push_back(elem);
+        }
+        list
+    }
+}
+
+// The list owns its elements outright, like a Box would.
+unsafe impl<T: Send> Send for XorList<T> {}
+unsafe impl<T: Sync> Sync for XorList<T> {}
+// The elements stay where they are in the pool's blocks when the list moves.
+impl<T> Unpin for XorList<T> {}
+
+// Each end remembers the pair (the address it came from, the address it's at).
+pub struct Iter<'a, T> {
+    list: &'a XorList<T>,
+    front: (usize, usize),
+    back: (usize, usize),
+    len: usize,
+}
+
+impl<'a, T> Iter<'a, T> {
+    fn step(list: &'a XorList<T>, (from, at): &mut (usize, usize)) -> &'a T {
+        let elem = list.elem(*at).unwrap();
+        let to = list.link(*at) ^ *from;
+        (*from, *at) = (*at, to);
+        elem
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        Some(Self::step(self.list, &mut self.front))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        Some(Self::step(self.list, &mut self.back))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a XorList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct Cursor<'a, T> {
+    list: &'a XorList<T>,
+    // The node before `cur` (0 at the front), and `cur` itself (0 on the ghost).
+    prev: usize,
+    cur: usize,
+    index: Option<usize>,
+}
+
+impl<'a, T> Cursor<'a, T> {
+    pub fn index(&self) -> Option<usize> {
+        self.index
+    }
+
+    pub fn current(&self) -> Option<&'a T> {
+        self.list.elem(self.cur)
+    }
+
+    pub fn move_next(&mut self) {
+        if self.cur == 0 {
+            (self.prev, self.cur) = (0, self.list.front);
+            self.index = (self.cur != 0).then_some(0);
+        } else {
+            let next = self.list.link(self.cur) ^ self.prev;
+            (self.prev, self.cur) = if next == 0 { (0, 0) } else { (self.cur, next) };
+            self.index = self.index.map(|i| i + 1).filter(|_| next != 0);
+        }
+    }
+
+    pub fn move_prev(&mut self) {
+        if self.cur == 0 {
+            let back = self.list.back;
+            // The back's link is just its prev.
+            (self.prev, self.cur) = if back == 0 {
+                (0, 0)
+            } else {
+                (self.list.link(back), back)
+            };
+            self.index = self.list.len.checked_sub(1);
+        } else if self.prev == 0 {
+            // Off the front, onto the ghost.
+            (self.prev, self.cur, self.index) = (0, 0, None);
+        } else {
+            // The new prev is whatever's on the far side of our prev.
+            let before = self.list.link(self.prev) ^ self.cur;
+            (self.prev, self.cur) = (before, self.prev);
+            self.index = self.index.map(|i| i - 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::XorList;
+
+    #[test]
+    fn basics() {
+        let mut list = XorList::new();
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.pop_back(), None);
+
+        list.push_back(2);
+        list.push_back(3);
+        list.push_front(1);
+        assert_eq!(list.front(), Some(&1));
+        assert_eq!(list.back(), Some(&3));
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(
+            list.iter().rev().copied().collect::<Vec<_>>(),
+            vec![3, 2, 1]
+        );
+
+        assert_eq!(list.pop_back(), Some(3));
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.pop_front(), Some(2));
+        assert!(list.is_empty());
+        assert_eq!(list.back(), None);
+    }
+
+    #[test]
+    fn iter_meets_in_the_middle() {
+        let list: XorList<i32> = (0..5).collect();
+        let mut iter = list.iter();
+        assert_eq!(iter.next(), Some(&0));
+        assert_eq!(iter.next_back(), Some(&4));
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.next_back(), Some(&3));
+        assert_eq!(iter.len(), 1);
+        assert_eq!(iter.next(), Some(&2));
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    fn cursor() {
+        let list: XorList<i32> = (1..=3).collect();
+        let mut cursor = list.cursor();
+        assert_eq!(cursor.current(), None);
+
+        cursor.move_next();
+        assert_eq!((cursor.index(), cursor.current()), (Some(0), Some(&1)));
+        cursor.move_next();
+        cursor.move_next();
+        assert_eq!((cursor.index(), cursor.current()), (Some(2), Some(&3)));
+        cursor.move_next();
+        assert_eq!((cursor.index(), cursor.current()), (None, None));
+
+        // And back the other way, across the ghost.
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&3));
+        cursor.move_prev();
+        cursor.move_prev();
+        assert_eq!((cursor.index(), cursor.current()), (Some(0), Some(&1)));
+        cursor.move_prev();
+        assert_eq!(cursor.current(), None);
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&3));
+    }
+
+    #[test]
+    fn reuses_nodes_across_chunks() {
+        // Grow over several chunks, then churn: freed nodes come back before new chunks do.
+        let mut list = XorList::new();
+        for i in 0..100 {
+            list.push_back(i.to_string());
+        }
+        let chunks = list.pool.chunks.len();
+        for i in 0..1000 {
+            let s = list.pop_front().unwrap();
+            assert_eq!(s, i.to_string());
+            list.push_back((i + 100).to_string());
+        }
+        assert_eq!(list.pool.chunks.len(), chunks);
+        assert_eq!(list.iter().next().map(String::as_str), Some("1000"));
+        assert_eq!(list.iter().next_back().map(String::as_str), Some("1099"));
+    }
+}
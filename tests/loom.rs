@@ -0,0 +1,139 @@
+// Model-checked tests for the concurrent modules. loom runs each closure once for every possible
+// interleaving of its threads (and every allowed reordering of their atomics), so keep them tiny.
+// They only exist when loom is swapped in:
+//
+//     RUSTFLAGS="--cfg loom" cargo test --release --test loom
+
+#![cfg(loom)]
+
+use linked_list_rc::epoch;
+use linked_list_rc::mpsc_channel::channel;
+use linked_list_rc::ms_queue::MsQueue;
+use linked_list_rc::spsc_queue::queue;
+use linked_list_rc::treiber_stack::TreiberStack;
+use loom::sync::Arc;
+use loom::thread;
+
+// Bounding preemptions keeps the epoch collector's state space manageable; most real bugs need
+// only two or three badly timed context switches to show up.
+fn model(f: impl Fn() + Sync + Send + 'static) {
+    let mut builder = loom::model::Builder::new();
+    builder.preemption_bound = Some(3);
+    builder.check(f);
+}
+
+#[test]
+fn treiber_push_pop() {
+    model(|| {
+        let stack = Arc::new(TreiberStack::new());
+        stack.push(1);
+
+        let s = stack.clone();
+        let a = thread::spawn(move || {
+            s.push(2);
+            s.pop()
+        });
+        let s = stack.clone();
+        let b = thread::spawn(move || s.pop());
+        let popped = [a.join().unwrap(), b.join().unwrap()];
+
+        // Whatever the order, each value comes out exactly once.
+        let mut all: Vec<_> = popped.into_iter().chain([stack.pop()]).flatten().collect();
+        all.sort();
+        assert_eq!(all, vec![1, 2]);
+    });
+}
+
+#[test]
+fn treiber_concurrent_pops_with_collection() {
+    model(|| {
+        let stack = Arc::new(TreiberStack::new());
+        stack.push(1);
+        stack.push(2);
+
+        // Both threads try to move the epoch on and free what was popped, while the other one may
+        // still be looking at that node.
+        let pop_and_flush = |s: Arc<TreiberStack<i32>>| {
+            move || {
+                let v = s.pop();
+                epoch::pin().flush();
+                v
+            }
+        };
+        let a = thread::spawn(pop_and_flush(stack.clone()));
+        let b = thread::spawn(pop_and_flush(stack.clone()));
+
+        let mut all = vec![a.join().unwrap().unwrap(), b.join().unwrap().unwrap()];
+        all.sort();
+        assert_eq!(all, vec![1, 2]);
+    });
+}
+
+#[test]
+fn ms_queue_push_pop() {
+    model(|| {
+        let queue = Arc::new(MsQueue::new());
+        let q = queue.clone();
+        let producer = thread::spawn(move || {
+            q.push(1);
+            q.push(2);
+        });
+        let q = queue.clone();
+        let consumer = thread::spawn(move || q.pop());
+        producer.join().unwrap();
+        let first = consumer.join().unwrap();
+
+        // FIFO: if the consumer got anything early, it was the first value.
+        let rest: Vec<_> = std::iter::from_fn(|| queue.pop()).collect();
+        match first {
+            Some(1) => assert_eq!(rest, vec![2]),
+            None => assert_eq!(rest, vec![1, 2]),
+            other => panic!("popped {other:?} first"),
+        }
+    });
+}
+
+#[test]
+fn spsc_reuses_nodes() {
+    model(|| {
+        let (mut tx, mut rx) = queue(1);
+        let producer = thread::spawn(move || {
+            for i in 0..3 {
+                tx.push(i);
+            }
+        });
+
+        let mut got = Vec::new();
+        while got.len() < 3 {
+            match rx.pop() {
+                Some(v) => got.push(v),
+                None => thread::yield_now(),
+            }
+        }
+        producer.join().unwrap();
+        assert_eq!(got, vec![0, 1, 2]);
+    });
+}
+
+#[test]
+fn mpsc_two_senders() {
+    model(|| {
+        let (tx, rx) = channel();
+        let tx2 = tx.clone();
+        let a = thread::spawn(move || tx.send(1).unwrap());
+        let b = thread::spawn(move || tx2.send(2).unwrap());
+
+        // recv() may park before either send lands; it must still be woken for both, and then
+        // see the disconnect. It runs on its own thread: loom can't tell a late unpark() from a
+        // wakeup of whatever else that thread is blocked on, like a join.
+        let receiver = thread::spawn(move || {
+            let mut got = vec![rx.recv().unwrap(), rx.recv().unwrap()];
+            got.sort();
+            assert_eq!(got, vec![1, 2]);
+            assert!(rx.recv().is_err());
+        });
+        a.join().unwrap();
+        b.join().unwrap();
+        receiver.join().unwrap();
+    });
+}
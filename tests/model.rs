@@ -0,0 +1,506 @@
+// Differential tests: every list against a VecDeque. proptest generates a sequence of operations,
+// each one is done to the list and to the VecDeque, and after each the two have to agree, on
+// what the operation returned and on the whole contents front to back. When they don't, proptest
+// shrinks the sequence to the shortest one that still fails and prints it.
+//
+//     cargo test --test model
+//     PROPTEST_CASES=10000 cargo test --release --test model
+//
+// The unit tests check the cases someone thought of. This finds the ones nobody did: a split at
+// the last node, a remove right after an append, a pop that empties a list built by inserts.
+//
+// Not every list can do every operation. A list skips the ones it has no method for, and the
+// model skips them with it, so each list is checked on exactly what it offers.
+//
+// pointer_list's Box list is generic_and_iterators::List, so it's checked there, on Global and
+// again on a Bump. The persistent lists aren't here: every operation they have is that list's
+// too, written once in pointer_list.rs, and the unit tests there check what the sharing changes.
+
+#![cfg_attr(feature = "nightly", feature(allocator_api))]
+
+use linked_list_rc::allocator::{Allocator, Bump};
+use linked_list_rc::arena_list::ArenaList;
+use linked_list_rc::ghost_list::{GhostList, GhostToken};
+use linked_list_rc::indexed_list::IndexedList;
+use linked_list_rc::small_list::SmallList;
+use linked_list_rc::traits::Collection;
+use linked_list_rc::unrolled_list::UnrolledList;
+use linked_list_rc::xor_list::XorList;
+use linked_list_rc::{
+    doubly_linked_deque, generic_and_iterators, unsafe_doubly_linked_deque,
+    unsafe_single_linked_queue,
+};
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::sample::Index;
+use std::collections::VecDeque;
+use std::mem;
+
+#[derive(Clone, Debug)]
+enum Op {
+    PushFront(u8),
+    PushBack(u8),
+    PopFront,
+    PopBack,
+    // Indexes are resolved against the length when the operation runs: 0..=len for Insert and
+    // SplitOff, 0..len for Remove (which is skipped on an empty list).
+    Insert(Index, u8),
+    Remove(Index),
+    // Keep the front, return the rest.
+    SplitOff(Index),
+    Append(Vec<u8>),
+}
+
+// What an operation gave back.
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Nothing,
+    Elem(Option<u8>),
+    Split(Vec<u8>),
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        3 => any::<u8>().prop_map(Op::PushFront),
+        3 => any::<u8>().prop_map(Op::PushBack),
+        2 => Just(Op::PopFront),
+        2 => Just(Op::PopBack),
+        2 => (any::<Index>(), any::<u8>()).prop_map(|(i, x)| Op::Insert(i, x)),
+        2 => any::<Index>().prop_map(Op::Remove),
+        1 => any::<Index>().prop_map(Op::SplitOff),
+        1 => vec(any::<u8>(), 0..8).prop_map(Op::Append),
+    ]
+}
+
+trait Subject {
+    // None if this list has no way to do `op`. `len` is the current length.
+    fn apply(&mut self, op: &Op, len: usize) -> Option<Outcome>;
+
+    fn contents(&mut self) -> Vec<u8>;
+}
+
+impl Subject for VecDeque<u8> {
+    fn apply(&mut self, op: &Op, len: usize) -> Option<Outcome> {
+        Some(match op {
+            Op::PushFront(x) => {
+                self.push_front(*x);
+                Outcome::Nothing
+            }
+            Op::PushBack(x) => {
+                self.push_back(*x);
+                Outcome::Nothing
+            }
+            Op::PopFront => Outcome::Elem(self.pop_front()),
+            Op::PopBack => Outcome::Elem(self.pop_back()),
+            Op::Insert(i, x) => {
+                self.insert(i.index(len + 1), *x);
+                Outcome::Nothing
+            }
+            Op::Remove(i) => Outcome::Elem(self.remove(i.index(len))),
+            Op::SplitOff(i) => Outcome::Split(self.split_off(i.index(len + 1)).into()),
+            Op::Append(xs) => {
+                self.extend(xs);
+                Outcome::Nothing
+            }
+        })
+    }
+
+    fn contents(&mut self) -> Vec<u8> {
+        self.iter().copied().collect()
+    }
+}
+
+// `list` starts out empty. It's passed in because some lists need something to be made with: an
+// allocator, or a GhostToken.
+fn check(mut list: impl Subject, ops: &[Op]) {
+    let mut model = VecDeque::new();
+    for op in ops {
+        if matches!(op, Op::Remove(_)) && model.is_empty() {
+            continue;
+        }
+        let len = model.len();
+        let Some(got) = list.apply(op, len) else {
+            continue;
+        };
+        assert_eq!(got, model.apply(op, len).unwrap(), "{op:?}");
+        assert_eq!(list.contents(), model.contents(), "after {op:?}");
+    }
+}
+
+// A cursor on element `at`.
+fn cursor_at(
+    list: &mut unsafe_doubly_linked_deque::List<u8>,
+    at: usize,
+) -> unsafe_doubly_linked_deque::CursorMut<'_, u8> {
+    let mut cursor = list.cursor_mut();
+    for _ in 0..=at {
+        cursor.move_next();
+    }
+    cursor
+}
+
+impl Subject for unsafe_doubly_linked_deque::List<u8> {
+    fn apply(&mut self, op: &Op, len: usize) -> Option<Outcome> {
+        Some(match op {
+            Op::PushFront(x) => {
+                self.push_front(*x);
+                Outcome::Nothing
+            }
+            Op::PushBack(x) => {
+                self.push_back(*x);
+                Outcome::Nothing
+            }
+            Op::PopFront => Outcome::Elem(self.pop_front()),
+            Op::PopBack => Outcome::Elem(self.pop_back()),
+            Op::Insert(i, x) => {
+                match i.index(len + 1) {
+                    at if at == len => self.push_back(*x),
+                    at => cursor_at(self, at).insert_before(*x),
+                }
+                Outcome::Nothing
+            }
+            Op::Remove(i) => Outcome::Elem(cursor_at(self, i.index(len)).remove_current()),
+            Op::SplitOff(i) => {
+                let rest = match i.index(len + 1) {
+                    0 => mem::take(self),
+                    at => cursor_at(self, at - 1).split_after(),
+                };
+                Outcome::Split(rest.into_iter().collect())
+            }
+            Op::Append(xs) => {
+                self.append(&mut xs.iter().copied().collect());
+                Outcome::Nothing
+            }
+        })
+    }
+
+    fn contents(&mut self) -> Vec<u8> {
+        // Both ways, so a broken `front` link shows up too.
+        let forwards: Vec<u8> = self.iter().copied().collect();
+        let mut backwards: Vec<u8> = self.iter().rev().copied().collect();
+        backwards.reverse();
+        assert_eq!(forwards, backwards);
+        assert_eq!(forwards.len(), self.len());
+        forwards
+    }
+}
+
+impl Subject for unsafe_single_linked_queue::List<u8> {
+    fn apply(&mut self, op: &Op, len: usize) -> Option<Outcome> {
+        Some(match op {
+            Op::PushBack(x) => {
+                self.push(*x);
+                Outcome::Nothing
+            }
+            Op::PopFront => Outcome::Elem(self.pop()),
+            Op::SplitOff(i) => Outcome::Split(self.split_off(i.index(len + 1)).into_vec()),
+            Op::Append(xs) => {
+                self.append(&mut unsafe_single_linked_queue::List::from_vec(xs.clone()));
+                Outcome::Nothing
+            }
+            _ => return None,
+        })
+    }
+
+    fn contents(&mut self) -> Vec<u8> {
+        let elems: Vec<u8> = self.iter().copied().collect();
+        assert_eq!(elems.len(), self.len());
+        // The tail pointer has to be on the last node.
+        assert_eq!(self.peek_back(), elems.last());
+        elems
+    }
+}
+
+// The top of the stack is the front. With any allocator, so new_in(&bump) too.
+impl<A: Allocator> Subject for generic_and_iterators::List<u8, A> {
+    fn apply(&mut self, op: &Op, len: usize) -> Option<Outcome> {
+        Some(match op {
+            Op::PushFront(x) => {
+                self.push(*x);
+                Outcome::Nothing
+            }
+            Op::PopFront => Outcome::Elem(self.pop()),
+            Op::Remove(i) => {
+                let at = i.index(len);
+                let mut seen = 0;
+                Outcome::Elem(self.remove_first(|_| {
+                    seen += 1;
+                    seen > at
+                }))
+            }
+            _ => return None,
+        })
+    }
+
+    fn contents(&mut self) -> Vec<u8> {
+        self.iter().copied().collect()
+    }
+}
+
+// The same stack, with the first few elements inline.
+impl<const N: usize> Subject for SmallList<u8, N> {
+    fn apply(&mut self, op: &Op, len: usize) -> Option<Outcome> {
+        Some(match op {
+            Op::PushFront(x) => {
+                self.push(*x);
+                Outcome::Nothing
+            }
+            Op::PopFront => Outcome::Elem(self.pop()),
+            Op::Remove(i) => {
+                let at = i.index(len);
+                let mut seen = 0;
+                Outcome::Elem(self.remove_first(|_| {
+                    seen += 1;
+                    seen > at
+                }))
+            }
+            _ => return None,
+        })
+    }
+
+    fn contents(&mut self) -> Vec<u8> {
+        assert_eq!(self.validate(), Ok(()));
+        let elems: Vec<u8> = self.iter().copied().collect();
+        assert_eq!(elems.len(), self.len());
+        elems
+    }
+}
+
+// No iter(): the contents are read by draining it and pushing them back.
+impl Subject for doubly_linked_deque::List<u8> {
+    fn apply(&mut self, op: &Op, _len: usize) -> Option<Outcome> {
+        Some(match op {
+            Op::PushFront(x) => {
+                self.push_front(*x);
+                Outcome::Nothing
+            }
+            Op::PushBack(x) => {
+                self.push_back(*x);
+                Outcome::Nothing
+            }
+            Op::PopFront => Outcome::Elem(self.pop_front()),
+            Op::PopBack => Outcome::Elem(self.pop_back()),
+            _ => return None,
+        })
+    }
+
+    fn contents(&mut self) -> Vec<u8> {
+        let elems: Vec<u8> = mem::take(self).into_iter().collect();
+        for &x in &elems {
+            self.push_back(x);
+        }
+        assert_eq!(Collection::len(self), elems.len());
+        elems
+    }
+}
+
+impl Subject for ArenaList<u8> {
+    fn apply(&mut self, op: &Op, len: usize) -> Option<Outcome> {
+        let handle = |list: &ArenaList<u8>, at: usize| {
+            let mut handle = list.front_handle().unwrap();
+            for _ in 0..at {
+                handle = list.next(handle).unwrap();
+            }
+            handle
+        };
+        Some(match op {
+            Op::PushFront(x) => {
+                self.push_front(*x);
+                Outcome::Nothing
+            }
+            Op::PushBack(x) => {
+                self.push_back(*x);
+                Outcome::Nothing
+            }
+            Op::PopFront => Outcome::Elem(self.pop_front()),
+            Op::PopBack => Outcome::Elem(self.pop_back()),
+            Op::Insert(i, x) => {
+                match i.index(len + 1) {
+                    at if at == len => {
+                        self.push_back(*x);
+                    }
+                    at => {
+                        self.insert_before(handle(self, at), *x).unwrap();
+                    }
+                }
+                Outcome::Nothing
+            }
+            Op::Remove(i) => Outcome::Elem(self.remove(handle(self, i.index(len)))),
+            Op::Append(xs) => {
+                self.extend(xs.iter().copied());
+                Outcome::Nothing
+            }
+            Op::SplitOff(_) => return None,
+        })
+    }
+
+    fn contents(&mut self) -> Vec<u8> {
+        let elems: Vec<u8> = self.iter().copied().collect();
+        assert!(self.iter().rev().copied().eq(elems.iter().rev().copied()));
+        assert_eq!(elems.len(), self.len());
+        elems
+    }
+}
+
+// Indexed and unrolled lists: the same API, by index.
+macro_rules! by_index {
+    ($list:ty) => {
+        impl Subject for $list {
+            fn apply(&mut self, op: &Op, len: usize) -> Option<Outcome> {
+                Some(match op {
+                    Op::PushFront(x) => {
+                        self.push_front(*x);
+                        Outcome::Nothing
+                    }
+                    Op::PushBack(x) => {
+                        self.push_back(*x);
+                        Outcome::Nothing
+                    }
+                    Op::PopFront => Outcome::Elem(self.pop_front()),
+                    Op::PopBack => Outcome::Elem(self.pop_back()),
+                    Op::Insert(i, x) => {
+                        self.insert(i.index(len + 1), *x);
+                        Outcome::Nothing
+                    }
+                    Op::Remove(i) => Outcome::Elem(Some(self.remove(i.index(len)))),
+                    Op::Append(xs) => {
+                        for &x in xs {
+                            self.push_back(x);
+                        }
+                        Outcome::Nothing
+                    }
+                    Op::SplitOff(_) => return None,
+                })
+            }
+
+            fn contents(&mut self) -> Vec<u8> {
+                let elems: Vec<u8> = self.iter().copied().collect();
+                assert_eq!(elems.len(), self.len());
+                for (i, x) in elems.iter().enumerate() {
+                    assert_eq!(self.get(i), Some(x));
+                }
+                elems
+            }
+        }
+    };
+}
+
+by_index!(IndexedList<u8>);
+// Small nodes, so that inserts and removes split and merge them all the time.
+by_index!(UnrolledList<u8, 4>);
+
+impl Subject for XorList<u8> {
+    fn apply(&mut self, op: &Op, _len: usize) -> Option<Outcome> {
+        Some(match op {
+            Op::PushFront(x) => {
+                self.push_front(*x);
+                Outcome::Nothing
+            }
+            Op::PushBack(x) => {
+                self.push_back(*x);
+                Outcome::Nothing
+            }
+            Op::PopFront => Outcome::Elem(self.pop_front()),
+            Op::PopBack => Outcome::Elem(self.pop_back()),
+            _ => return None,
+        })
+    }
+
+    fn contents(&mut self) -> Vec<u8> {
+        let elems: Vec<u8> = self.iter().copied().collect();
+        assert!(self.iter().rev().copied().eq(elems.iter().rev().copied()));
+        elems
+    }
+}
+
+// Every operation needs the token, so the list carries its own.
+struct Ghost<'id> {
+    list: GhostList<'id, u8>,
+    token: GhostToken<'id>,
+}
+
+impl Subject for Ghost<'_> {
+    fn apply(&mut self, op: &Op, _len: usize) -> Option<Outcome> {
+        let token = &mut self.token;
+        Some(match op {
+            Op::PushFront(x) => {
+                self.list.push_front(*x, token);
+                Outcome::Nothing
+            }
+            Op::PushBack(x) => {
+                self.list.push_back(*x, token);
+                Outcome::Nothing
+            }
+            Op::PopFront => Outcome::Elem(self.list.pop_front(token)),
+            Op::PopBack => Outcome::Elem(self.list.pop_back(token)),
+            _ => return None,
+        })
+    }
+
+    fn contents(&mut self) -> Vec<u8> {
+        assert_eq!(self.list.validate(&self.token), Ok(()));
+        let elems: Vec<u8> = self.list.iter(&self.token).copied().collect();
+        assert_eq!(elems.len(), self.list.len());
+        elems
+    }
+}
+
+proptest! {
+    #[test]
+    fn unsafe_doubly_linked_deque(ops in vec(op(), 0..100)) {
+        check(unsafe_doubly_linked_deque::List::<u8>::new(), &ops);
+    }
+
+    #[test]
+    fn unsafe_single_linked_queue(ops in vec(op(), 0..100)) {
+        check(unsafe_single_linked_queue::List::<u8>::new(), &ops);
+    }
+
+    #[test]
+    fn generic_and_iterators(ops in vec(op(), 0..100)) {
+        check(generic_and_iterators::List::<u8>::new(), &ops);
+    }
+
+    // Nodes from a Bump that has room for every push: it never frees, so pops don't make room.
+    #[test]
+    fn generic_and_iterators_bump(ops in vec(op(), 0..100)) {
+        let bump = Bump::with_capacity(100 * 16);
+        check(generic_and_iterators::List::new_in(&bump), &ops);
+    }
+
+    #[test]
+    fn doubly_linked_deque(ops in vec(op(), 0..100)) {
+        check(doubly_linked_deque::List::<u8>::new(), &ops);
+    }
+
+    #[test]
+    fn arena_list(ops in vec(op(), 0..100)) {
+        check(ArenaList::<u8>::new(), &ops);
+    }
+
+    #[test]
+    fn indexed_list(ops in vec(op(), 0..100)) {
+        check(IndexedList::<u8>::new(), &ops);
+    }
+
+    #[test]
+    fn unrolled_list(ops in vec(op(), 0..100)) {
+        check(UnrolledList::<u8, 4>::new(), &ops);
+    }
+
+    #[test]
+    fn xor_list(ops in vec(op(), 0..100)) {
+        check(XorList::<u8>::new(), &ops);
+    }
+
+    // Small enough to spill to the list and come back.
+    #[test]
+    fn small_list(ops in vec(op(), 0..100)) {
+        check(SmallList::<u8, 4>::new(), &ops);
+    }
+
+    #[test]
+    fn ghost_list(ops in vec(op(), 0..100)) {
+        GhostToken::scope(|token| check(Ghost { list: GhostList::new(), token }, &ops));
+    }
+}
@@ -0,0 +1,255 @@
+// Panic-safety tests: every list with elements that panic on cue. A Bomb counts its drops, and the
+// one that's armed panics in Drop or Clone. Each test catches the panic and then checks the list
+// either cleaned up after itself (every other element dropped, nothing leaked) or is still a valid
+// list that can be used and dropped as normal. Run under Miri, that last part is also a check the
+// unsafe lists didn't free anything twice or leave a dangling link:
+//
+//     cargo +nightly miri test --test panics
+
+use linked_list_rc::allocator::Bump;
+use linked_list_rc::arena_list::ArenaList;
+use linked_list_rc::circular_list::CircularList;
+use linked_list_rc::pointer_list::{ArcList, BoxList, RcList};
+use linked_list_rc::skip_list::SkipMap;
+use linked_list_rc::small_list::SmallList;
+use linked_list_rc::sorted_list::SortedList;
+use linked_list_rc::unrolled_list::UnrolledList;
+use linked_list_rc::xor_list::XorList;
+use linked_list_rc::{
+    doubly_linked_deque, generic_and_iterators, unsafe_doubly_linked_deque,
+    unsafe_single_linked_queue,
+};
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+
+const LEN: u32 = 10;
+// The element that panics, somewhere in the middle.
+const ARMED: u32 = 5;
+
+thread_local! {
+    static DROPS: Cell<u32> = const { Cell::new(0) };
+    static CLONES: Cell<u32> = const { Cell::new(0) };
+    // Which element panics, in Drop or in Clone. Each goes off once, then disarms itself.
+    static DROP_PANICS: Cell<Option<u32>> = const { Cell::new(None) };
+    static CLONE_PANICS: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Bomb(u32);
+
+impl Drop for Bomb {
+    fn drop(&mut self) {
+        DROPS.set(DROPS.get() + 1);
+        if DROP_PANICS.get() == Some(self.0) {
+            DROP_PANICS.set(None);
+            panic!("dropping {}", self.0);
+        }
+    }
+}
+
+impl Clone for Bomb {
+    fn clone(&self) -> Self {
+        if CLONE_PANICS.get() == Some(self.0) {
+            CLONE_PANICS.set(None);
+            panic!("cloning {}", self.0);
+        }
+        CLONES.set(CLONES.get() + 1);
+        Bomb(self.0)
+    }
+}
+
+fn bombs() -> Vec<Bomb> {
+    (0..LEN).map(Bomb).collect()
+}
+
+// Build a list from 0..LEN, then drop it with one element panicking in its Drop. The panic gets
+// out, and every element is dropped anyway, including the ones after the one that panicked.
+fn check_drop<L>(build: impl FnOnce(Vec<Bomb>) -> L) {
+    DROPS.set(0);
+    let list = build(bombs());
+    DROP_PANICS.set(Some(ARMED));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| drop(list)));
+    assert!(result.is_err());
+    assert_eq!(DROPS.get(), LEN, "{}", std::any::type_name::<L>());
+}
+
+#[test]
+fn drop_carries_on() {
+    check_drop(|bombs| {
+        let mut list = generic_and_iterators::List::new();
+        bombs.into_iter().for_each(|b| list.push(b));
+        list
+    });
+    check_drop(|bombs| {
+        let mut list = doubly_linked_deque::List::new();
+        bombs.into_iter().for_each(|b| list.push_back(b));
+        list
+    });
+    check_drop(unsafe_single_linked_queue::List::from_vec);
+    check_drop(|bombs| {
+        bombs
+            .into_iter()
+            .collect::<unsafe_doubly_linked_deque::List<_>>()
+    });
+    check_drop(|bombs| {
+        let mut list = generic_and_iterators::List::new_in(Bump::with_capacity(1024));
+        bombs.into_iter().for_each(|b| list.push(b));
+        list
+    });
+    check_drop(|bombs| bombs.into_iter().collect::<XorList<_>>());
+    check_drop(|bombs| bombs.into_iter().collect::<CircularList<_>>());
+    check_drop(|bombs| bombs.into_iter().collect::<UnrolledList<_, 4>>());
+    check_drop(|bombs| bombs.into_iter().collect::<ArenaList<_>>());
+    check_drop(|bombs| bombs.into_iter().collect::<BoxList<_>>());
+    check_drop(|bombs| bombs.into_iter().collect::<RcList<_>>());
+    check_drop(|bombs| bombs.into_iter().collect::<ArcList<_>>());
+    check_drop(|bombs| {
+        bombs
+            .into_iter()
+            .map(|b| (b.0, b))
+            .collect::<SkipMap<_, _>>()
+    });
+    check_drop(|bombs| bombs.into_iter().collect::<SortedList<_>>());
+    check_drop(|bombs| {
+        let mut list = SmallList::<_, 4>::new();
+        bombs.into_iter().for_each(|b| list.push(b));
+        list
+    });
+}
+
+// An IntoIter part way through owns what's left, and drops it all the same way.
+#[test]
+fn into_iter_drop_carries_on() {
+    check_drop(|bombs| {
+        let mut iter = bombs
+            .into_iter()
+            .collect::<UnrolledList<_, 4>>()
+            .into_iter();
+        iter.next();
+        iter
+    });
+    check_drop(|bombs| {
+        let mut iter = bombs
+            .into_iter()
+            .collect::<unsafe_doubly_linked_deque::List<_>>()
+            .into_iter();
+        iter.next_back();
+        iter
+    });
+}
+
+// A clone that panics part way drops the clones made so far, and leaves the original as it was.
+// Which ones were made first depends on the list (SortedList clones back to front), so it's
+// counted rather than assumed.
+fn check_clone<L: Clone>(list: L, contents: impl Fn(&L) -> Vec<u32>) {
+    DROPS.set(0);
+    CLONES.set(0);
+    CLONE_PANICS.set(Some(ARMED));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| list.clone()));
+    assert!(result.is_err());
+    assert!(CLONES.get() > 0);
+    assert_eq!(DROPS.get(), CLONES.get(), "{}", std::any::type_name::<L>());
+    assert_eq!(contents(&list), (0..LEN).collect::<Vec<_>>());
+    // And it's still a list: a clone that doesn't panic is equal to it.
+    assert_eq!(contents(&list.clone()), contents(&list));
+}
+
+#[test]
+fn clone_panics() {
+    check_clone(
+        bombs()
+            .into_iter()
+            .collect::<unsafe_doubly_linked_deque::List<_>>(),
+        |list| list.iter().map(|b| b.0).collect(),
+    );
+    check_clone(bombs().into_iter().collect::<CircularList<_>>(), |list| {
+        list.iter().map(|b| b.0).collect()
+    });
+    check_clone(
+        bombs().into_iter().collect::<UnrolledList<_, 4>>(),
+        |list| list.iter().map(|b| b.0).collect(),
+    );
+    check_clone(bombs().into_iter().collect::<ArenaList<_>>(), |list| {
+        list.iter().map(|b| b.0).collect()
+    });
+    check_clone(bombs().into_iter().collect::<SortedList<_>>(), |list| {
+        list.iter().map(|b| b.0).collect()
+    });
+}
+
+// An iterator that panics part way through an extend leaves the list valid, with a len that
+// agrees with its contents. Those are `kept` of the elements that came before the panic: all of
+// them for a list that pushes as it goes, none for one that collects first (SortedList merges the
+// whole lot at the end).
+fn check_extend<L: Extend<Bomb> + Default>(
+    kept: u32,
+    contents: impl Fn(&L) -> Vec<u32>,
+    len: impl Fn(&L) -> usize,
+) {
+    let mut list = L::default();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        list.extend((0..LEN).map(|x| {
+            assert_ne!(x, ARMED);
+            Bomb(x)
+        }))
+    }));
+    assert!(result.is_err());
+    let expected: Vec<_> = (0..kept).collect();
+    assert_eq!(contents(&list), expected, "{}", std::any::type_name::<L>());
+    assert_eq!(len(&list), expected.len());
+    list.extend([Bomb(LEN)]);
+    assert_eq!(len(&list), expected.len() + 1);
+}
+
+#[test]
+fn extend_panics() {
+    check_extend::<unsafe_doubly_linked_deque::List<_>>(
+        ARMED,
+        |list| list.iter().map(|b| b.0).collect(),
+        |list| list.len(),
+    );
+    check_extend::<unsafe_single_linked_queue::List<_>>(
+        ARMED,
+        |list| list.iter().map(|b| b.0).collect(),
+        |list| list.len(),
+    );
+    check_extend::<CircularList<_>>(
+        ARMED,
+        |list| list.iter().map(|b| b.0).collect(),
+        |list| list.len(),
+    );
+    check_extend::<UnrolledList<_, 4>>(
+        ARMED,
+        |list| list.iter().map(|b| b.0).collect(),
+        |list| list.len(),
+    );
+    check_extend::<ArenaList<_>>(
+        ARMED,
+        |list| list.iter().map(|b| b.0).collect(),
+        |list| list.len(),
+    );
+    check_extend::<SortedList<_>>(
+        0,
+        |list| list.iter().map(|b| b.0).collect(),
+        |list| list.len(),
+    );
+}
+
+// retain with a predicate that panics half way: what it already removed stays removed, and what
+// it didn't get to stays.
+#[test]
+fn retain_panics() {
+    let mut list: SortedList<_> = bombs().into_iter().collect();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        list.retain(|b| {
+            assert_ne!(b.0, ARMED);
+            b.0 % 2 == 1
+        })
+    }));
+    assert!(result.is_err());
+    let left: Vec<_> = list.iter().map(|b| b.0).collect();
+    assert_eq!(left, [1, 3, 5, 6, 7, 8, 9]);
+    assert_eq!(list.len(), left.len());
+    list.insert(Bomb(0));
+    assert_eq!(list.iter().next(), Some(&Bomb(0)));
+}